@@ -0,0 +1,80 @@
+use std::fmt;
+
+use models::{AuthUser, EmailAddress, UserSubmittedAuthCredentials};
+use sha2::{Digest, Sha256};
+use tokio::time::Duration;
+
+use crate::cache::ExpiringCache;
+
+/// How long a successful basic auth verification is trusted for. Password
+/// changes take up to this long to apply to basic auth.
+const BASIC_AUTH_CACHE_TTL: Duration = Duration::from_secs(60);
+
+/// Remembers successful basic auth verifications for a short while, so that
+/// clients sending credentials with every request (like Nix with a netrc
+/// file) don't pay for a password hash verification each time.
+///
+/// Entries are keyed by a SHA-256 hash of the credentials and a random
+/// per-process key, so the cache never holds passwords, and its keys are
+/// useless outside of this process.
+pub(crate) struct BasicAuthCache {
+  cache: ExpiringCache<[u8; 32], AuthUser>,
+  key:   [u8; 32],
+}
+
+impl fmt::Debug for BasicAuthCache {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("BasicAuthCache")
+      .field("cache", &self.cache)
+      .field("key", &"<redacted>")
+      .finish()
+  }
+}
+
+impl BasicAuthCache {
+  /// Creates an empty cache with a fresh random key.
+  pub(crate) fn new() -> Self {
+    let mut key = [0; 32];
+    getrandom::fill(&mut key).expect("failed to generate basic auth cache key");
+    Self {
+      cache: ExpiringCache::new(BASIC_AUTH_CACHE_TTL),
+      key,
+    }
+  }
+
+  /// Gets the user the credentials were recently verified for.
+  pub(crate) async fn get(
+    &self,
+    email: &EmailAddress,
+    creds: &UserSubmittedAuthCredentials,
+  ) -> Option<AuthUser> {
+    self.cache.get(&self.cache_key(email, creds)).await
+  }
+
+  /// Remembers that the credentials were verified for a user.
+  pub(crate) async fn insert(
+    &self,
+    email: &EmailAddress,
+    creds: &UserSubmittedAuthCredentials,
+    user: AuthUser,
+  ) {
+    self.cache.insert(self.cache_key(email, creds), user).await
+  }
+
+  fn cache_key(
+    &self,
+    email: &EmailAddress,
+    creds: &UserSubmittedAuthCredentials,
+  ) -> [u8; 32] {
+    let UserSubmittedAuthCredentials::Password { password } = creds;
+
+    // fields are length-prefixed so that they can't run into each other
+    let mut hasher = Sha256::new();
+    hasher.update(self.key);
+    for field in [email.as_str(), password.as_str()] {
+      hasher.update((field.len() as u64).to_le_bytes());
+      hasher.update(field.as_bytes());
+    }
+    hasher.finalize().into()
+  }
+}
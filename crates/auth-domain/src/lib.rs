@@ -1,6 +1,7 @@
 //! Provides the [`AuthDomainService`], the entry point for users,
 //! authentication, and authorization logic.
 
+mod basic_auth;
 mod cache;
 #[cfg(test)]
 mod tests;
@@ -16,7 +17,7 @@ use models::{
 };
 use tracing::debug;
 
-use self::cache::ExpiringCache;
+use self::{basic_auth::BasicAuthCache, cache::ExpiringCache};
 
 /// The authentication session type.
 pub type AuthSession = axum_login::AuthSession<AuthDomainService>;
@@ -24,8 +25,9 @@ pub type AuthSession = axum_login::AuthSession<AuthDomainService>;
 /// A dynamic [`AuthDomainService`] trait object.
 #[derive(Clone, Debug)]
 pub struct AuthDomainService {
-  domain:     DomainService,
-  user_cache: Arc<ExpiringCache<RecordId<User>, AuthUser>>,
+  domain:           DomainService,
+  user_cache:       Arc<ExpiringCache<RecordId<User>, AuthUser>>,
+  basic_auth_cache: Arc<BasicAuthCache>,
 }
 
 impl AuthDomainService {
@@ -35,8 +37,34 @@ impl AuthDomainService {
     Self {
       domain,
       user_cache: Arc::new(ExpiringCache::new(Duration::from_secs(0))),
+      basic_auth_cache: Arc::new(BasicAuthCache::new()),
     }
   }
+
+  /// Authenticates with credentials sent along with a request, like with HTTP
+  /// basic auth. Successful verifications are remembered briefly, since these
+  /// credentials are sent with every request.
+  #[tracing::instrument(skip(self, creds))]
+  pub async fn authenticate_per_request(
+    &self,
+    creds: (EmailAddress, UserSubmittedAuthCredentials),
+  ) -> Result<Option<AuthUser>, AuthenticationError> {
+    let (email, submitted) = &creds;
+    if let Some(user) = self.basic_auth_cache.get(email, submitted).await {
+      debug!(%email, "basic auth cache hit from AuthDomain");
+      return Ok(Some(user));
+    }
+
+    let user = self.authenticate(creds.clone()).await?;
+    if let Some(user) = &user {
+      let (email, submitted) = creds;
+      self
+        .basic_auth_cache
+        .insert(&email, &submitted, user.clone())
+        .await;
+    }
+    Ok(user)
+  }
 }
 
 impl AuthnBackend for AuthDomainService {
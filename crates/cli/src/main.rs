@@ -5,6 +5,7 @@
 
 mod app_state;
 mod authenticate;
+mod push;
 mod upload;
 
 use clap::{Parser, Subcommand};
@@ -15,7 +16,8 @@ use tracing_subscriber::{
 };
 
 use self::{
-  app_state::AppState, authenticate::AuthenticateCommand, upload::UploadCommand,
+  app_state::AppState, authenticate::AuthenticateCommand, push::PushCommand,
+  upload::UploadCommand,
 };
 
 #[expect(dead_code)]
@@ -48,6 +50,7 @@ pub(crate) struct CliArgs {
 pub(crate) enum SubCommand {
   Authenticate(AuthenticateCommand),
   Upload(UploadCommand),
+  Push(PushCommand),
 }
 
 impl SubCommand {
@@ -65,6 +68,12 @@ impl SubCommand {
           .await
           .context("failed to execute `upload` subcommand")?;
       }
+      SubCommand::Push(push) => {
+        push
+          .execute(app_state)
+          .await
+          .context("failed to execute `push` subcommand")?;
+      }
     }
 
     Ok(())
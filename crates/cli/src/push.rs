@@ -0,0 +1,408 @@
+use std::collections::{HashMap, HashSet, VecDeque};
+
+use clap::Args;
+use futures::{StreamExt, stream::FuturesUnordered};
+use miette::{Context, IntoDiagnostic, bail};
+use models::StorePath;
+use serde::Deserialize;
+
+use crate::{
+  Action,
+  app_state::AppState,
+  authenticate::AuthenticateCommand,
+  upload::{Installable, PathInfo, UploadTarget, upload_path},
+};
+
+/// The number of paths sent in each missing-paths or store-entries query.
+const MISSING_QUERY_CHUNK_SIZE: usize = 1000;
+
+#[derive(Args, Debug)]
+pub(crate) struct PushCommand {
+  /// The flake installable whose closure to push.
+  installable: Installable,
+  /// The store to store the uploaded entries in.
+  #[arg(long, short)]
+  store:       String,
+  /// The number of paths to upload at once.
+  #[arg(long, short = 'j', default_value_t = 4)]
+  concurrency: usize,
+  /// The caches to push to.
+  #[arg(required = true, num_args = 1..)]
+  caches:      Vec<String>,
+}
+
+#[derive(Deserialize)]
+struct MissingResponse {
+  missing: Vec<String>,
+}
+
+/// Asks a cache which of the given paths it doesn't have.
+async fn missing_paths(
+  app_state: &AppState,
+  cache: &str,
+  paths: &[String],
+) -> miette::Result<HashSet<String>> {
+  let client = app_state.http_client();
+  let url = format!("{}/c/{cache}/missing", app_state.api_url_base());
+
+  let mut missing = HashSet::new();
+  for chunk in paths.chunks(MISSING_QUERY_CHUNK_SIZE) {
+    let resp = client
+      .post(&url)
+      .json(&serde_json::json!({ "paths": chunk }))
+      .send()
+      .await
+      .into_diagnostic()
+      .context("failed to send missing-paths request")?
+      .error_for_status()
+      .into_diagnostic()
+      .context("missing-paths request failed")?
+      .json::<MissingResponse>()
+      .await
+      .into_diagnostic()
+      .context("failed to deserialize missing-paths response")?;
+    missing.extend(resp.missing);
+  }
+
+  Ok(missing)
+}
+
+#[derive(Deserialize)]
+struct StoreEntriesResponse {
+  entries: HashMap<String, String>,
+}
+
+/// Asks the target store which of the given paths it already has an entry
+/// for, through another cache. Returns their entry IDs.
+async fn store_entries(
+  app_state: &AppState,
+  store: &str,
+  paths: &[String],
+) -> miette::Result<HashMap<String, String>> {
+  let client = app_state.http_client();
+  let url = format!("{}/entries/lookup", app_state.api_url_base());
+
+  let mut entries = HashMap::new();
+  for chunk in paths.chunks(MISSING_QUERY_CHUNK_SIZE) {
+    let resp = client
+      .post(&url)
+      .query(&[("target_store", store)])
+      .json(&serde_json::json!({ "paths": chunk }))
+      .send()
+      .await
+      .into_diagnostic()
+      .context("failed to send store-entries request")?
+      .error_for_status()
+      .into_diagnostic()
+      .context("store-entries request failed")?
+      .json::<StoreEntriesResponse>()
+      .await
+      .into_diagnostic()
+      .context("failed to deserialize store-entries response")?;
+    entries.extend(resp.entries);
+  }
+
+  Ok(entries)
+}
+
+/// Links an entry which is already in the target store into some caches,
+/// instead of uploading it again.
+async fn link_entry(
+  app_state: &AppState,
+  entry_id: &str,
+  caches: &[String],
+) -> miette::Result<()> {
+  for cache in caches {
+    let url = format!(
+      "{}/entry/{entry_id}/caches/{cache}",
+      app_state.api_url_base()
+    );
+    let resp = app_state
+      .http_client()
+      .put(url)
+      .send()
+      .await
+      .into_diagnostic()
+      .context("failed to send link request")?;
+    let status = resp.status();
+    if !status.is_success() {
+      let text_resp = resp.text().await.unwrap_or_default();
+      bail!(
+        "linking into cache `{cache}` was rejected ({status}): {text_resp}"
+      );
+    }
+  }
+  Ok(())
+}
+
+/// A path to push, with the caches that are missing it.
+struct PushJob {
+  store_path: StorePath<String>,
+  pathinfo:   PathInfo,
+  caches:     Vec<String>,
+}
+
+/// The outcome of pushing a path.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum PushOutcome {
+  /// The path was uploaded.
+  Uploaded,
+  /// The path was already in the target store, and was linked.
+  Linked,
+  /// Pushing the path failed.
+  Failed,
+  /// The path wasn't pushed, because a path it references failed.
+  Blocked,
+}
+
+/// Pushes paths in dependency order. A path is only started once every path
+/// it references has been pushed, so that a cache never holds a path whose
+/// references it's missing. Paths which depend on a path that failed aren't
+/// pushed at all.
+async fn push_in_dependency_order(
+  app_state: &AppState,
+  target: &UploadTarget,
+  in_store: &HashMap<String, String>,
+  jobs: &[PushJob],
+  concurrency: usize,
+) -> Vec<PushOutcome> {
+  let (mut pending_references, dependents) = dependency_graph(jobs);
+  let mut ready = (0..jobs.len())
+    .filter(|i| pending_references[*i] == 0)
+    .collect::<VecDeque<_>>();
+  let mut outcomes = vec![None; jobs.len()];
+
+  let mut running = FuturesUnordered::new();
+  loop {
+    while running.len() < concurrency {
+      let Some(i) = ready.pop_front() else {
+        break;
+      };
+      running.push(async move {
+        (i, push_job(app_state, target, in_store, &jobs[i]).await)
+      });
+    }
+
+    let Some((i, outcome)) = running.next().await else {
+      break;
+    };
+    outcomes[i] = Some(outcome);
+    if outcome == PushOutcome::Failed {
+      block_dependents(jobs, &dependents, &mut outcomes, i);
+      continue;
+    }
+    for &dependent in &dependents[i] {
+      pending_references[dependent] -= 1;
+      if pending_references[dependent] == 0 && outcomes[dependent].is_none() {
+        ready.push_back(dependent);
+      }
+    }
+  }
+
+  // only a reference cycle leaves paths which were never started
+  outcomes
+    .into_iter()
+    .zip(jobs)
+    .map(|(outcome, job)| {
+      outcome.unwrap_or_else(|| {
+        tracing::error!(
+          store_path = %job.store_path,
+          "not pushing path, since it's in a reference cycle"
+        );
+        PushOutcome::Blocked
+      })
+    })
+    .collect()
+}
+
+/// Returns, for each job, the number of other jobs it references, and the
+/// jobs which reference it. References to paths which aren't being pushed are
+/// already cached, so they're ignored, as are self-references.
+fn dependency_graph(jobs: &[PushJob]) -> (Vec<usize>, Vec<Vec<usize>>) {
+  let index_by_path = jobs
+    .iter()
+    .enumerate()
+    .map(|(i, job)| (&job.store_path, i))
+    .collect::<HashMap<_, _>>();
+
+  let mut pending_references = vec![0; jobs.len()];
+  let mut dependents = vec![Vec::new(); jobs.len()];
+  for (i, job) in jobs.iter().enumerate() {
+    for reference in job.pathinfo.references() {
+      match index_by_path.get(reference) {
+        Some(&dependency) if dependency != i => {
+          pending_references[i] += 1;
+          dependents[dependency].push(i);
+        }
+        _ => (),
+      }
+    }
+  }
+  (pending_references, dependents)
+}
+
+/// Marks every job which transitively depends on a failed job as blocked.
+fn block_dependents(
+  jobs: &[PushJob],
+  dependents: &[Vec<usize>],
+  outcomes: &mut [Option<PushOutcome>],
+  failed: usize,
+) {
+  let mut to_block = dependents[failed].clone();
+  while let Some(i) = to_block.pop() {
+    if outcomes[i].is_some() {
+      continue;
+    }
+    tracing::warn!(
+      store_path = %jobs[i].store_path,
+      failed_reference = %jobs[failed].store_path,
+      "not pushing path, since a path it depends on failed"
+    );
+    outcomes[i] = Some(PushOutcome::Blocked);
+    to_block.extend(&dependents[i]);
+  }
+}
+
+/// Pushes a single path, linking it if it's already in the target store and
+/// uploading it otherwise.
+async fn push_job(
+  app_state: &AppState,
+  target: &UploadTarget,
+  in_store: &HashMap<String, String>,
+  job: &PushJob,
+) -> PushOutcome {
+  let PushJob {
+    store_path,
+    pathinfo,
+    caches,
+  } = job;
+  let result = match in_store.get(&store_path.to_absolute_path()) {
+    Some(entry_id) => link_entry(app_state, entry_id, caches)
+      .await
+      .map(|()| PushOutcome::Linked),
+    None => upload_path(app_state, target, caches, store_path, pathinfo)
+      .await
+      .map(|()| PushOutcome::Uploaded),
+  };
+  match &result {
+    Ok(PushOutcome::Linked) => tracing::info!(%store_path, "linked path"),
+    Ok(_) => tracing::info!(%store_path, "uploaded path"),
+    Err(e) => tracing::error!(%store_path, "failed to push path: {e:?}"),
+  }
+  result.unwrap_or(PushOutcome::Failed)
+}
+
+impl Action for PushCommand {
+  type Error = miette::Report;
+  type Output = ();
+
+  async fn execute(
+    self,
+    app_state: &AppState,
+  ) -> Result<Self::Output, Self::Error> {
+    let closure = PathInfo::calculate_closure(&self.installable)
+      .await
+      .context(format!(
+        "failed to get closure path-info for installable `{}`",
+        self.installable
+      ))?;
+    tracing::info!(count = closure.len(), "calculated closure");
+
+    let target = UploadTarget::calculate(&self.caches, &self.store).await?;
+
+    // authenticate with origin. session cookie gets saved in client.
+    let _creds = (AuthenticateCommand {})
+      .execute(app_state)
+      .await
+      .context("failed to authenticate")?;
+
+    // find out which caches are missing each path
+    let paths = closure
+      .iter()
+      .map(|(p, _)| p.to_absolute_path())
+      .collect::<Vec<_>>();
+    let mut missing_by_cache = HashMap::new();
+    for cache in target.caches() {
+      let missing = missing_paths(app_state, cache, &paths)
+        .await
+        .context(format!("failed to query missing paths in cache `{cache}`"))?;
+      tracing::debug!(cache, count = missing.len(), "got missing paths");
+      missing_by_cache.insert(cache.clone(), missing);
+    }
+
+    // paths already in the target store through another cache can't be
+    // uploaded again, so they're linked instead
+    let missing_anywhere = paths
+      .iter()
+      .filter(|p| missing_by_cache.values().any(|m| m.contains(*p)))
+      .cloned()
+      .collect::<Vec<_>>();
+    let in_store = match missing_anywhere.is_empty() {
+      true => HashMap::new(),
+      false => {
+        let store = target.target_store().to_string();
+        store_entries(app_state, &store, &missing_anywhere)
+          .await
+          .context(format!("failed to query entries in store `{store}`"))?
+      }
+    };
+    tracing::debug!(count = in_store.len(), "got paths already in store");
+
+    let mut skipped = 0;
+    let mut jobs = Vec::new();
+    for (store_path, pathinfo) in closure {
+      let absolute_path = store_path.to_absolute_path();
+      let caches = target
+        .caches()
+        .iter()
+        .filter(|c| missing_by_cache[*c].contains(&absolute_path))
+        .cloned()
+        .collect::<Vec<_>>();
+      match caches.is_empty() {
+        true => skipped += 1,
+        false => jobs.push(PushJob {
+          store_path,
+          pathinfo,
+          caches,
+        }),
+      }
+    }
+    tracing::info!(
+      to_push = jobs.len(),
+      skipped,
+      "skipping paths which are already cached"
+    );
+
+    let outcomes = push_in_dependency_order(
+      app_state,
+      &target,
+      &in_store,
+      &jobs,
+      self.concurrency.max(1),
+    )
+    .await;
+
+    let count = |o: PushOutcome| outcomes.iter().filter(|p| **p == o).count();
+    let uploaded = count(PushOutcome::Uploaded);
+    let linked = count(PushOutcome::Linked);
+    let failed = count(PushOutcome::Failed);
+    let blocked = count(PushOutcome::Blocked);
+    tracing::info!(
+      uploaded,
+      linked,
+      skipped,
+      failed,
+      blocked,
+      "finished pushing closure"
+    );
+
+    if failed + blocked > 0 {
+      bail!(
+        "failed to push {failed} paths, and {blocked} paths which depend on \
+         them"
+      );
+    }
+
+    Ok(())
+  }
+}
@@ -1,13 +1,20 @@
+mod chunked;
 mod current_system;
+mod nar_stream;
 mod path_info;
 
-use std::{fmt, io::Read, str::FromStr};
+use std::{fmt, str::FromStr};
 
 use clap::Args;
 use miette::{Context, IntoDiagnostic, bail, miette};
-use models::{FileSize, Slug, StorePath};
-
-use self::{current_system::CurrentSystem, path_info::PathInfo};
+use models::{Slug, StorePath};
+
+pub(crate) use self::path_info::PathInfo;
+use self::{
+  chunked::{CHUNKED_UPLOAD_THRESHOLD, upload_chunked},
+  current_system::CurrentSystem,
+  nar_stream::stream_nar,
+};
 use crate::{Action, app_state::AppState, authenticate::AuthenticateCommand};
 
 #[derive(Clone, Debug)]
@@ -62,102 +69,181 @@ impl Action for UploadCommand {
 
     let store_path = pathinfo_result.store_path();
     tracing::debug!(%store_path, "got path-info");
-    let deriver_store_path: StorePath<String> = StorePath::from_absolute_path(
-      pathinfo
-        .deriver()
-        .strip_suffix(".drv")
-        .ok_or(miette!(
-          "deriver path from `nix path-info` did not have \".drv\" suffix"
-        ))?
-        .as_bytes(),
-    )
-    .into_diagnostic()
-    .context(
-      "failed to parse deriver path from `nix path-info` as a store path",
-    )?;
 
+    let target = UploadTarget::calculate(&self.caches, &self.store).await?;
+
+    // authenticate with origin. session cookie gets saved in client.
+    let _creds = (AuthenticateCommand {})
+      .execute(app_state)
+      .await
+      .context("failed to authenticate")?;
+
+    upload_path(app_state, &target, target.caches(), store_path, pathinfo)
+      .await?;
+
+    Ok(())
+  }
+}
+
+/// Where uploaded paths are sent.
+pub(crate) struct UploadTarget {
+  /// The caches to upload to.
+  caches:         Vec<String>,
+  /// The store to store the uploaded entries in.
+  target_store:   Slug,
+  /// The system the uploaded paths were built on.
+  current_system: CurrentSystem,
+}
+
+impl UploadTarget {
+  pub(crate) async fn calculate(
+    caches: &[String],
+    store: &str,
+  ) -> miette::Result<Self> {
     let current_system = CurrentSystem::calculate()
       .await
       .context("failed to determine current system")?;
 
-    let cache_list = self
-      .caches
-      .into_iter()
-      .map(|c| (c.clone(), Slug::new(&c)))
+    let caches = caches
+      .iter()
+      .map(|c| (c.clone(), Slug::new(c)))
       .inspect(|(o, n)| {
         if *o != n.to_string() {
           tracing::warn!("coercing cache name `{o}` into `{n}`")
         }
       })
       .map(|(_, s)| s.to_string())
-      .collect::<Vec<_>>()
-      .join(",");
-    tracing::debug!(%cache_list, "using cache list");
+      .collect::<Vec<_>>();
+    tracing::debug!(?caches, "using cache list");
 
-    let target_store = Slug::new(&self.store);
-    if self.store != target_store.to_string() {
+    let target_store = Slug::new(store);
+    if store != target_store.to_string() {
       tracing::warn!(
         "coercing store name `{original}` into `{new}`",
-        original = self.store,
+        original = store,
         new = target_store
       );
     }
     tracing::debug!(%target_store, "using target store");
 
-    // authenticate with origin. session cookie gets saved in client.
-    let _creds = (AuthenticateCommand {})
-      .execute(app_state)
-      .await
-      .context("failed to authenticate")?;
+    Ok(Self {
+      caches,
+      target_store,
+      current_system,
+    })
+  }
 
-    tracing::debug!(%store_path, "building NAR");
-    let mut nar_reader = pathinfo_result
-      .nar_encoder()
-      .into_diagnostic()
-      .context("failed to pack nix store path as a NAR")?;
-    let mut buffer = Vec::new();
-    nar_reader
-      .read_to_end(&mut buffer)
-      .into_diagnostic()
-      .context("failed to read bytes from nar encoder")?;
-    tracing::debug!("buffered {} of NAR", FileSize::new(buffer.len() as _));
-    let nar_belt = belt::Belt::from(bytes::Bytes::from(buffer));
-
-    let client = app_state.http_client();
-
-    let url = format!("{}/upload", app_state.api_url_base());
-    let req = client
-      .post(url)
-      .query(&[
-        ("caches", cache_list),
-        ("store_path", store_path.to_string()),
-        ("target_store", target_store.to_string()),
-        ("deriver_store_path", deriver_store_path.to_string()),
-        ("deriver_system", current_system.to_string()),
-      ])
-      .body(reqwest::Body::wrap_stream(nar_belt));
-
-    tracing::debug!("sending upload request");
-    let resp = req
-      .send()
-      .await
-      .into_diagnostic()
-      .context("failed to send upload request")?;
+  /// The caches to upload to.
+  pub(crate) fn caches(&self) -> &[String] { &self.caches }
 
-    let text_resp = resp
-      .text()
-      .await
+  /// The store to store the uploaded entries in.
+  pub(crate) fn target_store(&self) -> &Slug { &self.target_store }
+}
+
+/// Uploads a single store path to some of the target's caches. The session
+/// must already be authenticated.
+pub(crate) async fn upload_path(
+  app_state: &AppState,
+  target: &UploadTarget,
+  caches: &[String],
+  store_path: &StorePath<String>,
+  pathinfo: &PathInfo,
+) -> miette::Result<()> {
+  let deriver_store_path = pathinfo
+    .deriver()
+    .map(|deriver| {
+      StorePath::<String>::from_absolute_path(
+        deriver
+          .strip_suffix(".drv")
+          .ok_or(miette!(
+            "deriver path from `nix path-info` did not have \".drv\" suffix"
+          ))?
+          .as_bytes(),
+      )
       .into_diagnostic()
-      .context("failed to read response body")?;
+      .context(
+        "failed to parse deriver path from `nix path-info` as a store path",
+      )
+    })
+    .transpose()?;
+
+  tracing::debug!(%store_path, "building NAR");
+  let nar_encoder = nix_nar::Encoder::new(store_path.to_absolute_path())
+    .into_diagnostic()
+    .context("failed to pack nix store path as a NAR")?;
+  let nar_belt = stream_nar(nar_encoder);
+
+  let client = app_state.http_client();
+
+  let mut query = vec![
+    ("caches", caches.join(",")),
+    ("store_path", store_path.to_string()),
+    ("target_store", target.target_store.to_string()),
+    ("deriver_system", target.current_system.to_string()),
+    ("nar_hash", pathinfo.nar_hash().to_owned()),
+    ("signatures", pathinfo.signatures().join(",")),
+    // claimed so that the server checks for exactly these, like Nix would,
+    // rather than discovering references heuristically
+    (
+      "references",
+      pathinfo
+        .references()
+        .iter()
+        .map(|r| r.to_string())
+        .collect::<Vec<_>>()
+        .join(","),
+    ),
+  ];
+  if let Some(deriver_store_path) = deriver_store_path {
+    query.push(("deriver_store_path", deriver_store_path.to_string()));
+  }
+  if let Some(ca_hash) = pathinfo.ca_hash() {
+    query.push(("ca", ca_hash.to_nix_nixbase32_string()));
+  }
 
-    tracing::debug!(body = text_resp, "got upload response");
+  if pathinfo.nar_size().inner() > CHUNKED_UPLOAD_THRESHOLD {
+    tracing::debug!(%store_path, "uploading NAR in parts");
+    return upload_chunked(
+      app_state,
+      &query,
+      store_path,
+      pathinfo.nar_hash(),
+      nar_belt,
+    )
+    .await;
+  }
 
-    let json_resp: serde_json::Value = serde_json::from_str(&text_resp)
-      .into_diagnostic()
-      .context("failed to deserialize response body as JSON")?;
+  let url = format!("{}/upload", app_state.api_url_base());
+  let req = client
+    .post(url)
+    .query(&query)
+    .body(reqwest::Body::wrap_stream(nar_belt));
 
-    tracing::debug!(body = ?json_resp, "parsed upload response");
+  tracing::debug!(%store_path, "sending upload request");
+  let resp = req
+    .send()
+    .await
+    .into_diagnostic()
+    .context("failed to send upload request")?;
 
-    Ok(())
+  let status = resp.status();
+  let text_resp = resp
+    .text()
+    .await
+    .into_diagnostic()
+    .context("failed to read response body")?;
+
+  tracing::debug!(body = text_resp, "got upload response");
+
+  if !status.is_success() {
+    bail!("upload was rejected ({status}): {text_resp}");
   }
+
+  let json_resp: serde_json::Value = serde_json::from_str(&text_resp)
+    .into_diagnostic()
+    .context(format!("upload was rejected: {text_resp}"))?;
+
+  tracing::debug!(body = ?json_resp, "parsed upload response");
+
+  Ok(())
 }
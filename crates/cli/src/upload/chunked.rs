@@ -0,0 +1,277 @@
+use std::{collections::HashMap, path::PathBuf};
+
+use belt::Belt;
+use bytes::{Bytes, BytesMut};
+use futures::StreamExt;
+use miette::{Context, IntoDiagnostic, Result};
+use models::StorePath;
+use serde::{Deserialize, Serialize};
+
+use crate::app_state::AppState;
+
+/// NARs larger than this are uploaded in parts through an upload session.
+pub(crate) const CHUNKED_UPLOAD_THRESHOLD: u64 = 256 * 1024 * 1024;
+/// The size of each part of a chunked upload.
+const PART_SIZE: usize = 64 * 1024 * 1024;
+/// The number of times a part is sent before giving up.
+const PART_ATTEMPTS: usize = 3;
+
+/// The state of an upload session, saved so that it can be resumed after a
+/// restart.
+#[derive(Debug, Serialize, Deserialize)]
+struct SessionState {
+  session_id: String,
+  nar_hash:   String,
+}
+
+#[derive(Deserialize)]
+struct StartSessionResponse {
+  session_id: String,
+}
+
+#[derive(Deserialize)]
+struct SessionStatusResponse {
+  parts: Vec<SessionPart>,
+}
+
+#[derive(Deserialize)]
+struct SessionPart {
+  number: u32,
+  size:   u64,
+}
+
+/// The file where the session state for a store path is saved.
+fn session_state_path(store_path: &StorePath<String>) -> Option<PathBuf> {
+  std::env::var_os("XDG_STATE_HOME")
+    .map(PathBuf::from)
+    .or_else(|| {
+      std::env::var_os("HOME").map(|h| PathBuf::from(h).join(".local/state"))
+    })
+    .map(|d| {
+      d.join("rambit")
+        .join("upload-sessions")
+        .join(format!("{store_path}.json"))
+    })
+}
+
+async fn load_session_state(path: &PathBuf) -> Option<SessionState> {
+  let contents = tokio::fs::read(path).await.ok()?;
+  serde_json::from_slice(&contents).ok()
+}
+
+async fn save_session_state(path: &PathBuf, state: &SessionState) {
+  let result = async {
+    if let Some(parent) = path.parent() {
+      tokio::fs::create_dir_all(parent).await?;
+    }
+    tokio::fs::write(path, serde_json::to_vec(state)?).await
+  }
+  .await;
+  if let Err(e) = result {
+    tracing::warn!(?path, "failed to save upload session state: {e}");
+  }
+}
+
+/// Finds the parts already received by a saved session, if it's still alive.
+async fn resume_session(
+  app_state: &AppState,
+  state: &SessionState,
+) -> Option<HashMap<u32, u64>> {
+  let url = format!(
+    "{}/upload/sessions/{}",
+    app_state.api_url_base(),
+    state.session_id
+  );
+  let resp = app_state
+    .http_client()
+    .get(url)
+    .send()
+    .await
+    .ok()?
+    .error_for_status()
+    .ok()?
+    .json::<SessionStatusResponse>()
+    .await
+    .ok()?;
+
+  Some(resp.parts.into_iter().map(|p| (p.number, p.size)).collect())
+}
+
+async fn start_session(
+  app_state: &AppState,
+  query: &[(&str, String)],
+) -> Result<String> {
+  let url = format!("{}/upload/sessions", app_state.api_url_base());
+  let resp = app_state
+    .http_client()
+    .post(url)
+    .query(query)
+    .send()
+    .await
+    .into_diagnostic()
+    .context("failed to send upload session request")?;
+  let status = resp.status();
+  let text_resp = resp
+    .text()
+    .await
+    .into_diagnostic()
+    .context("failed to read response body")?;
+
+  if !status.is_success() {
+    miette::bail!("upload session was rejected ({status}): {text_resp}");
+  }
+
+  let resp: StartSessionResponse = serde_json::from_str(&text_resp)
+    .into_diagnostic()
+    .context(format!("upload session was rejected: {text_resp}"))?;
+  Ok(resp.session_id)
+}
+
+async fn send_part(
+  app_state: &AppState,
+  session_id: &str,
+  number: u32,
+  data: Bytes,
+) -> Result<()> {
+  let url = format!(
+    "{}/upload/sessions/{session_id}/parts/{number}",
+    app_state.api_url_base()
+  );
+
+  let mut attempt = 1;
+  loop {
+    let result = app_state
+      .http_client()
+      .put(&url)
+      .body(data.clone())
+      .send()
+      .await
+      .and_then(|r| r.error_for_status());
+    match result {
+      Ok(_) => return Ok(()),
+      Err(e) if attempt < PART_ATTEMPTS => {
+        tracing::warn!(number, attempt, "failed to send part, retrying: {e}");
+        attempt += 1;
+      }
+      Err(e) => {
+        return Err(e)
+          .into_diagnostic()
+          .context(format!("failed to send part {number}"));
+      }
+    }
+  }
+}
+
+/// Uploads a NAR in parts through an upload session, resuming a saved session
+/// for the same store path if there is one.
+pub(crate) async fn upload_chunked(
+  app_state: &AppState,
+  query: &[(&str, String)],
+  store_path: &StorePath<String>,
+  nar_hash: &str,
+  nar_belt: Belt,
+) -> Result<()> {
+  let state_path = session_state_path(store_path);
+
+  // resume the saved session if it's for the same NAR and still alive
+  let saved_state = match &state_path {
+    Some(path) => load_session_state(path)
+      .await
+      .filter(|s| s.nar_hash == nar_hash),
+    None => None,
+  };
+  let resumed = match &saved_state {
+    Some(state) => resume_session(app_state, state)
+      .await
+      .map(|parts| (state.session_id.clone(), parts)),
+    None => None,
+  };
+  let (session_id, received_parts) = match resumed {
+    Some((session_id, parts)) => {
+      tracing::info!(
+        %store_path,
+        received = parts.len(),
+        "resuming upload session"
+      );
+      (session_id, parts)
+    }
+    None => {
+      let session_id = start_session(app_state, query).await?;
+      if let Some(path) = &state_path {
+        save_session_state(path, &SessionState {
+          session_id: session_id.clone(),
+          nar_hash:   nar_hash.to_owned(),
+        })
+        .await;
+      }
+      (session_id, HashMap::new())
+    }
+  };
+
+  // the NAR encoding is deterministic, so parts which were already received
+  // with the same size are skipped
+  let mut nar_belt = nar_belt;
+  let mut buffer = BytesMut::with_capacity(PART_SIZE);
+  let mut number = 0;
+  let mut finished = false;
+  while !finished {
+    match nar_belt.next().await {
+      Some(chunk) => {
+        let chunk = chunk
+          .into_diagnostic()
+          .context("failed to read NAR stream")?;
+        buffer.extend_from_slice(&chunk);
+        if buffer.len() < PART_SIZE {
+          continue;
+        }
+      }
+      None => finished = true,
+    }
+
+    while buffer.len() >= PART_SIZE || (finished && !buffer.is_empty()) {
+      let len = buffer.len().min(PART_SIZE);
+      let part = buffer.split_to(len).freeze();
+      if received_parts.get(&number) == Some(&(part.len() as u64)) {
+        tracing::debug!(number, "skipping part which was already received");
+      } else {
+        tracing::debug!(number, size = part.len(), "sending part");
+        send_part(app_state, &session_id, number, part).await?;
+      }
+      number += 1;
+    }
+  }
+
+  let url = format!(
+    "{}/upload/sessions/{session_id}/finalize",
+    app_state.api_url_base()
+  );
+  let resp = app_state
+    .http_client()
+    .post(url)
+    .send()
+    .await
+    .into_diagnostic()
+    .context("failed to send finalize request")?;
+  let status = resp.status();
+  let text_resp = resp
+    .text()
+    .await
+    .into_diagnostic()
+    .context("failed to read response body")?;
+
+  tracing::debug!(body = text_resp, "got finalize response");
+
+  if !status.is_success() {
+    miette::bail!("upload was rejected ({status}): {text_resp}");
+  }
+
+  let _: serde_json::Value = serde_json::from_str(&text_resp)
+    .into_diagnostic()
+    .context(format!("upload was rejected: {text_resp}"))?;
+
+  if let Some(path) = &state_path {
+    let _ = tokio::fs::remove_file(path).await;
+  }
+
+  Ok(())
+}
@@ -0,0 +1,43 @@
+use std::io::{self, Read};
+
+use belt::Belt;
+use bytes::Bytes;
+use tokio::sync::mpsc;
+
+/// The size of each chunk read from the NAR encoder.
+const CHUNK_SIZE: usize = 64 * 1024;
+/// The number of chunks buffered ahead of the upload.
+const CHANNEL_CAPACITY: usize = 16;
+
+/// Streams the output of a NAR encoder into a [`Belt`].
+///
+/// The encoder is synchronous, so it runs on the blocking pool and hands off
+/// chunks through a bounded channel, which keeps memory use bounded regardless
+/// of the size of the NAR.
+pub(crate) fn stream_nar(mut encoder: nix_nar::Encoder) -> Belt {
+  let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(CHANNEL_CAPACITY);
+
+  tokio::task::spawn_blocking(move || {
+    loop {
+      let mut chunk = vec![0; CHUNK_SIZE];
+      let item = match encoder.read(&mut chunk) {
+        Ok(0) => return,
+        Ok(n) => {
+          chunk.truncate(n);
+          Ok(Bytes::from(chunk))
+        }
+        Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+        Err(e) => Err(e),
+      };
+      let is_err = item.is_err();
+      // if the receiver hung up then the upload was abandoned
+      if tx.blocking_send(item).is_err() || is_err {
+        return;
+      }
+    }
+  });
+
+  Belt::new(futures::stream::unfold(rx, |mut rx| async move {
+    rx.recv().await.map(|item| (item, rx))
+  }))
+}
@@ -11,12 +11,14 @@ use super::Installable;
 pub(crate) struct PathInfo {
   #[serde(alias = "ca")]
   ca_hash:    Option<CAHash>,
-  deriver:    String,
+  deriver:    Option<String>,
   #[serde(alias = "narHash")]
   nar_hash:   String,
   #[serde(alias = "narSize")]
   nar_size:   FileSize,
   references: HashSet<StorePath<String>>,
+  #[serde(default)]
+  signatures: Vec<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,18 +31,17 @@ impl PathInfoResult {
   pub(crate) fn get(&self) -> &Option<PathInfo> { &self.data }
 
   pub(crate) fn store_path(&self) -> &StorePath<String> { &self.store_path }
-
-  pub(crate) fn nar_encoder(
-    &self,
-  ) -> Result<nix_nar::Encoder, nix_nar::NarError> {
-    nix_nar::Encoder::new(self.store_path.to_absolute_path())
-  }
 }
 
 #[derive(thiserror::Error, miette::Diagnostic, Debug)]
 pub(crate) enum PathInfoError {
   #[error("failed to spawn `nix path-info` process: {0}")]
   Process(std::io::Error),
+  #[error("`nix path-info` failed ({status}): {stderr}")]
+  Failed {
+    status: std::process::ExitStatus,
+    stderr: String,
+  },
   #[error("failed to deserialize JSON from `nix path-info` output: {0}")]
   JsonParse(serde_json::Error),
   #[error("`nix path-info` JSON output form is unexpected: {0}")]
@@ -49,60 +50,43 @@ pub(crate) enum PathInfoError {
   StorePathDeserialization(models::nix_compat::store_path::Error),
   #[error("failed to deserialize path info: {0}")]
   PathInfoDeserialization(serde_json::Error),
+  #[error(
+    "installable has more than one output, select one with \
+     `<installable>^<output>` or push them all with `push`: {0:?}"
+  )]
+  MultipleOutputs(Vec<String>),
 }
 
 impl PathInfo {
-  pub(crate) fn deriver(&self) -> &str { &self.deriver }
+  pub(crate) fn deriver(&self) -> Option<&str> { self.deriver.as_deref() }
 
-  pub(crate) async fn calculate(
-    installable: &Installable,
-  ) -> Result<PathInfoResult, PathInfoError> {
-    tracing::debug!(%installable, "getting path-info");
+  pub(crate) fn ca_hash(&self) -> Option<&CAHash> { self.ca_hash.as_ref() }
 
-    let mut command = Command::new("nix");
+  pub(crate) fn nar_hash(&self) -> &str { &self.nar_hash }
 
-    command.env("NIX_PATH", "");
+  pub(crate) fn nar_size(&self) -> FileSize { self.nar_size }
 
-    command.args(["path-info", "--json"]);
-    command.args(["--extra-experimental-features", "nix-command flakes"]);
-    command.args(["--option", "warn-dirty", "false"]);
-    command.arg(installable.to_string());
+  pub(crate) fn references(&self) -> &HashSet<StorePath<String>> {
+    &self.references
+  }
 
-    let output = command.output().await.map_err(PathInfoError::Process)?;
+  pub(crate) fn signatures(&self) -> &[String] { &self.signatures }
 
-    let root = serde_json::from_slice::<serde_json::Value>(&output.stdout)
-      .map_err(PathInfoError::JsonParse)?;
+  pub(crate) async fn calculate(
+    installable: &Installable,
+  ) -> Result<PathInfoResult, PathInfoError> {
+    tracing::debug!(%installable, "getting path-info");
 
-    if !root.is_object() {
-      Err(PathInfoError::JsonValidation(miette!(
-        "`nix path-info` JSON output is not an object"
-      )))?;
-    }
-    let mut root_object = root.as_object().unwrap().clone();
-
-    if root_object.len() == 2 {
-      let keys = root_object
-        .keys()
-        .map(|p| {
-          StorePath::<String>::from_absolute_path(p.as_bytes())
-            .expect("failed to parse key as store path")
-        })
-        .collect::<Vec<_>>();
-      if keys[0].name().strip_prefix(keys[1].name()) == Some("-man") {
-        root_object.remove(&keys[0].to_absolute_path());
-        tracing::warn!(store_path = %keys[0], "removed `-man` path from `nix path-info` output");
-      }
-      if keys[1].name().strip_prefix(keys[0].name()) == Some("-man") {
-        root_object.remove(&keys[1].to_absolute_path());
-        tracing::warn!(store_path = %keys[1], "removed `-man` path from `nix path-info` output");
-      }
-    }
+    let root_object = path_info_json(installable, false).await?;
 
-    if root_object.len() != 1 {
-      Err(PathInfoError::JsonValidation(miette!(
-        "`nix path-info` JSON output does not have 1 key: found keys: {:?}",
-        root_object.keys().collect::<Vec<_>>()
-      )))?;
+    match root_object.len() {
+      0 => Err(PathInfoError::JsonValidation(miette!(
+        "`nix path-info` JSON output has no keys"
+      )))?,
+      1 => (),
+      _ => Err(PathInfoError::MultipleOutputs(
+        root_object.keys().cloned().collect(),
+      ))?,
     }
 
     let store_path_string = root_object.keys().nth(0).unwrap();
@@ -124,4 +108,71 @@ impl PathInfo {
 
     Ok(PathInfoResult { store_path, data })
   }
+
+  /// Calculates the path-info of every path in the runtime closure of all the
+  /// outputs of an installable.
+  pub(crate) async fn calculate_closure(
+    installable: &Installable,
+  ) -> Result<Vec<(StorePath<String>, PathInfo)>, PathInfoError> {
+    tracing::debug!(%installable, "getting closure path-info");
+
+    let root_object = path_info_json(installable, true).await?;
+
+    root_object
+      .into_iter()
+      .map(|(store_path_string, value)| {
+        let store_path =
+          StorePath::from_absolute_path(store_path_string.as_bytes())
+            .map_err(PathInfoError::StorePathDeserialization)?;
+        match value {
+          serde_json::Value::Object(map) => Ok((
+            store_path,
+            PathInfo::deserialize(&serde_json::Value::Object(map))
+              .map_err(PathInfoError::PathInfoDeserialization)?,
+          )),
+          v => Err(PathInfoError::JsonValidation(miette!(
+            "got unexpected data for `{store_path_string}` in `nix path-info` \
+             JSON output: {v}"
+          ))),
+        }
+      })
+      .collect()
+  }
+}
+
+/// Runs `nix path-info --json` on an installable and returns the JSON object
+/// it outputs, keyed by store path.
+async fn path_info_json(
+  installable: &Installable,
+  recursive: bool,
+) -> Result<serde_json::Map<String, serde_json::Value>, PathInfoError> {
+  let mut command = Command::new("nix");
+
+  command.env("NIX_PATH", "");
+
+  command.args(["path-info", "--json"]);
+  if recursive {
+    command.arg("--recursive");
+  }
+  command.args(["--extra-experimental-features", "nix-command flakes"]);
+  command.args(["--option", "warn-dirty", "false"]);
+  command.arg(installable.to_string());
+
+  let output = command.output().await.map_err(PathInfoError::Process)?;
+  if !output.status.success() {
+    return Err(PathInfoError::Failed {
+      status: output.status,
+      stderr: String::from_utf8_lossy(&output.stderr).trim().to_owned(),
+    });
+  }
+
+  let root = serde_json::from_slice::<serde_json::Value>(&output.stdout)
+    .map_err(PathInfoError::JsonParse)?;
+
+  match root {
+    serde_json::Value::Object(map) => Ok(map),
+    _ => Err(PathInfoError::JsonValidation(miette!(
+      "`nix path-info` JSON output is not an object"
+    ))),
+  }
 }
@@ -0,0 +1,384 @@
+//! Types and impl for uploads through Nix's HTTP binary cache protocol.
+//!
+//! Nix's `HttpBinaryCacheStore` uploads a path by `PUT`ting its NAR to
+//! `nar/<filehash>.nar[.<ext>]`, and then its narinfo to `<digest>.narinfo`.
+//! The NAR is held as a pending blob in the cache's default store until its
+//! narinfo arrives, at which point it's streamed through the normal upload
+//! pipeline with the narinfo's claims, and the pending blob is deleted.
+//! Pending blobs are also recorded as orphaned when they're uploaded, so ones
+//! whose narinfo never arrives are swept after the orphaned blob grace period.
+
+use std::path::PathBuf;
+
+use belt::Belt;
+use futures::TryStreamExt;
+use miette::{Context, IntoDiagnostic, miette};
+use models::{
+  Cache, CompressionAlgorithm, Digest, EntityName, FileSize, NarDeriverData,
+  OrphanedBlob, RecordId, Store, User, nix_compat::narinfo::NarInfo,
+};
+use storage::{BlobKey, BlobStorage, BlobStorageError};
+use time::UtcDateTime;
+
+use crate::{
+  DomainService,
+  nar_file_name::{NarFileName, NarFileNameError},
+  store_dir::narinfo_from_store_dir,
+  upload::{
+    UploadExecutionError, UploadPlanningError, UploadRequest, UploadResponse,
+  },
+};
+
+/// The request struct for the
+/// [`binary_cache_put_nar`](DomainService::binary_cache_put_nar) fn.
+#[derive(Debug)]
+pub struct BinaryCacheNarRequest {
+  /// The uploading user's authentication.
+  pub auth:         RecordId<User>,
+  /// The name of the cache being uploaded to.
+  pub cache_name:   EntityName,
+  /// The file name of the NAR, e.g. `<filehash>.nar.xz`.
+  pub file_name:    String,
+  /// The NAR file.
+  pub nar_contents: Belt,
+}
+
+/// The request struct for the
+/// [`binary_cache_put_narinfo`](DomainService::binary_cache_put_narinfo) fn.
+#[derive(Debug)]
+pub struct BinaryCacheNarinfoRequest {
+  /// The uploading user's authentication.
+  pub auth:       RecordId<User>,
+  /// The name of the cache being uploaded to.
+  pub cache_name: EntityName,
+  /// The store path digest the narinfo was uploaded under.
+  pub digest:     Digest,
+  /// The narinfo's contents.
+  pub narinfo:    String,
+}
+
+/// The error enum for binary cache uploads.
+#[derive(thiserror::Error, Debug)]
+pub enum BinaryCacheUploadError {
+  /// The user is unauthorized to upload to this cache.
+  #[error("The user is unauthorized to upload to this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// The cache has no default store to upload into.
+  #[error("The cache \"{0}\" has no default store")]
+  NoDefaultStore(EntityName),
+  /// The NAR file name is malformed.
+  #[error("The NAR file name is malformed: \"{0}\"")]
+  InvalidNarFileName(String),
+  /// The narinfo is malformed.
+  #[error("The narinfo is malformed: {0}")]
+  InvalidNarinfo(String),
+  /// The NAR's compression is not supported.
+  #[error("Unsupported NAR compression: \"{0}\"")]
+  UnsupportedCompression(String),
+  /// The NAR referenced by the narinfo has not been uploaded.
+  #[error("The NAR \"{0}\" has not been uploaded")]
+  NarNotFound(String),
+  /// The upload could not be planned.
+  #[error(transparent)]
+  Planning(#[from] UploadPlanningError),
+  /// The upload could not be executed.
+  #[error(transparent)]
+  Execution(#[from] UploadExecutionError),
+  /// Failed to write to or read from storage.
+  #[error("Failed to access storage: {0}")]
+  StorageFailure(#[from] BlobStorageError),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Holds a NAR uploaded through the binary cache protocol until its narinfo
+  /// is uploaded.
+  #[tracing::instrument(skip(self))]
+  pub async fn binary_cache_put_nar(
+    &self,
+    req: BinaryCacheNarRequest,
+  ) -> Result<(), BinaryCacheUploadError> {
+    parse_nar_file_name(&req.file_name)?;
+    let (cache, store) = self
+      .fetch_binary_cache_target(req.auth, req.cache_name)
+      .await?;
+    let store_client = binary_cache_store_client(&store).await?;
+
+    // recorded first, so that even a partially written NAR gets swept
+    self
+      .mutate
+      .create_orphaned_blob(&OrphanedBlob {
+        id:           RecordId::new(),
+        store:        store.id,
+        storage_path: pending_nar_storage_path(&cache, &req.file_name),
+        orphaned_at:  UtcDateTime::now(),
+      })
+      .await
+      .into_diagnostic()
+      .context("failed to record pending NAR")
+      .map_err(BinaryCacheUploadError::InternalError)?;
+
+    store_client
+      .put_stream(
+        &pending_nar_key(&cache, &req.file_name),
+        Box::pin(req.nar_contents),
+        storage::UploadOptions { overwrite: true },
+      )
+      .await?;
+
+    Ok(())
+  }
+
+  /// Creates an entry from a narinfo uploaded through the binary cache
+  /// protocol, using the NAR it points to.
+  ///
+  /// The narinfo's `NarHash`, `NarSize` and `References` are verified against
+  /// the NAR. The pending NAR is deleted whether or not the upload succeeds.
+  #[tracing::instrument(skip(self))]
+  pub async fn binary_cache_put_narinfo(
+    &self,
+    req: BinaryCacheNarinfoRequest,
+  ) -> Result<UploadResponse, BinaryCacheUploadError> {
+    let (cache, store) = self
+      .fetch_binary_cache_target(req.auth, req.cache_name)
+      .await?;
+
+    let contents = narinfo_from_store_dir(&req.narinfo, &cache.store_dir)
+      .ok_or_else(|| {
+        BinaryCacheUploadError::InvalidNarinfo(format!(
+          "store path is not in the cache's store directory: \"{}\"",
+          cache.store_dir
+        ))
+      })?;
+    let narinfo = NarInfo::parse(&contents)
+      .map_err(|e| BinaryCacheUploadError::InvalidNarinfo(e.to_string()))?;
+    if Digest::from_bytes(*narinfo.store_path.digest()) != req.digest {
+      return Err(BinaryCacheUploadError::InvalidNarinfo(format!(
+        "store path does not match the uploaded digest: \"{}\"",
+        narinfo.store_path
+      )));
+    }
+
+    let file_name = narinfo.url.strip_prefix("nar/").ok_or(
+      BinaryCacheUploadError::InvalidNarinfo(format!(
+        "NAR URL is not under `nar/`: \"{}\"",
+        narinfo.url
+      )),
+    )?;
+    let compression = parse_nar_file_name(file_name)?.compression;
+    let declared_compression = match narinfo.compression {
+      None => compression,
+      Some("none") => None,
+      Some(name) => Some(CompressionAlgorithm::from_nix_name(name).ok_or(
+        BinaryCacheUploadError::UnsupportedCompression(name.to_owned()),
+      )?),
+    };
+    if declared_compression != compression {
+      return Err(BinaryCacheUploadError::InvalidNarinfo(format!(
+        "compression does not match the NAR URL: \"{}\"",
+        narinfo.url
+      )));
+    }
+
+    let store_client = binary_cache_store_client(&store).await?;
+
+    let key = pending_nar_key(&cache, file_name);
+    if store_client.head(&key).await?.is_none() {
+      return Err(BinaryCacheUploadError::NarNotFound(narinfo.url.to_owned()));
+    }
+    let nar_contents = Belt::new(
+      store_client
+        .get_stream(&key)
+        .await?
+        .map_err(BlobStorageError::into_io_error),
+    );
+
+    let upload_req = UploadRequest {
+      nar_contents,
+      compression,
+      auth: req.auth,
+      caches: vec![cache.name.clone()],
+      target_store: store.name.clone(),
+      store_path: narinfo.store_path.to_owned(),
+      deriver_data: NarDeriverData {
+        system:  narinfo.system.map(str::to_owned),
+        deriver: narinfo.deriver.map(|d| d.to_owned()),
+      },
+      ca_hash: narinfo.ca.clone(),
+      nar_hash: Some(narinfo.nar_hash),
+      nar_size: Some(FileSize::new(narinfo.nar_size)),
+      references: Some(
+        narinfo.references.iter().map(|r| r.to_owned()).collect(),
+      ),
+      signatures: narinfo.signatures.iter().map(|s| s.to_owned()).collect(),
+    };
+    let result: Result<_, BinaryCacheUploadError> = async {
+      let plan = self.plan_upload(upload_req).await?;
+      Ok(self.execute_upload(plan).await?)
+    }
+    .await;
+
+    if let Err(e) = store_client.delete(&key).await {
+      tracing::warn!(file_name, "failed to delete pending NAR: {e}");
+    }
+
+    result
+  }
+
+  /// Checks that the user may upload `nix-cache-info` to a cache. Its
+  /// contents are ignored, since the cache's settings are managed by Rambit.
+  #[tracing::instrument(skip(self))]
+  pub async fn binary_cache_put_cache_info(
+    &self,
+    auth: RecordId<User>,
+    cache_name: EntityName,
+  ) -> Result<(), BinaryCacheUploadError> {
+    self.fetch_binary_cache_target(auth, cache_name).await?;
+    Ok(())
+  }
+
+  /// Checks that the user may upload a NAR listing to a cache. Its contents
+  /// are ignored, since listings are generated from the NAR when it's
+  /// uploaded.
+  #[tracing::instrument(skip(self))]
+  pub async fn binary_cache_put_listing(
+    &self,
+    auth: RecordId<User>,
+    cache_name: EntityName,
+  ) -> Result<(), BinaryCacheUploadError> {
+    self.fetch_binary_cache_target(auth, cache_name).await?;
+    Ok(())
+  }
+
+  /// Fetches a cache and its default store, and makes sure the user belongs
+  /// to the cache's org.
+  async fn fetch_binary_cache_target(
+    &self,
+    auth: RecordId<User>,
+    cache_name: EntityName,
+  ) -> Result<(Cache, Store), BinaryCacheUploadError> {
+    let user = self
+      .meta
+      .fetch_user_by_id(auth)
+      .await
+      .into_diagnostic()
+      .context("failed to find user")
+      .map_err(BinaryCacheUploadError::InternalError)?
+      .ok_or(miette!("authenticated user not found"))
+      .map_err(BinaryCacheUploadError::InternalError)?;
+
+    let cache = self
+      .meta
+      .fetch_cache_by_name(cache_name.clone())
+      .await
+      .into_diagnostic()
+      .context("failed to search for cache")
+      .map_err(BinaryCacheUploadError::InternalError)?
+      .ok_or(BinaryCacheUploadError::CacheNotFound(cache_name))?;
+
+    if !user.belongs_to_org(cache.org) {
+      return Err(BinaryCacheUploadError::Unauthorized);
+    }
+
+    let store_id = cache
+      .default_store
+      .ok_or(BinaryCacheUploadError::NoDefaultStore(cache.name.clone()))?;
+    let store = self
+      .meta
+      .fetch_store_by_id(store_id)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch store")
+      .map_err(BinaryCacheUploadError::InternalError)?
+      .ok_or(BinaryCacheUploadError::NoDefaultStore(cache.name.clone()))?;
+
+    Ok((cache, store))
+  }
+}
+
+/// Parses a NAR file name, which makes sure it's a bare
+/// `<filehash>.nar[.<ext>]` that can't escape the pending NAR prefix.
+fn parse_nar_file_name(
+  file_name: &str,
+) -> Result<NarFileName, BinaryCacheUploadError> {
+  NarFileName::parse(file_name).map_err(|e| match e {
+    NarFileNameError::Malformed => {
+      BinaryCacheUploadError::InvalidNarFileName(file_name.to_owned())
+    }
+    NarFileNameError::UnsupportedCompression(extension) => {
+      BinaryCacheUploadError::UnsupportedCompression(extension)
+    }
+  })
+}
+
+/// The path within the store of a NAR waiting for its narinfo.
+fn pending_nar_storage_path(cache: &Cache, file_name: &str) -> PathBuf {
+  PathBuf::from(format!(
+    "binary-cache-uploads/{cache_id}/{file_name}",
+    cache_id = cache.id
+  ))
+}
+
+/// The key of a NAR waiting for its narinfo.
+fn pending_nar_key(cache: &Cache, file_name: &str) -> BlobKey {
+  BlobKey::new(pending_nar_storage_path(cache, file_name).to_string_lossy())
+}
+
+async fn binary_cache_store_client(
+  store: &Store,
+) -> Result<BlobStorage, BinaryCacheUploadError> {
+  crate::storage_glue::storage_creds_to_blob_storage(store.credentials.clone())
+    .await
+    .context("failed to create storage client for store")
+    .map_err(BinaryCacheUploadError::InternalError)
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nar_file_names_are_validated() {
+    assert!(matches!(
+      parse_nar_file_name(
+        "1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3.nar"
+      )
+      .map(|n| n.compression),
+      Ok(None)
+    ));
+    assert!(matches!(
+      parse_nar_file_name(
+        "1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3.nar.xz"
+      )
+      .map(|n| n.compression),
+      Ok(Some(CompressionAlgorithm::Xz))
+    ));
+    assert!(matches!(
+      parse_nar_file_name(
+        "1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3.nar.zst"
+      )
+      .map(|n| n.compression),
+      Ok(Some(CompressionAlgorithm::Zstd))
+    ));
+
+    assert!(matches!(
+      parse_nar_file_name(
+        "1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3.nar.gz"
+      ),
+      Err(BinaryCacheUploadError::UnsupportedCompression(c)) if c == "gz"
+    ));
+    assert!(matches!(
+      parse_nar_file_name("../../nar/foo.nar"),
+      Err(BinaryCacheUploadError::InvalidNarFileName(_))
+    ));
+    assert!(matches!(
+      parse_nar_file_name("foo"),
+      Err(BinaryCacheUploadError::InvalidNarFileName(_))
+    ));
+  }
+}
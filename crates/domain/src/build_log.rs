@@ -0,0 +1,256 @@
+//! Types and impl for build logs.
+//!
+//! Nix uploads a derivation's build log to `log/<drv>` of a binary cache with
+//! `nix store copy-log`, and fetches it from there with `nix log`. Logs are
+//! stored zstd-compressed in the cache's default store, and tracked by a
+//! [`BuildLog`] per cache and derivation.
+
+use belt::Belt;
+use futures::TryStreamExt;
+use miette::{Context, IntoDiagnostic, miette};
+use models::{
+  BuildLog, Cache, CompressionAlgorithm, EntityName, FileSize, RecordId, Store,
+  StorePath, User,
+};
+use storage::{BlobKey, BlobStorage, BlobStorageError};
+use time::UtcDateTime;
+
+use crate::{
+  DomainService,
+  compression::{compress, decompress},
+};
+
+/// The request struct for the
+/// [`upload_build_log`](DomainService::upload_build_log) fn.
+#[derive(Debug)]
+pub struct BuildLogUploadRequest {
+  /// The uploading user's authentication.
+  pub auth:        RecordId<User>,
+  /// The name of the cache being uploaded to.
+  pub cache_name:  EntityName,
+  /// The path of the derivation whose build produced the log.
+  pub drv_path:    StorePath<String>,
+  /// The compression of the uploaded log, if it's compressed.
+  pub compression: Option<CompressionAlgorithm>,
+  /// The log's contents.
+  pub data:        Belt,
+}
+
+/// The request struct for the
+/// [`fetch_build_log`](DomainService::fetch_build_log) fn.
+#[derive(Debug)]
+pub struct BuildLogRequest {
+  /// The user's authentication.
+  pub auth:         Option<RecordId<User>>,
+  /// The name of the cache the log was uploaded to.
+  pub cache_name:   EntityName,
+  /// The path of the derivation whose build produced the log.
+  pub drv_path:     StorePath<String>,
+  /// Whether the client accepts the log zstd-compressed, as it is stored.
+  pub accepts_zstd: bool,
+}
+
+/// The response struct for the
+/// [`fetch_build_log`](DomainService::fetch_build_log) fn.
+#[derive(Debug)]
+pub struct BuildLogResponse {
+  /// The log's contents.
+  pub data:        Belt,
+  /// The compression of the data, if it's still compressed.
+  pub compression: Option<CompressionAlgorithm>,
+}
+
+/// The error enum for build log uploads and fetches.
+#[derive(thiserror::Error, Debug)]
+pub enum BuildLogError {
+  /// The user is unauthorized to access this cache.
+  #[error("The user is unauthorized to access this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// The cache has no default store to upload into.
+  #[error("The cache \"{0}\" has no default store")]
+  NoDefaultStore(EntityName),
+  /// The store path is not a derivation.
+  #[error("The store path is not a derivation: \"{0}\"")]
+  NotADerivation(StorePath<String>),
+  /// No log was uploaded for the derivation.
+  #[error("No build log was found for \"{0}\"")]
+  LogNotFound(StorePath<String>),
+  /// Failed to write to or read from storage.
+  #[error("Failed to access storage: {0}")]
+  StorageFailure(#[from] BlobStorageError),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Stores the build log of a derivation in a cache, replacing any log
+  /// already uploaded for it.
+  #[tracing::instrument(skip(self))]
+  pub async fn upload_build_log(
+    &self,
+    req: BuildLogUploadRequest,
+  ) -> Result<RecordId<BuildLog>, BuildLogError> {
+    if !req.drv_path.name().ends_with(".drv") {
+      return Err(BuildLogError::NotADerivation(req.drv_path));
+    }
+
+    let user = self
+      .meta
+      .fetch_user_by_id(req.auth)
+      .await
+      .into_diagnostic()
+      .context("failed to find user")
+      .map_err(BuildLogError::InternalError)?
+      .ok_or(miette!("authenticated user not found"))
+      .map_err(BuildLogError::InternalError)?;
+    let cache = self.fetch_build_log_cache(req.cache_name).await?;
+    if !user.belongs_to_org(cache.org) {
+      return Err(BuildLogError::Unauthorized);
+    }
+
+    // a replaced log stays in the store it was first uploaded to
+    let existing = self
+      .meta
+      .fetch_build_log_by_cache_id_and_drv_path(cache.id, &req.drv_path)
+      .await
+      .into_diagnostic()
+      .context("failed to search for build log")
+      .map_err(BuildLogError::InternalError)?;
+    let is_replacement = existing.is_some();
+    let mut build_log = match existing {
+      Some(build_log) => build_log,
+      None => BuildLog {
+        id:          RecordId::new(),
+        org:         cache.org,
+        cache:       cache.id,
+        drv_path:    req.drv_path,
+        store:       cache
+          .default_store
+          .ok_or(BuildLogError::NoDefaultStore(cache.name.clone()))?,
+        stored_size: FileSize::new(0),
+        uploaded_by: user.id,
+        uploaded_at: UtcDateTime::now(),
+      },
+    };
+
+    // logs are stored zstd-compressed, however they're uploaded
+    let data = match req.compression {
+      Some(CompressionAlgorithm::Zstd) => req.data,
+      Some(algorithm) => {
+        compress(decompress(req.data, algorithm), CompressionAlgorithm::Zstd)
+      }
+      None => compress(req.data, CompressionAlgorithm::Zstd),
+    };
+
+    let store_client = self.build_log_store_client(build_log.store).await?;
+    let key = BlobKey::new(build_log.storage_path().to_string_lossy());
+    store_client
+      .put_stream(&key, Box::pin(data), storage::UploadOptions {
+        overwrite: true,
+      })
+      .await?;
+    let metadata =
+      store_client
+        .head(&key)
+        .await?
+        .ok_or(BuildLogError::InternalError(miette!(
+          "uploaded build log does not exist"
+        )))?;
+
+    build_log.stored_size = FileSize::new(metadata.size);
+    build_log.uploaded_by = user.id;
+    build_log.uploaded_at = UtcDateTime::now();
+    let result = match is_replacement {
+      true => self.mutate.patch_build_log(&build_log).await,
+      false => self.mutate.create_build_log(&build_log).await.map(|_| ()),
+    };
+    result
+      .into_diagnostic()
+      .context("failed to save build log")
+      .map_err(BuildLogError::InternalError)?;
+
+    Ok(build_log.id)
+  }
+
+  /// Fetches the build log of a derivation from a cache.
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_build_log(
+    &self,
+    req: BuildLogRequest,
+  ) -> Result<BuildLogResponse, BuildLogError> {
+    let cache = self.fetch_build_log_cache(req.cache_name).await?;
+    if !self
+      .may_read_cache(req.auth, &cache)
+      .await
+      .map_err(BuildLogError::InternalError)?
+    {
+      return Err(BuildLogError::Unauthorized);
+    }
+
+    let build_log = self
+      .meta
+      .fetch_build_log_by_cache_id_and_drv_path(cache.id, &req.drv_path)
+      .await
+      .into_diagnostic()
+      .context("failed to search for build log")
+      .map_err(BuildLogError::InternalError)?
+      .ok_or(BuildLogError::LogNotFound(req.drv_path))?;
+
+    let store_client = self.build_log_store_client(build_log.store).await?;
+    let key = BlobKey::new(build_log.storage_path().to_string_lossy());
+    let data = Belt::new(
+      store_client
+        .get_stream(&key)
+        .await?
+        .map_err(BlobStorageError::into_io_error),
+    );
+
+    Ok(match req.accepts_zstd {
+      true => BuildLogResponse {
+        data,
+        compression: Some(CompressionAlgorithm::Zstd),
+      },
+      false => BuildLogResponse {
+        data:        decompress(data, CompressionAlgorithm::Zstd),
+        compression: None,
+      },
+    })
+  }
+
+  async fn fetch_build_log_cache(
+    &self,
+    cache_name: EntityName,
+  ) -> Result<Cache, BuildLogError> {
+    self
+      .meta
+      .fetch_cache_by_name(cache_name.clone())
+      .await
+      .into_diagnostic()
+      .context("failed to search for cache")
+      .map_err(BuildLogError::InternalError)?
+      .ok_or(BuildLogError::CacheNotFound(cache_name))
+  }
+
+  async fn build_log_store_client(
+    &self,
+    store_id: RecordId<Store>,
+  ) -> Result<BlobStorage, BuildLogError> {
+    let store = self
+      .meta
+      .fetch_store_by_id(store_id)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch store")
+      .map_err(BuildLogError::InternalError)?
+      .ok_or(miette!("build log store not found"))
+      .map_err(BuildLogError::InternalError)?;
+    crate::storage_glue::storage_creds_to_blob_storage(store.credentials)
+      .await
+      .context("failed to create storage client for store")
+      .map_err(BuildLogError::InternalError)
+  }
+}
@@ -0,0 +1,331 @@
+//! Cache settings types and impl.
+
+use miette::{Context, IntoDiagnostic, miette};
+use models::{
+  Cache, CacheUpstream, EntityName, NixCacheInfoSettings, RecordId, StoreDir,
+  User,
+};
+
+use crate::{
+  DomainService, signing::parse_public_key, upstream::is_valid_upstream_url,
+};
+
+/// The request struct for the
+/// [`update_cache_trusted_keys`](DomainService::update_cache_trusted_keys) fn.
+#[derive(Debug)]
+pub struct UpdateTrustedKeysRequest {
+  /// The user's authentication.
+  pub auth: RecordId<User>,
+  /// The name of the cache to update.
+  pub cache_name: EntityName,
+  /// Public keys, in `name:base64` form, whose signatures are accepted on
+  /// uploads to the cache.
+  pub trusted_public_keys: Vec<String>,
+  /// Whether uploads to the cache must carry a signature from one of its
+  /// trusted public keys.
+  pub require_trusted_signature: bool,
+}
+
+/// The request struct for the
+/// [`update_cache_default_store`](DomainService::update_cache_default_store)
+/// fn.
+#[derive(Debug)]
+pub struct UpdateDefaultStoreRequest {
+  /// The user's authentication.
+  pub auth:       RecordId<User>,
+  /// The name of the cache to update.
+  pub cache_name: EntityName,
+  /// The name of the store in the cache's org to use as the default, or
+  /// `None` to unset it.
+  pub store_name: Option<EntityName>,
+}
+
+/// The request struct for the
+/// [`update_cache_nix_cache_info`](DomainService::update_cache_nix_cache_info)
+/// fn.
+#[derive(Debug)]
+pub struct UpdateNixCacheInfoRequest {
+  /// The user's authentication.
+  pub auth:       RecordId<User>,
+  /// The name of the cache to update.
+  pub cache_name: EntityName,
+  /// The settings the cache advertises in its `nix-cache-info`.
+  pub settings:   NixCacheInfoSettings,
+}
+
+/// The request struct for the
+/// [`update_cache_store_dir`](DomainService::update_cache_store_dir) fn.
+#[derive(Debug)]
+pub struct UpdateStoreDirRequest {
+  /// The user's authentication.
+  pub auth:       RecordId<User>,
+  /// The name of the cache to update.
+  pub cache_name: EntityName,
+  /// The store directory the cache's paths live in.
+  pub store_dir:  StoreDir,
+}
+
+/// The request struct for the
+/// [`update_cache_upstreams`](DomainService::update_cache_upstreams) fn.
+#[derive(Debug)]
+pub struct UpdateUpstreamsRequest {
+  /// The user's authentication.
+  pub auth:       RecordId<User>,
+  /// The name of the cache to update.
+  pub cache_name: EntityName,
+  /// The cache's upstreams, in order of preference.
+  pub upstreams:  Vec<UpstreamSettings>,
+}
+
+/// The settings of an upstream binary cache.
+#[derive(Debug)]
+pub struct UpstreamSettings {
+  /// The base URL of the binary cache.
+  pub url:           String,
+  /// Public keys, in `name:base64` form, one of which must have signed a path
+  /// for it to be served from the upstream.
+  pub public_keys:   Vec<String>,
+  /// The name of the store in the cache's org that paths fetched from the
+  /// upstream are persisted into, if any.
+  pub persist_store: Option<EntityName>,
+}
+
+/// The error enum for cache settings updates.
+#[derive(thiserror::Error, Debug)]
+pub enum UpdateCacheSettingsError {
+  /// The user is unauthorized to modify this cache.
+  #[error("The user is unauthorized to modify this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// A supplied public key is malformed.
+  #[error("The public key is malformed: \"{0}\"")]
+  InvalidPublicKey(String),
+  /// An upstream URL is not an `http://` or `https://` URL of a public host.
+  #[error("The upstream URL is not an HTTP URL of a public host: \"{0}\"")]
+  InvalidUpstreamUrl(String),
+  /// An upstream has no public keys, so none of its paths could be trusted.
+  #[error("The upstream has no public keys: \"{0}\"")]
+  MissingUpstreamPublicKeys(String),
+  /// The requested store was not found in the cache's org.
+  #[error("The requested store was not found: \"{0}\"")]
+  StoreNotFound(EntityName),
+  /// The cache already has entries, which are all in its current store
+  /// directory.
+  #[error("The cache is not empty: \"{0}\"")]
+  CacheNotEmpty(EntityName),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Sets the public keys whose signatures a cache accepts on uploads, and
+  /// whether it requires one.
+  #[tracing::instrument(skip(self))]
+  pub async fn update_cache_trusted_keys(
+    &self,
+    req: UpdateTrustedKeysRequest,
+  ) -> Result<(), UpdateCacheSettingsError> {
+    if let Some(key) = req
+      .trusted_public_keys
+      .iter()
+      .find(|k| parse_public_key(k).is_err())
+    {
+      return Err(UpdateCacheSettingsError::InvalidPublicKey(key.clone()));
+    }
+
+    let mut cache = self
+      .fetch_cache_for_settings(req.auth, req.cache_name)
+      .await?;
+
+    cache.trusted_public_keys = req.trusted_public_keys;
+    cache.require_trusted_signature = req.require_trusted_signature;
+
+    self.save_cache_settings(&cache).await
+  }
+
+  /// Sets the store that NARs uploaded to a cache through the binary cache
+  /// protocol are stored in.
+  #[tracing::instrument(skip(self))]
+  pub async fn update_cache_default_store(
+    &self,
+    req: UpdateDefaultStoreRequest,
+  ) -> Result<(), UpdateCacheSettingsError> {
+    let mut cache = self
+      .fetch_cache_for_settings(req.auth, req.cache_name)
+      .await?;
+
+    cache.default_store = match req.store_name {
+      Some(store_name) => Some(
+        self
+          .meta
+          .fetch_store_by_org_and_name(cache.org, store_name.clone())
+          .await
+          .into_diagnostic()
+          .context("failed to search for store")
+          .map_err(UpdateCacheSettingsError::InternalError)?
+          .ok_or(UpdateCacheSettingsError::StoreNotFound(store_name))?
+          .id,
+      ),
+      None => None,
+    };
+
+    self.save_cache_settings(&cache).await
+  }
+
+  /// Sets the upstream binary caches that a cache falls through to for paths
+  /// it doesn't have.
+  #[tracing::instrument(skip(self))]
+  pub async fn update_cache_upstreams(
+    &self,
+    req: UpdateUpstreamsRequest,
+  ) -> Result<(), UpdateCacheSettingsError> {
+    for upstream in req.upstreams.iter() {
+      // upstreams are fetched by the server, so they mustn't point at it or
+      // its network
+      if !is_valid_upstream_url(&upstream.url).await {
+        return Err(UpdateCacheSettingsError::InvalidUpstreamUrl(
+          upstream.url.clone(),
+        ));
+      }
+      if upstream.public_keys.is_empty() {
+        return Err(UpdateCacheSettingsError::MissingUpstreamPublicKeys(
+          upstream.url.clone(),
+        ));
+      }
+      if let Some(key) = upstream
+        .public_keys
+        .iter()
+        .find(|k| parse_public_key(k).is_err())
+      {
+        return Err(UpdateCacheSettingsError::InvalidPublicKey(key.clone()));
+      }
+    }
+
+    let mut cache = self
+      .fetch_cache_for_settings(req.auth, req.cache_name)
+      .await?;
+
+    let mut upstreams = Vec::with_capacity(req.upstreams.len());
+    for upstream in req.upstreams {
+      let persist_store = match upstream.persist_store {
+        Some(store_name) => Some(
+          self
+            .meta
+            .fetch_store_by_org_and_name(cache.org, store_name.clone())
+            .await
+            .into_diagnostic()
+            .context("failed to search for store")
+            .map_err(UpdateCacheSettingsError::InternalError)?
+            .ok_or(UpdateCacheSettingsError::StoreNotFound(store_name))?
+            .id,
+        ),
+        None => None,
+      };
+      upstreams.push(CacheUpstream {
+        url: upstream.url,
+        public_keys: upstream.public_keys,
+        persist_store,
+      });
+    }
+    cache.upstreams = upstreams;
+
+    self.save_cache_settings(&cache).await
+  }
+
+  /// Sets the priority and mass-query settings a cache advertises to Nix.
+  #[tracing::instrument(skip(self))]
+  pub async fn update_cache_nix_cache_info(
+    &self,
+    req: UpdateNixCacheInfoRequest,
+  ) -> Result<(), UpdateCacheSettingsError> {
+    let mut cache = self
+      .fetch_cache_for_settings(req.auth, req.cache_name)
+      .await?;
+
+    cache.nix_cache_info = req.settings;
+
+    self.save_cache_settings(&cache).await
+  }
+
+  /// Sets the store directory of a cache. This is only allowed while the
+  /// cache is empty, since its entries' paths are in the old directory.
+  #[tracing::instrument(skip(self))]
+  pub async fn update_cache_store_dir(
+    &self,
+    req: UpdateStoreDirRequest,
+  ) -> Result<(), UpdateCacheSettingsError> {
+    let mut cache = self
+      .fetch_cache_for_settings(req.auth, req.cache_name)
+      .await?;
+
+    if cache.store_dir == req.store_dir {
+      return Ok(());
+    }
+
+    let entry_count = self
+      .meta
+      .count_entries_in_cache(cache.id)
+      .await
+      .into_diagnostic()
+      .context("failed to count entries in cache")
+      .map_err(UpdateCacheSettingsError::InternalError)?;
+    if entry_count > 0 {
+      return Err(UpdateCacheSettingsError::CacheNotEmpty(cache.name));
+    }
+
+    cache.store_dir = req.store_dir;
+
+    self.save_cache_settings(&cache).await
+  }
+
+  /// Fetches a cache whose settings the user wants to change, and makes sure
+  /// they belong to its org.
+  async fn fetch_cache_for_settings(
+    &self,
+    auth: RecordId<User>,
+    cache_name: EntityName,
+  ) -> Result<Cache, UpdateCacheSettingsError> {
+    let cache = self
+      .meta
+      .fetch_cache_by_name(cache_name.clone())
+      .await
+      .into_diagnostic()
+      .context("failed to search for cache")
+      .map_err(UpdateCacheSettingsError::InternalError)?
+      .ok_or(UpdateCacheSettingsError::CacheNotFound(cache_name))?;
+
+    let user = self
+      .meta
+      .fetch_user_by_id(auth)
+      .await
+      .into_diagnostic()
+      .context("failed to find user")
+      .map_err(UpdateCacheSettingsError::InternalError)?
+      .ok_or(miette!("authenticated user not found"))
+      .map_err(UpdateCacheSettingsError::InternalError)?;
+
+    if !user.belongs_to_org(cache.org) {
+      return Err(UpdateCacheSettingsError::Unauthorized);
+    }
+
+    Ok(cache)
+  }
+
+  async fn save_cache_settings(
+    &self,
+    cache: &Cache,
+  ) -> Result<(), UpdateCacheSettingsError> {
+    self
+      .mutate
+      .patch_cache(cache)
+      .await
+      .into_diagnostic()
+      .context("failed to update cache")
+      .map_err(UpdateCacheSettingsError::InternalError)?;
+    self.lookups.invalidate_cache(&cache.name);
+    Ok(())
+  }
+}
@@ -0,0 +1,98 @@
+use async_compression::tokio::bufread::{
+  BzDecoder, BzEncoder, XzDecoder, XzEncoder, ZstdDecoder, ZstdEncoder,
+};
+use belt::Belt;
+use models::CompressionAlgorithm;
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// Compresses a [`Belt`] with the given algorithm as it is read.
+pub(crate) fn compress(data: Belt, algorithm: CompressionAlgorithm) -> Belt {
+  let reader = StreamReader::new(data);
+  match algorithm {
+    CompressionAlgorithm::Zstd => {
+      Belt::new(ReaderStream::new(ZstdEncoder::new(reader)))
+    }
+    CompressionAlgorithm::Xz => {
+      Belt::new(ReaderStream::new(XzEncoder::new(reader)))
+    }
+    CompressionAlgorithm::Bzip2 => {
+      Belt::new(ReaderStream::new(BzEncoder::new(reader)))
+    }
+  }
+}
+
+/// Decompresses a [`Belt`] with the given algorithm as it is read.
+pub(crate) fn decompress(data: Belt, algorithm: CompressionAlgorithm) -> Belt {
+  let reader = StreamReader::new(data);
+  match algorithm {
+    CompressionAlgorithm::Zstd => {
+      Belt::new(ReaderStream::new(ZstdDecoder::new(reader)))
+    }
+    CompressionAlgorithm::Xz => {
+      Belt::new(ReaderStream::new(XzDecoder::new(reader)))
+    }
+    CompressionAlgorithm::Bzip2 => {
+      Belt::new(ReaderStream::new(BzDecoder::new(reader)))
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use bytes::Bytes;
+
+  use super::*;
+
+  #[tokio::test]
+  async fn all_algorithms_round_trip() {
+    let input = Bytes::from_static(include_bytes!(
+      "../../owl/test/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0"
+    ));
+
+    for algorithm in [
+      CompressionAlgorithm::Zstd,
+      CompressionAlgorithm::Xz,
+      CompressionAlgorithm::Bzip2,
+    ] {
+      let compressed = compress(Belt::new_from_bytes(input.clone()), algorithm)
+        .collect_bytes()
+        .await
+        .unwrap();
+      assert!(compressed.len() < input.len());
+
+      let decompressed =
+        decompress(Belt::new_from_bytes(compressed), algorithm)
+          .collect_bytes()
+          .await
+          .unwrap();
+      assert_eq!(decompressed, input, "{algorithm:?} did not round trip");
+    }
+  }
+
+  #[tokio::test]
+  async fn mismatched_encodings_are_rejected() {
+    let input = Bytes::from_static(include_bytes!(
+      "../../owl/test/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0"
+    ));
+
+    // a NAR uploaded with the wrong `Content-Encoding` fails to decompress,
+    // rather than being passed through as garbage
+    for (actual, declared) in [
+      (CompressionAlgorithm::Zstd, CompressionAlgorithm::Xz),
+      (CompressionAlgorithm::Xz, CompressionAlgorithm::Bzip2),
+      (CompressionAlgorithm::Bzip2, CompressionAlgorithm::Zstd),
+    ] {
+      let compressed = compress(Belt::new_from_bytes(input.clone()), actual)
+        .collect_bytes()
+        .await
+        .unwrap();
+      let result = decompress(Belt::new_from_bytes(compressed), declared)
+        .collect_bytes()
+        .await;
+      assert!(
+        result.is_err(),
+        "{actual:?} was decompressed as {declared:?}"
+      );
+    }
+  }
+}
@@ -1,20 +1,27 @@
 use db::DatabaseError;
 use miette::{Context, IntoDiagnostic, Report, miette};
 use models::{
-  Cache, EmailAddress, EntityName, HumanName, Org, OrgIdent, RecordId, Store,
-  User, UserAuthCredentials, UserSubmittedAuthCredentials,
+  Cache, ENTRY_INDEX_VERSION, EmailAddress, EntityName, HumanName, Org,
+  OrgIdent, RecordId, Store, User, UserAuthCredentials,
+  UserSubmittedAuthCredentials,
 };
 
 use crate::DomainService;
 
 impl DomainService {
-  /// Creates a [`Cache`].
+  /// Creates a [`Cache`], generating a signing key for it if it doesn't
+  /// already have one. A new cache has no entries, so it starts out indexed.
   #[tracing::instrument(skip(self))]
   pub async fn create_cache(
     &self,
     cache: &Cache,
   ) -> Result<RecordId<Cache>, DatabaseError> {
-    self.mutate.create_cache(cache).await
+    let mut cache = cache.clone();
+    self.secrets.ensure_signing_key(&mut cache);
+    cache.entry_index_version = ENTRY_INDEX_VERSION;
+    let id = self.mutate.create_cache(&cache).await?;
+    self.lookups.invalidate_cache(&cache.name);
+    Ok(id)
   }
 
   /// Creates a [`Store`].
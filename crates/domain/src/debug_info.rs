@@ -0,0 +1,110 @@
+//! Types and impl for debug info lookups.
+//!
+//! Nix's `index-debug-info` writes a `debuginfo/<build-id>` pointer for each
+//! separate debug info file in a binary cache, which `dwarffs` and
+//! `nixseparatedebuginfod` fetch to find the NAR holding a binary's debug
+//! symbols. Here the pointers are answered from the build IDs that were found
+//! when NARs were uploaded.
+
+use miette::{Context, IntoDiagnostic};
+use models::{EntityName, RecordId, User};
+use serde::Serialize;
+
+use crate::{DomainService, nar_file_name::NarFileName};
+
+/// The request struct for the [`debug_info`](DomainService::debug_info) fn.
+#[derive(Debug)]
+pub struct DebugInfoRequest {
+  /// The user's authentication.
+  pub auth:       Option<RecordId<User>>,
+  /// The name of the cache to look in.
+  pub cache_name: EntityName,
+  /// The ELF build ID of the debug info file, in hex.
+  pub build_id:   String,
+}
+
+/// The response struct for the [`debug_info`](DomainService::debug_info) fn,
+/// in the JSON format Nix uses.
+#[derive(Debug, Serialize)]
+pub struct DebugInfoResponse {
+  /// The URL of the NAR holding the file, relative to the pointer.
+  pub archive: String,
+  /// The path of the file within the NAR.
+  pub member:  String,
+}
+
+/// The error enum for debug info lookups.
+#[derive(thiserror::Error, Debug)]
+pub enum DebugInfoError {
+  /// The user is unauthorized to access this cache.
+  #[error("The user is unauthorized to access this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// The build ID is not hex.
+  #[error("The build ID is malformed: \"{0}\"")]
+  InvalidBuildId(String),
+  /// No debug info file with the build ID was uploaded.
+  #[error("No debug info was found for build ID \"{0}\"")]
+  DebugInfoNotFound(String),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Finds the NAR holding the debug info file with an ELF build ID in a
+  /// cache.
+  #[tracing::instrument(skip(self))]
+  pub async fn debug_info(
+    &self,
+    req: DebugInfoRequest,
+  ) -> Result<DebugInfoResponse, DebugInfoError> {
+    let is_valid = !req.build_id.is_empty()
+      && req.build_id.len() % 2 == 0
+      && req.build_id.bytes().all(|b| b.is_ascii_hexdigit());
+    if !is_valid {
+      return Err(DebugInfoError::InvalidBuildId(req.build_id));
+    }
+    // build IDs are indexed in lowercase
+    let build_id = req.build_id.to_ascii_lowercase();
+
+    let cache = self
+      .lookup_cache_by_name(&req.cache_name)
+      .await
+      .map_err(DebugInfoError::InternalError)?
+      .ok_or(DebugInfoError::CacheNotFound(req.cache_name))?;
+
+    if !self
+      .may_read_cache(req.auth, &cache)
+      .await
+      .map_err(DebugInfoError::InternalError)?
+    {
+      return Err(DebugInfoError::Unauthorized);
+    }
+
+    let entry = self
+      .meta
+      .fetch_entry_by_cache_id_and_build_id(cache.id, &build_id)
+      .await
+      .into_diagnostic()
+      .context("failed to search for entry by build ID")
+      .map_err(DebugInfoError::InternalError)?
+      .ok_or_else(|| DebugInfoError::DebugInfoNotFound(build_id.clone()))?;
+
+    let member = entry
+      .intrensic_data
+      .debug_info
+      .iter()
+      .find(|d| d.build_id == build_id)
+      .map(|d| d.member.clone())
+      .ok_or_else(|| DebugInfoError::DebugInfoNotFound(build_id.clone()))?;
+
+    Ok(DebugInfoResponse {
+      // note: this is a relative path from the debuginfo endpoint
+      archive: format!("../nar/{}", NarFileName::for_entry(&entry)),
+      member,
+    })
+  }
+}
@@ -1,15 +1,172 @@
 use db::DatabaseError;
-use models::{Entry, RecordId};
+use miette::{Context, IntoDiagnostic, miette};
+use models::{Entry, OrphanedBlob, RecordId};
+use storage::BlobKey;
+use time::{Duration, UtcDateTime};
 
-use crate::DomainService;
+use crate::{DomainService, nar_listing::listing_storage_path};
+
+/// How long a blob stays orphaned before it's deleted. This is longer than any
+/// upload which found the blob before it was orphaned could still be running.
+const ORPHANED_BLOB_GRACE_PERIOD: Duration = Duration::hours(24);
+/// How often orphaned blobs are swept.
+const ORPHANED_BLOB_SWEEP_INTERVAL: std::time::Duration =
+  std::time::Duration::from_secs(60 * 60);
 
 impl DomainService {
   /// Deletes an [`Entry`].
+  ///
+  /// If no other entry references the entry's NAR blob, the blob is marked as
+  /// orphaned, and is deleted from its store once it's been orphaned for a
+  /// grace period. Failing to mark the blob is only logged, since the entry is
+  /// already gone.
   #[tracing::instrument(skip(self))]
   pub async fn delete_entry(
     &self,
     id: RecordId<Entry>,
   ) -> Result<Entry, DatabaseError> {
-    self.mutate.delete_entry(id).await
+    let entry = self.mutate.delete_entry(id).await?;
+    self.lookups.invalidate_entry(&entry);
+
+    if let Err(e) = self.release_blob(&entry).await {
+      tracing::error!(
+        storage_path = ?entry.storage_data.storage_path,
+        "failed to release NAR blob of deleted entry: {e:?}"
+      );
+    }
+
+    Ok(entry)
+  }
+
+  /// Spawns a task which periodically deletes the blobs that have been
+  /// orphaned for longer than the grace period.
+  pub fn spawn_orphaned_blob_sweeper(&self) {
+    let domain = self.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(ORPHANED_BLOB_SWEEP_INTERVAL);
+      loop {
+        ticker.tick().await;
+        domain.sweep_orphaned_blobs().await;
+      }
+    });
+  }
+
+  /// Marks the NAR blob of a deleted entry as orphaned if nothing references
+  /// it anymore.
+  async fn release_blob(&self, entry: &Entry) -> miette::Result<()> {
+    let storage_data = &entry.storage_data;
+
+    let reference_count = self
+      .meta
+      .count_entries_by_store_id_and_storage_path(
+        storage_data.store,
+        &storage_data.storage_path,
+      )
+      .await
+      .into_diagnostic()
+      .context("failed to count references to blob")?;
+    if reference_count > 0 {
+      tracing::debug!(reference_count, "blob is still referenced");
+      return Ok(());
+    }
+
+    self
+      .mutate
+      .create_orphaned_blob(&OrphanedBlob {
+        id:           RecordId::new(),
+        store:        storage_data.store,
+        storage_path: storage_data.storage_path.clone(),
+        orphaned_at:  UtcDateTime::now(),
+      })
+      .await
+      .into_diagnostic()
+      .context("failed to mark blob as orphaned")?;
+
+    Ok(())
+  }
+
+  /// Deletes the blobs which have been orphaned for longer than the grace
+  /// period, unless an entry has come to reference them since.
+  async fn sweep_orphaned_blobs(&self) {
+    let orphaned_blobs = match self.meta.fetch_all_orphaned_blobs().await {
+      Ok(orphaned_blobs) => orphaned_blobs,
+      Err(e) => {
+        tracing::warn!("failed to fetch orphaned blobs to sweep: {e}");
+        return;
+      }
+    };
+
+    let cutoff = UtcDateTime::now() - ORPHANED_BLOB_GRACE_PERIOD;
+    for orphaned_blob in orphaned_blobs
+      .into_iter()
+      .filter(|b| b.orphaned_at < cutoff)
+    {
+      if let Err(e) = self.sweep_orphaned_blob(&orphaned_blob).await {
+        tracing::warn!(
+          storage_path = ?orphaned_blob.storage_path,
+          "failed to sweep orphaned blob: {e:?}"
+        );
+      }
+    }
+  }
+
+  /// Deletes an orphaned blob if nothing references it. The record is deleted
+  /// first, so that a blob is never deleted by two sweeps.
+  async fn sweep_orphaned_blob(
+    &self,
+    orphaned_blob: &OrphanedBlob,
+  ) -> miette::Result<()> {
+    let reference_count = self
+      .meta
+      .count_entries_by_store_id_and_storage_path(
+        orphaned_blob.store,
+        &orphaned_blob.storage_path,
+      )
+      .await
+      .into_diagnostic()
+      .context("failed to count references to blob")?;
+
+    self
+      .mutate
+      .delete_orphaned_blob(orphaned_blob.id)
+      .await
+      .into_diagnostic()
+      .context("failed to delete orphaned blob record")?;
+    if reference_count > 0 {
+      tracing::debug!(reference_count, "orphaned blob was shared again");
+      return Ok(());
+    }
+
+    let store = self
+      .meta
+      .fetch_store_by_id(orphaned_blob.store)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch store")?
+      .ok_or(miette!("store of orphaned blob not found"))?;
+    let store_client =
+      crate::storage_glue::storage_creds_to_blob_storage(store.credentials)
+        .await
+        .context("failed to create storage client for store")?;
+
+    let blob_key = BlobKey::new(orphaned_blob.storage_path.to_string_lossy());
+    // entries uploaded before listings were generated have none
+    let listing_key =
+      BlobKey::new(listing_storage_path(&orphaned_blob.storage_path));
+    for key in [blob_key, listing_key] {
+      if store_client
+        .head(&key)
+        .await
+        .context("failed to check for blob")?
+        .is_some()
+      {
+        store_client
+          .delete(&key)
+          .await
+          .context("failed to delete blob")?;
+      }
+    }
+
+    Ok(())
   }
 }
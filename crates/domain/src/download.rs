@@ -2,12 +2,14 @@
 
 mod execute;
 mod plan;
+mod range;
 #[cfg(test)]
 mod tests;
 
 use models::{EntityName, RecordId, StorePath, User};
 
-pub use self::{execute::*, plan::*};
+pub(crate) use self::range::slice_stream;
+pub use self::{execute::*, plan::*, range::ByteRange};
 
 /// The request struct for the
 /// [`plan_download`](crate::DomainService::plan_download) fn.
@@ -20,3 +22,16 @@ pub struct DownloadRequest {
   /// The entry's store path.
   pub store_path: StorePath<String>,
 }
+
+/// The request struct for the
+/// [`plan_nar_file_download`](crate::DomainService::plan_nar_file_download)
+/// fn.
+#[derive(Debug)]
+pub struct NarFileDownloadRequest {
+  /// The downloading user's authentication.
+  pub auth:       Option<RecordId<User>>,
+  /// The name of the cache to look for the file in.
+  pub cache_name: EntityName,
+  /// The NAR's file name, `<filehash>.nar[.<ext>]`.
+  pub file_name:  String,
+}
@@ -2,19 +2,20 @@ use belt::Belt;
 use futures::TryStreamExt;
 use metrics_types::egress::UnstampedEgressUsageEvent;
 use miette::Context;
-use models::{CompressionStatus, FileSize};
+use models::FileSize;
 use storage::{BlobKey, BlobStorageError};
 
-use super::plan::DownloadPlan;
+use super::{plan::DownloadPlan, range::ByteRange};
 use crate::DomainService;
 
 /// The response struct for the
 /// [`execute_download`](DomainService::execute_download) fn.
 #[derive(Debug)]
 pub struct DownloadResponse {
-  /// The data being downloaded.
+  /// The data being downloaded, compressed if the entry is stored
+  /// compressed. Only the requested range is included, if one was given.
   pub data:         Belt,
-  /// The file size of the data being downloaded.
+  /// The file size of the whole entry, as it is stored.
   pub file_size:    FileSize,
   /// The egress event to be sent.
   pub egress_event: UnstampedEgressUsageEvent,
@@ -33,42 +34,48 @@ pub enum DownloadExecutionError {
 }
 
 impl DomainService {
-  /// Downloads an entry's payload from storage.
+  /// Downloads an entry's payload from storage, or just a range of it. The
+  /// range must be within the [stored size](DownloadPlan::stored_size).
   #[tracing::instrument(skip(self, plan), fields(plan.entry.id, plan.entry.store_path))]
   pub async fn execute_download(
     &self,
     plan: DownloadPlan,
+    range: Option<ByteRange>,
   ) -> Result<DownloadResponse, DownloadExecutionError> {
     // build a client to fetch from the store
     let store_client = crate::storage_glue::storage_creds_to_blob_storage(
-      plan.store.credentials,
+      plan.store.credentials.clone(),
     )
     .await
     .context("failed to create storage client for store")
     .map_err(DownloadExecutionError::InternalError)?;
 
-    // fetch the data from the store
-    let path =
-      BlobKey::new(plan.entry.storage_data.storage_path.to_string_lossy());
-    let data = store_client
-      .get_stream(&path)
+    // fetch the data from the store. the belt counts the bytes served for
+    // egress, so only the range is fed through it.
+    let path = plan.entry.storage_data.storage_path.to_string_lossy();
+    let data = match range {
+      Some(range) => crate::storage_glue::get_stream_range(
+        &plan.store.credentials,
+        &store_client,
+        &path,
+        range.start..=range.end,
+      )
       .await
-      .map_err(DownloadExecutionError::StorageFailure)?;
-    let data = Belt::new(data.map_err(BlobStorageError::into_io_error));
-
-    // decompress if needed
-    let comp_status = plan.entry.storage_data.compression_status;
-    let (file_size, data) = match comp_status {
-      // CompressionStatus::Compressed {
-      //   uncompressed_size,
-      //   algorithm,
-      //   ..
-      // } => {
-      //   todo!()
-      // }
-      CompressionStatus::Uncompressed { size } => (size, data),
+      .context("failed to read range from store")
+      .map_err(DownloadExecutionError::InternalError)?,
+      None => Belt::new(
+        store_client
+          .get_stream(&BlobKey::new(path))
+          .await
+          .map_err(DownloadExecutionError::StorageFailure)?
+          .map_err(BlobStorageError::into_io_error),
+      ),
     };
 
+    // the data is passed through as it is stored. if it's compressed, the
+    // narinfo advertises the compression and the client decompresses it.
+    let file_size = plan.entry.storage_data.compression_status.stored_size();
+
     Ok(DownloadResponse {
       data,
       file_size,
@@ -1,8 +1,15 @@
 use metrics_types::egress::UnstampedEgressUsageEvent;
 use miette::{Context, IntoDiagnostic, miette};
-use models::{Digest, EntityName, Entry, Store, StorePath, Visibility};
+use models::{
+  Cache, Digest, EntityName, Entry, FileSize, RecordId, Store, StorePath, User,
+  Visibility,
+};
 
-use crate::{DomainService, download::DownloadRequest};
+use crate::{
+  DomainService,
+  download::{DownloadRequest, NarFileDownloadRequest},
+  nar_file_name::NarFileName,
+};
 
 /// A download plan produced by [`plan_download`](DomainService::plan_download)
 /// fn.
@@ -16,6 +23,16 @@ pub struct DownloadPlan {
   pub(crate) egress_event: UnstampedEgressUsageEvent,
 }
 
+impl DownloadPlan {
+  /// The size of the entry's NAR file as it is stored.
+  pub fn stored_size(&self) -> FileSize {
+    self.entry.storage_data.compression_status.stored_size()
+  }
+
+  /// The SHA-256 digest of the entry's NAR file as it is stored.
+  pub fn file_hash(&self) -> [u8; 32] { self.entry.file_hash() }
+}
+
 /// The error enum for the [`plan_download`](DomainService::plan_download) fn.
 #[derive(thiserror::Error, Debug)]
 pub enum DownloadPlanningError {
@@ -36,6 +53,17 @@ pub enum DownloadPlanningError {
     /// The entry store path.
     store_path: StorePath<String>,
   },
+  /// The requested NAR file was not found.
+  #[error(
+    "The requested NAR file was not found: \"{file_name}\" in cache \
+     \"{cache}\""
+  )]
+  FileNotFound {
+    /// The cache.
+    cache:     EntityName,
+    /// The NAR file name.
+    file_name: String,
+  },
   /// Some other internal error.
   #[error("Unexpected error: {0}")]
   InternalError(miette::Report),
@@ -48,18 +76,80 @@ impl DomainService {
     &self,
     req: DownloadRequest,
   ) -> Result<DownloadPlan, DownloadPlanningError> {
+    let cache = self
+      .fetch_cache_for_download(req.auth, req.cache_name)
+      .await?;
+
+    // fetch the entry
+    let entry = self
+      .meta
+      .fetch_entry_by_cache_id_and_entry_digest(
+        cache.id,
+        Digest::from_bytes(*req.store_path.digest()),
+      )
+      .await
+      .into_diagnostic()
+      .context("failed to search for entry")
+      .map_err(DownloadPlanningError::InternalError)?
+      .ok_or(DownloadPlanningError::EntryNotFound {
+        cache:      cache.name.clone(),
+        store_path: req.store_path.clone(),
+      })?;
+
+    self.plan_entry_download(cache, entry).await
+  }
+
+  /// Plans a download of a NAR file by its `<filehash>.nar[.<ext>]` name, as
+  /// linked from narinfos.
+  #[tracing::instrument(skip(self))]
+  pub async fn plan_nar_file_download(
+    &self,
+    req: NarFileDownloadRequest,
+  ) -> Result<DownloadPlan, DownloadPlanningError> {
+    let cache = self
+      .fetch_cache_for_download(req.auth, req.cache_name)
+      .await?;
+
+    let not_found = || DownloadPlanningError::FileNotFound {
+      cache:     cache.name.clone(),
+      file_name: req.file_name.clone(),
+    };
+    let file_name =
+      NarFileName::parse(&req.file_name).map_err(|_| not_found())?;
+
+    // fetch the entry, making sure the extension matches how it's stored
+    let entry = self
+      .meta
+      .fetch_entry_by_cache_id_and_file_hash(cache.id, &file_name.file_hash)
+      .await
+      .into_diagnostic()
+      .context("failed to search for entry")
+      .map_err(DownloadPlanningError::InternalError)?
+      .filter(|e| NarFileName::for_entry(e) == file_name)
+      .ok_or_else(not_found)?;
+
+    self.plan_entry_download(cache, entry).await
+  }
+
+  /// Fetches the cache being downloaded from, and makes sure the user may read
+  /// from it.
+  pub(crate) async fn fetch_cache_for_download(
+    &self,
+    auth: Option<RecordId<User>>,
+    cache_name: EntityName,
+  ) -> Result<Cache, DownloadPlanningError> {
     // fetch the cache and make sure it exists
     let cache = self
       .meta
-      .fetch_cache_by_name(req.cache_name.clone())
+      .fetch_cache_by_name(cache_name.clone())
       .await
       .into_diagnostic()
       .context("failed to search for cache")
       .map_err(DownloadPlanningError::InternalError)?
-      .ok_or(DownloadPlanningError::CacheNotFound(req.cache_name.clone()))?;
+      .ok_or(DownloadPlanningError::CacheNotFound(cache_name))?;
 
     // fetch the user if an ID was given
-    let user = match req.auth {
+    let user = match auth {
       Some(auth) => Some(
         self
           .meta
@@ -87,22 +177,15 @@ impl DomainService {
       (Visibility::Public, _) => (),
     }
 
-    // fetch the entry
-    let entry = self
-      .meta
-      .fetch_entry_by_cache_id_and_entry_digest(
-        cache.id,
-        Digest::from_bytes(*req.store_path.digest()),
-      )
-      .await
-      .into_diagnostic()
-      .context("failed to search for entry")
-      .map_err(DownloadPlanningError::InternalError)?
-      .ok_or(DownloadPlanningError::EntryNotFound {
-        cache:      cache.name.clone(),
-        store_path: req.store_path.clone(),
-      })?;
+    Ok(cache)
+  }
 
+  /// Plans the download of an entry found in a cache.
+  async fn plan_entry_download(
+    &self,
+    cache: Cache,
+    entry: Entry,
+  ) -> Result<DownloadPlan, DownloadPlanningError> {
     // fetch the store the entry resides in
     let store = self
       .meta
@@ -116,7 +199,7 @@ impl DomainService {
 
     let egress_event = UnstampedEgressUsageEvent {
       entry_id:   entry.id,
-      entry_path: entry.store_path.to_absolute_path(),
+      entry_path: entry.store_dir.absolute_path(&entry.store_path),
       cache_id:   cache.id,
       store_id:   store.id,
       org_id:     entry.org,
@@ -0,0 +1,98 @@
+use bytes::Bytes;
+use futures::{Stream, TryStreamExt, stream};
+
+/// An inclusive range of bytes of a download.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ByteRange {
+  /// The offset of the first byte in the range.
+  pub start: u64,
+  /// The offset of the last byte in the range.
+  pub end:   u64,
+}
+
+impl ByteRange {
+  /// The number of bytes in the range.
+  pub fn size(&self) -> u64 { self.end - self.start + 1 }
+}
+
+/// Cuts a stream of chunks down to the bytes in the range. The stream is
+/// dropped as soon as the range has been read, so nothing after it is
+/// fetched. This is the fallback for stores which can't read ranges
+/// themselves, since everything before the range is still read.
+pub(crate) fn slice_stream<E>(
+  stream: impl Stream<Item = Result<Bytes, E>>,
+  range: ByteRange,
+) -> impl Stream<Item = Result<Bytes, E>> {
+  stream::try_unfold(
+    (Box::pin(stream), 0),
+    move |(mut stream, mut offset)| async move {
+      while offset <= range.end {
+        let Some(chunk) = stream.try_next().await? else {
+          break;
+        };
+        let chunk_start = offset;
+        let chunk_len = chunk.len() as u64;
+        offset += chunk_len;
+
+        let from = range.start.saturating_sub(chunk_start).min(chunk_len);
+        let to = (range.end + 1).saturating_sub(chunk_start).min(chunk_len);
+        if from < to {
+          let chunk = chunk.slice(from as usize..to as usize);
+          return Ok(Some((chunk, (stream, offset))));
+        }
+      }
+      Ok(None)
+    },
+  )
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  /// Slices the data, fed in chunks of the given size.
+  async fn slice_in_chunks(
+    data: &'static [u8],
+    chunk_len: usize,
+    range: ByteRange,
+  ) -> Vec<u8> {
+    let chunks = data
+      .chunks(chunk_len)
+      .map(|c| Ok::<_, ()>(Bytes::from_static(c)));
+    slice_stream(stream::iter(chunks), range)
+      .map_ok(|chunk| chunk.to_vec())
+      .try_concat()
+      .await
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn slices_ranges_across_chunks() {
+    let data = b"0123456789abcdef";
+    for chunk_len in 1..=data.len() {
+      for (start, end) in [(0, 15), (0, 0), (3, 9), (15, 15), (8, 15)] {
+        let range = ByteRange { start, end };
+        assert_eq!(
+          slice_in_chunks(data, chunk_len, range).await,
+          data[start as usize..=end as usize],
+          "failed for {range:?} with chunks of {chunk_len} bytes"
+        );
+      }
+    }
+  }
+
+  #[tokio::test]
+  async fn stops_reading_after_the_range() {
+    let chunks = vec![
+      Ok(Bytes::from_static(b"0123")),
+      Ok(Bytes::from_static(b"4567")),
+      Err("read past the range"),
+    ];
+    let sliced =
+      slice_stream(stream::iter(chunks), ByteRange { start: 2, end: 5 })
+        .map_ok(|chunk| chunk.to_vec())
+        .try_concat()
+        .await;
+    assert_eq!(sliced, Ok(b"2345".to_vec()));
+  }
+}
@@ -0,0 +1,211 @@
+//! Types and impl for linking existing entries into caches.
+
+use miette::{Context, IntoDiagnostic, miette};
+use models::{Cache, Digest, EntityName, Entry, RecordId, StoreDir, User};
+
+use crate::{DomainService, signing::verify_with_keys};
+
+/// The request struct for the
+/// [`link_entry_to_cache`](DomainService::link_entry_to_cache) and
+/// [`unlink_entry_from_cache`](DomainService::unlink_entry_from_cache) fns.
+#[derive(Debug)]
+pub struct EntryCacheRequest {
+  /// The user's authentication.
+  pub auth:       RecordId<User>,
+  /// The entry to link or unlink.
+  pub entry_id:   RecordId<Entry>,
+  /// The name of the cache to link the entry into or unlink it from.
+  pub cache_name: EntityName,
+}
+
+/// The error enum for the
+/// [`link_entry_to_cache`](DomainService::link_entry_to_cache) and
+/// [`unlink_entry_from_cache`](DomainService::unlink_entry_from_cache) fns.
+#[derive(thiserror::Error, Debug)]
+pub enum EntryCacheError {
+  /// The user is unauthorized to modify this entry or cache.
+  #[error("The user is unauthorized to modify this entry or cache")]
+  Unauthorized,
+  /// The requested entry was not found.
+  #[error("The requested entry was not found: {0}")]
+  EntryNotFound(RecordId<Entry>),
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// An entry with that path already exists in the cache.
+  #[error(
+    "An entry with that path already exists in the cache: entry {entry} in \
+     cache {cache}"
+  )]
+  DuplicateEntryInCache {
+    /// The entry that already exists in the cache.
+    entry: RecordId<Entry>,
+    /// The cache that contains the duplicate.
+    cache: RecordId<Cache>,
+  },
+  /// The entry is not in the cache.
+  #[error("The entry {entry} is not in the cache {cache}")]
+  EntryNotInCache {
+    /// The entry.
+    entry: RecordId<Entry>,
+    /// The cache.
+    cache: RecordId<Cache>,
+  },
+  /// The entry's store directory differs from the cache's.
+  #[error(
+    "The entry is in the store directory \"{entry}\", but the cache uses \
+     \"{cache}\""
+  )]
+  StoreDirMismatch {
+    /// The entry's store directory.
+    entry: StoreDir,
+    /// The cache's store directory.
+    cache: StoreDir,
+  },
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Makes an existing [`Entry`] accessible from another [`Cache`] in the
+  /// same org, without re-uploading it.
+  #[tracing::instrument(skip(self))]
+  pub async fn link_entry_to_cache(
+    &self,
+    req: EntryCacheRequest,
+  ) -> Result<(), EntryCacheError> {
+    let (mut entry, cache) = self.fetch_entry_and_cache(&req).await?;
+
+    if entry.caches.contains(&cache.id) {
+      return Ok(());
+    }
+
+    if entry.store_dir != cache.store_dir {
+      return Err(EntryCacheError::StoreDirMismatch {
+        entry: entry.store_dir,
+        cache: cache.store_dir,
+      });
+    }
+
+    // make sure no other entry exists for this path in the cache
+    let duplicate_entry_by_cache = self
+      .meta
+      .fetch_entry_by_cache_id_and_entry_digest(
+        cache.id,
+        Digest::from_bytes(*entry.store_path.digest()),
+      )
+      .await
+      .into_diagnostic()
+      .context("failed to search for conflicting entries by cache and path")
+      .map_err(EntryCacheError::InternalError)?;
+    if let Some(duplicate) = duplicate_entry_by_cache {
+      return Err(EntryCacheError::DuplicateEntryInCache {
+        entry: duplicate.id,
+        cache: cache.id,
+      });
+    }
+
+    // sign the entry with the cache's key, so that clients which only trust
+    // this cache accept it
+    let fingerprint = entry
+      .intrensic_data
+      .fingerprint(&entry.store_dir, &entry.store_path);
+    let signatures = self
+      .sign_for_caches([&cache], &fingerprint)
+      .await
+      .context("failed to sign entry")
+      .map_err(EntryCacheError::InternalError)?;
+    entry.authenticity_data.signatures.extend(signatures);
+
+    entry.caches.push(cache.id);
+    self
+      .mutate
+      .patch_entry(&entry)
+      .await
+      .into_diagnostic()
+      .context("failed to update entry")
+      .map_err(EntryCacheError::InternalError)?;
+    self.lookups.invalidate_entry(&entry);
+    Ok(())
+  }
+
+  /// Removes an [`Entry`] from a [`Cache`] without deleting the entry.
+  #[tracing::instrument(skip(self))]
+  pub async fn unlink_entry_from_cache(
+    &self,
+    req: EntryCacheRequest,
+  ) -> Result<(), EntryCacheError> {
+    let (mut entry, cache) = self.fetch_entry_and_cache(&req).await?;
+
+    if !entry.caches.contains(&cache.id) {
+      return Err(EntryCacheError::EntryNotInCache {
+        entry: entry.id,
+        cache: cache.id,
+      });
+    }
+
+    // drop the cache's signatures, which vouched for the entry being in it
+    if let Some(key) = &cache.signing_key {
+      let fingerprint = entry
+        .intrensic_data
+        .fingerprint(&entry.store_dir, &entry.store_path);
+      entry
+        .authenticity_data
+        .signatures
+        .retain(|s| !verify_with_keys([&key.public_key], s, &fingerprint));
+    }
+
+    entry.caches.retain(|c| *c != cache.id);
+    self
+      .mutate
+      .patch_entry(&entry)
+      .await
+      .into_diagnostic()
+      .context("failed to update entry")
+      .map_err(EntryCacheError::InternalError)?;
+    self.lookups.invalidate_entry(&entry);
+    Ok(())
+  }
+
+  /// Fetches the entry and cache of a request, and makes sure the user, entry,
+  /// and cache all belong to the same org.
+  async fn fetch_entry_and_cache(
+    &self,
+    req: &EntryCacheRequest,
+  ) -> Result<(Entry, Cache), EntryCacheError> {
+    let user = self
+      .meta
+      .fetch_user_by_id(req.auth)
+      .await
+      .into_diagnostic()
+      .context("failed to find user")
+      .map_err(EntryCacheError::InternalError)?
+      .ok_or(miette!("authenticated user not found"))
+      .map_err(EntryCacheError::InternalError)?;
+
+    let entry = self
+      .meta
+      .fetch_entry_by_id(req.entry_id)
+      .await
+      .into_diagnostic()
+      .context("failed to find entry")
+      .map_err(EntryCacheError::InternalError)?
+      .ok_or(EntryCacheError::EntryNotFound(req.entry_id))?;
+
+    let cache = self
+      .meta
+      .fetch_cache_by_name(req.cache_name.clone())
+      .await
+      .into_diagnostic()
+      .context("failed to search for cache")
+      .map_err(EntryCacheError::InternalError)?
+      .ok_or(EntryCacheError::CacheNotFound(req.cache_name.clone()))?;
+
+    if !user.belongs_to_org(entry.org) || cache.org != entry.org {
+      return Err(EntryCacheError::Unauthorized);
+    }
+
+    Ok((entry, cache))
+  }
+}
@@ -2,13 +2,33 @@
 
 pub mod authenticate;
 mod billing;
+pub mod binary_cache;
+pub mod build_log;
+pub mod cache_settings;
+mod compression;
 mod create;
+pub mod debug_info;
 mod delete_entry;
 pub mod download;
+pub mod entry_caches;
+mod lookup_cache;
+pub mod missing_paths;
 pub mod mutate_user;
+mod nar_file_name;
+pub mod nar_listing;
 pub mod narinfo;
+pub mod nix_cache_info;
+pub mod realisation;
+mod reindex;
+pub mod signing;
 mod storage_glue;
+mod store_dir;
+pub mod store_entries;
 pub mod upload;
+pub mod upload_session;
+pub mod upstream;
+
+use std::sync::Arc;
 
 pub use belt;
 pub use billing_domain;
@@ -20,13 +40,25 @@ use meta_domain::MetaService;
 pub use models;
 use mutate_domain::MutationService;
 pub use mutate_domain::{self, UpdateActiveOrgError};
+pub use owl;
+
+pub use self::create::CreateUserError;
+use self::{
+  lookup_cache::LookupCache, reindex::Reindexing, signing::SecretCipher,
+  upstream::UpstreamClient,
+};
 
 /// The domain service type.
 #[derive(Debug, Clone)]
 pub struct DomainService {
-  meta:    MetaService,
-  mutate:  MutationService,
-  billing: BillingService,
+  meta:                   MetaService,
+  mutate:                 MutationService,
+  billing:                BillingService,
+  secrets:                SecretCipher,
+  http:                   UpstreamClient,
+  lookups:                LookupCache,
+  reindexing:             Reindexing,
+  signing_key_generation: Arc<tokio::sync::Mutex<()>>,
 }
 
 impl DomainService {
@@ -35,11 +67,17 @@ impl DomainService {
     meta: MetaService,
     mutate: MutationService,
     billing: BillingService,
+    secrets: SecretCipher,
   ) -> Self {
     Self {
       meta,
       mutate,
       billing,
+      secrets,
+      http: UpstreamClient::new(),
+      lookups: LookupCache::default(),
+      reindexing: Reindexing::default(),
+      signing_key_generation: Arc::default(),
     }
   }
 
@@ -0,0 +1,259 @@
+//! An in-process cache of the lookups behind narinfo requests.
+//!
+//! Nix asks every substituter for every path, so most narinfo requests are
+//! for paths a cache doesn't have, and the same caches and digests are looked
+//! up over and over. Both hits and misses are held for a short while, and
+//! anything that adds, removes or changes an entry or cache invalidates them.
+
+use std::{
+  collections::HashMap,
+  sync::{Arc, Mutex},
+  time::{Duration, Instant},
+};
+
+use miette::{Context, IntoDiagnostic};
+use models::{Cache, Digest, EntityName, Entry};
+
+use crate::DomainService;
+
+/// How long a lookup is held for. Invalidation keeps lookups fresh within a
+/// process, so this only bounds staleness from changes made elsewhere.
+const LOOKUP_TTL: Duration = Duration::from_secs(30);
+/// The most caches or digests held before the cache is cleared out.
+const MAX_LOOKUPS: usize = 100_000;
+
+/// A cache of cache-by-name and entry-by-digest lookups.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct LookupCache {
+  inner: Arc<Mutex<Lookups>>,
+}
+
+#[derive(Debug, Default)]
+struct Lookups {
+  caches:     HashMap<EntityName, Lookup<Cache>>,
+  /// Entries by digest and then by the name of the cache they're in.
+  entries:    HashMap<Digest, HashMap<EntityName, Lookup<Entry>>>,
+  /// Bumped on every invalidation, so that lookups which started before it
+  /// aren't stored.
+  generation: u64,
+}
+
+#[derive(Debug)]
+struct Lookup<T> {
+  value:      Option<T>,
+  fetched_at: Instant,
+}
+
+impl<T: Clone> Lookup<T> {
+  fn new(value: Option<T>) -> Self {
+    Self {
+      value,
+      fetched_at: Instant::now(),
+    }
+  }
+
+  fn fresh_value(&self) -> Option<Option<T>> {
+    (self.fetched_at.elapsed() < LOOKUP_TTL).then(|| self.value.clone())
+  }
+}
+
+impl LookupCache {
+  /// The current generation, to be taken before starting a lookup and passed
+  /// back when storing it.
+  pub(crate) fn generation(&self) -> u64 { self.lock().generation }
+
+  /// Returns a held lookup of a cache by name, or `None` if there isn't one.
+  pub(crate) fn cache(&self, name: &EntityName) -> Option<Option<Cache>> {
+    self.lock().caches.get(name)?.fresh_value()
+  }
+
+  /// Holds a lookup of a cache by name.
+  pub(crate) fn put_cache(
+    &self,
+    generation: u64,
+    name: EntityName,
+    cache: Option<Cache>,
+  ) {
+    let mut lookups = self.lock();
+    if lookups.generation != generation {
+      return;
+    }
+    if lookups.caches.len() >= MAX_LOOKUPS {
+      lookups.caches.clear();
+    }
+    lookups.caches.insert(name, Lookup::new(cache));
+  }
+
+  /// Returns a held lookup of an entry by the name of its cache and its
+  /// digest, or `None` if there isn't one.
+  pub(crate) fn entry(
+    &self,
+    cache_name: &EntityName,
+    digest: &Digest,
+  ) -> Option<Option<Entry>> {
+    self
+      .lock()
+      .entries
+      .get(digest)?
+      .get(cache_name)?
+      .fresh_value()
+  }
+
+  /// Holds a lookup of an entry by the name of its cache and its digest.
+  pub(crate) fn put_entry(
+    &self,
+    generation: u64,
+    cache_name: EntityName,
+    digest: Digest,
+    entry: Option<Entry>,
+  ) {
+    let mut lookups = self.lock();
+    if lookups.generation != generation {
+      return;
+    }
+    if lookups.entries.len() >= MAX_LOOKUPS {
+      lookups.entries.clear();
+    }
+    lookups
+      .entries
+      .entry(digest)
+      .or_default()
+      .insert(cache_name, Lookup::new(entry));
+  }
+
+  /// Drops the lookups of a cache, and of the entries in it.
+  pub(crate) fn invalidate_cache(&self, name: &EntityName) {
+    let mut lookups = self.lock();
+    lookups.generation += 1;
+    lookups.caches.remove(name);
+    for entries in lookups.entries.values_mut() {
+      entries.remove(name);
+    }
+  }
+
+  /// Drops the lookups of entries with a digest, in every cache.
+  pub(crate) fn invalidate_digest(&self, digest: &Digest) {
+    let mut lookups = self.lock();
+    lookups.generation += 1;
+    lookups.entries.remove(digest);
+  }
+
+  /// Drops the lookups of an entry's digest, in every cache.
+  pub(crate) fn invalidate_entry(&self, entry: &Entry) {
+    self.invalidate_digest(&Digest::from_bytes(*entry.store_path.digest()));
+  }
+
+  fn lock(&self) -> std::sync::MutexGuard<'_, Lookups> {
+    // the lookups are always left consistent, so a panic elsewhere while the
+    // lock was held doesn't matter
+    self.inner.lock().unwrap_or_else(|e| e.into_inner())
+  }
+}
+
+impl DomainService {
+  /// Fetches a cache by name, through the lookup cache. A cache whose
+  /// entries aren't all in the current entry indexes starts being
+  /// re-indexed.
+  pub(crate) async fn lookup_cache_by_name(
+    &self,
+    name: &EntityName,
+  ) -> miette::Result<Option<Cache>> {
+    if let Some(cache) = self.lookups.cache(name) {
+      if let Some(cache) = &cache {
+        self.ensure_entries_indexed(cache);
+      }
+      return Ok(cache);
+    }
+
+    let generation = self.lookups.generation();
+    let cache = self
+      .meta
+      .fetch_cache_by_name(name.clone())
+      .await
+      .into_diagnostic()
+      .context("failed to search for cache")?;
+    if let Some(cache) = &cache {
+      self.ensure_entries_indexed(cache);
+    }
+    self
+      .lookups
+      .put_cache(generation, name.clone(), cache.clone());
+    Ok(cache)
+  }
+
+  /// Fetches the entry with a digest in a cache, through the lookup cache.
+  pub(crate) async fn lookup_entry_by_digest(
+    &self,
+    cache: &Cache,
+    digest: Digest,
+  ) -> miette::Result<Option<Entry>> {
+    if let Some(entry) = self.lookups.entry(&cache.name, &digest) {
+      return Ok(entry);
+    }
+
+    let generation = self.lookups.generation();
+    let entry = self
+      .meta
+      .fetch_entry_by_cache_id_and_entry_digest(cache.id, digest)
+      .await
+      .context("failed to search for entry")?;
+    self.lookups.put_entry(
+      generation,
+      cache.name.clone(),
+      digest,
+      entry.clone(),
+    );
+    Ok(entry)
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn digest(byte: u8) -> Digest { Digest::from_bytes([byte; 20]) }
+
+  #[test]
+  fn holds_hits_and_misses() {
+    let lookups = LookupCache::default();
+    let cache_name = EntityName::new("aaron");
+
+    assert_eq!(lookups.entry(&cache_name, &digest(1)), None);
+    let generation = lookups.generation();
+    lookups.put_entry(generation, cache_name.clone(), digest(1), None);
+    assert_eq!(lookups.entry(&cache_name, &digest(1)), Some(None));
+    assert_eq!(lookups.entry(&EntityName::new("other"), &digest(1)), None);
+
+    let generation = lookups.generation();
+    lookups.put_cache(generation, cache_name.clone(), None);
+    assert_eq!(lookups.cache(&cache_name), Some(None));
+  }
+
+  #[test]
+  fn invalidation_drops_lookups() {
+    let lookups = LookupCache::default();
+    let cache_name = EntityName::new("aaron");
+
+    let generation = lookups.generation();
+    lookups.put_entry(generation, cache_name.clone(), digest(1), None);
+    lookups.put_entry(generation, cache_name.clone(), digest(2), None);
+    lookups.invalidate_digest(&digest(1));
+    assert_eq!(lookups.entry(&cache_name, &digest(1)), None);
+    assert_eq!(lookups.entry(&cache_name, &digest(2)), Some(None));
+
+    lookups.invalidate_cache(&cache_name);
+    assert_eq!(lookups.entry(&cache_name, &digest(2)), None);
+  }
+
+  #[test]
+  fn lookups_racing_invalidation_are_not_held() {
+    let lookups = LookupCache::default();
+    let cache_name = EntityName::new("aaron");
+
+    // a miss is looked up, and the path is uploaded before it's stored
+    let generation = lookups.generation();
+    lookups.invalidate_digest(&digest(1));
+    lookups.put_entry(generation, cache_name.clone(), digest(1), None);
+    assert_eq!(lookups.entry(&cache_name, &digest(1)), None);
+  }
+}
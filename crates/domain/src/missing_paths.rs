@@ -0,0 +1,87 @@
+//! Missing paths query types and impl.
+
+use miette::{Context, IntoDiagnostic};
+use models::{Digest, EntityName, RecordId, User};
+
+use crate::DomainService;
+
+/// The maximum number of digests accepted by a single
+/// [`missing_paths`](DomainService::missing_paths) query.
+pub const MAX_MISSING_PATHS_QUERY_SIZE: usize = 10_000;
+
+/// The request struct for the [`missing_paths`](DomainService::missing_paths)
+/// fn.
+#[derive(Debug)]
+pub struct MissingPathsRequest {
+  /// The user's authentication.
+  pub auth:       Option<RecordId<User>>,
+  /// The name of the cache to query.
+  pub cache_name: EntityName,
+  /// The store path digests to check.
+  pub digests:    Vec<Digest>,
+}
+
+/// The error enum for the [`missing_paths`](DomainService::missing_paths) fn.
+#[derive(thiserror::Error, Debug)]
+pub enum MissingPathsError {
+  /// The user is unauthorized to read from this cache.
+  #[error("The user is unauthorized to read from this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// Too many digests were queried at once.
+  #[error(
+    "Too many paths were queried at once: {0} (maximum is \
+     {MAX_MISSING_PATHS_QUERY_SIZE})"
+  )]
+  TooManyPaths(usize),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Returns the given digests which have no entry in a cache, in the order
+  /// they were given.
+  #[tracing::instrument(skip(self, req), fields(cache_name = %req.cache_name))]
+  pub async fn missing_paths(
+    &self,
+    req: MissingPathsRequest,
+  ) -> Result<Vec<Digest>, MissingPathsError> {
+    if req.digests.len() > MAX_MISSING_PATHS_QUERY_SIZE {
+      return Err(MissingPathsError::TooManyPaths(req.digests.len()));
+    }
+
+    // looking the cache up starts re-indexing its entries if they need it
+    let cache = self
+      .lookup_cache_by_name(&req.cache_name)
+      .await
+      .map_err(MissingPathsError::InternalError)?
+      .ok_or(MissingPathsError::CacheNotFound(req.cache_name))?;
+
+    if !self
+      .may_read_cache(req.auth, &cache)
+      .await
+      .map_err(MissingPathsError::InternalError)?
+    {
+      return Err(MissingPathsError::Unauthorized);
+    }
+
+    let present = self
+      .meta
+      .fetch_digests_present_in_cache(&cache, &req.digests)
+      .await
+      .into_diagnostic()
+      .context("failed to search for entries by cache and digest")
+      .map_err(MissingPathsError::InternalError)?;
+
+    Ok(
+      req
+        .digests
+        .into_iter()
+        .filter(|d| !present.contains(d))
+        .collect(),
+    )
+  }
+}
@@ -0,0 +1,112 @@
+use std::fmt;
+
+use models::{CompressionAlgorithm, Entry, nix_compat::nixbase32};
+
+/// The file name of a NAR in a binary cache, `<filehash>.nar[.<ext>]`, where
+/// the file hash is of the NAR file as it is stored.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct NarFileName {
+  pub(crate) file_hash:   [u8; 32],
+  pub(crate) compression: Option<CompressionAlgorithm>,
+}
+
+/// The ways a NAR file name can fail to parse.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) enum NarFileNameError {
+  /// The file name is not of the form `<filehash>.nar[.<ext>]`.
+  Malformed,
+  /// The extension is not that of a supported compression.
+  UnsupportedCompression(String),
+}
+
+impl NarFileName {
+  /// The file name of an entry's NAR.
+  pub(crate) fn for_entry(entry: &Entry) -> Self {
+    Self {
+      file_hash:   entry.file_hash(),
+      compression: entry.storage_data.compression_status.algorithm(),
+    }
+  }
+
+  /// Parses a file name. Only bare file names parse, so the result can't
+  /// contain path separators.
+  pub(crate) fn parse(file_name: &str) -> Result<Self, NarFileNameError> {
+    let (file_hash, compression) = match file_name.split_once(".nar") {
+      Some((file_hash, "")) => (file_hash, None),
+      Some((file_hash, extension)) => {
+        let extension = extension
+          .strip_prefix('.')
+          .ok_or(NarFileNameError::Malformed)?;
+        let compression = CompressionAlgorithm::from_nix_extension(extension)
+          .ok_or(NarFileNameError::UnsupportedCompression(
+          extension.to_owned(),
+        ))?;
+        (file_hash, Some(compression))
+      }
+      None => return Err(NarFileNameError::Malformed),
+    };
+
+    let file_hash = nixbase32::decode_fixed::<32>(file_hash)
+      .map_err(|_| NarFileNameError::Malformed)?;
+
+    Ok(Self {
+      file_hash,
+      compression,
+    })
+  }
+}
+
+impl fmt::Display for NarFileName {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "{}.nar", nixbase32::encode(&self.file_hash))?;
+    if let Some(compression) = self.compression {
+      write!(f, ".{}", compression.nix_extension())?;
+    }
+    Ok(())
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const FILE_HASH: &str =
+    "1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3";
+
+  #[test]
+  fn nar_file_names_round_trip() {
+    for file_name in [
+      format!("{FILE_HASH}.nar"),
+      format!("{FILE_HASH}.nar.xz"),
+      format!("{FILE_HASH}.nar.zst"),
+      format!("{FILE_HASH}.nar.bz2"),
+    ] {
+      let parsed = NarFileName::parse(&file_name).unwrap();
+      assert_eq!(parsed.to_string(), file_name);
+    }
+
+    assert_eq!(
+      NarFileName::parse(&format!("{FILE_HASH}.nar.zst"))
+        .unwrap()
+        .compression,
+      Some(CompressionAlgorithm::Zstd)
+    );
+  }
+
+  #[test]
+  fn malformed_nar_file_names_are_rejected() {
+    assert_eq!(
+      NarFileName::parse(&format!("{FILE_HASH}.nar.gz")),
+      Err(NarFileNameError::UnsupportedCompression("gz".to_owned()))
+    );
+    assert_eq!(
+      NarFileName::parse("../../nar/foo.nar"),
+      Err(NarFileNameError::Malformed)
+    );
+    assert_eq!(NarFileName::parse("foo"), Err(NarFileNameError::Malformed));
+    assert_eq!(
+      NarFileName::parse(&format!("{FILE_HASH}.narxz")),
+      Err(NarFileNameError::Malformed)
+    );
+  }
+}
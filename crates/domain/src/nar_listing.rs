@@ -0,0 +1,111 @@
+//! NAR listing types and impl.
+
+use std::path::Path;
+
+use bytes::Bytes;
+use futures::TryStreamExt;
+use miette::{Context, IntoDiagnostic, miette};
+use models::{Digest, EntityName, RecordId, User};
+use storage::BlobKey;
+
+use crate::DomainService;
+
+/// The request struct for the [`nar_listing`](DomainService::nar_listing) fn.
+#[derive(Debug)]
+pub struct NarListingRequest {
+  /// The user's authentication.
+  pub auth:       Option<RecordId<User>>,
+  /// The name of the cache the entry is stored in.
+  pub cache_name: EntityName,
+  /// The store path digest of the entry.
+  pub digest:     Digest,
+}
+
+/// The error enum for the [`nar_listing`](DomainService::nar_listing) fn.
+#[derive(thiserror::Error, Debug)]
+pub enum NarListingError {
+  /// The user is unauthorized to read from this cache.
+  #[error("The user is unauthorized to read from this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// The requested entry was not found.
+  #[error("The requested entry was not found: \"{0}\"")]
+  EntryNotFound(Digest),
+  /// The entry was uploaded before listings were generated, so it has none.
+  #[error("The requested entry has no listing: \"{0}\"")]
+  ListingNotFound(Digest),
+  /// Failed to read from storage.
+  #[error("Failed to read from storage: {0}")]
+  StorageFailure(#[from] storage::BlobStorageError),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+/// The storage path of the listing of the NAR stored at a path.
+pub(crate) fn listing_storage_path(storage_path: &Path) -> String {
+  format!("{}.ls", storage_path.to_string_lossy())
+}
+
+impl DomainService {
+  /// Fetches the JSON listing of an entry's NAR, as stored when it was
+  /// uploaded.
+  #[tracing::instrument(skip(self))]
+  pub async fn nar_listing(
+    &self,
+    req: NarListingRequest,
+  ) -> Result<Bytes, NarListingError> {
+    let cache = self
+      .lookup_cache_by_name(&req.cache_name)
+      .await
+      .map_err(NarListingError::InternalError)?
+      .ok_or(NarListingError::CacheNotFound(req.cache_name))?;
+
+    if !self
+      .may_read_cache(req.auth, &cache)
+      .await
+      .map_err(NarListingError::InternalError)?
+    {
+      return Err(NarListingError::Unauthorized);
+    }
+
+    let entry = self
+      .lookup_entry_by_digest(&cache, req.digest)
+      .await
+      .map_err(NarListingError::InternalError)?
+      .ok_or(NarListingError::EntryNotFound(req.digest))?;
+
+    let store = self
+      .meta
+      .fetch_store_by_id(entry.storage_data.store)
+      .await
+      .into_diagnostic()
+      .context("failed to find store")
+      .map_err(NarListingError::InternalError)?
+      .ok_or(miette!("store not found"))
+      .map_err(NarListingError::InternalError)?;
+    let store_client =
+      crate::storage_glue::storage_creds_to_blob_storage(store.credentials)
+        .await
+        .context("failed to create storage client for store")
+        .map_err(NarListingError::InternalError)?;
+
+    let key =
+      BlobKey::new(listing_storage_path(&entry.storage_data.storage_path));
+    if store_client.head(&key).await?.is_none() {
+      return Err(NarListingError::ListingNotFound(req.digest));
+    }
+    let listing = store_client
+      .get_stream(&key)
+      .await?
+      .try_fold(Vec::new(), |mut listing, chunk| async move {
+        listing.extend_from_slice(&chunk);
+        Ok(listing)
+      })
+      .await?;
+
+    Ok(listing.into())
+  }
+}
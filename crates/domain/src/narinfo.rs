@@ -1,12 +1,18 @@
 //! Narinfo types and impl.
 
+use std::fmt;
+
 use miette::{Context, IntoDiagnostic, miette};
 use models::{
-  Digest, EntityName, Entry, RecordId, Signature, StorePath, User, Visibility,
+  Cache, Digest, EntityName, Entry, RecordId, Signature, StoreDir, StorePath,
+  User, Visibility,
   nix_compat::narinfo::{Flags, NarInfo},
 };
 
-use crate::DomainService;
+use crate::{
+  DomainService, nar_file_name::NarFileName, store_dir::render_narinfo,
+  upstream::UpstreamNarinfo,
+};
 
 /// The request struct for the [`narinfo`](DomainService::narinfo) fn.
 #[derive(Debug)]
@@ -19,57 +25,97 @@ pub struct NarinfoRequest {
   pub digest:     Digest,
 }
 
-/// The response struct for the [`narinfo`](DomainService::narinfo) fn.
+/// The response struct for the [`narinfo`](DomainService::narinfo) fn. Its
+/// [`Display`] impl renders the narinfo in the cache's store directory.
+///
+/// [`Display`]: fmt::Display
 #[derive(Debug)]
 pub struct NarinfoResponse {
-  entry:            Entry,
+  source:           NarinfoSource,
   nar_relative_url: String,
+  store_dir:        StoreDir,
+}
+
+/// Where a narinfo comes from.
+#[derive(Debug)]
+enum NarinfoSource {
+  /// An entry in the cache.
+  Entry(Entry),
+  /// One of the cache's upstreams, with the cache's own signatures.
+  Upstream {
+    narinfo:    UpstreamNarinfo,
+    signatures: Vec<Signature<String>>,
+  },
 }
 
 impl NarinfoResponse {
   /// Returns the requested [`NarInfo`].
   #[tracing::instrument(skip(self))]
   pub fn narinfo(&self) -> NarInfo<'_> {
-    let mut references = self
-      .entry
-      .intrensic_data
-      .references
-      .iter()
-      .map(StorePath::as_ref)
-      .collect::<Vec<_>>();
-    references.sort_unstable();
-    let mut signatures = self
-      .entry
-      .authenticity_data
-      .signatures
-      .iter()
-      .map(Signature::as_ref)
-      .collect::<Vec<_>>();
-    signatures.sort_unstable_by_key(|s| s.to_string());
-
-    NarInfo {
-      flags: Flags::empty(),
-      store_path: self.entry.store_path.as_ref(),
-      nar_hash: self.entry.intrensic_data.nar_hash,
-      nar_size: self.entry.intrensic_data.nar_size.inner(),
-      references,
-      signatures,
-      ca: self.entry.intrensic_data.ca_hash.clone(),
-      system: self.entry.deriver_data.system.as_deref(),
-      deriver: self
-        .entry
-        .deriver_data
-        .deriver
-        .as_ref()
-        .map(StorePath::as_ref),
-      url: &self.nar_relative_url,
-      compression: None,
-      file_hash: Some(self.entry.intrensic_data.nar_hash),
-      file_size: Some(self.entry.intrensic_data.nar_size.inner()),
+    match &self.source {
+      NarinfoSource::Entry(entry) => {
+        entry_narinfo(entry, &self.nar_relative_url)
+      }
+      NarinfoSource::Upstream {
+        narinfo,
+        signatures,
+      } => {
+        let mut narinfo = narinfo.narinfo();
+        narinfo.url = &self.nar_relative_url;
+        narinfo
+          .signatures
+          .extend(signatures.iter().map(Signature::as_ref));
+        narinfo
+      }
     }
   }
 }
 
+impl fmt::Display for NarinfoResponse {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&render_narinfo(&self.narinfo(), &self.store_dir))
+  }
+}
+
+fn entry_narinfo<'a>(
+  entry: &'a Entry,
+  nar_relative_url: &'a str,
+) -> NarInfo<'a> {
+  let mut references = entry
+    .intrensic_data
+    .references
+    .iter()
+    .map(StorePath::as_ref)
+    .collect::<Vec<_>>();
+  references.sort_unstable();
+  let authenticity_data = &entry.authenticity_data;
+  let mut signatures = authenticity_data
+    .signatures
+    .iter()
+    .chain(&authenticity_data.unverified_signatures)
+    .map(Signature::as_ref)
+    .collect::<Vec<_>>();
+  signatures.sort_unstable_by_key(|s| s.to_string());
+
+  let compression_status = &entry.storage_data.compression_status;
+
+  NarInfo {
+    flags: Flags::empty(),
+    store_path: entry.store_path.as_ref(),
+    nar_hash: entry.intrensic_data.nar_hash,
+    nar_size: entry.intrensic_data.nar_size.inner(),
+    references,
+    signatures,
+    ca: entry.intrensic_data.ca_hash.clone(),
+    system: entry.deriver_data.system.as_deref(),
+    deriver: entry.deriver_data.deriver.as_ref().map(StorePath::as_ref),
+    url: nar_relative_url,
+    compression: compression_status.algorithm().map(|a| a.nix_name()),
+    file_hash: Some(entry.file_hash()),
+    file_size: Some(compression_status.stored_size().inner()),
+  }
+}
+
 /// The error enum for the [`narinfo`](DomainService::narinfo) fn.
 #[derive(thiserror::Error, Debug)]
 pub enum NarinfoError {
@@ -93,56 +139,99 @@ impl DomainService {
     &self,
     req: NarinfoRequest,
   ) -> Result<NarinfoResponse, NarinfoError> {
+    // most requests are misses, so the lookups go through the lookup cache
     let cache = self
-      .meta
-      .fetch_cache_by_name(req.cache_name.clone())
+      .lookup_cache_by_name(&req.cache_name)
       .await
-      .into_diagnostic()
-      .context("failed to search for cache")
       .map_err(NarinfoError::InternalError)?
       .ok_or(NarinfoError::CacheNotFound(req.cache_name))?;
 
-    let user = match req.auth {
-      Some(user_id) => Some(
-        self
-          .meta
-          .fetch_user_by_id(user_id)
-          .await
-          .into_diagnostic()
-          .context("failed to find user")
-          .map_err(NarinfoError::InternalError)?
-          .ok_or(miette!("authenticated user not found"))
-          .map_err(NarinfoError::InternalError)?,
-      ),
-      None => None,
+    if !self
+      .may_read_cache(req.auth, &cache)
+      .await
+      .map_err(NarinfoError::InternalError)?
+    {
+      return Err(NarinfoError::Unauthorized);
+    }
+
+    let entry = self
+      .lookup_entry_by_digest(&cache, req.digest)
+      .await
+      .map_err(NarinfoError::InternalError)?;
+
+    let Some(entry) = entry else {
+      return self.upstream_narinfo(&cache, req.digest).await;
     };
 
-    // reject user if cache is private and org IDs don't match
-    match (cache.visibility, user) {
-      // let them through if it's private but org IDs match
-      (Visibility::Private, Some(user)) if user.belongs_to_org(cache.org) => (),
-      // otherwise don't let them through if it's private
-      (Visibility::Private, _) => {
-        return Err(NarinfoError::Unauthorized);
-      }
-      // if it's not private don't worry about it
-      _ => (),
+    // note: this is a relative path from the narinfo endpoint. NAR files are
+    // found through the cache-and-file-hash index, so until the cache's
+    // entries are all in it the NAR is linked by store path instead.
+    let url = match cache.entries_indexed() {
+      true => format!("nar/{}", NarFileName::for_entry(&entry)),
+      false => format!("download/{}", entry.store_path),
+    };
+
+    Ok(NarinfoResponse {
+      source:           NarinfoSource::Entry(entry),
+      nar_relative_url: url,
+      store_dir:        cache.store_dir,
+    })
+  }
+
+  /// Whether the user may read from the cache. Private caches may only be
+  /// read by members of their org, and the user is only fetched for those.
+  pub(crate) async fn may_read_cache(
+    &self,
+    auth: Option<RecordId<User>>,
+    cache: &Cache,
+  ) -> miette::Result<bool> {
+    if cache.visibility != Visibility::Private {
+      return Ok(true);
     }
+    let Some(user_id) = auth else {
+      return Ok(false);
+    };
 
-    let entry = self
+    let user = self
       .meta
-      .fetch_entry_by_cache_id_and_entry_digest(cache.id, req.digest)
+      .fetch_user_by_id(user_id)
       .await
-      .context("failed to search for entry")
-      .map_err(NarinfoError::InternalError)?
-      .ok_or(NarinfoError::EntryNotFound(req.digest))?;
+      .into_diagnostic()
+      .context("failed to find user")?
+      .ok_or(miette!("authenticated user not found"))?;
+    Ok(user.belongs_to_org(cache.org))
+  }
+
+  /// Falls through to the cache's upstreams for a path it doesn't have. The
+  /// narinfo is signed with the cache's key, since its upstream signature was
+  /// verified.
+  async fn upstream_narinfo(
+    &self,
+    cache: &Cache,
+    digest: Digest,
+  ) -> Result<NarinfoResponse, NarinfoError> {
+    let narinfo = self
+      .find_upstream_narinfo(cache, digest)
+      .await
+      .ok_or(NarinfoError::EntryNotFound(digest))?;
+
+    let signatures = self
+      .sign_for_caches([cache], &narinfo.fingerprint())
+      .await
+      .context("failed to sign upstream narinfo")
+      .map_err(NarinfoError::InternalError)?;
 
-    // note: this is a relative path from the narinfo endpoint
-    let url = format!("download/{store_path}", store_path = entry.store_path);
+    // the NAR is streamed from the upstream through the cache, which finds
+    // the narinfo again by its digest
+    let url = format!("upstream/{digest}");
 
     Ok(NarinfoResponse {
-      entry,
+      source:           NarinfoSource::Upstream {
+        narinfo,
+        signatures,
+      },
       nar_relative_url: url,
+      store_dir:        cache.store_dir.clone(),
     })
   }
 }
@@ -0,0 +1,125 @@
+//! `nix-cache-info` types and impl.
+
+use std::fmt;
+
+use miette::{Context, IntoDiagnostic};
+use models::{EntityName, NixCacheInfoSettings, RecordId, StoreDir, User};
+
+use crate::DomainService;
+
+/// The request struct for the [`nix_cache_info`](DomainService::nix_cache_info)
+/// fn.
+#[derive(Debug)]
+pub struct NixCacheInfoRequest {
+  /// The user's authentication.
+  pub auth:       Option<RecordId<User>>,
+  /// The name of the cache.
+  pub cache_name: EntityName,
+}
+
+/// The response struct for the
+/// [`nix_cache_info`](DomainService::nix_cache_info) fn. Its [`Display`]
+/// impl renders the `nix-cache-info` file.
+///
+/// [`Display`]: fmt::Display
+#[derive(Debug)]
+pub struct NixCacheInfoResponse {
+  store_dir: StoreDir,
+  settings:  NixCacheInfoSettings,
+}
+
+impl fmt::Display for NixCacheInfoResponse {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    writeln!(f, "StoreDir: {}", self.store_dir)?;
+    writeln!(
+      f,
+      "WantMassQuery: {}",
+      u8::from(self.settings.want_mass_query)
+    )?;
+    writeln!(f, "Priority: {}", self.settings.priority)
+  }
+}
+
+/// The error enum for the [`nix_cache_info`](DomainService::nix_cache_info)
+/// fn.
+#[derive(thiserror::Error, Debug)]
+pub enum NixCacheInfoError {
+  /// The user is unauthorized to read from this cache.
+  #[error("The user is unauthorized to read from this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Returns the `nix-cache-info` of a cache.
+  #[tracing::instrument(skip(self))]
+  pub async fn nix_cache_info(
+    &self,
+    req: NixCacheInfoRequest,
+  ) -> Result<NixCacheInfoResponse, NixCacheInfoError> {
+    let cache = self
+      .meta
+      .fetch_cache_by_name(req.cache_name.clone())
+      .await
+      .into_diagnostic()
+      .context("failed to search for cache")
+      .map_err(NixCacheInfoError::InternalError)?
+      .ok_or(NixCacheInfoError::CacheNotFound(req.cache_name))?;
+
+    if !self
+      .may_read_cache(req.auth, &cache)
+      .await
+      .map_err(NixCacheInfoError::InternalError)?
+    {
+      return Err(NixCacheInfoError::Unauthorized);
+    }
+
+    Ok(NixCacheInfoResponse {
+      store_dir: cache.store_dir,
+      settings:  cache.nix_cache_info,
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn nix_cache_info_renders_settings() {
+    let response = NixCacheInfoResponse {
+      store_dir: StoreDir::default(),
+      settings:  NixCacheInfoSettings {
+        priority:        50,
+        want_mass_query: false,
+      },
+    };
+    assert_eq!(
+      response.to_string(),
+      "StoreDir: /nix/store\nWantMassQuery: 0\nPriority: 50\n"
+    );
+
+    let response = NixCacheInfoResponse {
+      store_dir: StoreDir::default(),
+      settings:  NixCacheInfoSettings::default(),
+    };
+    assert_eq!(
+      response.to_string(),
+      "StoreDir: /nix/store\nWantMassQuery: 1\nPriority: 30\n"
+    );
+
+    let response = NixCacheInfoResponse {
+      store_dir: StoreDir::new("/gpfs/nix/store").unwrap(),
+      settings:  NixCacheInfoSettings::default(),
+    };
+    assert_eq!(
+      response.to_string(),
+      "StoreDir: /gpfs/nix/store\nWantMassQuery: 1\nPriority: 30\n"
+    );
+  }
+}
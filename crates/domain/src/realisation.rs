@@ -0,0 +1,424 @@
+//! Types and impl for realisations of content-addressed derivation outputs.
+//!
+//! With `ca-derivations`, Nix uploads a realisation document to
+//! `realisations/<drv-output>.doi` of a binary cache after building an output,
+//! and fetches it from there to find where the output was built before
+//! substituting it. Signatures are handled like a narinfo's: only those from
+//! trusted keys are kept, and the cache adds its own.
+
+use std::collections::BTreeMap;
+
+use miette::{Context, IntoDiagnostic, miette};
+use models::{
+  DrvOutput, EntityName, Realisation, RecordId, Signature, StorePath, User,
+};
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+
+use crate::{
+  DomainService,
+  signing::{cache_trusts_key_name, verify_with_trusted_keys},
+};
+
+/// The request struct for the
+/// [`upload_realisation`](DomainService::upload_realisation) fn.
+#[derive(Debug)]
+pub struct RealisationUploadRequest {
+  /// The uploading user's authentication.
+  pub auth:       RecordId<User>,
+  /// The name of the cache being uploaded to.
+  pub cache_name: EntityName,
+  /// The derivation output the document was uploaded under.
+  pub drv_output: DrvOutput,
+  /// The realisation document, as JSON.
+  pub document:   String,
+}
+
+/// The request struct for the
+/// [`realisation`](DomainService::realisation) fn.
+#[derive(Debug)]
+pub struct RealisationRequest {
+  /// The user's authentication.
+  pub auth:       Option<RecordId<User>>,
+  /// The name of the cache the realisation was uploaded to.
+  pub cache_name: EntityName,
+  /// The realised derivation output.
+  pub drv_output: DrvOutput,
+}
+
+/// The error enum for realisation uploads and fetches.
+#[derive(thiserror::Error, Debug)]
+pub enum RealisationError {
+  /// The user is unauthorized to access this cache.
+  #[error("The user is unauthorized to access this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// The requested realisation was not found.
+  #[error("The requested realisation was not found: \"{0}\"")]
+  RealisationNotFound(DrvOutput),
+  /// The realisation document is malformed.
+  #[error("The realisation is malformed: {0}")]
+  InvalidRealisation(String),
+  /// The cache requires a signature from a trusted key but none was supplied.
+  #[error("The cache \"{0}\" requires a signature from a trusted key")]
+  MissingTrustedSignature(EntityName),
+  /// A supplied signature does not verify against its key.
+  #[error("The signature from key \"{0}\" is invalid")]
+  InvalidSignature(String),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+/// A realisation, in the JSON format of the documents Nix uploads and
+/// fetches. Store paths are given by their base names.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct RealisationDocument {
+  id:                     String,
+  out_path:               String,
+  #[serde(default)]
+  signatures:             Vec<String>,
+  #[serde(default)]
+  dependent_realisations: BTreeMap<String, String>,
+}
+
+/// The parts of a realisation which are signed, in the order Nix serializes
+/// them.
+#[derive(Serialize)]
+#[serde(rename_all = "camelCase")]
+struct RealisationFingerprint {
+  dependent_realisations: BTreeMap<String, String>,
+  id:                     String,
+  out_path:               String,
+}
+
+/// The fingerprint of a realisation, which is the compact JSON of its document
+/// without signatures, with keys sorted like Nix does.
+fn realisation_fingerprint(
+  drv_output: &DrvOutput,
+  out_path: &StorePath<String>,
+  dependent_realisations: &BTreeMap<DrvOutput, StorePath<String>>,
+) -> String {
+  serde_json::to_string(&RealisationFingerprint {
+    dependent_realisations: dependent_realisations
+      .iter()
+      .map(|(o, p)| (o.to_string(), p.to_string()))
+      .collect(),
+    id:                     drv_output.to_string(),
+    out_path:               out_path.to_string(),
+  })
+  .expect("realisation fingerprints always serialize")
+}
+
+/// The parsed contents of a realisation document.
+struct ParsedRealisation {
+  out_path:               StorePath<String>,
+  signatures:             Vec<Signature<String>>,
+  dependent_realisations: BTreeMap<DrvOutput, StorePath<String>>,
+}
+
+/// Parses a realisation document, which must be for the given output.
+fn parse_realisation_document(
+  document: &str,
+  drv_output: &DrvOutput,
+) -> Result<ParsedRealisation, RealisationError> {
+  let invalid = |e: String| RealisationError::InvalidRealisation(e);
+  let parse_store_path = |path: &str| {
+    StorePath::<String>::from_bytes(path.as_bytes())
+      .map_err(|_| invalid(format!("store path is malformed: \"{path}\"")))
+  };
+
+  let document = serde_json::from_str::<RealisationDocument>(document)
+    .map_err(|e| invalid(e.to_string()))?;
+
+  let id = document
+    .id
+    .parse::<DrvOutput>()
+    .map_err(|e| invalid(e.to_string()))?;
+  if id != *drv_output {
+    return Err(invalid(format!(
+      "document is for \"{id}\", but was uploaded as \"{drv_output}\""
+    )));
+  }
+
+  let signatures = document
+    .signatures
+    .iter()
+    .map(|s| {
+      Signature::<&str>::parse(s)
+        .map(|s| s.to_owned())
+        .map_err(|_| invalid(format!("signature is malformed: \"{s}\"")))
+    })
+    .collect::<Result<Vec<_>, _>>()?;
+
+  let dependent_realisations = document
+    .dependent_realisations
+    .iter()
+    .map(|(output, path)| {
+      let output = output
+        .parse::<DrvOutput>()
+        .map_err(|e| invalid(e.to_string()))?;
+      Ok((output, parse_store_path(path)?))
+    })
+    .collect::<Result<BTreeMap<_, _>, _>>()?;
+
+  Ok(ParsedRealisation {
+    out_path: parse_store_path(&document.out_path)?,
+    signatures,
+    dependent_realisations,
+  })
+}
+
+/// Renders a realisation as the document Nix fetches.
+fn realisation_document(realisation: &Realisation) -> String {
+  let mut signatures = realisation
+    .signatures
+    .iter()
+    .map(|s| s.to_string())
+    .collect::<Vec<_>>();
+  signatures.sort_unstable();
+
+  serde_json::to_string(&RealisationDocument {
+    id: realisation.drv_output.to_string(),
+    out_path: realisation.out_path.to_string(),
+    signatures,
+    dependent_realisations: realisation
+      .dependent_realisations
+      .iter()
+      .map(|(o, p)| (o.to_string(), p.to_string()))
+      .collect(),
+  })
+  .expect("realisation documents always serialize")
+}
+
+impl DomainService {
+  /// Stores a realisation uploaded to a cache, replacing any realisation
+  /// already uploaded for the same output.
+  ///
+  /// Signatures from keys the cache doesn't trust are dropped, and the rest
+  /// are verified. The realisation is signed with the cache's key.
+  #[tracing::instrument(skip(self))]
+  pub async fn upload_realisation(
+    &self,
+    req: RealisationUploadRequest,
+  ) -> Result<RecordId<Realisation>, RealisationError> {
+    let parsed = parse_realisation_document(&req.document, &req.drv_output)?;
+
+    let user = self
+      .meta
+      .fetch_user_by_id(req.auth)
+      .await
+      .into_diagnostic()
+      .context("failed to find user")
+      .map_err(RealisationError::InternalError)?
+      .ok_or(miette!("authenticated user not found"))
+      .map_err(RealisationError::InternalError)?;
+    let cache = self
+      .meta
+      .fetch_cache_by_name(req.cache_name.clone())
+      .await
+      .into_diagnostic()
+      .context("failed to search for cache")
+      .map_err(RealisationError::InternalError)?
+      .ok_or(RealisationError::CacheNotFound(req.cache_name))?;
+    if !user.belongs_to_org(cache.org) {
+      return Err(RealisationError::Unauthorized);
+    }
+
+    // keep only signatures from keys the cache trusts, since there's no way
+    // to verify the rest
+    let (signatures, untrusted_signatures): (Vec<_>, Vec<_>) = parsed
+      .signatures
+      .into_iter()
+      .partition(|s| cache_trusts_key_name(&cache, s.name()));
+    for signature in untrusted_signatures {
+      tracing::debug!(
+        key = signature.name(),
+        "ignoring signature from untrusted key"
+      );
+    }
+    if cache.require_trusted_signature && signatures.is_empty() {
+      return Err(RealisationError::MissingTrustedSignature(cache.name));
+    }
+
+    let fingerprint = realisation_fingerprint(
+      &req.drv_output,
+      &parsed.out_path,
+      &parsed.dependent_realisations,
+    );
+    if let Some(signature) = signatures
+      .iter()
+      .find(|s| !verify_with_trusted_keys([&cache], s, &fingerprint))
+    {
+      return Err(RealisationError::InvalidSignature(signature.name().clone()));
+    }
+
+    let mut all_signatures = self
+      .sign_for_caches([&cache], &fingerprint)
+      .await
+      .context("failed to sign realisation")
+      .map_err(RealisationError::InternalError)?;
+    all_signatures.extend(signatures);
+
+    let existing = self
+      .meta
+      .fetch_realisation_by_cache_id_and_drv_output(cache.id, &req.drv_output)
+      .await
+      .into_diagnostic()
+      .context("failed to search for realisation")
+      .map_err(RealisationError::InternalError)?;
+    let is_replacement = existing.is_some();
+    let realisation = Realisation {
+      id:                     existing
+        .map(|r| r.id)
+        .unwrap_or_else(RecordId::new),
+      org:                    cache.org,
+      cache:                  cache.id,
+      drv_output:             req.drv_output,
+      out_path:               parsed.out_path,
+      signatures:             all_signatures,
+      dependent_realisations: parsed.dependent_realisations,
+      uploaded_by:            user.id,
+      uploaded_at:            UtcDateTime::now(),
+    };
+
+    let result = match is_replacement {
+      true => self.mutate.patch_realisation(&realisation).await,
+      false => self
+        .mutate
+        .create_realisation(&realisation)
+        .await
+        .map(|_| ()),
+    };
+    result
+      .into_diagnostic()
+      .context("failed to save realisation")
+      .map_err(RealisationError::InternalError)?;
+
+    Ok(realisation.id)
+  }
+
+  /// Fetches the document of a realisation uploaded to a cache.
+  #[tracing::instrument(skip(self))]
+  pub async fn realisation(
+    &self,
+    req: RealisationRequest,
+  ) -> Result<String, RealisationError> {
+    let cache = self
+      .lookup_cache_by_name(&req.cache_name)
+      .await
+      .map_err(RealisationError::InternalError)?
+      .ok_or(RealisationError::CacheNotFound(req.cache_name))?;
+
+    if !self
+      .may_read_cache(req.auth, &cache)
+      .await
+      .map_err(RealisationError::InternalError)?
+    {
+      return Err(RealisationError::Unauthorized);
+    }
+
+    let realisation = self
+      .meta
+      .fetch_realisation_by_cache_id_and_drv_output(cache.id, &req.drv_output)
+      .await
+      .into_diagnostic()
+      .context("failed to search for realisation")
+      .map_err(RealisationError::InternalError)?
+      .ok_or(RealisationError::RealisationNotFound(req.drv_output))?;
+
+    Ok(realisation_document(&realisation))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DRV_OUTPUT: &str = concat!(
+    "sha256:1b4f0e9851971998e732078544c96b36c3d01cedf7caa332359d6f1d83567014",
+    "!out"
+  );
+  const DEPENDENT_DRV_OUTPUT: &str = concat!(
+    "sha256:6e3e5a1bcb5b3e2f3e56cb6c3b0a8c5d2c4f4a1a2e8e3a1b9c4d7e6f5a4b3c2d",
+    "!lib"
+  );
+  const OUT_PATH: &str = "ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0";
+  const DEPENDENT_OUT_PATH: &str =
+    "1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3-lib";
+
+  fn document(signatures: &[&str]) -> String {
+    serde_json::json!({
+      "id": DRV_OUTPUT,
+      "outPath": OUT_PATH,
+      "signatures": signatures,
+      "dependentRealisations": { DEPENDENT_DRV_OUTPUT: DEPENDENT_OUT_PATH },
+    })
+    .to_string()
+  }
+
+  #[test]
+  fn fingerprints_match_nix() {
+    let drv_output = DRV_OUTPUT.parse::<DrvOutput>().unwrap();
+    let parsed =
+      parse_realisation_document(&document(&[]), &drv_output).unwrap();
+
+    assert_eq!(
+      realisation_fingerprint(
+        &drv_output,
+        &parsed.out_path,
+        &parsed.dependent_realisations
+      ),
+      format!(
+        "{{\"dependentRealisations\":{{\"{DEPENDENT_DRV_OUTPUT}\":\"\
+         {DEPENDENT_OUT_PATH}\"}},\"id\":\"{DRV_OUTPUT}\",\"outPath\":\"\
+         {OUT_PATH}\"}}"
+      )
+    );
+  }
+
+  #[test]
+  fn documents_must_match_their_output() {
+    let other_output = DEPENDENT_DRV_OUTPUT.parse::<DrvOutput>().unwrap();
+    assert!(matches!(
+      parse_realisation_document(&document(&[]), &other_output),
+      Err(RealisationError::InvalidRealisation(_))
+    ));
+  }
+
+  #[test]
+  fn documents_round_trip() {
+    let drv_output = DRV_OUTPUT.parse::<DrvOutput>().unwrap();
+    let signature = concat!(
+      "cache.nixos.org-1:",
+      "0CpHca+06TwFp9VkMyz5OaphT3E8mnS+1SWymYlvFaghKSYPCMQ66TS1XPAr1+y9rfQZ",
+      "PLaHrBjjnIRktE/nAA=="
+    );
+    let parsed =
+      parse_realisation_document(&document(&[signature]), &drv_output).unwrap();
+
+    let realisation = Realisation {
+      id: RecordId::new(),
+      org: RecordId::new(),
+      cache: RecordId::new(),
+      drv_output,
+      out_path: parsed.out_path,
+      signatures: parsed.signatures,
+      dependent_realisations: parsed.dependent_realisations,
+      uploaded_by: RecordId::new(),
+      uploaded_at: UtcDateTime::now(),
+    };
+
+    assert_eq!(
+      serde_json::from_str::<serde_json::Value>(&realisation_document(
+        &realisation
+      ))
+      .unwrap(),
+      serde_json::from_str::<serde_json::Value>(&document(&[signature]))
+        .unwrap()
+    );
+  }
+}
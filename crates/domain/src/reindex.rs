@@ -0,0 +1,119 @@
+//! Re-indexing of the entries persisted before an entry index existed.
+//!
+//! Indexes are only computed when a record is written, so when an index is
+//! added to [`Entry`](models::Entry) the entries already persisted are
+//! missing from it. Each cache records the
+//! [`ENTRY_INDEX_VERSION`](models::ENTRY_INDEX_VERSION) its entries were last
+//! written with, and the first time an outdated cache is looked up its entries
+//! are rewritten in the background. Until then, lookups that depend on the
+//! newer indexes fall back to ones that predate them.
+
+use std::{
+  collections::HashSet,
+  sync::{Arc, Mutex},
+};
+
+use miette::{Context, IntoDiagnostic, miette};
+use models::{Cache, ENTRY_INDEX_VERSION, RecordId};
+
+use crate::DomainService;
+
+/// The caches whose entries are being re-indexed in this process.
+#[derive(Clone, Debug, Default)]
+pub(crate) struct Reindexing {
+  inner: Arc<Mutex<HashSet<RecordId<Cache>>>>,
+}
+
+impl Reindexing {
+  /// Marks a cache as being re-indexed, returning `false` if it already was.
+  fn start(&self, cache: RecordId<Cache>) -> bool {
+    self
+      .inner
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .insert(cache)
+  }
+
+  /// Marks a cache as no longer being re-indexed.
+  fn finish(&self, cache: RecordId<Cache>) {
+    self
+      .inner
+      .lock()
+      .unwrap_or_else(|e| e.into_inner())
+      .remove(&cache);
+  }
+}
+
+impl DomainService {
+  /// Spawns a task re-indexing the cache's entries if they aren't all in the
+  /// current entry indexes and no task is doing so already.
+  pub(crate) fn ensure_entries_indexed(&self, cache: &Cache) {
+    if cache.entries_indexed() || !self.reindexing.start(cache.id) {
+      return;
+    }
+
+    let domain = self.clone();
+    let cache_id = cache.id;
+    tokio::spawn(async move {
+      if let Err(e) = domain.reindex_entries(cache_id).await {
+        tracing::error!(%cache_id, "failed to re-index cache entries: {e:?}");
+      }
+      domain.reindexing.finish(cache_id);
+    });
+  }
+
+  /// Rewrites every entry in a cache so that it's in the current entry
+  /// indexes, and then records the cache as indexed.
+  #[tracing::instrument(skip(self))]
+  async fn reindex_entries(
+    &self,
+    cache_id: RecordId<Cache>,
+  ) -> miette::Result<()> {
+    let entry_ids = self
+      .meta
+      .fetch_entries_by_cache(cache_id)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch cache entries")?;
+    tracing::info!(count = entry_ids.len(), "re-indexing cache entries");
+
+    for entry_id in entry_ids {
+      // entries are fetched one at a time right before they're written, so
+      // that changes made since the cache was listed aren't overwritten
+      let Some(entry) = self
+        .meta
+        .fetch_entry_by_id(entry_id)
+        .await
+        .into_diagnostic()
+        .context("failed to fetch entry")?
+      else {
+        continue;
+      };
+      self
+        .mutate
+        .patch_entry(&entry)
+        .await
+        .into_diagnostic()
+        .context("failed to rewrite entry")?;
+    }
+
+    let mut cache = self
+      .meta
+      .fetch_cache_by_id(cache_id)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch cache")?
+      .ok_or(miette!("re-indexed cache not found"))?;
+    cache.entry_index_version = ENTRY_INDEX_VERSION;
+    self
+      .mutate
+      .patch_cache(&cache)
+      .await
+      .into_diagnostic()
+      .context("failed to record cache as re-indexed")?;
+    self.lookups.invalidate_cache(&cache.name);
+
+    tracing::info!("re-indexed cache entries");
+    Ok(())
+  }
+}
@@ -0,0 +1,386 @@
+//! Cache signing keys and their encryption at rest.
+
+use std::fmt;
+
+use chacha20poly1305::{
+  XChaCha20Poly1305, XNonce,
+  aead::{Aead, AeadCore, KeyInit, OsRng},
+};
+use data_encoding::BASE64;
+use ed25519_dalek::Signer;
+use miette::{Context, IntoDiagnostic, Report, miette};
+use models::{
+  Cache, CacheSigningKey, EncryptedSecret, EntityName, RecordId, Signature,
+  User,
+};
+
+use crate::DomainService;
+
+/// Encrypts and decrypts secrets, like cache signing keys, which are stored at
+/// rest.
+#[derive(Clone)]
+pub struct SecretCipher(XChaCha20Poly1305);
+
+impl fmt::Debug for SecretCipher {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_tuple("SecretCipher").finish_non_exhaustive()
+  }
+}
+
+impl SecretCipher {
+  /// Create a new [`SecretCipher`] from a 256-bit key.
+  pub fn new(key: [u8; 32]) -> Self {
+    Self(XChaCha20Poly1305::new(&key.into()))
+  }
+
+  /// Create a new [`SecretCipher`] from environment variables.
+  pub fn new_from_env() -> Result<Self, Report> {
+    let key = std::env::var("SECRET_ENCRYPTION_KEY")
+      .into_diagnostic()
+      .context("failed to read var `SECRET_ENCRYPTION_KEY`")?;
+    let key = BASE64
+      .decode(key.trim().as_bytes())
+      .into_diagnostic()
+      .context("`SECRET_ENCRYPTION_KEY` is not valid base64")?;
+    let key: [u8; 32] = key
+      .try_into()
+      .map_err(|_| miette!("`SECRET_ENCRYPTION_KEY` is not 32 bytes long"))?;
+
+    Ok(Self::new(key))
+  }
+
+  /// Encrypts a secret.
+  fn encrypt(&self, plaintext: &[u8]) -> EncryptedSecret {
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let ciphertext = self
+      .0
+      .encrypt(&nonce, plaintext)
+      .expect("failed to encrypt secret");
+    EncryptedSecret {
+      nonce: nonce.to_vec(),
+      ciphertext,
+    }
+  }
+
+  /// Decrypts a secret.
+  fn decrypt(&self, secret: &EncryptedSecret) -> Result<Vec<u8>, Report> {
+    miette::ensure!(
+      secret.nonce.len() == 24,
+      "encrypted secret has a malformed nonce"
+    );
+    self
+      .0
+      .decrypt(
+        XNonce::from_slice(&secret.nonce),
+        secret.ciphertext.as_slice(),
+      )
+      .map_err(|_| miette!("failed to decrypt secret"))
+  }
+
+  /// Generates a new signing keypair, with the secret half encrypted.
+  pub(crate) fn generate_signing_key(&self, name: &str) -> CacheSigningKey {
+    let signing_key = ed25519_dalek::SigningKey::generate(&mut OsRng);
+    let public_key = format!(
+      "{name}:{key}",
+      key = BASE64.encode(signing_key.verifying_key().as_bytes())
+    );
+
+    CacheSigningKey {
+      name: name.to_owned(),
+      public_key,
+      secret_key: self.encrypt(signing_key.as_bytes()),
+    }
+  }
+
+  /// Generates a signing key for a cache which has none, returning whether
+  /// one was generated.
+  pub(crate) fn ensure_signing_key(&self, cache: &mut Cache) -> bool {
+    if cache.signing_key.is_some() {
+      return false;
+    }
+    cache.signing_key = Some(self.generate_signing_key(cache.name.as_ref()));
+    true
+  }
+
+  /// Signs a narinfo fingerprint with a signing key.
+  pub(crate) fn sign(
+    &self,
+    key: &CacheSigningKey,
+    fingerprint: &str,
+  ) -> Result<Signature<String>, Report> {
+    let secret_key: [u8; 32] = self
+      .decrypt(&key.secret_key)
+      .context("failed to decrypt signing key")?
+      .try_into()
+      .map_err(|_| miette!("decrypted signing key is not 32 bytes long"))?;
+    let signing_key = ed25519_dalek::SigningKey::from_bytes(&secret_key);
+
+    let signature = signing_key.sign(fingerprint.as_bytes());
+    Ok(Signature::new(key.name.clone(), signature.to_bytes()))
+  }
+}
+
+/// Parses a public key in the `name:base64` form used by
+/// `trusted-public-keys`.
+pub(crate) fn parse_public_key(
+  input: &str,
+) -> Result<(&str, ed25519_dalek::VerifyingKey), Report> {
+  let (name, key) = input
+    .split_once(':')
+    .ok_or(miette!("public key is missing a name: `{input}`"))?;
+  miette::ensure!(!name.is_empty(), "public key has an empty name: `{input}`");
+  let key: [u8; 32] = BASE64
+    .decode(key.as_bytes())
+    .into_diagnostic()
+    .context("public key is not valid base64")?
+    .try_into()
+    .map_err(|_| miette!("public key is not 32 bytes long: `{input}`"))?;
+  let key = ed25519_dalek::VerifyingKey::from_bytes(&key)
+    .into_diagnostic()
+    .context("public key is not a valid Ed25519 key")?;
+  Ok((name, key))
+}
+
+/// Returns whether the cache trusts a key with the given name.
+pub(crate) fn cache_trusts_key_name(cache: &Cache, name: &str) -> bool {
+  cache
+    .trusted_public_keys
+    .iter()
+    .filter_map(|k| parse_public_key(k).ok())
+    .any(|(n, _)| n == name)
+}
+
+/// Returns whether a signature over the fingerprint verifies against any key
+/// trusted by the given caches.
+pub(crate) fn verify_with_trusted_keys<'a>(
+  caches: impl IntoIterator<Item = &'a Cache>,
+  signature: &Signature<String>,
+  fingerprint: &str,
+) -> bool {
+  verify_with_keys(
+    caches
+      .into_iter()
+      .flat_map(|c| c.trusted_public_keys.iter()),
+    signature,
+    fingerprint,
+  )
+}
+
+/// Returns whether a signature over the fingerprint verifies against any of
+/// the given public keys, in `name:base64` form.
+pub(crate) fn verify_with_keys<'a>(
+  keys: impl IntoIterator<Item = &'a String>,
+  signature: &Signature<String>,
+  fingerprint: &str,
+) -> bool {
+  keys
+    .into_iter()
+    .filter_map(|k| parse_public_key(k).ok())
+    .filter(|(name, _)| name == signature.name())
+    .any(|(_, key)| signature.verify(fingerprint.as_bytes(), &key))
+}
+
+/// The request struct for the
+/// [`cache_public_key`](DomainService::cache_public_key) fn.
+#[derive(Debug)]
+pub struct CachePublicKeyRequest {
+  /// The user's authentication.
+  pub auth:       Option<RecordId<User>>,
+  /// The name of the cache.
+  pub cache_name: EntityName,
+}
+
+/// The error enum for the
+/// [`cache_public_key`](DomainService::cache_public_key) fn.
+#[derive(thiserror::Error, Debug)]
+pub enum CachePublicKeyError {
+  /// The user is unauthorized to read from this cache.
+  #[error("The user is unauthorized to read from this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Returns the public key of a cache, in the `name:base64` form used by
+  /// `trusted-public-keys`.
+  #[tracing::instrument(skip(self))]
+  pub async fn cache_public_key(
+    &self,
+    req: CachePublicKeyRequest,
+  ) -> Result<String, CachePublicKeyError> {
+    let cache = self
+      .meta
+      .fetch_cache_by_name(req.cache_name.clone())
+      .await
+      .into_diagnostic()
+      .context("failed to search for cache")
+      .map_err(CachePublicKeyError::InternalError)?
+      .ok_or(CachePublicKeyError::CacheNotFound(req.cache_name.clone()))?;
+
+    if !self
+      .may_read_cache(req.auth, &cache)
+      .await
+      .map_err(CachePublicKeyError::InternalError)?
+    {
+      return Err(CachePublicKeyError::Unauthorized);
+    }
+
+    self
+      .cache_signing_key(&cache)
+      .await
+      .map(|k| k.public_key)
+      .map_err(CachePublicKeyError::InternalError)
+  }
+
+  /// Signs a narinfo fingerprint with the signing key of each given cache.
+  pub(crate) async fn sign_for_caches<'a>(
+    &self,
+    caches: impl IntoIterator<Item = &'a Cache>,
+    fingerprint: &str,
+  ) -> Result<Vec<Signature<String>>, Report> {
+    let mut signatures = Vec::new();
+    for cache in caches {
+      let key = self.cache_signing_key(cache).await?;
+      signatures.push(self.secrets.sign(&key, fingerprint)?);
+    }
+    Ok(signatures)
+  }
+
+  /// Returns the signing key of a cache. Caches created before signing was
+  /// introduced have none, so one is generated and persisted for them.
+  async fn cache_signing_key(
+    &self,
+    cache: &Cache,
+  ) -> Result<CacheSigningKey, Report> {
+    if let Some(key) = &cache.signing_key {
+      return Ok(key.clone());
+    }
+
+    // keys are generated one at a time, and the cache is fetched again once
+    // the lock is held, so that concurrent requests don't each persist a key
+    // and invalidate the others' signatures
+    let _guard = self.signing_key_generation.lock().await;
+    let mut cache = self
+      .meta
+      .fetch_cache_by_id(cache.id)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch cache")?
+      .ok_or(miette!("cache not found"))?;
+    if self.secrets.ensure_signing_key(&mut cache) {
+      self
+        .mutate
+        .patch_cache(&cache)
+        .await
+        .into_diagnostic()
+        .context("failed to persist generated signing key")?;
+      self.lookups.invalidate_cache(&cache.name);
+      tracing::info!(cache_name = %cache.name, "generated cache signing key");
+    }
+
+    cache.signing_key.ok_or(miette!("cache has no signing key"))
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use models::{ENTRY_INDEX_VERSION, Visibility};
+
+  use super::*;
+
+  #[test]
+  fn signatures_verify_against_public_key() {
+    let cipher = SecretCipher::new([7; 32]);
+    let key = cipher.generate_signing_key("aaron");
+    assert!(key.public_key.starts_with("aaron:"));
+
+    let fingerprint =
+      "1;/nix/store/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0;sha256:\
+       0000000000000000000000000000000000000000000000000000;1234;";
+    let signature = cipher.sign(&key, fingerprint).unwrap();
+    assert_eq!(signature.name(), "aaron");
+
+    let public_key = BASE64
+      .decode(key.public_key.strip_prefix("aaron:").unwrap().as_bytes())
+      .unwrap();
+    let public_key =
+      ed25519_dalek::VerifyingKey::from_bytes(&public_key.try_into().unwrap())
+        .unwrap();
+    assert!(signature.verify(fingerprint.as_bytes(), &public_key));
+  }
+
+  #[test]
+  fn trusted_keys_verify_signatures() {
+    let cipher = SecretCipher::new([7; 32]);
+    let key = cipher.generate_signing_key("aaron");
+    let fingerprint =
+      "1;/nix/store/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0;sha256:\
+       0000000000000000000000000000000000000000000000000000;1234;";
+    let signature = cipher.sign(&key, fingerprint).unwrap();
+
+    let cache = Cache {
+      id: RecordId::new(),
+      org: RecordId::new(),
+      name: EntityName::new("albert"),
+      visibility: Visibility::Public,
+      signing_key: None,
+      trusted_public_keys: vec![key.public_key.clone()],
+      require_trusted_signature: true,
+      default_store: None,
+      upstreams: vec![],
+      nix_cache_info: Default::default(),
+      store_dir: Default::default(),
+      entry_index_version: ENTRY_INDEX_VERSION,
+    };
+
+    assert!(cache_trusts_key_name(&cache, "aaron"));
+    assert!(!cache_trusts_key_name(&cache, "albert"));
+    assert!(verify_with_trusted_keys([&cache], &signature, fingerprint));
+    assert!(!verify_with_trusted_keys(
+      [&cache],
+      &signature,
+      "1;/nix/store/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0;sha256:\
+       0000000000000000000000000000000000000000000000000000;1235;"
+    ));
+  }
+
+  #[test]
+  fn caches_without_signing_keys_get_one() {
+    let cipher = SecretCipher::new([7; 32]);
+    // a cache persisted before signing was introduced
+    let mut cache = Cache {
+      id: RecordId::new(),
+      org: RecordId::new(),
+      name: EntityName::new("albert"),
+      visibility: Visibility::Public,
+      signing_key: None,
+      trusted_public_keys: vec![],
+      require_trusted_signature: false,
+      default_store: None,
+      upstreams: vec![],
+      nix_cache_info: Default::default(),
+      store_dir: Default::default(),
+      entry_index_version: ENTRY_INDEX_VERSION,
+    };
+
+    assert!(cipher.ensure_signing_key(&mut cache));
+    let key = cache.signing_key.clone().unwrap();
+    assert!(key.public_key.starts_with("albert:"));
+    assert!(cipher.sign(&key, "fingerprint").is_ok());
+
+    // an existing key is kept
+    assert!(!cipher.ensure_signing_key(&mut cache));
+    assert_eq!(cache.signing_key, Some(key));
+  }
+
+  #[test]
+  fn secrets_do_not_decrypt_with_other_keys() {
+    let key = SecretCipher::new([7; 32]).generate_signing_key("aaron");
+    assert!(SecretCipher::new([8; 32]).decrypt(&key.secret_key).is_err());
+  }
+}
@@ -1,8 +1,26 @@
+use std::{io, ops::RangeInclusive, path::Path};
+
+use belt::Belt;
+use bytes::Bytes;
+use futures::TryStreamExt;
+use miette::{Context, IntoDiagnostic, Report, miette};
 use models::{
   LocalStorageCredentials, MemoryStorageCredentials, R2StorageCredentials,
   StorageCredentials,
 };
-use storage::{BlobStorage, BlobStorageResult};
+use s3::{Bucket, Region, creds::Credentials, serde_types::Part};
+use storage::{BlobKey, BlobStorage, BlobStorageError, BlobStorageResult};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
+use tokio_util::io::ReaderStream;
+
+use crate::download::{ByteRange, slice_stream};
+
+/// How long the presigned URLs of ranged reads from object stores are valid.
+const PRESIGNED_URL_EXPIRY_SECS: u32 = 60;
+/// The largest object which object stores copy in one request.
+const MAX_COPY_SIZE: u64 = 5 * 1024 * 1024 * 1024;
+/// The content type of blobs in object stores.
+const BLOB_CONTENT_TYPE: &str = "application/octet-stream";
 
 pub async fn storage_creds_to_blob_storage(
   creds: StorageCredentials,
@@ -28,3 +46,332 @@ pub async fn storage_creds_to_blob_storage(
     ),
   }
 }
+
+/// Streams an inclusive range of bytes of a blob. Object stores are asked for
+/// just the range, and local stores seek to it, so reading the end of a large
+/// blob doesn't read all of it. Otherwise, the blob is read up to the end of
+/// the range, and the bytes before it are skipped.
+pub async fn get_stream_range(
+  creds: &StorageCredentials,
+  store_client: &BlobStorage,
+  key: &str,
+  range: RangeInclusive<u64>,
+) -> Result<Belt, Report> {
+  let ranged = match creds {
+    StorageCredentials::R2(creds) => {
+      Some(get_r2_stream_range(creds, key, range.clone()).await?)
+    }
+    StorageCredentials::Local(LocalStorageCredentials(root)) => {
+      get_local_stream_range(store_client, root, key, range.clone()).await?
+    }
+    StorageCredentials::Memory(MemoryStorageCredentials) => None,
+  };
+  if let Some(data) = ranged {
+    return Ok(data);
+  }
+
+  let data = store_client
+    .get_stream(&BlobKey::new(key))
+    .await
+    .into_diagnostic()
+    .context("failed to read blob")?
+    .map_err(BlobStorageError::into_io_error);
+  let range = ByteRange {
+    start: *range.start(),
+    end:   *range.end(),
+  };
+  Ok(Belt::new(slice_stream(data, range)))
+}
+
+/// Requests a range of an R2 object through a presigned URL, which streams the
+/// response.
+async fn get_r2_stream_range(
+  creds: &R2StorageCredentials,
+  key: &str,
+  range: RangeInclusive<u64>,
+) -> Result<Belt, Report> {
+  let bucket = r2_bucket(creds)?;
+  let url = bucket
+    .presign_get(key, PRESIGNED_URL_EXPIRY_SECS, None)
+    .await
+    .into_diagnostic()
+    .context("failed to presign R2 request")?;
+  let response = reqwest::Client::new()
+    .get(url)
+    .header(
+      reqwest::header::RANGE,
+      format!("bytes={}-{}", range.start(), range.end()),
+    )
+    .send()
+    .await
+    .into_diagnostic()
+    .context("failed to request range from R2")?
+    .error_for_status()
+    .into_diagnostic()
+    .context("R2 failed to serve range")?;
+  miette::ensure!(
+    response.status() == reqwest::StatusCode::PARTIAL_CONTENT,
+    "R2 served the whole object instead of the range"
+  );
+
+  Ok(Belt::new(response.bytes_stream().map_err(io::Error::other)))
+}
+
+/// Starts a multipart upload of a blob if the store supports them, returning
+/// the upload's ID. Returns `None` for stores which don't, whose parts have to
+/// be stored as blobs of their own.
+pub async fn start_multipart_upload(
+  creds: &StorageCredentials,
+  key: &str,
+) -> Result<Option<String>, Report> {
+  let StorageCredentials::R2(creds) = creds else {
+    return Ok(None);
+  };
+  let response = r2_bucket(creds)?
+    .initiate_multipart_upload(key, BLOB_CONTENT_TYPE)
+    .await
+    .into_diagnostic()
+    .context("failed to start R2 multipart upload")?;
+  Ok(Some(response.upload_id))
+}
+
+/// Sends a part of a multipart upload. Parts are numbered from zero. Returns
+/// the ETag the store gave the part, which is needed to complete the upload.
+pub async fn put_multipart_part(
+  creds: &StorageCredentials,
+  key: &str,
+  upload_id: &str,
+  number: u32,
+  data: Bytes,
+) -> Result<String, Report> {
+  let StorageCredentials::R2(creds) = creds else {
+    miette::bail!("the store doesn't support multipart uploads");
+  };
+  let part = r2_bucket(creds)?
+    .put_multipart_chunk(
+      data.to_vec(),
+      key,
+      number + 1,
+      upload_id,
+      BLOB_CONTENT_TYPE,
+    )
+    .await
+    .into_diagnostic()
+    .context("failed to send part to R2 multipart upload")?;
+  Ok(part.etag)
+}
+
+/// Completes a multipart upload from the numbers and ETags of its parts, so
+/// that the store assembles them into the blob.
+pub async fn complete_multipart_upload(
+  creds: &StorageCredentials,
+  key: &str,
+  upload_id: &str,
+  parts: impl IntoIterator<Item = (u32, String)>,
+) -> Result<(), Report> {
+  let StorageCredentials::R2(creds) = creds else {
+    miette::bail!("the store doesn't support multipart uploads");
+  };
+  let parts = parts
+    .into_iter()
+    .map(|(number, etag)| Part {
+      part_number: number + 1,
+      etag,
+    })
+    .collect();
+  let response = r2_bucket(creds)?
+    .complete_multipart_upload(key, upload_id, parts)
+    .await
+    .into_diagnostic()
+    .context("failed to complete R2 multipart upload")?;
+  miette::ensure!(
+    (200..300).contains(&response.status_code()),
+    "R2 failed to complete multipart upload with status {}",
+    response.status_code()
+  );
+  Ok(())
+}
+
+/// Aborts a multipart upload, deleting the parts sent to it.
+pub async fn abort_multipart_upload(
+  creds: &StorageCredentials,
+  key: &str,
+  upload_id: &str,
+) -> Result<(), Report> {
+  let StorageCredentials::R2(creds) = creds else {
+    miette::bail!("the store doesn't support multipart uploads");
+  };
+  r2_bucket(creds)?
+    .abort_upload(key, upload_id)
+    .await
+    .into_diagnostic()
+    .context("failed to abort R2 multipart upload")
+}
+
+/// Moves a blob to another key. Object stores copy the blob themselves unless
+/// it's too large to copy in one request, and otherwise it's streamed through.
+pub async fn rename_blob(
+  creds: &StorageCredentials,
+  store_client: &BlobStorage,
+  from: &str,
+  to: &str,
+) -> Result<(), Report> {
+  let from_key = BlobKey::new(from);
+  let size = store_client
+    .head(&from_key)
+    .await
+    .into_diagnostic()
+    .context("failed to read blob metadata")?
+    .ok_or(miette!("blob to move does not exist"))?
+    .size;
+
+  match creds {
+    StorageCredentials::R2(creds) if size <= MAX_COPY_SIZE => {
+      let status = r2_bucket(creds)?
+        .copy_object_internal(from, to)
+        .await
+        .into_diagnostic()
+        .context("failed to copy R2 object")?;
+      miette::ensure!(
+        (200..300).contains(&status),
+        "R2 failed to copy object with status {status}"
+      );
+    }
+    _ => {
+      let data = store_client
+        .get_stream(&from_key)
+        .await
+        .into_diagnostic()
+        .context("failed to read blob")?
+        .map_err(BlobStorageError::into_io_error);
+      store_client
+        .put_stream(
+          &BlobKey::new(to),
+          Box::pin(Belt::new(data)),
+          storage::UploadOptions { overwrite: true },
+        )
+        .await
+        .into_diagnostic()
+        .context("failed to write blob")?;
+    }
+  }
+
+  store_client
+    .delete(&from_key)
+    .await
+    .into_diagnostic()
+    .context("failed to delete moved blob")
+}
+
+/// Builds a client for the bucket of an R2 store.
+fn r2_bucket(creds: &R2StorageCredentials) -> Result<Box<Bucket>, Report> {
+  let R2StorageCredentials::Default {
+    access_key,
+    secret_access_key,
+    endpoint,
+    bucket,
+  } = creds;
+  let credentials = Credentials::new(
+    Some(access_key),
+    Some(secret_access_key),
+    None,
+    None,
+    None,
+  )
+  .into_diagnostic()
+  .context("failed to build R2 credentials")?;
+  let region = Region::Custom {
+    region:   "auto".to_owned(),
+    endpoint: endpoint.clone(),
+  };
+  Ok(
+    Bucket::new(bucket, region, credentials)
+      .into_diagnostic()
+      .context("failed to build R2 bucket")?
+      .with_path_style(),
+  )
+}
+
+/// Seeks to a range of a blob in a local store. Returns `None` if the blob's
+/// file doesn't hold just the blob's bytes where it's expected, so that the
+/// range is read through the store instead.
+async fn get_local_stream_range(
+  store_client: &BlobStorage,
+  root: &Path,
+  key: &str,
+  range: RangeInclusive<u64>,
+) -> Result<Option<Belt>, Report> {
+  let Some(metadata) = store_client
+    .head(&BlobKey::new(key))
+    .await
+    .into_diagnostic()
+    .context("failed to read blob metadata")?
+  else {
+    return Ok(None);
+  };
+  let Ok(mut file) = tokio::fs::File::open(root.join(key)).await else {
+    return Ok(None);
+  };
+  let file_size = file
+    .metadata()
+    .await
+    .into_diagnostic()
+    .context("failed to read blob file metadata")?
+    .len();
+  if file_size != metadata.size {
+    return Ok(None);
+  }
+
+  file
+    .seek(io::SeekFrom::Start(*range.start()))
+    .await
+    .into_diagnostic()
+    .context("failed to seek in blob file")?;
+  let len = range.end() - range.start() + 1;
+  Ok(Some(Belt::new(ReaderStream::new(file.take(len)))))
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const DATA: &[u8] = b"0123456789abcdef";
+
+  async fn read_range(
+    creds: StorageCredentials,
+    range: RangeInclusive<u64>,
+  ) -> Bytes {
+    let store_client =
+      storage_creds_to_blob_storage(creds.clone()).await.unwrap();
+    store_client
+      .put_stream(
+        &BlobKey::new("nar/blob"),
+        Box::pin(Belt::new_from_bytes(Bytes::from_static(DATA))),
+        storage::UploadOptions { overwrite: true },
+      )
+      .await
+      .unwrap();
+
+    get_stream_range(&creds, &store_client, "nar/blob", range)
+      .await
+      .unwrap()
+      .collect_bytes()
+      .await
+      .unwrap()
+  }
+
+  #[tokio::test]
+  async fn ranges_are_read_from_local_stores() {
+    let root = std::env::temp_dir()
+      .join(format!("rambit-storage-range-test-{}", std::process::id()));
+    let creds = StorageCredentials::Local(LocalStorageCredentials(root));
+    assert_eq!(read_range(creds.clone(), 3..=9).await, DATA[3..=9]);
+    assert_eq!(read_range(creds, 15..=15).await, DATA[15..=15]);
+  }
+
+  #[tokio::test]
+  async fn ranges_are_read_from_other_stores() {
+    let creds = StorageCredentials::Memory(MemoryStorageCredentials);
+    assert_eq!(read_range(creds, 3..=9).await, DATA[3..=9]);
+  }
+}
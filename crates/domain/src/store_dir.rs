@@ -0,0 +1,100 @@
+//! Conversion of narinfos between store directories.
+//!
+//! `nix_compat` only knows about `/nix/store`, so the `StorePath` line of a
+//! narinfo for a cache with another store directory is converted into the
+//! default store directory before parsing, and back out of it after
+//! rendering.
+
+use std::borrow::Cow;
+
+use models::{StoreDir, nix_compat::narinfo::NarInfo};
+
+/// The key of the only narinfo line which holds an absolute path.
+const STORE_PATH_KEY: &str = "StorePath: ";
+
+/// Converts a narinfo in the given store directory into the default one, so
+/// that it can be parsed. Returns `None` if the narinfo's store path is in a
+/// different store directory.
+pub(crate) fn narinfo_from_store_dir<'a>(
+  narinfo: &'a str,
+  store_dir: &StoreDir,
+) -> Option<Cow<'a, str>> {
+  if *store_dir == StoreDir::default() {
+    return Some(Cow::Borrowed(narinfo));
+  }
+
+  let prefix = format!("{STORE_PATH_KEY}{store_dir}/");
+  let default_prefix = format!("{STORE_PATH_KEY}{}/", StoreDir::default());
+  let mut converted = String::with_capacity(narinfo.len());
+  for line in narinfo.split_inclusive('\n') {
+    if let Some(rest) = line.strip_prefix(&prefix) {
+      converted.push_str(&default_prefix);
+      converted.push_str(rest);
+    } else if line.starts_with(STORE_PATH_KEY) {
+      return None;
+    } else {
+      converted.push_str(line);
+    }
+  }
+  Some(Cow::Owned(converted))
+}
+
+/// Renders a narinfo with its store path in the given store directory.
+pub(crate) fn render_narinfo(
+  narinfo: &NarInfo<'_>,
+  store_dir: &StoreDir,
+) -> String {
+  let rendered = narinfo.to_string();
+  if *store_dir == StoreDir::default() {
+    return rendered;
+  }
+
+  let prefix = format!("{STORE_PATH_KEY}{store_dir}/");
+  let default_prefix = format!("{STORE_PATH_KEY}{}/", StoreDir::default());
+  rendered
+    .split_inclusive('\n')
+    .map(|line| match line.strip_prefix(&default_prefix) {
+      Some(rest) => Cow::Owned(format!("{prefix}{rest}")),
+      None => Cow::Borrowed(line),
+    })
+    .collect()
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const NARINFO: &str = concat!(
+    "StorePath: /gpfs/nix/store/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0\n",
+    "URL: nar/1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3.nar\n",
+    "Compression: none\n",
+    "NarHash: sha256:1w1fff338fvdw53sqgamddn1b2xgds473pv6y13gizdbqjv4i5p3\n",
+    "NarSize: 1234\n",
+    "References: \n",
+  );
+
+  #[test]
+  fn narinfos_round_trip_through_store_dirs() {
+    let store_dir = StoreDir::new("/gpfs/nix/store").unwrap();
+
+    let converted = narinfo_from_store_dir(NARINFO, &store_dir)
+      .expect("narinfo is in the store directory");
+    let narinfo = NarInfo::parse(&converted).expect("failed to parse narinfo");
+    assert_eq!(
+      store_dir.absolute_path(&narinfo.store_path),
+      "/gpfs/nix/store/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0"
+    );
+
+    let rendered = render_narinfo(&narinfo, &store_dir);
+    assert!(rendered.starts_with(concat!(
+      "StorePath: /gpfs/nix/store/",
+      "ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0\n"
+    )));
+  }
+
+  #[test]
+  fn narinfos_in_other_store_dirs_are_rejected() {
+    let store_dir = StoreDir::new("/other/store").unwrap();
+    assert!(narinfo_from_store_dir(NARINFO, &store_dir).is_none());
+  }
+}
@@ -0,0 +1,120 @@
+//! Types and impl for looking up the entries a store already holds.
+//!
+//! A path can only be stored once per store, so a client pushing to a cache
+//! which is missing a path that's already in its target store (through another
+//! cache) links the existing entry instead of uploading it again.
+
+use std::collections::HashMap;
+
+use futures::future::try_join_all;
+use meta_domain::SearchByUserError;
+use miette::{Context, IntoDiagnostic, miette};
+use models::{EntityName, Entry, Org, RecordId, StorePath, User};
+
+use crate::DomainService;
+
+/// The maximum number of paths accepted by a single
+/// [`store_entries`](DomainService::store_entries) query.
+pub const MAX_STORE_ENTRIES_QUERY_SIZE: usize = 10_000;
+
+/// The number of lookups run concurrently by
+/// [`store_entries`](DomainService::store_entries).
+const STORE_ENTRY_LOOKUP_BATCH_SIZE: usize = 64;
+
+/// The request struct for the [`store_entries`](DomainService::store_entries)
+/// fn.
+#[derive(Debug)]
+pub struct StoreEntriesRequest {
+  /// The user's authentication.
+  pub auth:        RecordId<User>,
+  /// The name of the store to look in.
+  pub store_name:  EntityName,
+  /// The store paths to look up.
+  pub store_paths: Vec<StorePath<String>>,
+}
+
+/// The error enum for the [`store_entries`](DomainService::store_entries) fn.
+#[derive(thiserror::Error, Debug)]
+pub enum StoreEntriesError {
+  /// The store was not found in any of the user's orgs.
+  #[error("The store was not found: \"{0}\"")]
+  StoreNotFound(EntityName),
+  /// Multiple stores were found with the given name in different
+  /// organizations.
+  #[error(
+    "The store name \"{1}\" is ambiguous: multiple results found in orgs {0:?}"
+  )]
+  StoreAmbiguous(Vec<RecordId<Org>>, EntityName),
+  /// Too many paths were queried at once.
+  #[error(
+    "Too many paths were queried at once: {0} (maximum is \
+     {MAX_STORE_ENTRIES_QUERY_SIZE})"
+  )]
+  TooManyPaths(usize),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl DomainService {
+  /// Finds which of the given paths already have an [`Entry`] in a store of
+  /// the user's, and returns their entry IDs.
+  #[tracing::instrument(skip(self, req), fields(store_name = %req.store_name))]
+  pub async fn store_entries(
+    &self,
+    req: StoreEntriesRequest,
+  ) -> Result<HashMap<StorePath<String>, RecordId<Entry>>, StoreEntriesError>
+  {
+    if req.store_paths.len() > MAX_STORE_ENTRIES_QUERY_SIZE {
+      return Err(StoreEntriesError::TooManyPaths(req.store_paths.len()));
+    }
+
+    // only the user's own stores are searched, so finding one authorizes it
+    let stores = self
+      .meta
+      .search_stores_by_name_and_user(req.auth, req.store_name.clone())
+      .await
+      .map_err(|e| match e {
+        SearchByUserError::MissingUser(u) => StoreEntriesError::InternalError(
+          miette!("authenticated user not found: {u}"),
+        ),
+        SearchByUserError::DatabaseError(e) => {
+          StoreEntriesError::InternalError(
+            Err::<(), _>(e)
+              .into_diagnostic()
+              .context("failed to search for stores by user")
+              .unwrap_err(),
+          )
+        }
+      })?;
+    let store = match stores.len() {
+      0 => Err(StoreEntriesError::StoreNotFound(req.store_name)),
+      1 => Ok(stores.first().unwrap().clone()),
+      _ => Err(StoreEntriesError::StoreAmbiguous(
+        stores.iter().map(|s| s.org).collect(),
+        req.store_name,
+      )),
+    }?;
+
+    let mut found = HashMap::new();
+    for batch in req.store_paths.chunks(STORE_ENTRY_LOOKUP_BATCH_SIZE) {
+      let entries = try_join_all(batch.iter().map(|path| {
+        self
+          .meta
+          .fetch_entry_by_store_id_and_entry_path(store.id, path)
+      }))
+      .await
+      .into_diagnostic()
+      .context("failed to search for entries by store and path")
+      .map_err(StoreEntriesError::InternalError)?;
+      found.extend(
+        batch
+          .iter()
+          .zip(entries)
+          .filter_map(|(path, entry)| Some((path.clone(), entry?.id))),
+      );
+    }
+
+    Ok(found)
+  }
+}
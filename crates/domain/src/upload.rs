@@ -1,12 +1,24 @@
 //! Upload types.
 
+pub(crate) mod digest;
 mod execute;
 mod plan;
+mod tee;
 #[cfg(test)]
 mod tests;
 
+use std::collections::HashSet;
+
 use belt::Belt;
-use models::{EntityName, NarDeriverData, RecordId, StorePath, User};
+use models::{
+  CAHash, CompressionAlgorithm, EntityName, FileSize, NarDeriverData, RecordId,
+  Signature, StorePath, User,
+};
+
+pub use self::{
+  execute::{UploadExecutionError, UploadResponse},
+  plan::{UploadPlan, UploadPlanningError},
+};
 
 /// The request struct for the
 /// [`plan_upload`](crate::DomainService::plan_upload) fn.
@@ -14,6 +26,10 @@ use models::{EntityName, NarDeriverData, RecordId, StorePath, User};
 pub struct UploadRequest {
   /// The data to be uploaded.
   pub nar_contents: Belt,
+  /// The compression of the uploaded data, if it's compressed. The NAR is
+  /// decompressed for interrogation, and stored as uploaded if the store uses
+  /// the same compression, or re-encoded otherwise.
+  pub compression:  Option<CompressionAlgorithm>,
   /// The uploading user's authentication.
   pub auth:         RecordId<User>,
   /// The name of the cache to register the entry in.
@@ -24,4 +40,19 @@ pub struct UploadRequest {
   pub store_path:   StorePath<String>,
   /// Data about the NAR's deriver.
   pub deriver_data: NarDeriverData,
+  /// The content-addressed hash of the entry, if it's content-addressed. It's
+  /// verified against the NAR.
+  pub ca_hash:      Option<CAHash>,
+  /// The NAR hash claimed by the uploader. If a NAR with this hash is already
+  /// in the target store, its blob is reused instead of being written again.
+  /// It's verified against the NAR.
+  pub nar_hash:     Option<[u8; 32]>,
+  /// The NAR size claimed by the uploader. It's verified against the NAR.
+  pub nar_size:     Option<FileSize>,
+  /// The references claimed by the uploader. Each must be found in the NAR,
+  /// and they replace the scanned references if given.
+  pub references:   Option<HashSet<StorePath<String>>>,
+  /// Signatures supplied by the uploader. Those from keys trusted by the
+  /// caches are verified and kept; the rest are ignored.
+  pub signatures:   Vec<Signature<String>>,
 }
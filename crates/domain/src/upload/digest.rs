@@ -0,0 +1,37 @@
+use std::sync::{Arc, Mutex};
+
+use belt::Belt;
+use futures::TryStreamExt;
+use sha2::{Digest, Sha256};
+
+/// A handle to the SHA-256 digest of the data which has passed through a
+/// [`Belt`] wrapped by [`digesting`].
+#[derive(Clone)]
+pub(crate) struct DigestHandle(Arc<Mutex<Sha256>>);
+
+impl DigestHandle {
+  /// Returns the digest of the data read so far.
+  pub(crate) fn finalize(&self) -> [u8; 32] {
+    self
+      .0
+      .lock()
+      .expect("digest mutex was poisoned")
+      .clone()
+      .finalize()
+      .into()
+  }
+}
+
+/// Wraps a [`Belt`] so that the data read through it is hashed.
+pub(crate) fn digesting(data: Belt) -> (Belt, DigestHandle) {
+  let handle = DigestHandle(Arc::new(Mutex::new(Sha256::new())));
+  let hasher = handle.clone();
+  let data = Belt::new(data.inspect_ok(move |bytes| {
+    hasher
+      .0
+      .lock()
+      .expect("digest mutex was poisoned")
+      .update(bytes);
+  }));
+  (data, handle)
+}
@@ -1,18 +1,28 @@
-use std::path::PathBuf;
+use std::{
+  collections::HashSet,
+  path::{Path, PathBuf},
+};
 
 use belt::Belt;
+use futures::TryStreamExt;
 use metrics_types::compute::ComputeUsageEvent;
 use miette::{Context, IntoDiagnostic};
 use models::{
-  CompressionStatus, Entry, FileSize, NarAuthenticityData, NarStorageData,
-  RecordId, model::Model,
+  CAHash, Cache, CompressionAlgorithm, CompressionStatus, Entry, FileSize,
+  NarAuthenticityData, NarIntrensicData, NarStorageData, RecordId, Signature,
+  Store, StorePath, model::Model, nix_compat::nixbase32,
 };
 use serde::{Deserialize, Serialize};
-use storage::BlobKey;
+use storage::{BlobKey, BlobStorage};
 use tracing::{Instrument, info_span};
 
-use super::plan::UploadPlan;
-use crate::DomainService;
+use super::{digest::digesting, plan::UploadPlan, tee::tee};
+use crate::{
+  DomainService,
+  compression::{compress, decompress},
+  nar_listing::listing_storage_path,
+  signing::{verify_with_keys, verify_with_trusted_keys},
+};
 
 /// The response struct for the
 /// [`execute_upload`](DomainService::execute_upload) fn.
@@ -37,6 +47,18 @@ pub enum UploadExecutionError {
   /// Failed to validate NAR.
   #[error("Failed to validate NAR: {0}")]
   NarValidationError(#[from] owl::InterrogatorError),
+  /// The NAR hash claimed by the uploader does not match the NAR.
+  #[error("The claimed NAR hash does not match the NAR")]
+  NarHashMismatch,
+  /// The NAR size claimed by the uploader does not match the NAR.
+  #[error("The claimed NAR size does not match the NAR")]
+  NarSizeMismatch,
+  /// A reference claimed by the uploader was not found in the NAR.
+  #[error("The claimed reference \"{0}\" was not found in the NAR")]
+  ReferenceNotFound(StorePath<String>),
+  /// A supplied signature did not verify against the NAR's fingerprint.
+  #[error("The signature by key \"{0}\" is invalid for this NAR")]
+  InvalidSignature(String),
   /// Some other internal error.
   #[error("Unexpected error: {0}")]
   InternalError(miette::Report),
@@ -44,6 +66,10 @@ pub enum UploadExecutionError {
 
 impl DomainService {
   /// Uploads a payload to storage, creates an entry, and adds it to a cache.
+  ///
+  /// If the NAR is already in the target store, the existing blob is shared
+  /// with the new entry rather than being stored again. New blobs are keyed
+  /// by NAR hash, and are deleted again if the upload fails.
   #[tracing::instrument(skip(self, plan), fields(plan.store_path))]
   pub async fn execute_upload(
     &self,
@@ -51,63 +77,190 @@ impl DomainService {
   ) -> Result<UploadResponse, UploadExecutionError> {
     let entry_id = RecordId::new();
 
-    // WARNING: buffer all the data right now because we need it to validate
-    // the NAR and to upload to storage
-    let big_terrible_buffer = plan
-      .nar_contents
-      .collect_bytes()
-      .instrument(info_span!("collect_big_terrible_buffer"))
-      .await
-      .map_err(UploadExecutionError::InputDataError)?;
-    let byte_count = big_terrible_buffer.len();
-
-    // validate the NAR and gather intrensic data
-    let nar_interrogator = owl::NarInterrogator;
-    let mut nar_intrensic_data = nar_interrogator
-      .interrogate(Belt::new_from_bytes(big_terrible_buffer.clone()))
-      .await
-      .map_err(UploadExecutionError::NarValidationError)?;
-
-    // remove any self-reference from the intrensic data
-    let removed_self_reference =
-      nar_intrensic_data.references.remove(&plan.store_path);
-    if !removed_self_reference {
-      tracing::warn!("no self-reference found in entry {entry_id}");
-    }
-
     let store_client = crate::storage_glue::storage_creds_to_blob_storage(
-      plan.target_store.credentials,
+      plan.target_store.credentials.clone(),
     )
     .await
     .context("failed to create storage client for store")
     .map_err(UploadExecutionError::InternalError)?;
 
-    let storage_path = PathBuf::from(plan.store_path.to_string());
-    let storage_key = BlobKey::new(storage_path.clone().to_string_lossy());
-    store_client
-      .put_stream(
-        &storage_key,
-        Box::pin(Belt::new_from_bytes(big_terrible_buffer)),
-        storage::UploadOptions { overwrite: true },
-      )
-      .await?;
-    let metadata = store_client.head(&storage_key).await?.ok_or(
-      UploadExecutionError::InternalError(miette::miette!(
-        "uploaded file does not exist"
-      )),
-    )?;
-    let file_size = FileSize::new(metadata.size);
-
-    let compression_status =
-      CompressionStatus::Uncompressed { size: file_size };
-
-    let nar_storage_data = NarStorageData {
-      store: plan.target_store.id,
-      storage_path,
-      compression_status,
+    // like Nix, only the claimed references and the path itself are searched
+    // for when references are claimed. unclaimed references are discovered
+    // heuristically, so clients that know them should claim them.
+    let interrogator = match &plan.references {
+      Some(references) => owl::NarInterrogator::new(plan.store_dir.clone())
+        .with_candidates(
+          references.iter().cloned().chain([plan.store_path.clone()]),
+        ),
+      None => owl::NarInterrogator::new(plan.store_dir.clone()),
     };
 
-    let nar_authenticity_data = NarAuthenticityData::default();
+    let claims = NarClaims {
+      store_path: &plan.store_path,
+      nar_hash:   plan.nar_hash,
+      nar_size:   plan.nar_size,
+      references: plan.references.as_ref(),
+    };
+
+    // `new_blob` is the path of a blob written for this upload, which is
+    // deleted again if the upload fails
+    let (mut nar_intrensic_data, listing, nar_storage_data, new_blob) =
+      match plan.existing_blob {
+        // the content is already in the store, so it only needs to be
+        // interrogated
+        Some(existing_blob) => {
+          let nar_contents = match plan.compression {
+            Some(algorithm) => decompress(plan.nar_contents, algorithm),
+            None => plan.nar_contents,
+          };
+          let (nar_intrensic_data, listing) = interrogator
+            .interrogate_with_listing(nar_contents, plan.ca_hash)
+            .await
+            .map_err(UploadExecutionError::NarValidationError)?;
+          verify_claims(&claims, &nar_intrensic_data)?;
+          (nar_intrensic_data, listing, existing_blob, None)
+        }
+        None => {
+          let store_compression =
+            plan.target_store.config.compression.algorithm();
+
+          // blobs are keyed by NAR hash, so if it's claimed the blob is
+          // written straight to its key
+          let storage_path = match plan.nar_hash {
+            Some(nar_hash) => {
+              let blob_path = blob_storage_path(&nar_hash, store_compression);
+              match blob_path_is_free(&store_client, &blob_path).await? {
+                true => blob_path,
+                false => fallback_blob_storage_path(entry_id),
+              }
+            }
+            None => fallback_blob_storage_path(entry_id),
+          };
+          let (nar_intrensic_data, listing, new_blob) = match plan.staged_blob {
+            // the data is already stored as the store would store it
+            Some(staged_blob) if plan.compression == store_compression => {
+              interrogate_blob(
+                &store_client,
+                &plan.target_store,
+                staged_blob,
+                plan.nar_contents,
+                plan.compression,
+                &interrogator,
+                plan.ca_hash,
+              )
+              .await?
+            }
+            _ => {
+              write_blob(
+                &store_client,
+                &plan.target_store,
+                storage_path,
+                plan.nar_contents,
+                plan.compression,
+                &interrogator,
+                plan.ca_hash,
+              )
+              .await?
+            }
+          };
+
+          if let Err(e) = verify_claims(&claims, &nar_intrensic_data) {
+            delete_blob(&store_client, &new_blob.storage_path).await;
+            return Err(e);
+          }
+
+          // the same content may have been stored since the upload was
+          // planned, or without a claimed NAR hash, so share the existing
+          // blob if there is one
+          let existing_blob = match self
+            .meta
+            .fetch_entry_by_store_id_and_nar_hash(
+              plan.target_store.id,
+              &nar_intrensic_data.nar_hash,
+            )
+            .await
+            .into_diagnostic()
+            .context(
+              "failed to search for existing blobs by store and NAR hash",
+            )
+            .map_err(UploadExecutionError::InternalError)
+          {
+            Ok(entry) => entry.map(|e| e.storage_data),
+            Err(e) => {
+              delete_blob(&store_client, &new_blob.storage_path).await;
+              return Err(e);
+            }
+          };
+          match existing_blob {
+            Some(existing_blob) => {
+              // a concurrent upload of the same NAR may have written the same
+              // key, which is then the existing blob
+              if existing_blob.storage_path != new_blob.storage_path {
+                delete_blob(&store_client, &new_blob.storage_path).await;
+              }
+              (nar_intrensic_data, listing, existing_blob, None)
+            }
+            None => {
+              let new_blob = match place_blob(
+                &store_client,
+                &plan.target_store,
+                new_blob.clone(),
+                &nar_intrensic_data.nar_hash,
+                entry_id,
+              )
+              .await
+              {
+                Ok(new_blob) => new_blob,
+                Err(e) => {
+                  delete_blob(&store_client, &new_blob.storage_path).await;
+                  return Err(e);
+                }
+              };
+              let new_blob_path = new_blob.storage_path.clone();
+              (nar_intrensic_data, listing, new_blob, Some(new_blob_path))
+            }
+          }
+        }
+      };
+
+    // the claimed references were all found in the NAR, and are what any
+    // supplied signatures were made over, so they take precedence
+    if let Some(references) = plan.references {
+      nar_intrensic_data.references = references;
+    }
+
+    // any self-reference is kept, since like the other references it's part
+    // of the fingerprint Nix signs, and of the narinfo clients verify
+    let byte_count = nar_intrensic_data.nar_size.inner();
+
+    let fingerprint =
+      nar_intrensic_data.fingerprint(&plan.store_dir, &plan.store_path);
+    let signatures = match verify_signatures(
+      &plan.caches,
+      &plan.upstream_public_keys,
+      &plan.signatures,
+      &fingerprint,
+    ) {
+      // sign the entry with the keys of the caches it's being added to
+      Ok(()) => self
+        .sign_for_caches(&plan.caches, &fingerprint)
+        .await
+        .context("failed to sign entry")
+        .map_err(UploadExecutionError::InternalError),
+      Err(e) => Err(e),
+    };
+    let mut signatures = match signatures {
+      Ok(signatures) => signatures,
+      Err(e) => {
+        discard_new_blob(&store_client, new_blob.as_deref()).await;
+        return Err(e);
+      }
+    };
+    signatures.extend(plan.signatures);
+    let nar_authenticity_data = NarAuthenticityData {
+      signatures,
+      unverified_signatures: plan.unverified_signatures,
+    };
 
     // insert entry
     let entry = Entry {
@@ -115,22 +268,37 @@ impl DomainService {
       org:               plan.org_id,
       caches:            plan.caches.iter().map(Model::id).collect(),
       store_path:        plan.store_path,
+      store_dir:         plan.store_dir,
       intrensic_data:    nar_intrensic_data,
       storage_data:      nar_storage_data,
       authenticity_data: nar_authenticity_data,
       deriver_data:      plan.deriver_data,
     };
-    self
+    let created = self
       .mutate
       .create_entry(&entry)
       .await
       .into_diagnostic()
       .context("failed to create entry")
-      .map_err(UploadExecutionError::InternalError)?;
+      .map_err(UploadExecutionError::InternalError);
+    if let Err(e) = created {
+      discard_new_blob(&store_client, new_blob.as_deref()).await;
+      return Err(e);
+    }
+    self.lookups.invalidate_entry(&entry);
+
+    // the listing is stored alongside the blob once the entry exists. a shared
+    // blob keeps the listing it has, and only gets one if it was stored before
+    // listings were generated.
+    write_listing(
+      &store_client,
+      &entry.storage_data.storage_path,
+      &listing,
+      new_blob.is_none(),
+    )
+    .await;
 
-    let compute_event = plan
-      .compute_event
-      .stamp_with_now(entry_id, byte_count.try_into().unwrap());
+    let compute_event = plan.compute_event.stamp_with_now(entry_id, byte_count);
 
     Ok(UploadResponse {
       entry_id,
@@ -138,3 +306,433 @@ impl DomainService {
     })
   }
 }
+
+/// The uploader's claims about a NAR.
+struct NarClaims<'a> {
+  store_path: &'a StorePath<String>,
+  nar_hash:   Option<[u8; 32]>,
+  nar_size:   Option<FileSize>,
+  references: Option<&'a HashSet<StorePath<String>>>,
+}
+
+/// Checks the uploader's claims about a NAR against its interrogated data.
+fn verify_claims(
+  claims: &NarClaims<'_>,
+  nar_intrensic_data: &NarIntrensicData,
+) -> Result<(), UploadExecutionError> {
+  if claims
+    .nar_hash
+    .is_some_and(|h| h != nar_intrensic_data.nar_hash)
+  {
+    return Err(UploadExecutionError::NarHashMismatch);
+  }
+  if claims
+    .nar_size
+    .is_some_and(|s| s != nar_intrensic_data.nar_size)
+  {
+    return Err(UploadExecutionError::NarSizeMismatch);
+  }
+
+  // references are compared by digest, since discovered paths may have
+  // picked up trailing characters after their names
+  if let Some(references) = claims.references {
+    let scanned_digests = nar_intrensic_data
+      .references
+      .iter()
+      .map(|r| *r.digest())
+      .collect::<HashSet<_>>();
+    if let Some(missing) = references.iter().find(|r| {
+      // a NAR doesn't necessarily contain its own path
+      *r != claims.store_path && !scanned_digests.contains(r.digest())
+    }) {
+      return Err(UploadExecutionError::ReferenceNotFound(missing.clone()));
+    }
+  }
+
+  Ok(())
+}
+
+/// Checks that every supplied signature verifies against the fingerprint with
+/// a key trusted by one of the caches, or of the upstream the NAR came from.
+fn verify_signatures(
+  caches: &[Cache],
+  upstream_public_keys: &[String],
+  signatures: &[Signature<String>],
+  fingerprint: &str,
+) -> Result<(), UploadExecutionError> {
+  match signatures.iter().find(|s| {
+    !verify_with_trusted_keys(caches, s, fingerprint)
+      && !verify_with_keys(upstream_public_keys, s, fingerprint)
+  }) {
+    Some(signature) => Err(UploadExecutionError::InvalidSignature(
+      signature.name().clone(),
+    )),
+    None => Ok(()),
+  }
+}
+
+/// Streams a NAR into a new blob in the store, interrogating it on the way.
+///
+/// If the NAR was uploaded with the compression the store uses, it's stored
+/// as uploaded. Otherwise it's decompressed and re-encoded as the store is
+/// configured.
+async fn write_blob(
+  store_client: &BlobStorage,
+  store: &Store,
+  storage_path: PathBuf,
+  nar_contents: Belt,
+  upload_compression: Option<CompressionAlgorithm>,
+  interrogator: &owl::NarInterrogator,
+  ca_hash: Option<CAHash>,
+) -> Result<
+  (NarIntrensicData, owl::NarListing, NarStorageData),
+  UploadExecutionError,
+> {
+  let storage_key = BlobKey::new(storage_path.to_string_lossy());
+  let compression_algorithm = store.config.compression.algorithm();
+
+  // stream the data to storage and through the interrogator at the same
+  // time. if interrogation fails, its half of the tee is dropped, which
+  // fails the storage write as well.
+  let (interrogation_belt, storage_belt) = match upload_compression {
+    Some(algorithm) if compression_algorithm == Some(algorithm) => {
+      let (interrogation_belt, storage_belt) = tee(nar_contents);
+      (decompress(interrogation_belt, algorithm), storage_belt)
+    }
+    _ => {
+      let nar_contents = match upload_compression {
+        Some(algorithm) => decompress(nar_contents, algorithm),
+        None => nar_contents,
+      };
+      let (interrogation_belt, storage_belt) = tee(nar_contents);
+      let storage_belt = match compression_algorithm {
+        Some(algorithm) => compress(storage_belt, algorithm),
+        None => storage_belt,
+      };
+      (interrogation_belt, storage_belt)
+    }
+  };
+  let (storage_belt, stored_digest) = digesting(storage_belt);
+  let (interrogation_result, storage_result) = tokio::join!(
+    interrogator.interrogate_with_listing(interrogation_belt, ca_hash),
+    store_client
+      .put_stream(
+        &storage_key,
+        Box::pin(storage_belt),
+        storage::UploadOptions { overwrite: true },
+      )
+      .instrument(info_span!("stream_nar_to_storage")),
+  );
+  // the blob may have been written in full before the NAR was rejected
+  if interrogation_result.is_err() || storage_result.is_err() {
+    delete_blob(store_client, &storage_path).await;
+  }
+  let (nar_intrensic_data, listing) =
+    interrogation_result.map_err(UploadExecutionError::NarValidationError)?;
+  storage_result?;
+
+  let nar_storage_data = match stored_blob_data(
+    store_client,
+    store,
+    storage_path.clone(),
+    &nar_intrensic_data,
+    stored_digest.finalize(),
+  )
+  .await
+  {
+    Ok(nar_storage_data) => nar_storage_data,
+    Err(e) => {
+      delete_blob(store_client, &storage_path).await;
+      return Err(e);
+    }
+  };
+
+  Ok((nar_intrensic_data, listing, nar_storage_data))
+}
+
+/// Interrogates a NAR which is already stored in a blob as the store would
+/// store it, reading the NAR from the blob.
+async fn interrogate_blob(
+  store_client: &BlobStorage,
+  store: &Store,
+  storage_path: PathBuf,
+  nar_contents: Belt,
+  compression: Option<CompressionAlgorithm>,
+  interrogator: &owl::NarInterrogator,
+  ca_hash: Option<CAHash>,
+) -> Result<
+  (NarIntrensicData, owl::NarListing, NarStorageData),
+  UploadExecutionError,
+> {
+  // the stored data is hashed in full, even past where the NAR ends
+  let (interrogation_belt, stored_belt) = tee(nar_contents);
+  let interrogation_belt = match compression {
+    Some(algorithm) => decompress(interrogation_belt, algorithm),
+    None => interrogation_belt,
+  };
+  let (stored_belt, stored_digest) = digesting(stored_belt);
+  let (interrogation_result, stored_result) = tokio::join!(
+    interrogator.interrogate_with_listing(interrogation_belt, ca_hash),
+    stored_belt.try_for_each(|_| futures::future::ok(())),
+  );
+  let (nar_intrensic_data, listing) =
+    interrogation_result.map_err(UploadExecutionError::NarValidationError)?;
+  stored_result?;
+
+  let nar_storage_data = stored_blob_data(
+    store_client,
+    store,
+    storage_path,
+    &nar_intrensic_data,
+    stored_digest.finalize(),
+  )
+  .await?;
+
+  Ok((nar_intrensic_data, listing, nar_storage_data))
+}
+
+/// Describes a stored blob, given the hash of its data.
+async fn stored_blob_data(
+  store_client: &BlobStorage,
+  store: &Store,
+  storage_path: PathBuf,
+  nar_intrensic_data: &NarIntrensicData,
+  stored_hash: [u8; 32],
+) -> Result<NarStorageData, UploadExecutionError> {
+  let storage_key = BlobKey::new(storage_path.to_string_lossy());
+  let metadata = store_client.head(&storage_key).await?.ok_or(
+    UploadExecutionError::InternalError(miette::miette!(
+      "uploaded file does not exist"
+    )),
+  )?;
+  let stored_size = FileSize::new(metadata.size);
+
+  let compression_status = match store.config.compression.algorithm() {
+    Some(algorithm) => CompressionStatus::Compressed {
+      compressed_size: stored_size,
+      uncompressed_size: nar_intrensic_data.nar_size,
+      compressed_hash: stored_hash,
+      algorithm,
+    },
+    None => CompressionStatus::Uncompressed { size: stored_size },
+  };
+
+  Ok(NarStorageData {
+    store: store.id,
+    storage_path,
+    compression_status,
+  })
+}
+
+/// Stores a NAR's listing next to its blob, unless it's a shared blob which
+/// already has one. Failures are only logged, since the listing is only needed
+/// for browsing the NAR.
+async fn write_listing(
+  store_client: &BlobStorage,
+  storage_path: &Path,
+  listing: &owl::NarListing,
+  shared_blob: bool,
+) {
+  let storage_key = BlobKey::new(listing_storage_path(storage_path));
+  if shared_blob {
+    match store_client.head(&storage_key).await {
+      Ok(Some(_)) => return,
+      Ok(None) => (),
+      Err(e) => {
+        tracing::warn!(?storage_path, "failed to check for NAR listing: {e}");
+        return;
+      }
+    }
+  }
+
+  let data = Belt::new_from_bytes(listing.to_json().into());
+  if let Err(e) = store_client
+    .put_stream(&storage_key, Box::pin(data), storage::UploadOptions {
+      overwrite: true,
+    })
+    .await
+  {
+    tracing::warn!(?storage_path, "failed to store NAR listing: {e}");
+  }
+}
+
+/// The path within a store where a blob is keyed by its NAR hash and its
+/// compression.
+fn blob_storage_path(
+  nar_hash: &[u8; 32],
+  compression: Option<CompressionAlgorithm>,
+) -> PathBuf {
+  let extension = match compression {
+    Some(algorithm) => format!(".{}", algorithm.nix_extension()),
+    None => String::new(),
+  };
+  PathBuf::from(format!(
+    "nar/{}.nar{extension}",
+    nixbase32::encode(nar_hash)
+  ))
+}
+
+/// The path within a store where a blob is keyed by its entry's ID, for when
+/// its NAR hash isn't known yet, or its NAR hash key is taken by a blob which
+/// may still be being written or deleted.
+fn fallback_blob_storage_path(entry_id: RecordId<Entry>) -> PathBuf {
+  PathBuf::from(format!("nar/{entry_id}"))
+}
+
+/// Checks whether no blob is stored at a path.
+async fn blob_path_is_free(
+  store_client: &BlobStorage,
+  storage_path: &Path,
+) -> Result<bool, UploadExecutionError> {
+  let storage_key = BlobKey::new(storage_path.to_string_lossy());
+  Ok(store_client.head(&storage_key).await?.is_none())
+}
+
+/// Moves a new blob to its NAR hash key if it isn't there already and the key
+/// is free. A blob staged outside of the NAR blobs is moved under its entry's
+/// ID otherwise.
+async fn place_blob(
+  store_client: &BlobStorage,
+  store: &Store,
+  new_blob: NarStorageData,
+  nar_hash: &[u8; 32],
+  entry_id: RecordId<Entry>,
+) -> Result<NarStorageData, UploadExecutionError> {
+  let blob_path =
+    blob_storage_path(nar_hash, new_blob.compression_status.algorithm());
+  let fallback_path = fallback_blob_storage_path(entry_id);
+  if new_blob.storage_path == blob_path {
+    return Ok(new_blob);
+  }
+
+  let storage_path = match blob_path_is_free(store_client, &blob_path).await? {
+    true => blob_path,
+    false if new_blob.storage_path == fallback_path => return Ok(new_blob),
+    false => fallback_path,
+  };
+  crate::storage_glue::rename_blob(
+    &store.credentials,
+    store_client,
+    &new_blob.storage_path.to_string_lossy(),
+    &storage_path.to_string_lossy(),
+  )
+  .await
+  .context("failed to move blob to its key")
+  .map_err(UploadExecutionError::InternalError)?;
+
+  Ok(NarStorageData {
+    storage_path,
+    ..new_blob
+  })
+}
+
+/// Deletes the blob written for an upload which failed, if one was.
+async fn discard_new_blob(store_client: &BlobStorage, new_blob: Option<&Path>) {
+  if let Some(new_blob) = new_blob {
+    delete_blob(store_client, new_blob).await;
+  }
+}
+
+/// Deletes a blob which turned out not to be needed, and its listing if it has
+/// one. Failures are only logged, since the blob is unreferenced either way.
+async fn delete_blob(store_client: &BlobStorage, storage_path: &Path) {
+  let keys = [
+    BlobKey::new(storage_path.to_string_lossy()),
+    BlobKey::new(listing_storage_path(storage_path)),
+  ];
+  for key in keys {
+    let result = match store_client.head(&key).await {
+      Ok(Some(_)) => store_client.delete(&key).await,
+      Ok(None) => Ok(()),
+      Err(e) => Err(e),
+    };
+    if let Err(e) = result {
+      tracing::warn!(?storage_path, "failed to delete unneeded blob: {e}");
+    }
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use std::collections::HashSet;
+
+  use models::{ENTRY_INDEX_VERSION, EntityName, StoreDir, Visibility};
+
+  use super::*;
+
+  const BAT: &str = "ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0";
+  const GLIBC: &str = "4yz8qa58nmysad5w88rgdhq15rkssqr6-glibc-2.40";
+  const NAR_HASH: &str = "1ssjv71gggikyp52k4xhp96d3w120k49qd2xfkcl8vvi5z0wwnya";
+  /// The public half of the secret key `test-1:DYAl56fNhk2XoMVfpkAkt0+hO+zUd8
+  /// tBU5w7GvhdJdaXNfC5Ljm1tOoQYuKsrP6scINw54ZgTNEr8YwpMxlEwg==`.
+  const PUBLIC_KEY: &str =
+    "test-1:lzXwuS45tbTqEGLirKz+rHCDcOeGYEzRK/GMKTMZRMI=";
+  /// The signature `nix store sign` makes with that key for bat, which
+  /// references itself and glibc.
+  const SIGNATURE: &str = concat!(
+    "test-1:ApL4PGlvrpsostgD/6Rxqjimg+zSk0w4iESO1sGmYIWOxwAzRuwaSrH1YCG7D0hf",
+    "uAx7GvVgxmKCQ46zUqL0BQ=="
+  );
+
+  fn cache_trusting(public_key: &str) -> Cache {
+    Cache {
+      id: RecordId::new(),
+      org: RecordId::new(),
+      name: EntityName::new("albert"),
+      visibility: Visibility::Public,
+      signing_key: None,
+      trusted_public_keys: vec![public_key.to_owned()],
+      require_trusted_signature: true,
+      default_store: None,
+      upstreams: vec![],
+      nix_cache_info: Default::default(),
+      store_dir: Default::default(),
+      entry_index_version: ENTRY_INDEX_VERSION,
+    }
+  }
+
+  fn bat_data(references: &[&str]) -> NarIntrensicData {
+    NarIntrensicData {
+      nar_hash:   nixbase32::decode_fixed(NAR_HASH.as_bytes()).unwrap(),
+      nar_size:   FileSize::new(1234),
+      references: references
+        .iter()
+        .map(|r| StorePath::from_bytes(r.as_bytes()).unwrap())
+        .collect::<HashSet<_>>(),
+      ca_hash:    None,
+      debug_info: Vec::new(),
+    }
+  }
+
+  #[test]
+  fn blobs_are_keyed_by_nar_hash_and_compression() {
+    let nar_hash = nixbase32::decode_fixed(NAR_HASH.as_bytes()).unwrap();
+    assert_eq!(
+      blob_storage_path(&nar_hash, None),
+      PathBuf::from(format!("nar/{NAR_HASH}.nar"))
+    );
+    assert_eq!(
+      blob_storage_path(&nar_hash, Some(CompressionAlgorithm::Zstd)),
+      PathBuf::from(format!("nar/{NAR_HASH}.nar.zst"))
+    );
+  }
+
+  #[test]
+  fn nix_signatures_of_self_referencing_paths_verify() {
+    let bat = StorePath::from_bytes(BAT.as_bytes()).unwrap();
+    let caches = [cache_trusting(PUBLIC_KEY)];
+    let signatures = [Signature::<&str>::parse(SIGNATURE).unwrap().to_owned()];
+
+    let fingerprint =
+      bat_data(&[BAT, GLIBC]).fingerprint(&StoreDir::default(), &bat);
+    assert!(verify_signatures(&caches, &[], &signatures, &fingerprint).is_ok());
+
+    // without the self-reference, the fingerprint isn't what Nix signed
+    let fingerprint =
+      bat_data(&[GLIBC]).fingerprint(&StoreDir::default(), &bat);
+    assert!(matches!(
+      verify_signatures(&caches, &[], &signatures, &fingerprint),
+      Err(UploadExecutionError::InvalidSignature(name)) if name == "test-1"
+    ));
+  }
+}
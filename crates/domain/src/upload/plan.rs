@@ -1,33 +1,60 @@
+use std::{collections::HashSet, path::PathBuf};
+
 use belt::Belt;
 use meta_domain::SearchByUserError;
 use metrics_types::compute::UnstampedComputeUsageEvent;
 use miette::{Context, IntoDiagnostic, miette};
 use models::{
-  Cache, Digest, EntityName, Entry, NarDeriverData, Org, RecordId, Store,
+  CAHash, Cache, CompressionAlgorithm, Digest, EntityName, Entry, FileSize,
+  NarDeriverData, NarStorageData, Org, RecordId, Signature, Store, StoreDir,
   StorePath,
 };
 
 use super::UploadRequest;
-use crate::DomainService;
+use crate::{DomainService, signing::cache_trusts_key_name};
 
 /// The upload plan produced by [`plan_upload`](DomainService::plan_upload)
 /// fn.
 #[derive(Debug)]
 pub struct UploadPlan {
   /// The data to be uploaded.
-  pub(crate) nar_contents:  Belt,
+  pub(crate) nar_contents:          Belt,
+  /// The compression of the uploaded data.
+  pub(crate) compression:           Option<CompressionAlgorithm>,
   /// The store path of the entry.
-  pub(crate) store_path:    StorePath<String>,
+  pub(crate) store_path:            StorePath<String>,
   /// The store to store the data in.
-  pub(crate) target_store:  Store,
+  pub(crate) target_store:          Store,
   /// The org that everything is scoped to.
-  pub(crate) org_id:        RecordId<Org>,
+  pub(crate) org_id:                RecordId<Org>,
   /// The caches for the entry to be registered in.
-  pub(crate) caches:        Vec<Cache>,
+  pub(crate) caches:                Vec<Cache>,
   /// Data about the NAR's deriver
-  pub(crate) deriver_data:  NarDeriverData,
+  pub(crate) deriver_data:          NarDeriverData,
+  /// The claimed content-addressed hash of the entry.
+  pub(crate) ca_hash:               Option<CAHash>,
+  /// The NAR hash claimed by the uploader.
+  pub(crate) nar_hash:              Option<[u8; 32]>,
+  /// The NAR size claimed by the uploader.
+  pub(crate) nar_size:              Option<FileSize>,
+  /// The references claimed by the uploader.
+  pub(crate) references:            Option<HashSet<StorePath<String>>>,
+  /// An existing blob in the target store with the claimed NAR hash.
+  pub(crate) existing_blob:         Option<NarStorageData>,
+  /// A blob in the target store which the uploaded data was read from, like
+  /// one a multipart upload assembled. It's moved into place rather than
+  /// written again if it's compressed as the store compresses blobs.
+  pub(crate) staged_blob:           Option<PathBuf>,
+  /// Signatures supplied by the uploader, from keys trusted by the caches.
+  pub(crate) signatures:            Vec<Signature<String>>,
+  /// Signatures supplied by the uploader from keys no cache trusts, which
+  /// are kept without being verified.
+  pub(crate) unverified_signatures: Vec<Signature<String>>,
+  /// Public keys of the upstream the NAR was fetched from, which supplied
+  /// signatures may verify against as well.
+  pub(crate) upstream_public_keys:  Vec<String>,
   /// The compute event to be sent.
-  pub(crate) compute_event: UnstampedComputeUsageEvent,
+  pub(crate) compute_event:         UnstampedComputeUsageEvent,
 }
 
 /// The error enum produced by [`plan_upload`](DomainService::plan_upload) fn.
@@ -63,6 +90,12 @@ pub enum UploadPlanningError {
     /// The cache that contains the duplicate.
     cache: RecordId<Cache>,
   },
+  /// The caches don't share a store directory.
+  #[error("The caches have different store directories: {0:?}")]
+  MismatchedStoreDirs(Vec<StoreDir>),
+  /// A cache requires a signature from a trusted key but none was supplied.
+  #[error("The cache \"{0}\" requires a signature from a trusted key")]
+  MissingTrustedSignature(EntityName),
   /// Some other internal error.
   #[error("Unexpected error: {0}")]
   InternalError(miette::Report),
@@ -143,6 +176,18 @@ impl DomainService {
       return Err(UploadPlanningError::Unauthorized);
     }
 
+    // the entry's references are scanned for in the caches' store directory,
+    // so they must all agree on it
+    let mut store_dirs = caches
+      .iter()
+      .map(|c| c.store_dir.clone())
+      .collect::<Vec<_>>();
+    store_dirs.dedup();
+    if store_dirs.len() > 1 {
+      return Err(UploadPlanningError::MismatchedStoreDirs(store_dirs));
+    }
+    let store_dir = store_dirs.pop().unwrap_or_default();
+
     // make sure no entry exists for this path and store
     let duplicate_entry_by_store = self
       .meta
@@ -177,19 +222,70 @@ impl DomainService {
       }
     }
 
+    // look for a blob in the target store which already has the claimed
+    // content, so it doesn't have to be written again
+    let existing_blob = match &req.nar_hash {
+      Some(nar_hash) => self
+        .meta
+        .fetch_entry_by_store_id_and_nar_hash(target_store.id, nar_hash)
+        .await
+        .into_diagnostic()
+        .context("failed to search for existing blobs by store and NAR hash")
+        .map_err(UploadPlanningError::InternalError)?
+        .map(|e| e.storage_data),
+      None => None,
+    };
+
+    // signatures from keys that a cache trusts are verified once the NAR hash
+    // is known. there's no way to verify the rest, so they're kept apart as
+    // unverified.
+    let (signatures, unverified_signatures): (Vec<_>, Vec<_>) = req
+      .signatures
+      .into_iter()
+      .partition(|s| caches.iter().any(|c| cache_trusts_key_name(c, s.name())));
+    for signature in &unverified_signatures {
+      tracing::debug!(
+        key = signature.name(),
+        "keeping signature from untrusted key unverified"
+      );
+    }
+
+    // make sure caches that require a trusted signature get one
+    if let Some(cache) = caches.iter().find(|c| {
+      c.require_trusted_signature
+        && !signatures
+          .iter()
+          .any(|s| cache_trusts_key_name(c, s.name()))
+    }) {
+      return Err(UploadPlanningError::MissingTrustedSignature(
+        cache.name.clone(),
+      ));
+    }
+
     let compute_event = UnstampedComputeUsageEvent {
-      entry_path: req.store_path.clone().to_absolute_path(),
+      entry_path: store_dir.absolute_path(&req.store_path),
       org_id,
       op_type: metrics_types::compute::OperationType::Upload,
     };
 
     Ok(UploadPlan {
       nar_contents: req.nar_contents,
+      compression: req.compression,
       store_path: req.store_path,
+      store_dir,
       target_store,
       org_id,
       caches,
       deriver_data: req.deriver_data,
+      ca_hash: req.ca_hash,
+      nar_hash: req.nar_hash,
+      nar_size: req.nar_size,
+      references: req.references,
+      existing_blob,
+      staged_blob: None,
+      signatures,
+      unverified_signatures,
+      upstream_public_keys: Vec::new(),
       compute_event,
     })
   }
@@ -0,0 +1,80 @@
+use std::io;
+
+use belt::Belt;
+use bytes::Bytes;
+use futures::StreamExt;
+use tokio::sync::mpsc;
+
+/// The number of chunks buffered for each branch of the tee.
+const TEE_CHANNEL_CAPACITY: usize = 16;
+
+/// Splits a [`Belt`] into two [`Belt`]s which both yield the full input.
+///
+/// Both branches are fed by a background task through bounded channels, so the
+/// slower consumer applies backpressure to the input and memory use stays
+/// bounded. Both branches are expected to be consumed to completion: if either
+/// branch is dropped early, the other branch yields an error and ends.
+pub(crate) fn tee(input: Belt) -> (Belt, Belt) {
+  let (a_tx, a_rx) = mpsc::channel(TEE_CHANNEL_CAPACITY);
+  let (b_tx, b_rx) = mpsc::channel(TEE_CHANNEL_CAPACITY);
+
+  tokio::spawn(async move {
+    let mut input = input;
+    while let Some(item) = input.next().await {
+      let (a_item, b_item) = match item {
+        Ok(bytes) => (Ok(bytes.clone()), Ok(bytes)),
+        Err(e) => (Err(duplicate_io_error(&e)), Err(e)),
+      };
+      let is_err = a_item.is_err();
+
+      let (a_res, b_res) = tokio::join!(a_tx.send(a_item), b_tx.send(b_item));
+      match (a_res, b_res) {
+        (Ok(()), Ok(())) => (),
+        (Err(_), Ok(())) => {
+          let _ = b_tx.send(Err(sibling_hung_up_error())).await;
+          return;
+        }
+        (Ok(()), Err(_)) => {
+          let _ = a_tx.send(Err(sibling_hung_up_error())).await;
+          return;
+        }
+        (Err(_), Err(_)) => return,
+      }
+
+      if is_err {
+        return;
+      }
+    }
+  });
+
+  (receiver_belt(a_rx), receiver_belt(b_rx))
+}
+
+fn receiver_belt(rx: mpsc::Receiver<io::Result<Bytes>>) -> Belt {
+  Belt::new(futures::stream::unfold(rx, |mut rx| async move {
+    rx.recv().await.map(|item| (item, rx))
+  }))
+}
+
+fn duplicate_io_error(e: &io::Error) -> io::Error {
+  io::Error::new(e.kind(), e.to_string())
+}
+
+fn sibling_hung_up_error() -> io::Error {
+  io::Error::other("the other consumer of the upload stream stopped early")
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[tokio::test]
+  async fn both_branches_yield_full_input() {
+    let input = Belt::new_from_bytes(Bytes::from_static(b"hello, world"));
+    let (a, b) = tee(input);
+
+    let (a, b) = tokio::join!(a.collect_bytes(), b.collect_bytes());
+    assert_eq!(a.unwrap(), Bytes::from_static(b"hello, world"));
+    assert_eq!(b.unwrap(), Bytes::from_static(b"hello, world"));
+  }
+}
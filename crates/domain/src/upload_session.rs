@@ -0,0 +1,717 @@
+//! Resumable upload session types and impl.
+//!
+//! A session is started with the same parameters as a normal upload. The NAR
+//! is then sent in numbered parts, which can be re-sent or resumed at any
+//! point until the session expires.
+//!
+//! In stores which support multipart uploads, the parts are sent to one, and
+//! finalizing the session has the store assemble them, so the NAR is only read
+//! back to be interrogated, and is then moved into place. Elsewhere, the parts
+//! are stored as blobs of their own, which finalizing streams in order through
+//! the normal upload pipeline. Either way, the session is deleted once its
+//! parts are assembled.
+//!
+//! Sessions which expire are swept periodically by a task started with
+//! [`spawn_upload_session_sweeper`](DomainService::spawn_upload_session_sweeper).
+
+use std::{collections::HashSet, sync::Arc};
+
+use belt::Belt;
+use bytes::{Bytes, BytesMut};
+use futures::{StreamExt, TryStreamExt};
+use miette::{Context, IntoDiagnostic, miette};
+use models::{
+  CAHash, CompressionAlgorithm, EntityName, FileSize, NarDeriverData, RecordId,
+  Signature, Store, StorePath, UploadSession, UploadSessionParams,
+  UploadSessionPart, User,
+};
+use storage::{BlobKey, BlobStorage, BlobStorageError};
+use time::{Duration, UtcDateTime};
+
+use crate::{
+  DomainService,
+  upload::{
+    UploadExecutionError, UploadPlanningError, UploadRequest, UploadResponse,
+  },
+};
+
+/// How long a session lives without receiving a part before it's considered
+/// stale and cleaned up.
+pub const UPLOAD_SESSION_TIMEOUT: Duration = Duration::hours(24);
+/// How often stale sessions are swept.
+const UPLOAD_SESSION_SWEEP_INTERVAL: std::time::Duration =
+  std::time::Duration::from_secs(60 * 60);
+/// The largest part which can be sent to a multipart upload. Parts are held in
+/// memory while they're sent.
+pub const MAX_MULTIPART_PART_SIZE: u64 = 256 * 1024 * 1024;
+/// The smallest part, other than the last, which can be sent to a multipart
+/// upload.
+pub const MIN_MULTIPART_PART_SIZE: u64 = 5 * 1024 * 1024;
+/// The highest part number which can be sent to a multipart upload.
+pub const MAX_MULTIPART_PART_NUMBER: u32 = 9_999;
+
+/// The request struct for the
+/// [`start_upload_session`](DomainService::start_upload_session) fn.
+#[derive(Debug)]
+pub struct StartUploadSessionRequest {
+  /// The uploading user's authentication.
+  pub auth:         RecordId<User>,
+  /// The name of the caches to register the entry in.
+  pub caches:       Vec<EntityName>,
+  /// The store to store the data in.
+  pub target_store: EntityName,
+  /// The store path of the entry.
+  pub store_path:   StorePath<String>,
+  /// Data about the NAR's deriver.
+  pub deriver_data: NarDeriverData,
+  /// The content-addressed hash of the entry, if it's content-addressed.
+  pub ca_hash:      Option<CAHash>,
+  /// The NAR hash claimed by the uploader.
+  pub nar_hash:     Option<[u8; 32]>,
+  /// The compression of the assembled parts, if they're compressed.
+  pub compression:  Option<CompressionAlgorithm>,
+  /// The references claimed by the uploader.
+  pub references:   Option<HashSet<StorePath<String>>>,
+  /// Signatures supplied by the uploader.
+  pub signatures:   Vec<Signature<String>>,
+}
+
+/// The error enum for upload session operations.
+#[derive(thiserror::Error, Debug)]
+pub enum UploadSessionError {
+  /// The user is unauthorized to access this session.
+  #[error("The user is unauthorized to access this upload session")]
+  Unauthorized,
+  /// The requested session was not found, or has expired.
+  #[error("The requested upload session was not found: {0}")]
+  SessionNotFound(RecordId<UploadSession>),
+  /// Parts are missing from the session.
+  #[error("The upload session is missing parts: {0:?}")]
+  MissingParts(Vec<u32>),
+  /// A part can't be sent to the store's multipart upload.
+  #[error("The part is invalid for this upload session: {0}")]
+  InvalidPart(String),
+  /// The upload could not be planned.
+  #[error(transparent)]
+  Planning(#[from] UploadPlanningError),
+  /// The upload could not be executed.
+  #[error(transparent)]
+  Execution(#[from] UploadExecutionError),
+  /// Failed to write to or read from storage.
+  #[error("Failed to access storage: {0}")]
+  StorageFailure(#[from] BlobStorageError),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+impl StartUploadSessionRequest {
+  fn into_upload_request(self, nar_contents: Belt) -> UploadRequest {
+    UploadRequest {
+      nar_contents,
+      compression: self.compression,
+      auth: self.auth,
+      caches: self.caches,
+      target_store: self.target_store,
+      store_path: self.store_path,
+      deriver_data: self.deriver_data,
+      ca_hash: self.ca_hash,
+      nar_hash: self.nar_hash,
+      nar_size: None,
+      references: self.references,
+      signatures: self.signatures,
+    }
+  }
+}
+
+/// The response struct for the
+/// [`upload_session`](DomainService::upload_session) fn.
+#[derive(Debug)]
+pub struct UploadSessionStatus {
+  /// The session.
+  pub session: UploadSession,
+  /// The parts received so far, in order of their numbers.
+  pub parts:   Vec<UploadSessionPart>,
+}
+
+impl DomainService {
+  /// Starts a resumable upload session.
+  ///
+  /// The upload is planned up front so that a session which could never be
+  /// finalized is rejected before any parts are sent.
+  #[tracing::instrument(skip(self))]
+  pub async fn start_upload_session(
+    &self,
+    req: StartUploadSessionRequest,
+  ) -> Result<UploadSession, UploadSessionError> {
+    let params = UploadSessionParams {
+      caches:       req.caches.clone(),
+      target_store: req.target_store.clone(),
+      store_path:   req.store_path.clone(),
+      deriver_data: req.deriver_data.clone(),
+      ca_hash:      req.ca_hash.clone(),
+      nar_hash:     req.nar_hash,
+      compression:  req.compression,
+      references:   req.references.clone(),
+      signatures:   req.signatures.clone(),
+    };
+    let auth = req.auth;
+
+    let plan = self
+      .plan_upload(req.into_upload_request(Belt::new_from_bytes(Bytes::new())))
+      .await?;
+    let store = plan.target_store.clone();
+    drop(plan);
+
+    let mut session = UploadSession {
+      id: RecordId::new(),
+      org: store.org,
+      owner: auth,
+      store: store.id,
+      params,
+      multipart_upload_id: None,
+      expires_at: UtcDateTime::now() + UPLOAD_SESSION_TIMEOUT,
+    };
+    session.multipart_upload_id = crate::storage_glue::start_multipart_upload(
+      &store.credentials,
+      &session.nar_storage_path().to_string_lossy(),
+    )
+    .await
+    .context("failed to start multipart upload")
+    .map_err(UploadSessionError::InternalError)?;
+
+    self
+      .mutate
+      .create_upload_session(&session)
+      .await
+      .into_diagnostic()
+      .context("failed to create upload session")
+      .map_err(UploadSessionError::InternalError)?;
+
+    Ok(session)
+  }
+
+  /// Fetches an upload session and its parts, e.g. to find which parts need to
+  /// be sent to resume it.
+  #[tracing::instrument(skip(self))]
+  pub async fn upload_session(
+    &self,
+    auth: RecordId<User>,
+    id: RecordId<UploadSession>,
+  ) -> Result<UploadSessionStatus, UploadSessionError> {
+    let session = self.fetch_owned_upload_session(auth, id).await?;
+    let parts = self.fetch_upload_session_parts(&session).await?;
+    Ok(UploadSessionStatus { session, parts })
+  }
+
+  /// Stores a part of an upload session, replacing any part with the same
+  /// number.
+  ///
+  /// Parts sent to a multipart upload are limited to
+  /// [`MAX_MULTIPART_PART_SIZE`] and numbered up to
+  /// [`MAX_MULTIPART_PART_NUMBER`], and every part but the last must be the
+  /// same size, of at least [`MIN_MULTIPART_PART_SIZE`].
+  #[tracing::instrument(skip(self, data))]
+  pub async fn upload_session_part(
+    &self,
+    auth: RecordId<User>,
+    id: RecordId<UploadSession>,
+    number: u32,
+    data: Belt,
+  ) -> Result<UploadSessionPart, UploadSessionError> {
+    let mut session = self.fetch_owned_upload_session(auth, id).await?;
+    let store = self.upload_session_store(&session).await?;
+
+    let (size, etag) = match &session.multipart_upload_id {
+      Some(upload_id) => {
+        if number > MAX_MULTIPART_PART_NUMBER {
+          return Err(UploadSessionError::InvalidPart(format!(
+            "part numbers are limited to {MAX_MULTIPART_PART_NUMBER}"
+          )));
+        }
+        let data = buffer_part(data).await?;
+        let size = data.len() as u64;
+        let etag = crate::storage_glue::put_multipart_part(
+          &store.credentials,
+          &session.nar_storage_path().to_string_lossy(),
+          upload_id,
+          number,
+          data,
+        )
+        .await
+        .map_err(UploadSessionError::InternalError)?;
+        (size, Some(etag))
+      }
+      None => {
+        let store_client = upload_session_store_client(&store).await?;
+        let key =
+          BlobKey::new(session.part_storage_path(number).to_string_lossy());
+        store_client
+          .put_stream(&key, Box::pin(data), storage::UploadOptions {
+            overwrite: true,
+          })
+          .await?;
+        let metadata = store_client.head(&key).await?.ok_or(
+          UploadSessionError::InternalError(miette!(
+            "uploaded part does not exist"
+          )),
+        )?;
+        (metadata.size, None)
+      }
+    };
+
+    // each part has its own record, so parts sent at the same time don't race
+    let existing_part = self
+      .meta
+      .fetch_upload_session_part_by_session_and_number(session.id, number)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch upload session part")
+      .map_err(UploadSessionError::InternalError)?;
+    let part = UploadSessionPart {
+      id: existing_part.as_ref().map_or_else(RecordId::new, |p| p.id),
+      session: session.id,
+      number,
+      size: FileSize::new(size),
+      etag,
+    };
+    match existing_part {
+      Some(_) => self.mutate.patch_upload_session_part(&part).await,
+      None => self
+        .mutate
+        .create_upload_session_part(&part)
+        .await
+        .map(|_| ()),
+    }
+    .into_diagnostic()
+    .context("failed to store upload session part")
+    .map_err(UploadSessionError::InternalError)?;
+
+    // concurrent parts all push the expiry out to about the same time, which
+    // is all that's patched
+    session.expires_at = UtcDateTime::now() + UPLOAD_SESSION_TIMEOUT;
+    self
+      .mutate
+      .patch_upload_session(&session)
+      .await
+      .into_diagnostic()
+      .context("failed to update upload session")
+      .map_err(UploadSessionError::InternalError)?;
+
+    Ok(part)
+  }
+
+  /// Finalizes an upload session, uploading its parts in order as one NAR.
+  ///
+  /// The session and its parts are deleted once the parts are assembled,
+  /// whether or not the upload succeeds, since a multipart upload can only be
+  /// completed once.
+  #[tracing::instrument(skip(self))]
+  pub async fn finalize_upload_session(
+    &self,
+    auth: RecordId<User>,
+    id: RecordId<UploadSession>,
+  ) -> Result<UploadResponse, UploadSessionError> {
+    let session = self.fetch_owned_upload_session(auth, id).await?;
+    let parts = self.fetch_upload_session_parts(&session).await?;
+
+    // parts must be numbered contiguously from zero
+    let last_part = parts.last().map(|p| p.number).unwrap_or(0);
+    let missing_parts = (0..=last_part)
+      .filter(|n| !parts.iter().any(|p| p.number == *n))
+      .collect::<Vec<_>>();
+    if !missing_parts.is_empty() {
+      return Err(UploadSessionError::MissingParts(missing_parts));
+    }
+    if session.multipart_upload_id.is_some() {
+      check_multipart_part_sizes(&parts)?;
+    }
+
+    let store = self.upload_session_store(&session).await?;
+    let store_client = Arc::new(upload_session_store_client(&store).await?);
+
+    // the data is only read once the upload is planned and the parts are
+    // assembled
+    let (nar_contents, staged_blob) = match session.multipart_upload_id {
+      Some(_) => {
+        let key = BlobKey::new(session.nar_storage_path().to_string_lossy());
+        let nar_client = Arc::clone(&store_client);
+        let nar_contents = Belt::new(
+          futures::stream::once(
+            async move { nar_client.get_stream(&key).await },
+          )
+          .try_flatten()
+          .map_err(BlobStorageError::into_io_error),
+        );
+        (nar_contents, Some(session.nar_storage_path()))
+      }
+      None => {
+        // stream each part in turn, only opening the next when the last is
+        // done
+        let part_keys = parts
+          .iter()
+          .map(|p| {
+            BlobKey::new(session.part_storage_path(p.number).to_string_lossy())
+          })
+          .collect::<Vec<_>>();
+        let parts_client = Arc::clone(&store_client);
+        let nar_contents = Belt::new(
+          futures::stream::iter(part_keys)
+            .then(move |key| {
+              let store_client = Arc::clone(&parts_client);
+              async move { store_client.get_stream(&key).await }
+            })
+            .try_flatten()
+            .map_err(BlobStorageError::into_io_error),
+        );
+        (nar_contents, None)
+      }
+    };
+
+    let params = session.params.clone();
+    let upload_req = UploadRequest {
+      nar_contents,
+      compression: params.compression,
+      auth,
+      caches: params.caches,
+      target_store: params.target_store,
+      store_path: params.store_path,
+      deriver_data: params.deriver_data,
+      ca_hash: params.ca_hash,
+      nar_hash: params.nar_hash,
+      nar_size: None,
+      references: params.references,
+      signatures: params.signatures,
+    };
+    let mut plan = self.plan_upload(upload_req).await?;
+    plan.staged_blob = staged_blob;
+
+    if let Some(upload_id) = &session.multipart_upload_id {
+      crate::storage_glue::complete_multipart_upload(
+        &store.credentials,
+        &session.nar_storage_path().to_string_lossy(),
+        upload_id,
+        parts
+          .iter()
+          .map(|p| (p.number, p.etag.clone().unwrap_or_default())),
+      )
+      .await
+      .context("failed to complete multipart upload")
+      .map_err(UploadSessionError::InternalError)?;
+    }
+
+    let result = self.execute_upload(plan).await;
+    self
+      .delete_upload_session(&session, &parts, &store, &store_client, true)
+      .await;
+
+    result.map_err(UploadSessionError::Execution)
+  }
+
+  /// Aborts an upload session, deleting it and its parts.
+  #[tracing::instrument(skip(self))]
+  pub async fn abort_upload_session(
+    &self,
+    auth: RecordId<User>,
+    id: RecordId<UploadSession>,
+  ) -> Result<(), UploadSessionError> {
+    let session = self.fetch_owned_upload_session(auth, id).await?;
+    let parts = self.fetch_upload_session_parts(&session).await?;
+    let store = self.upload_session_store(&session).await?;
+    let store_client = upload_session_store_client(&store).await?;
+    self
+      .delete_upload_session(&session, &parts, &store, &store_client, false)
+      .await;
+    Ok(())
+  }
+
+  /// Spawns a task which periodically deletes expired sessions and their
+  /// parts, so that abandoned sessions don't leak parts in stores which don't
+  /// get new sessions.
+  pub fn spawn_upload_session_sweeper(&self) {
+    let domain = self.clone();
+    tokio::spawn(async move {
+      let mut ticker = tokio::time::interval(UPLOAD_SESSION_SWEEP_INTERVAL);
+      loop {
+        ticker.tick().await;
+        domain.clean_up_stale_upload_sessions().await;
+      }
+    });
+  }
+
+  /// Fetches a session which belongs to the user and hasn't expired.
+  async fn fetch_owned_upload_session(
+    &self,
+    auth: RecordId<User>,
+    id: RecordId<UploadSession>,
+  ) -> Result<UploadSession, UploadSessionError> {
+    let session = self
+      .meta
+      .fetch_upload_session_by_id(id)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch upload session")
+      .map_err(UploadSessionError::InternalError)?
+      .ok_or(UploadSessionError::SessionNotFound(id))?;
+
+    if session.owner != auth {
+      return Err(UploadSessionError::Unauthorized);
+    }
+    if session.is_expired() {
+      return Err(UploadSessionError::SessionNotFound(id));
+    }
+
+    Ok(session)
+  }
+
+  /// Fetches the parts of a session, in order of their numbers.
+  async fn fetch_upload_session_parts(
+    &self,
+    session: &UploadSession,
+  ) -> Result<Vec<UploadSessionPart>, UploadSessionError> {
+    let mut parts = self
+      .meta
+      .fetch_upload_session_parts_by_session(session.id)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch upload session parts")
+      .map_err(UploadSessionError::InternalError)?;
+    parts.sort_unstable_by_key(|p| p.number);
+    Ok(parts)
+  }
+
+  /// Fetches the store of a session.
+  async fn upload_session_store(
+    &self,
+    session: &UploadSession,
+  ) -> Result<Store, UploadSessionError> {
+    self
+      .meta
+      .fetch_store_by_id(session.store)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch store")
+      .map_err(UploadSessionError::InternalError)?
+      .ok_or(miette!("store of upload session not found"))
+      .map_err(UploadSessionError::InternalError)
+  }
+
+  /// Deletes a session and its parts. Once a session's parts are assembled,
+  /// only what's left of the assembled NAR is deleted from the store. Failures
+  /// are only logged, since an orphaned part or session is harmless.
+  async fn delete_upload_session(
+    &self,
+    session: &UploadSession,
+    parts: &[UploadSessionPart],
+    store: &Store,
+    store_client: &BlobStorage,
+    assembled: bool,
+  ) {
+    match (&session.multipart_upload_id, assembled) {
+      (Some(upload_id), false) => {
+        if let Err(e) = crate::storage_glue::abort_multipart_upload(
+          &store.credentials,
+          &session.nar_storage_path().to_string_lossy(),
+          upload_id,
+        )
+        .await
+        {
+          tracing::warn!(
+            session = %session.id,
+            "failed to abort multipart upload: {e:?}"
+          );
+        }
+      }
+      (Some(_), true) => {
+        // the assembled NAR is moved away if it was kept
+        let key = BlobKey::new(session.nar_storage_path().to_string_lossy());
+        let result = match store_client.head(&key).await {
+          Ok(Some(_)) => store_client.delete(&key).await,
+          Ok(None) => Ok(()),
+          Err(e) => Err(e),
+        };
+        if let Err(e) = result {
+          tracing::warn!(
+            session = %session.id,
+            "failed to delete assembled upload session NAR: {e}"
+          );
+        }
+      }
+      (None, _) => {
+        for part in parts {
+          let key = BlobKey::new(
+            session.part_storage_path(part.number).to_string_lossy(),
+          );
+          if let Err(e) = store_client.delete(&key).await {
+            tracing::warn!(
+              session = %session.id,
+              part = part.number,
+              "failed to delete upload session part: {e}"
+            );
+          }
+        }
+      }
+    }
+
+    for part in parts {
+      if let Err(e) = self.mutate.delete_upload_session_part(part.id).await {
+        tracing::warn!(
+          session = %session.id,
+          part = part.number,
+          "failed to delete upload session part record: {e}"
+        );
+      }
+    }
+    if let Err(e) = self.mutate.delete_upload_session(session.id).await {
+      tracing::warn!(
+        session = %session.id,
+        "failed to delete upload session: {e}"
+      );
+    }
+  }
+
+  /// Deletes the expired sessions and their parts.
+  async fn clean_up_stale_upload_sessions(&self) {
+    let sessions = match self.meta.fetch_all_upload_sessions().await {
+      Ok(sessions) => sessions,
+      Err(e) => {
+        tracing::warn!("failed to fetch upload sessions to clean up: {e}");
+        return;
+      }
+    };
+
+    let stale = sessions
+      .into_iter()
+      .filter(UploadSession::is_expired)
+      .collect::<Vec<_>>();
+    if stale.is_empty() {
+      return;
+    }
+
+    tracing::info!(count = stale.len(), "cleaning up stale upload sessions");
+    for session in stale {
+      if let Err(e) = self.clean_up_upload_session(&session).await {
+        tracing::warn!(
+          session = %session.id,
+          "failed to clean up stale upload session: {e}"
+        );
+      }
+    }
+  }
+
+  /// Deletes a stale session and its parts.
+  async fn clean_up_upload_session(
+    &self,
+    session: &UploadSession,
+  ) -> Result<(), UploadSessionError> {
+    let parts = self.fetch_upload_session_parts(session).await?;
+    let store = self.upload_session_store(session).await?;
+    let store_client = upload_session_store_client(&store).await?;
+    self
+      .delete_upload_session(session, &parts, &store, &store_client, false)
+      .await;
+    Ok(())
+  }
+}
+
+/// Builds a client for the store of a session.
+async fn upload_session_store_client(
+  store: &Store,
+) -> Result<BlobStorage, UploadSessionError> {
+  crate::storage_glue::storage_creds_to_blob_storage(store.credentials.clone())
+    .await
+    .context("failed to create storage client for store")
+    .map_err(UploadSessionError::InternalError)
+}
+
+/// Reads a part which is sent to a multipart upload into memory, since the
+/// size of each part has to be known to send it.
+async fn buffer_part(data: Belt) -> Result<Bytes, UploadSessionError> {
+  let mut buffer = BytesMut::new();
+  let mut data = std::pin::pin!(data);
+  while let Some(chunk) = data
+    .try_next()
+    .await
+    .into_diagnostic()
+    .context("failed to read part")
+    .map_err(UploadSessionError::InternalError)?
+  {
+    buffer.extend_from_slice(&chunk);
+    if buffer.len() as u64 > MAX_MULTIPART_PART_SIZE {
+      return Err(UploadSessionError::InvalidPart(format!(
+        "parts are limited to {MAX_MULTIPART_PART_SIZE} bytes"
+      )));
+    }
+  }
+  Ok(buffer.freeze())
+}
+
+/// Checks that the parts of a multipart upload, in order, are sized as object
+/// stores require: every part but the last the same size, and no smaller than
+/// [`MIN_MULTIPART_PART_SIZE`].
+fn check_multipart_part_sizes(
+  parts: &[UploadSessionPart],
+) -> Result<(), UploadSessionError> {
+  let Some((_, leading_parts)) = parts.split_last() else {
+    return Ok(());
+  };
+  let Some(first_part) = leading_parts.first() else {
+    return Ok(());
+  };
+
+  if first_part.size.inner() < MIN_MULTIPART_PART_SIZE {
+    return Err(UploadSessionError::InvalidPart(format!(
+      "every part but the last must be at least {MIN_MULTIPART_PART_SIZE} \
+       bytes"
+    )));
+  }
+  if let Some(part) = leading_parts.iter().find(|p| p.size != first_part.size) {
+    return Err(UploadSessionError::InvalidPart(format!(
+      "every part but the last must be the same size, but part {} differs",
+      part.number
+    )));
+  }
+
+  Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  fn parts(sizes: &[u64]) -> Vec<UploadSessionPart> {
+    let session = RecordId::new();
+    (0..)
+      .zip(sizes)
+      .map(|(number, size)| UploadSessionPart {
+        id: RecordId::new(),
+        session,
+        number,
+        size: FileSize::new(*size),
+        etag: Some(format!("etag-{number}")),
+      })
+      .collect()
+  }
+
+  #[test]
+  fn multipart_parts_are_sized_as_object_stores_require() {
+    const MIB: u64 = 1024 * 1024;
+
+    for sizes in [&[][..], &[1], &[5 * MIB, 5 * MIB, 1], &[64 * MIB, 64 * MIB]]
+    {
+      assert!(
+        check_multipart_part_sizes(&parts(sizes)).is_ok(),
+        "{sizes:?}"
+      );
+    }
+    for sizes in [&[MIB, 1][..], &[64 * MIB, 32 * MIB, 1]] {
+      assert!(
+        matches!(
+          check_multipart_part_sizes(&parts(sizes)),
+          Err(UploadSessionError::InvalidPart(_))
+        ),
+        "{sizes:?}"
+      );
+    }
+  }
+}
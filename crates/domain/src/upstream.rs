@@ -0,0 +1,547 @@
+//! Upstream binary caches, which caches fall through to for missing paths.
+//!
+//! When a narinfo isn't in a cache, each of the cache's upstreams is asked
+//! for it in turn. An upstream narinfo is only served if it's signed by one of
+//! the upstream's public keys, and it's signed with the cache's own key as
+//! well, so clients only need to trust the cache. Its NAR is linked as
+//! `upstream/<digest>`, which finds the narinfo again and fetches the NAR
+//! from the same upstream. The NAR is buffered and checked against the
+//! narinfo before any of it is served. If the upstream has a persist store,
+//! the NAR is uploaded into it as it's served, and added to the cache.
+
+mod client;
+#[cfg(test)]
+mod tests;
+
+use std::{
+  io,
+  sync::atomic::{AtomicU64, Ordering},
+};
+
+use belt::Belt;
+use bytes::Bytes;
+use futures::{StreamExt, TryStreamExt};
+use metrics_types::compute::{OperationType, UnstampedComputeUsageEvent};
+use miette::{Context, IntoDiagnostic, Report, miette};
+use models::{
+  Cache, CacheUpstream, CompressionAlgorithm, Digest, EntityName, FileSize,
+  NarDeriverData, NarIntrensicData, RecordId, StoreDir, User,
+  nix_compat::narinfo::NarInfo,
+};
+use reqwest::Url;
+use tokio::{fs::File, io::AsyncSeekExt, sync::mpsc, task::JoinHandle};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+pub(crate) use self::client::{UpstreamClient, is_valid_upstream_url};
+use crate::{
+  DomainService,
+  compression::decompress,
+  download::DownloadPlanningError,
+  signing::verify_with_keys,
+  store_dir::narinfo_from_store_dir,
+  upload::{
+    UploadExecutionError, UploadPlan, UploadResponse, digest::digesting,
+  },
+};
+
+/// The number of chunks buffered for a NAR being persisted.
+const PERSIST_CHANNEL_CAPACITY: usize = 16;
+
+/// The request struct for the
+/// [`download_from_upstream`](DomainService::download_from_upstream) fn.
+#[derive(Debug)]
+pub struct UpstreamDownloadRequest {
+  /// The downloading user's authentication.
+  pub auth:       Option<RecordId<User>>,
+  /// The name of the cache whose upstreams to look for the path in.
+  pub cache_name: EntityName,
+  /// The store path digest of the path.
+  pub digest:     Digest,
+}
+
+/// The response struct for the
+/// [`download_from_upstream`](DomainService::download_from_upstream) fn.
+#[derive(Debug)]
+pub struct UpstreamDownloadResponse {
+  /// The NAR file, as the upstream serves it, once it's been checked against
+  /// the upstream's narinfo.
+  pub data:        Belt,
+  /// The size of the NAR file, if the upstream's narinfo states it.
+  pub file_size:   Option<FileSize>,
+  /// The upload of the NAR into the upstream's persist store, if it's being
+  /// persisted. It completes once `data` has been consumed.
+  pub persistence:
+    Option<JoinHandle<Result<UploadResponse, UploadExecutionError>>>,
+}
+
+/// The error enum for the
+/// [`download_from_upstream`](DomainService::download_from_upstream) fn.
+#[derive(thiserror::Error, Debug)]
+pub enum UpstreamDownloadError {
+  /// The user is unauthorized to download from this cache.
+  #[error("The user is unauthorized to download from this cache")]
+  Unauthorized,
+  /// The requested cache was not found.
+  #[error("The requested cache was not found: \"{0}\"")]
+  CacheNotFound(EntityName),
+  /// None of the cache's upstreams has the path.
+  #[error("The requested path was not found upstream: \"{0}\"")]
+  PathNotFound(Digest),
+  /// Some other internal error.
+  #[error("Unexpected error: {0}")]
+  InternalError(miette::Report),
+}
+
+/// A narinfo fetched from an upstream, whose signature has been verified.
+#[derive(Debug)]
+pub(crate) struct UpstreamNarinfo {
+  /// The upstream the narinfo was fetched from.
+  pub(crate) upstream: CacheUpstream,
+  /// The store directory of the cache the narinfo was fetched for.
+  store_dir:           StoreDir,
+  /// The narinfo's contents, converted into the default store directory.
+  contents:            String,
+}
+
+impl UpstreamNarinfo {
+  /// Returns the parsed narinfo.
+  pub(crate) fn narinfo(&self) -> NarInfo<'_> {
+    NarInfo::parse(&self.contents)
+      .expect("upstream narinfo was validated when it was fetched")
+  }
+
+  /// Computes the fingerprint which the narinfo's signatures sign.
+  pub(crate) fn fingerprint(&self) -> String {
+    narinfo_fingerprint(&self.narinfo(), &self.store_dir)
+  }
+}
+
+impl DomainService {
+  /// Finds a path in a cache's upstreams, returning the narinfo from the
+  /// first upstream which has it. Upstreams which fail are skipped.
+  pub(crate) async fn find_upstream_narinfo(
+    &self,
+    cache: &Cache,
+    digest: Digest,
+  ) -> Option<UpstreamNarinfo> {
+    for upstream in cache.upstreams.iter() {
+      match fetch_upstream_narinfo(
+        &self.http,
+        upstream,
+        &cache.store_dir,
+        digest,
+      )
+      .await
+      {
+        Ok(Some(narinfo)) => return Some(narinfo),
+        Ok(None) => (),
+        Err(e) => tracing::warn!(
+          upstream = upstream.url,
+          "failed to fetch narinfo from upstream: {e:?}"
+        ),
+      }
+    }
+    None
+  }
+
+  /// Streams a NAR from the first of a cache's upstreams which has it, and
+  /// persists it if that upstream has a persist store.
+  #[tracing::instrument(skip(self))]
+  pub async fn download_from_upstream(
+    &self,
+    req: UpstreamDownloadRequest,
+  ) -> Result<UpstreamDownloadResponse, UpstreamDownloadError> {
+    let cache = self
+      .fetch_cache_for_download(req.auth, req.cache_name)
+      .await
+      .map_err(|e| match e {
+        DownloadPlanningError::Unauthorized => {
+          UpstreamDownloadError::Unauthorized
+        }
+        DownloadPlanningError::CacheNotFound(name) => {
+          UpstreamDownloadError::CacheNotFound(name)
+        }
+        e => UpstreamDownloadError::InternalError(miette!("{e}")),
+      })?;
+
+    let upstream_narinfo = self
+      .find_upstream_narinfo(&cache, req.digest)
+      .await
+      .ok_or(UpstreamDownloadError::PathNotFound(req.digest))?;
+
+    let data = fetch_upstream_nar(&self.http, &upstream_narinfo)
+      .await
+      .context("failed to fetch NAR from upstream")
+      .map_err(UpstreamDownloadError::InternalError)?;
+    let file_size = upstream_narinfo.narinfo().file_size.map(FileSize::new);
+
+    if upstream_narinfo.upstream.persist_store.is_none() {
+      return Ok(UpstreamDownloadResponse {
+        data,
+        file_size,
+        persistence: None,
+      });
+    }
+
+    let (data, persist_data) = persist_channel(data);
+    let persistence = match self
+      .plan_upstream_persistence(&cache, &upstream_narinfo, persist_data)
+      .await
+    {
+      Ok(Some(plan)) => {
+        let domain = self.clone();
+        Some(tokio::spawn(
+          async move { domain.execute_upload(plan).await },
+        ))
+      }
+      Ok(None) => None,
+      Err(e) => {
+        tracing::warn!("failed to plan persisting upstream path: {e:?}");
+        None
+      }
+    };
+
+    Ok(UpstreamDownloadResponse {
+      data,
+      file_size,
+      persistence,
+    })
+  }
+
+  /// Plans the upload of a NAR fetched from an upstream into the upstream's
+  /// persist store, returning `None` if it can't or needn't be persisted.
+  async fn plan_upstream_persistence(
+    &self,
+    cache: &Cache,
+    upstream_narinfo: &UpstreamNarinfo,
+    nar_contents: Belt,
+  ) -> Result<Option<UploadPlan>, Report> {
+    let upstream = &upstream_narinfo.upstream;
+    let Some(store_id) = upstream.persist_store else {
+      return Ok(None);
+    };
+    let narinfo = upstream_narinfo.narinfo();
+
+    let compression = match narinfo.compression {
+      None | Some("none") => None,
+      Some(name) => match CompressionAlgorithm::from_nix_name(name) {
+        Some(algorithm) => Some(algorithm),
+        None => {
+          tracing::debug!(
+            compression = name,
+            "not persisting upstream NAR with unsupported compression"
+          );
+          return Ok(None);
+        }
+      },
+    };
+
+    let target_store = self
+      .meta
+      .fetch_store_by_id(store_id)
+      .await
+      .into_diagnostic()
+      .context("failed to fetch persist store")?
+      .ok_or(miette!("upstream persist store not found"))?;
+    miette::ensure!(
+      target_store.org == cache.org,
+      "upstream persist store is outside the cache's org"
+    );
+
+    let store_path = narinfo.store_path.to_owned();
+    if self
+      .meta
+      .fetch_entry_by_store_id_and_entry_path(target_store.id, &store_path)
+      .await
+      .into_diagnostic()
+      .context("failed to search for conflicting entries by store and path")?
+      .is_some()
+    {
+      tracing::debug!("upstream path is already in the persist store");
+      return Ok(None);
+    }
+
+    let existing_blob = self
+      .meta
+      .fetch_entry_by_store_id_and_nar_hash(target_store.id, &narinfo.nar_hash)
+      .await
+      .into_diagnostic()
+      .context("failed to search for existing blobs by store and NAR hash")?
+      .map(|e| e.storage_data);
+
+    // only the upstream's signatures can be verified, so keep just those
+    let fingerprint = upstream_narinfo.fingerprint();
+    let signatures = narinfo
+      .signatures
+      .iter()
+      .map(|s| s.to_owned())
+      .filter(|s| verify_with_keys(&upstream.public_keys, s, &fingerprint))
+      .collect();
+
+    let compute_event = UnstampedComputeUsageEvent {
+      entry_path: cache.store_dir.absolute_path(&store_path),
+      org_id:     cache.org,
+      op_type:    OperationType::Upload,
+    };
+
+    Ok(Some(UploadPlan {
+      nar_contents,
+      compression,
+      store_path,
+      store_dir: cache.store_dir.clone(),
+      target_store,
+      org_id: cache.org,
+      caches: vec![cache.clone()],
+      deriver_data: NarDeriverData {
+        system:  narinfo.system.map(str::to_owned),
+        deriver: narinfo.deriver.map(|d| d.to_owned()),
+      },
+      ca_hash: narinfo.ca.clone(),
+      nar_hash: Some(narinfo.nar_hash),
+      nar_size: Some(FileSize::new(narinfo.nar_size)),
+      references: Some(
+        narinfo.references.iter().map(|r| r.to_owned()).collect(),
+      ),
+      existing_blob,
+      staged_blob: None,
+      signatures,
+      unverified_signatures: Vec::new(),
+      upstream_public_keys: upstream.public_keys.clone(),
+      compute_event,
+    }))
+  }
+}
+
+/// Fetches a path's narinfo from an upstream, returning `None` if the
+/// upstream doesn't have it. Narinfos which aren't signed by one of the
+/// upstream's public keys, or whose path is outside the given store
+/// directory, are treated as missing.
+pub(crate) async fn fetch_upstream_narinfo(
+  client: &UpstreamClient,
+  upstream: &CacheUpstream,
+  store_dir: &StoreDir,
+  digest: Digest,
+) -> Result<Option<UpstreamNarinfo>, Report> {
+  let url = upstream_url(&upstream.url, &format!("{digest}.narinfo"))?;
+  let response = client.get(url).await.context("failed to request narinfo")?;
+
+  // nix treats both of these as the path being missing
+  if matches!(
+    response.status(),
+    reqwest::StatusCode::NOT_FOUND | reqwest::StatusCode::FORBIDDEN
+  ) {
+    return Ok(None);
+  }
+  let contents = response
+    .error_for_status()
+    .into_diagnostic()
+    .context("upstream failed to serve narinfo")?
+    .text()
+    .await
+    .into_diagnostic()
+    .context("failed to read narinfo")?;
+
+  let Some(contents) = narinfo_from_store_dir(&contents, store_dir) else {
+    tracing::warn!(
+      upstream = upstream.url,
+      "ignoring upstream narinfo outside the store directory \"{store_dir}\""
+    );
+    return Ok(None);
+  };
+  let narinfo = NarInfo::parse(&contents)
+    .map_err(|e| miette!("upstream narinfo is malformed: {e}"))?;
+  miette::ensure!(
+    Digest::from_bytes(*narinfo.store_path.digest()) == digest,
+    "upstream narinfo is for a different path: \"{}\"",
+    narinfo.store_path
+  );
+
+  let fingerprint = narinfo_fingerprint(&narinfo, store_dir);
+  if !narinfo.signatures.iter().any(|s| {
+    verify_with_keys(&upstream.public_keys, &s.to_owned(), &fingerprint)
+  }) {
+    tracing::warn!(
+      upstream = upstream.url,
+      store_path = %narinfo.store_path,
+      "ignoring upstream narinfo without a valid signature"
+    );
+    return Ok(None);
+  }
+
+  Ok(Some(UpstreamNarinfo {
+    upstream:  upstream.clone(),
+    store_dir: store_dir.clone(),
+    contents:  contents.into_owned(),
+  }))
+}
+
+/// Fetches the NAR file an upstream narinfo points to, and checks it against
+/// the narinfo. The file is buffered on disk until it's been checked, so none
+/// of a file that doesn't match is served.
+pub(crate) async fn fetch_upstream_nar(
+  client: &UpstreamClient,
+  upstream_narinfo: &UpstreamNarinfo,
+) -> Result<Belt, Report> {
+  let narinfo = upstream_narinfo.narinfo();
+  let url = upstream_url(&upstream_narinfo.upstream.url, narinfo.url)?;
+
+  let response = client
+    .get(url)
+    .await
+    .context("failed to request NAR")?
+    .error_for_status()
+    .into_diagnostic()
+    .context("upstream failed to serve NAR")?;
+  let data = Belt::new(response.bytes_stream().map_err(io::Error::other));
+
+  buffer_verified_nar_file(data, &narinfo).await
+}
+
+/// Resolves a path relative to an upstream's URL. The result must stay under
+/// the upstream's URL, so narinfos can't point the server at other hosts.
+fn upstream_url(upstream_url: &str, path: &str) -> Result<Url, Report> {
+  let base = Url::parse(&format!("{}/", upstream_url.trim_end_matches('/')))
+    .into_diagnostic()
+    .context("upstream URL is malformed")?;
+  let url = base
+    .join(path)
+    .into_diagnostic()
+    .context("upstream path is malformed")?;
+  miette::ensure!(
+    url.origin() == base.origin() && url.path().starts_with(base.path()),
+    "upstream path \"{path}\" is outside the upstream"
+  );
+  Ok(url)
+}
+
+/// Buffers an upstream NAR file into a temporary file, and checks its hash
+/// and size, and those of the NAR inside it, against the narinfo. Returns the
+/// file to be served if it matches.
+async fn buffer_verified_nar_file(
+  data: Belt,
+  narinfo: &NarInfo<'_>,
+) -> Result<Belt, Report> {
+  // the NAR hash is what the upstream signed, so the NAR is always checked,
+  // which needs its compression to be known
+  let compression = match narinfo.compression {
+    None | Some("none") => None,
+    Some(name) => Some(CompressionAlgorithm::from_nix_name(name).ok_or(
+      miette!("upstream NAR has unsupported compression \"{name}\""),
+    )?),
+  };
+
+  let mut file = temp_file().await?;
+  let (data, file_digest) = digesting(data);
+  let file_size = tokio::io::copy(&mut StreamReader::new(data), &mut file)
+    .await
+    .into_diagnostic()
+    .context("failed to buffer upstream NAR")?;
+  miette::ensure!(
+    narinfo.file_size.is_none_or(|s| s == file_size),
+    "upstream NAR file size does not match its narinfo"
+  );
+  miette::ensure!(
+    narinfo
+      .file_hash
+      .is_none_or(|h| h == file_digest.finalize()),
+    "upstream NAR file hash does not match its narinfo"
+  );
+
+  file
+    .rewind()
+    .await
+    .into_diagnostic()
+    .context("failed to rewind buffered NAR")?;
+  let nar = Belt::new(ReaderStream::new(
+    file
+      .try_clone()
+      .await
+      .into_diagnostic()
+      .context("failed to reopen buffered NAR")?,
+  ));
+  let nar = match compression {
+    Some(algorithm) => decompress(nar, algorithm),
+    None => nar,
+  };
+  let (nar, nar_digest) = digesting(nar);
+  let nar_size = nar
+    .try_fold(0, |size, bytes| {
+      futures::future::ready(Ok(size + bytes.len() as u64))
+    })
+    .await
+    .into_diagnostic()
+    .context("failed to read buffered NAR")?;
+  miette::ensure!(
+    nar_size == narinfo.nar_size && nar_digest.finalize() == narinfo.nar_hash,
+    "upstream NAR does not match its narinfo"
+  );
+
+  file
+    .rewind()
+    .await
+    .into_diagnostic()
+    .context("failed to rewind buffered NAR")?;
+  Ok(Belt::new(ReaderStream::new(file)))
+}
+
+/// Creates a temporary file. It's unlinked right away, so it's deleted once
+/// it's closed, even if the server stops first.
+async fn temp_file() -> Result<File, Report> {
+  static FILE_COUNTER: AtomicU64 = AtomicU64::new(0);
+  let path = std::env::temp_dir().join(format!(
+    "rambit-upstream-nar-{}-{}",
+    std::process::id(),
+    FILE_COUNTER.fetch_add(1, Ordering::Relaxed)
+  ));
+  let file = tokio::fs::OpenOptions::new()
+    .read(true)
+    .write(true)
+    .create_new(true)
+    .open(&path)
+    .await
+    .into_diagnostic()
+    .context("failed to create temporary file")?;
+  tokio::fs::remove_file(&path)
+    .await
+    .into_diagnostic()
+    .context("failed to unlink temporary file")?;
+  Ok(file)
+}
+
+/// Computes the fingerprint which a narinfo's signatures sign.
+fn narinfo_fingerprint(narinfo: &NarInfo<'_>, store_dir: &StoreDir) -> String {
+  let intrensic_data = NarIntrensicData {
+    nar_hash:   narinfo.nar_hash,
+    nar_size:   FileSize::new(narinfo.nar_size),
+    references: narinfo.references.iter().map(|r| r.to_owned()).collect(),
+    ca_hash:    narinfo.ca.clone(),
+    debug_info: Vec::new(),
+  };
+  intrensic_data.fingerprint(store_dir, &narinfo.store_path.to_owned())
+}
+
+/// Splits off a copy of a [`Belt`] to be persisted. Unlike a tee, the
+/// original is unaffected if the copy stops being read, so a failure to
+/// persist doesn't fail the download. If the original is dropped early, the
+/// copy ends early, and fails validation.
+fn persist_channel(data: Belt) -> (Belt, Belt) {
+  let (tx, rx) = mpsc::channel(PERSIST_CHANNEL_CAPACITY);
+
+  let data = data.then(move |item| {
+    let tx = tx.clone();
+    async move {
+      let copy: io::Result<Bytes> = match &item {
+        Ok(bytes) => Ok(bytes.clone()),
+        Err(e) => Err(io::Error::new(e.kind(), e.to_string())),
+      };
+      // the copy's reader may have stopped, which is fine
+      let _ = tx.send(copy).await;
+      item
+    }
+  });
+  let copy = futures::stream::unfold(rx, |mut rx| async move {
+    rx.recv().await.map(|item| (item, rx))
+  });
+
+  (Belt::new(data), Belt::new(copy))
+}
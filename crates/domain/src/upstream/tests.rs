@@ -0,0 +1,213 @@
+use std::{
+  path::PathBuf,
+  sync::atomic::{AtomicUsize, Ordering},
+};
+
+use ed25519_dalek::Signer;
+use models::{
+  Signature,
+  nix_compat::{
+    narinfo::{Flags, NarInfo},
+    nixbase32,
+    store_path::StorePathRef,
+  },
+};
+use sha2::{Digest as _, Sha256};
+use tower_http::services::ServeDir;
+
+use super::*;
+
+const STORE_PATH: &str =
+  "/nix/store/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0";
+const NAR_CONTENTS: &[u8] = b"pretend this is a NAR";
+
+/// Lays out a binary cache directory like `nix copy --to file://...` does,
+/// with one path in it, and serves it over HTTP. Returns the cache's URL.
+async fn serve_cache_dir(signing_seed: [u8; 32], nar_file: &[u8]) -> String {
+  static DIR_COUNTER: AtomicUsize = AtomicUsize::new(0);
+  let dir = std::env::temp_dir().join(format!(
+    "rambit-upstream-test-{}-{}",
+    std::process::id(),
+    DIR_COUNTER.fetch_add(1, Ordering::Relaxed)
+  ));
+  std::fs::create_dir_all(dir.join("nar")).unwrap();
+
+  let nar_hash: [u8; 32] = Sha256::digest(NAR_CONTENTS).into();
+  let nar_file_name = format!("nar/{}.nar", nixbase32::encode(&nar_hash));
+  let store_path = StorePathRef::from_absolute_path(STORE_PATH.as_bytes())
+    .expect("test store path is malformed");
+
+  let mut narinfo = NarInfo {
+    flags: Flags::empty(),
+    store_path,
+    nar_hash,
+    nar_size: NAR_CONTENTS.len() as u64,
+    references: vec![],
+    signatures: vec![],
+    ca: None,
+    system: None,
+    deriver: None,
+    url: &nar_file_name,
+    compression: Some("none"),
+    file_hash: None,
+    file_size: None,
+  };
+  let fingerprint = narinfo_fingerprint(&narinfo, &StoreDir::default());
+  let signature = Signature::new(
+    "upstream-1".to_owned(),
+    ed25519_dalek::SigningKey::from_bytes(&signing_seed)
+      .sign(fingerprint.as_bytes())
+      .to_bytes(),
+  );
+  narinfo.signatures.push(signature.as_ref());
+
+  std::fs::write(dir.join("nix-cache-info"), "StoreDir: /nix/store\n").unwrap();
+  std::fs::write(
+    dir.join(format!("{}.narinfo", store_digest())),
+    narinfo.to_string(),
+  )
+  .unwrap();
+  std::fs::write(dir.join(PathBuf::from(&nar_file_name)), nar_file).unwrap();
+
+  let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+  let url = format!("http://{}", listener.local_addr().unwrap());
+  let app = axum::Router::new().fallback_service(ServeDir::new(dir));
+  tokio::spawn(async move { axum::serve(listener, app).await });
+
+  url
+}
+
+fn public_key(signing_seed: [u8; 32]) -> String {
+  let key = ed25519_dalek::SigningKey::from_bytes(&signing_seed);
+  format!(
+    "upstream-1:{}",
+    data_encoding::BASE64.encode(key.verifying_key().as_bytes())
+  )
+}
+
+fn store_digest() -> Digest {
+  let store_path = StorePathRef::from_absolute_path(STORE_PATH.as_bytes())
+    .expect("test store path is malformed");
+  Digest::from_bytes(*store_path.digest())
+}
+
+fn upstream(url: String, signing_seed: [u8; 32]) -> CacheUpstream {
+  CacheUpstream {
+    url,
+    public_keys: vec![public_key(signing_seed)],
+    persist_store: None,
+  }
+}
+
+#[tokio::test]
+async fn signed_paths_are_fetched_from_upstream() {
+  let url = serve_cache_dir([1; 32], NAR_CONTENTS).await;
+  let upstream = upstream(url, [1; 32]);
+  let client = UpstreamClient::allowing_private();
+
+  let narinfo = fetch_upstream_narinfo(
+    &client,
+    &upstream,
+    &StoreDir::default(),
+    store_digest(),
+  )
+  .await
+  .expect("failed to fetch narinfo")
+  .expect("narinfo was not found");
+  assert_eq!(
+    narinfo.narinfo().store_path.to_absolute_path(),
+    STORE_PATH.to_owned()
+  );
+
+  let nar = fetch_upstream_nar(&client, &narinfo)
+    .await
+    .expect("failed to fetch NAR")
+    .collect_bytes()
+    .await
+    .expect("failed to read NAR");
+  assert_eq!(nar.as_ref(), NAR_CONTENTS);
+}
+
+#[tokio::test]
+async fn narinfos_without_a_trusted_signature_are_ignored() {
+  let url = serve_cache_dir([1; 32], NAR_CONTENTS).await;
+  let upstream = upstream(url, [2; 32]);
+
+  let narinfo = fetch_upstream_narinfo(
+    &UpstreamClient::allowing_private(),
+    &upstream,
+    &StoreDir::default(),
+    store_digest(),
+  )
+  .await
+  .expect("failed to fetch narinfo");
+  assert!(narinfo.is_none());
+}
+
+#[tokio::test]
+async fn missing_paths_are_not_found() {
+  let url = serve_cache_dir([1; 32], NAR_CONTENTS).await;
+  let upstream = upstream(url, [1; 32]);
+
+  let narinfo = fetch_upstream_narinfo(
+    &UpstreamClient::allowing_private(),
+    &upstream,
+    &StoreDir::default(),
+    Digest::from_bytes([0; 20]),
+  )
+  .await
+  .expect("failed to fetch narinfo");
+  assert!(narinfo.is_none());
+}
+
+#[tokio::test]
+async fn tampered_nars_fail() {
+  let url = serve_cache_dir([1; 32], b"pretend this is a bad NAR").await;
+  let upstream = upstream(url, [1; 32]);
+  let client = UpstreamClient::allowing_private();
+
+  let narinfo = fetch_upstream_narinfo(
+    &client,
+    &upstream,
+    &StoreDir::default(),
+    store_digest(),
+  )
+  .await
+  .expect("failed to fetch narinfo")
+  .expect("narinfo was not found");
+  // none of a NAR that doesn't match is served
+  assert!(fetch_upstream_nar(&client, &narinfo).await.is_err());
+}
+
+#[tokio::test]
+async fn upstreams_on_private_addresses_are_refused() {
+  let url = serve_cache_dir([1; 32], NAR_CONTENTS).await;
+  let upstream = upstream(url, [1; 32]);
+
+  let narinfo = fetch_upstream_narinfo(
+    &UpstreamClient::new(),
+    &upstream,
+    &StoreDir::default(),
+    store_digest(),
+  )
+  .await;
+  assert!(narinfo.is_err());
+}
+
+#[test]
+fn nar_urls_stay_under_the_upstream() {
+  let upstream = "https://cache.example.com/prefix";
+  assert_eq!(
+    upstream_url(upstream, "nar/abc.nar.xz").unwrap().as_str(),
+    "https://cache.example.com/prefix/nar/abc.nar.xz"
+  );
+  for path in [
+    "http://169.254.169.254/latest/meta-data",
+    "https://other.example.com/prefix/nar/abc.nar",
+    "//other.example.com/nar/abc.nar",
+    "/nar/abc.nar",
+    "../nar/abc.nar",
+  ] {
+    assert!(upstream_url(upstream, path).is_err(), "{path}");
+  }
+}
@@ -7,6 +7,7 @@ use axum::extract::FromRef;
 use domain::{
   DomainService, billing_domain::BillingService, db::Database,
   meta_domain::MetaService, mutate_domain::MutationService,
+  signing::SecretCipher,
 };
 use leptos::config::LeptosOptions;
 use metrics_domain::MetricsService;
@@ -60,7 +61,19 @@ pub struct AppState {
 impl AppState {
   /// Builds the [`AppState`].
   pub async fn build() -> Result<Self> {
-    let (org_db, user_db, store_db, entry_db, cache_db, session_db) = {
+    let (
+      org_db,
+      user_db,
+      store_db,
+      entry_db,
+      cache_db,
+      upload_session_db,
+      upload_session_part_db,
+      build_log_db,
+      realisation_db,
+      orphaned_blob_db,
+      session_db,
+    ) = {
       let url = std::env::var("POSTGRES_URL")
         .into_diagnostic()
         .context("`POSTGRES_URL` env var not populated")?;
@@ -70,6 +83,11 @@ impl AppState {
         .context("failed to connect to postgres")?;
 
       (
+        Database::new_postgres_from_pool(pool.clone()),
+        Database::new_postgres_from_pool(pool.clone()),
+        Database::new_postgres_from_pool(pool.clone()),
+        Database::new_postgres_from_pool(pool.clone()),
+        Database::new_postgres_from_pool(pool.clone()),
         Database::new_postgres_from_pool(pool.clone()),
         Database::new_postgres_from_pool(pool.clone()),
         Database::new_postgres_from_pool(pool.clone()),
@@ -84,6 +102,11 @@ impl AppState {
     store_db.initialize_schema().await?;
     entry_db.initialize_schema().await?;
     cache_db.initialize_schema().await?;
+    upload_session_db.initialize_schema().await?;
+    upload_session_part_db.initialize_schema().await?;
+    build_log_db.initialize_schema().await?;
+    realisation_db.initialize_schema().await?;
+    orphaned_blob_db.initialize_schema().await?;
     session_db.initialize_schema().await?;
 
     let meta_domain = MetaService::new(
@@ -92,6 +115,11 @@ impl AppState {
       store_db.clone(),
       entry_db.clone(),
       cache_db.clone(),
+      upload_session_db.clone(),
+      upload_session_part_db.clone(),
+      build_log_db.clone(),
+      realisation_db.clone(),
+      orphaned_blob_db.clone(),
     );
     let mutate_domain = MutationService::new(
       org_db.clone(),
@@ -99,13 +127,28 @@ impl AppState {
       store_db.clone(),
       entry_db.clone(),
       cache_db,
+      upload_session_db,
+      upload_session_part_db,
+      build_log_db,
+      realisation_db,
+      orphaned_blob_db,
     );
     let billing_domain = BillingService::new_from_env()
       .context("failed to create BillingService")?;
     let metrics_domain = MetricsService::new_from_env()
       .context("failed to create MetricService")?;
 
-    let domain = DomainService::new(meta_domain, mutate_domain, billing_domain);
+    let secret_cipher =
+      SecretCipher::new_from_env().context("failed to create SecretCipher")?;
+
+    let domain = DomainService::new(
+      meta_domain,
+      mutate_domain,
+      billing_domain,
+      secret_cipher,
+    );
+    domain.spawn_upload_session_sweeper();
+    domain.spawn_orphaned_blob_sweeper();
     let auth_domain = AuthDomainService::new(domain.clone());
     let session_store = DatabaseSessionStore::new(session_db);
 
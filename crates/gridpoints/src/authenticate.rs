@@ -3,7 +3,10 @@ use axum::{Json, http::StatusCode, response::IntoResponse};
 use domain::models::{EmailAddress, UserSubmittedAuthCredentials};
 use serde::Deserialize;
 
-use crate::util_traits::InternalError;
+use crate::{
+  error::{ApiError, ErrorCode},
+  util_traits::InternalError,
+};
 
 #[derive(Deserialize)]
 pub struct AuthenticateParams {
@@ -17,25 +20,23 @@ pub async fn authenticate(
   Json(params): Json<AuthenticateParams>,
 ) -> impl IntoResponse {
   let Some(email) = params.email else {
-    return (StatusCode::BAD_REQUEST, "Missing `email` field").into_response();
+    return ApiError::malformed("Missing `email` field").into_response();
   };
   if email.is_empty() {
-    return (StatusCode::BAD_REQUEST, "Empty `email` field").into_response();
+    return ApiError::malformed("Empty `email` field").into_response();
   }
   let email = match EmailAddress::try_new(email) {
     Ok(email) => email,
     Err(_) => {
-      return (StatusCode::BAD_REQUEST, "Malformed `email` field")
-        .into_response();
+      return ApiError::malformed("Malformed `email` field").into_response();
     }
   };
 
   let Some(password) = params.password else {
-    return (StatusCode::BAD_REQUEST, "Missing `password` field")
-      .into_response();
+    return ApiError::malformed("Missing `password` field").into_response();
   };
   if password.is_empty() {
-    return (StatusCode::BAD_REQUEST, "Empty `password` field").into_response();
+    return ApiError::malformed("Empty `password` field").into_response();
   }
 
   let user = match auth_session
@@ -44,7 +45,11 @@ pub async fn authenticate(
   {
     Ok(Some(user)) => user,
     Ok(None) => {
-      return (StatusCode::UNAUTHORIZED, Json(())).into_response();
+      return ApiError::new(
+        ErrorCode::Unauthenticated,
+        "invalid email or password",
+      )
+      .into_response();
     }
     Err(e) => {
       return e.internal("failed to authenticate");
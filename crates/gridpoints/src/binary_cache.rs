@@ -0,0 +1,123 @@
+use std::{collections::HashMap, io};
+
+use axum::{
+  body::{Body, Bytes},
+  extract::{Path, State},
+  http::StatusCode,
+  response::IntoResponse,
+};
+use domain::{
+  belt::Belt,
+  binary_cache::{BinaryCacheNarRequest, BinaryCacheNarinfoRequest},
+};
+use grid_state::AppState;
+use http_body_util::BodyExt;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+  narinfo::DigestFile,
+};
+
+#[axum::debug_handler]
+pub async fn put_nar(
+  cache_name: CacheNameExtractor,
+  Path(params): Path<HashMap<String, String>>,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  body: Body,
+) -> impl IntoResponse {
+  let file_name = params
+    .get("file_name")
+    .expect("binary cache route param names are malformed")
+    .clone();
+
+  let nar_contents = Belt::new(
+    body
+      .map_err(|e| io::Error::other(e.to_string()))
+      .into_data_stream(),
+  );
+
+  let req = BinaryCacheNarRequest {
+    auth: user.id,
+    cache_name: cache_name.value().clone(),
+    file_name,
+    nar_contents,
+  };
+
+  match app_state.domain.binary_cache_put_nar(req).await {
+    Ok(()) => StatusCode::OK.into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+#[axum::debug_handler]
+pub async fn put_narinfo(
+  cache_name: CacheNameExtractor,
+  Path(params): Path<HashMap<String, String>>,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  body: Bytes,
+) -> impl IntoResponse {
+  let file_name = params
+    .get("digest_with_suffix")
+    .expect("binary cache route param names are malformed");
+  let digest = match DigestFile::parse(file_name) {
+    Ok(DigestFile::Narinfo(digest)) => digest,
+    // listings are generated from the NAR when it's uploaded, so the one nix
+    // uploads with `write-nar-listing` is accepted and dropped
+    Ok(DigestFile::Listing(_)) => {
+      return match app_state
+        .domain
+        .binary_cache_put_listing(user.id, cache_name.value().clone())
+        .await
+      {
+        Ok(()) => StatusCode::OK.into_response(),
+        Err(err) => ApiError::from(err).into_response(),
+      };
+    }
+    Err(err) => return err.into_response(),
+  };
+  // listings may be compressed, so the body is only read as text for
+  // narinfos
+  let narinfo = match String::from_utf8(body.into()) {
+    Ok(narinfo) => narinfo,
+    Err(_) => {
+      return ApiError::malformed("Narinfo is not valid UTF-8").into_response();
+    }
+  };
+
+  let req = BinaryCacheNarinfoRequest {
+    auth: user.id,
+    cache_name: cache_name.value().clone(),
+    digest,
+    narinfo,
+  };
+
+  match app_state.domain.binary_cache_put_narinfo(req).await {
+    Ok(resp) => {
+      app_state
+        .metrics_domain
+        .send_event(resp.compute_event)
+        .await;
+      StatusCode::OK.into_response()
+    }
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+#[axum::debug_handler]
+pub async fn put_nix_cache_info(
+  cache_name: CacheNameExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  match app_state
+    .domain
+    .binary_cache_put_cache_info(user.id, cache_name.value().clone())
+    .await
+  {
+    Ok(()) => StatusCode::OK.into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
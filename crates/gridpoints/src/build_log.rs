@@ -0,0 +1,163 @@
+use std::{collections::HashMap, io};
+
+use axum::{
+  body::Body,
+  extract::{Path, State},
+  http::{
+    HeaderMap, StatusCode,
+    header::{ACCEPT_ENCODING, CONTENT_ENCODING, CONTENT_TYPE, VARY},
+  },
+  response::{IntoResponse, Response},
+};
+use domain::{
+  belt::Belt,
+  build_log::{BuildLogRequest, BuildLogUploadRequest},
+  models::StorePath,
+};
+use grid_state::AppState;
+use http_body_util::BodyExt;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, CompressionExtractor, UserAuthExtractor},
+};
+
+#[axum::debug_handler]
+pub async fn build_log(
+  cache_name: CacheNameExtractor,
+  user: Option<UserAuthExtractor>,
+  Path(params): Path<HashMap<String, String>>,
+  State(app_state): State<AppState>,
+  headers: HeaderMap,
+) -> impl IntoResponse {
+  let drv_path = match drv_path_from_params(&params) {
+    Ok(drv_path) => drv_path,
+    Err(err) => return err.into_response(),
+  };
+
+  let anonymous = user.is_none();
+  let req = BuildLogRequest {
+    auth: user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+    drv_path,
+    accepts_zstd: accepts_zstd(&headers),
+  };
+
+  let resp = match app_state.domain.fetch_build_log(req).await {
+    Ok(resp) => resp,
+    Err(err) => {
+      return ApiError::from(err).for_anonymous(anonymous).into_response();
+    }
+  };
+
+  let mut response: Response = (
+    [
+      (CONTENT_TYPE, "text/plain; charset=utf-8"),
+      (VARY, ACCEPT_ENCODING.as_str()),
+    ],
+    Body::from_stream(resp.data),
+  )
+    .into_response();
+  if let Some(compression) = resp.compression {
+    response.headers_mut().insert(
+      CONTENT_ENCODING,
+      compression
+        .nix_name()
+        .parse()
+        .expect("nix names are valid headers"),
+    );
+  }
+  response
+}
+
+#[axum::debug_handler]
+pub async fn put_build_log(
+  cache_name: CacheNameExtractor,
+  Path(params): Path<HashMap<String, String>>,
+  UserAuthExtractor(user): UserAuthExtractor,
+  CompressionExtractor(compression): CompressionExtractor,
+  State(app_state): State<AppState>,
+  body: Body,
+) -> impl IntoResponse {
+  let drv_path = match drv_path_from_params(&params) {
+    Ok(drv_path) => drv_path,
+    Err(err) => return err.into_response(),
+  };
+
+  let data = Belt::new(
+    body
+      .map_err(|e| io::Error::other(e.to_string()))
+      .into_data_stream(),
+  );
+
+  let req = BuildLogUploadRequest {
+    auth: user.id,
+    cache_name: cache_name.value().clone(),
+    drv_path,
+    compression,
+    data,
+  };
+
+  match app_state.domain.upload_build_log(req).await {
+    Ok(_) => StatusCode::OK.into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+/// Parses the derivation path from the route, which Nix gives as the base
+/// name of the `.drv`.
+fn drv_path_from_params(
+  params: &HashMap<String, String>,
+) -> Result<StorePath<String>, ApiError> {
+  let drv = params
+    .get("drv")
+    .expect("build log route param names are malformed");
+  StorePath::from_bytes(drv.as_bytes()).map_err(|_| {
+    ApiError::malformed(format!("Derivation path is malformed: `{drv}`"))
+  })
+}
+
+/// Whether the request accepts a zstd-encoded response.
+fn accepts_zstd(headers: &HeaderMap) -> bool {
+  headers
+    .get_all(ACCEPT_ENCODING)
+    .iter()
+    .filter_map(|v| v.to_str().ok())
+    .flat_map(|v| v.split(','))
+    .any(|coding| {
+      let mut params = coding.split(';').map(str::trim);
+      let name = params.next().unwrap_or_default();
+      // a zero quality value means the coding is refused
+      name.eq_ignore_ascii_case("zstd")
+        && !params.any(|p| {
+          p.strip_prefix("q=")
+            .and_then(|q| q.parse::<f32>().ok())
+            .is_some_and(|q| q == 0.0)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::http::HeaderValue;
+
+  use super::*;
+
+  #[test]
+  fn zstd_is_only_sent_when_accepted() {
+    let mut headers = HeaderMap::new();
+    assert!(!accepts_zstd(&headers));
+
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, br"));
+    assert!(!accepts_zstd(&headers));
+
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("gzip, zstd"));
+    assert!(accepts_zstd(&headers));
+
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("zstd;q=0.5"));
+    assert!(accepts_zstd(&headers));
+
+    headers.insert(ACCEPT_ENCODING, HeaderValue::from_static("zstd;q=0"));
+    assert!(!accepts_zstd(&headers));
+  }
+}
@@ -0,0 +1,39 @@
+use std::collections::HashMap;
+
+use axum::{
+  Json,
+  extract::{Path, State},
+  response::IntoResponse,
+};
+use domain::debug_info::DebugInfoRequest;
+use grid_state::AppState;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[axum::debug_handler]
+pub async fn debug_info(
+  cache_name: CacheNameExtractor,
+  Path(params): Path<HashMap<String, String>>,
+  user: Option<UserAuthExtractor>,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let build_id = params
+    .get("build_id")
+    .expect("debug info route param names are malformed")
+    .clone();
+
+  let anonymous = user.is_none();
+  let req = DebugInfoRequest {
+    auth: user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+    build_id,
+  };
+
+  match app_state.domain.debug_info(req).await {
+    Ok(resp) => Json(resp).into_response(),
+    Err(err) => ApiError::from(err).for_anonymous(anonymous).into_response(),
+  }
+}
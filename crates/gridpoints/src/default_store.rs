@@ -0,0 +1,33 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use domain::{cache_settings::UpdateDefaultStoreRequest, models::EntityName};
+use grid_state::AppState;
+use serde::Deserialize;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[derive(Deserialize)]
+pub struct DefaultStoreParams {
+  store: Option<String>,
+}
+
+#[axum::debug_handler]
+pub async fn update_default_store(
+  cache_name: CacheNameExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  Json(params): Json<DefaultStoreParams>,
+) -> impl IntoResponse {
+  let req = UpdateDefaultStoreRequest {
+    auth:       user.id,
+    cache_name: cache_name.value().clone(),
+    store_name: params.store.map(EntityName::new),
+  };
+
+  match app_state.domain.update_cache_default_store(req).await {
+    Ok(()) => Json(()).into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
@@ -1,19 +1,30 @@
-use std::collections::HashMap;
+use std::{collections::HashMap, str::FromStr};
 
 use axum::{
   body::Body,
   extract::{Path, State},
-  http::{StatusCode, header::CONTENT_LENGTH},
-  response::IntoResponse,
+  http::{
+    HeaderMap, StatusCode,
+    header::{ACCEPT_RANGES, CONTENT_LENGTH, CONTENT_RANGE, ETAG},
+  },
+  response::{IntoResponse, Response},
 };
 use domain::{
-  download::{DownloadRequest, DownloadResponse},
-  models::StorePath,
+  download::{
+    DownloadPlan, DownloadPlanningError, DownloadRequest, DownloadResponse,
+    NarFileDownloadRequest,
+  },
+  models::{Digest, StorePath, nix_compat::nixbase32},
+  upstream::{UpstreamDownloadRequest, UpstreamDownloadResponse},
 };
 use drop_stream::StreamDropCallbackExt;
 use grid_state::AppState;
 
-use super::extractors::{CacheNameExtractor, UserAuthExtractor};
+use super::{
+  error::{ApiError, ErrorCode},
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+  range::{RangeOutcome, resolve_range},
+};
 
 #[axum::debug_handler]
 pub async fn download(
@@ -21,6 +32,7 @@ pub async fn download(
   user: Option<UserAuthExtractor>,
   Path(params): Path<HashMap<String, String>>,
   State(app_state): State<AppState>,
+  headers: HeaderMap,
 ) -> impl IntoResponse {
   // get store path from path param
   let store_path = params
@@ -30,15 +42,15 @@ pub async fn download(
   let store_path = match StorePath::from_bytes(store_path.as_bytes()) {
     Ok(store_path) => store_path,
     Err(_) => {
-      return (
-        StatusCode::BAD_REQUEST,
-        format!("Store path is malformed: `{store_path}`"),
-      )
-        .into_response();
+      return ApiError::malformed(format!(
+        "Store path is malformed: `{store_path}`"
+      ))
+      .into_response();
     }
   };
 
   // build download request
+  let anonymous = user.is_none();
   let download_req = DownloadRequest {
     auth: user.map(|e| e.0.id),
     cache_name: cache_name.value().clone(),
@@ -46,10 +58,127 @@ pub async fn download(
   };
 
   // plan download operation
-  let download_plan = match app_state.domain.plan_download(download_req).await {
+  let download_plan = app_state.domain.plan_download(download_req).await;
+  download_response(&app_state, download_plan, anonymous, &headers).await
+}
+
+#[axum::debug_handler]
+pub async fn download_nar(
+  cache_name: CacheNameExtractor,
+  user: Option<UserAuthExtractor>,
+  Path(params): Path<HashMap<String, String>>,
+  State(app_state): State<AppState>,
+  headers: HeaderMap,
+) -> impl IntoResponse {
+  let file_name = params
+    .get("file_name")
+    .expect("download route param names are malformed")
+    .clone();
+
+  let anonymous = user.is_none();
+  let download_req = NarFileDownloadRequest {
+    auth: user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+    file_name,
+  };
+
+  let download_plan =
+    app_state.domain.plan_nar_file_download(download_req).await;
+  download_response(&app_state, download_plan, anonymous, &headers).await
+}
+
+#[axum::debug_handler]
+pub async fn download_upstream(
+  cache_name: CacheNameExtractor,
+  user: Option<UserAuthExtractor>,
+  Path(params): Path<HashMap<String, String>>,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let digest = params
+    .get("digest")
+    .expect("download route param names are malformed");
+  let digest = match Digest::from_str(digest) {
+    Ok(digest) => digest,
+    Err(_) => {
+      return ApiError::malformed(format!("Digest is malformed: `{digest}`"))
+        .into_response();
+    }
+  };
+
+  let anonymous = user.is_none();
+  let download_req = UpstreamDownloadRequest {
+    auth: user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+    digest,
+  };
+
+  let UpstreamDownloadResponse {
+    data,
+    file_size,
+    persistence,
+  } = match app_state.domain.download_from_upstream(download_req).await {
+    Ok(resp) => resp,
+    Err(err) => {
+      return ApiError::from(err).for_anonymous(anonymous).into_response();
+    }
+  };
+
+  // the path is persisted as it streams, so report it once that's done
+  if let Some(persistence) = persistence {
+    let metrics_domain = app_state.metrics_domain.clone();
+    tokio::spawn(async move {
+      match persistence.await {
+        Ok(Ok(resp)) => metrics_domain.send_event(resp.compute_event).await,
+        Ok(Err(err)) => {
+          tracing::warn!("failed to persist upstream path: {err}");
+        }
+        Err(err) => {
+          tracing::warn!("upstream persistence task failed: {err}");
+        }
+      }
+    });
+  }
+
+  let mut response = Body::from_stream(data).into_response();
+  if let Some(file_size) = file_size {
+    response
+      .headers_mut()
+      .insert(CONTENT_LENGTH, file_size.inner().into());
+  }
+  response
+}
+
+/// Executes a planned download and streams it back, or just the range
+/// requested in the headers.
+async fn download_response(
+  app_state: &AppState,
+  download_plan: Result<DownloadPlan, DownloadPlanningError>,
+  anonymous: bool,
+  headers: &HeaderMap,
+) -> Response {
+  let download_plan = match download_plan {
     Ok(plan) => plan,
     Err(err) => {
-      return format!("{err:#?}").into_response();
+      return ApiError::from(err).for_anonymous(anonymous).into_response();
+    }
+  };
+
+  // the file hash identifies the exact bytes stored, so it's a strong
+  // validator for resuming downloads
+  let etag = format!("\"{}\"", nixbase32::encode(&download_plan.file_hash()));
+  let stored_size = download_plan.stored_size().inner();
+  let range = match resolve_range(headers, stored_size, &etag) {
+    RangeOutcome::Full => None,
+    RangeOutcome::Partial(range) => Some(range),
+    RangeOutcome::Unsatisfiable => {
+      return (
+        [(CONTENT_RANGE, format!("bytes */{stored_size}"))],
+        ApiError::new(
+          ErrorCode::RangeNotSatisfiable,
+          format!("Range is outside of the file of {stored_size} bytes"),
+        ),
+      )
+        .into_response();
     }
   };
 
@@ -58,11 +187,13 @@ pub async fn download(
     data,
     file_size,
     egress_event,
-  } = match app_state.domain.execute_download(download_plan).await {
+  } = match app_state
+    .domain
+    .execute_download(download_plan, range)
+    .await
+  {
     Ok(resp) => resp,
-    Err(err) => {
-      return format!("{err:#?}").into_response();
-    }
+    Err(err) => return ApiError::from(err).into_response(),
   };
 
   // prepare a future to run when the stream is dropped, which sends the
@@ -74,10 +205,25 @@ pub async fn download(
       .send_event(egress_event.stamp_with_now(egress_counter.get()))
       .await;
   };
+  let body = Body::from_stream(data.on_drop_async(stream_drop_future));
 
-  (
-    [(CONTENT_LENGTH, file_size.inner().to_string())],
-    Body::from_stream(data.on_drop_async(stream_drop_future)),
-  )
-    .into_response()
+  let file_size = file_size.inner();
+  let validators = [(ACCEPT_RANGES, "bytes".to_owned()), (ETAG, etag)];
+  match range {
+    Some(range) => (
+      StatusCode::PARTIAL_CONTENT,
+      validators,
+      [
+        (CONTENT_LENGTH, range.size().to_string()),
+        (
+          CONTENT_RANGE,
+          format!("bytes {}-{}/{file_size}", range.start, range.end),
+        ),
+      ],
+      body,
+    )
+      .into_response(),
+    None => (validators, [(CONTENT_LENGTH, file_size.to_string())], body)
+      .into_response(),
+  }
 }
@@ -0,0 +1,46 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use domain::entry_caches::EntryCacheRequest;
+use grid_state::AppState;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, EntryIdExtractor, UserAuthExtractor},
+};
+
+#[axum::debug_handler]
+pub async fn link_entry(
+  EntryIdExtractor(entry_id): EntryIdExtractor,
+  cache_name: CacheNameExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let req = EntryCacheRequest {
+    auth: user.id,
+    entry_id,
+    cache_name: cache_name.value().clone(),
+  };
+
+  match app_state.domain.link_entry_to_cache(req).await {
+    Ok(()) => Json(()).into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+#[axum::debug_handler]
+pub async fn unlink_entry(
+  EntryIdExtractor(entry_id): EntryIdExtractor,
+  cache_name: CacheNameExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let req = EntryCacheRequest {
+    auth: user.id,
+    entry_id,
+    cache_name: cache_name.value().clone(),
+  };
+
+  match app_state.domain.unlink_entry_from_cache(req).await {
+    Ok(()) => Json(()).into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
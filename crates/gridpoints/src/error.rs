@@ -0,0 +1,154 @@
+mod domain_errors;
+
+use axum::{
+  Json,
+  http::StatusCode,
+  response::{IntoResponse, Response},
+};
+use serde::Serialize;
+
+/// A machine-readable error code. Clients match on these, so existing codes
+/// must keep their names.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ErrorCode {
+  /// A parameter or body of the request is missing or malformed.
+  MalformedRequest,
+  /// An uploaded NAR or narinfo failed validation.
+  InvalidUpload,
+  /// A supplied signature is invalid or missing.
+  InvalidSignature,
+  /// A setting is invalid.
+  InvalidSettings,
+  /// The request needs credentials, and none or bad ones were supplied.
+  Unauthenticated,
+  /// The user is not allowed to do this.
+  Forbidden,
+  /// The endpoint doesn't exist.
+  EndpointNotFound,
+  /// The cache doesn't exist.
+  CacheNotFound,
+  /// The store doesn't exist.
+  StoreNotFound,
+  /// The entry, path or NAR file doesn't exist.
+  EntryNotFound,
+  /// The upload session doesn't exist.
+  SessionNotFound,
+  /// The entry already exists.
+  DuplicateEntry,
+  /// The request conflicts with the current state of the resource.
+  Conflict,
+  /// The request is too large.
+  PayloadTooLarge,
+  /// The requested range is outside of the file.
+  RangeNotSatisfiable,
+  /// Something went wrong on our end.
+  InternalError,
+}
+
+impl ErrorCode {
+  /// The status code errors with this code are returned with.
+  pub fn status(self) -> StatusCode {
+    match self {
+      Self::MalformedRequest
+      | Self::InvalidUpload
+      | Self::InvalidSignature
+      | Self::InvalidSettings => StatusCode::BAD_REQUEST,
+      Self::Unauthenticated => StatusCode::UNAUTHORIZED,
+      Self::Forbidden => StatusCode::FORBIDDEN,
+      Self::EndpointNotFound
+      | Self::CacheNotFound
+      | Self::StoreNotFound
+      | Self::EntryNotFound
+      | Self::SessionNotFound => StatusCode::NOT_FOUND,
+      Self::DuplicateEntry | Self::Conflict => StatusCode::CONFLICT,
+      Self::PayloadTooLarge => StatusCode::PAYLOAD_TOO_LARGE,
+      Self::RangeNotSatisfiable => StatusCode::RANGE_NOT_SATISFIABLE,
+      Self::InternalError => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+  }
+}
+
+/// An error response, rendered as a JSON body with a stable code and a
+/// human-readable message.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+  code:    ErrorCode,
+  message: String,
+}
+
+impl ApiError {
+  /// Creates an error with the given code.
+  pub fn new(code: ErrorCode, message: impl Into<String>) -> Self {
+    Self {
+      code,
+      message: message.into(),
+    }
+  }
+
+  /// Creates an error for a malformed request.
+  pub fn malformed(message: impl Into<String>) -> Self {
+    Self::new(ErrorCode::MalformedRequest, message)
+  }
+
+  /// Creates an internal error. Its details are logged, and redacted from
+  /// the response.
+  pub fn internal(err: impl std::fmt::Debug, desc: &str) -> Self {
+    tracing::error!("{desc}: {err:#?}");
+    Self::new(ErrorCode::InternalError, "internal error")
+  }
+
+  /// Reports a forbidden request by an anonymous user as unauthenticated
+  /// instead, so that the client knows to retry with credentials.
+  pub fn for_anonymous(self, anonymous: bool) -> Self {
+    match (self.code, anonymous) {
+      (ErrorCode::Forbidden, true) => Self {
+        code: ErrorCode::Unauthenticated,
+        ..self
+      },
+      _ => self,
+    }
+  }
+
+  /// The error's code.
+  pub fn code(&self) -> ErrorCode { self.code }
+}
+
+impl IntoResponse for ApiError {
+  fn into_response(self) -> Response {
+    (self.code.status(), Json(self)).into_response()
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn errors_serialize_with_their_code() {
+    let err = ApiError::new(ErrorCode::CacheNotFound, "no such cache");
+    assert_eq!(err.code().status(), StatusCode::NOT_FOUND);
+    assert_eq!(
+      serde_json::to_value(&err).unwrap(),
+      serde_json::json!({
+        "code": "cache_not_found",
+        "message": "no such cache",
+      })
+    );
+  }
+
+  #[test]
+  fn internal_errors_are_redacted() {
+    let err = ApiError::internal("secret connection string", "failed");
+    assert_eq!(err.code(), ErrorCode::InternalError);
+    assert!(!err.message.contains("secret"));
+  }
+
+  #[test]
+  fn anonymous_forbidden_requests_are_unauthenticated() {
+    let err = ApiError::new(ErrorCode::Forbidden, "nope");
+    assert_eq!(err.for_anonymous(true).code(), ErrorCode::Unauthenticated);
+    let err = ApiError::new(ErrorCode::Forbidden, "nope");
+    assert_eq!(err.for_anonymous(false).code(), ErrorCode::Forbidden);
+  }
+}
@@ -0,0 +1,408 @@
+//! Conversions from domain errors into [`ApiError`]s.
+
+use domain::{
+  CreateUserError,
+  binary_cache::BinaryCacheUploadError,
+  build_log::BuildLogError,
+  cache_settings::UpdateCacheSettingsError,
+  debug_info::DebugInfoError,
+  download::{DownloadExecutionError, DownloadPlanningError},
+  entry_caches::EntryCacheError,
+  missing_paths::MissingPathsError,
+  nar_listing::NarListingError,
+  narinfo::NarinfoError,
+  nix_cache_info::NixCacheInfoError,
+  owl::InterrogatorError,
+  realisation::RealisationError,
+  signing::CachePublicKeyError,
+  store_entries::StoreEntriesError,
+  upload::{UploadExecutionError, UploadPlanningError},
+  upload_session::UploadSessionError,
+  upstream::UpstreamDownloadError,
+};
+
+use super::{ApiError, ErrorCode};
+
+/// Creates an error with the domain error's message.
+fn with_code(code: ErrorCode, err: impl ToString) -> ApiError {
+  ApiError::new(code, err.to_string())
+}
+
+impl From<NarinfoError> for ApiError {
+  fn from(err: NarinfoError) -> Self {
+    match err {
+      NarinfoError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      NarinfoError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      NarinfoError::EntryNotFound(_) => {
+        with_code(ErrorCode::EntryNotFound, err)
+      }
+      NarinfoError::InternalError(_) => {
+        ApiError::internal(err, "failed to build narinfo")
+      }
+    }
+  }
+}
+
+impl From<NarListingError> for ApiError {
+  fn from(err: NarListingError) -> Self {
+    match err {
+      NarListingError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      NarListingError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      NarListingError::EntryNotFound(_)
+      | NarListingError::ListingNotFound(_) => {
+        with_code(ErrorCode::EntryNotFound, err)
+      }
+      NarListingError::StorageFailure(_)
+      | NarListingError::InternalError(_) => {
+        ApiError::internal(err, "failed to fetch NAR listing")
+      }
+    }
+  }
+}
+
+impl From<BuildLogError> for ApiError {
+  fn from(err: BuildLogError) -> Self {
+    match err {
+      BuildLogError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      BuildLogError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      BuildLogError::NoDefaultStore(_) => with_code(ErrorCode::Conflict, err),
+      BuildLogError::NotADerivation(_) => {
+        with_code(ErrorCode::MalformedRequest, err)
+      }
+      BuildLogError::LogNotFound(_) => with_code(ErrorCode::EntryNotFound, err),
+      BuildLogError::StorageFailure(_) | BuildLogError::InternalError(_) => {
+        ApiError::internal(err, "failed to access build log")
+      }
+    }
+  }
+}
+
+impl From<RealisationError> for ApiError {
+  fn from(err: RealisationError) -> Self {
+    match err {
+      RealisationError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      RealisationError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      RealisationError::RealisationNotFound(_) => {
+        with_code(ErrorCode::EntryNotFound, err)
+      }
+      RealisationError::InvalidRealisation(_) => {
+        with_code(ErrorCode::InvalidUpload, err)
+      }
+      RealisationError::MissingTrustedSignature(_)
+      | RealisationError::InvalidSignature(_) => {
+        with_code(ErrorCode::InvalidSignature, err)
+      }
+      RealisationError::InternalError(_) => {
+        ApiError::internal(err, "failed to access realisation")
+      }
+    }
+  }
+}
+
+impl From<DebugInfoError> for ApiError {
+  fn from(err: DebugInfoError) -> Self {
+    match err {
+      DebugInfoError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      DebugInfoError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      DebugInfoError::InvalidBuildId(_) => {
+        with_code(ErrorCode::MalformedRequest, err)
+      }
+      DebugInfoError::DebugInfoNotFound(_) => {
+        with_code(ErrorCode::EntryNotFound, err)
+      }
+      DebugInfoError::InternalError(_) => {
+        ApiError::internal(err, "failed to look up debug info")
+      }
+    }
+  }
+}
+
+impl From<DownloadPlanningError> for ApiError {
+  fn from(err: DownloadPlanningError) -> Self {
+    match err {
+      DownloadPlanningError::Unauthorized => {
+        with_code(ErrorCode::Forbidden, err)
+      }
+      DownloadPlanningError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      DownloadPlanningError::EntryNotFound { .. }
+      | DownloadPlanningError::FileNotFound { .. } => {
+        with_code(ErrorCode::EntryNotFound, err)
+      }
+      DownloadPlanningError::InternalError(_) => {
+        ApiError::internal(err, "failed to plan download")
+      }
+    }
+  }
+}
+
+impl From<DownloadExecutionError> for ApiError {
+  fn from(err: DownloadExecutionError) -> Self {
+    ApiError::internal(err, "failed to execute download")
+  }
+}
+
+impl From<UpstreamDownloadError> for ApiError {
+  fn from(err: UpstreamDownloadError) -> Self {
+    match err {
+      UpstreamDownloadError::Unauthorized => {
+        with_code(ErrorCode::Forbidden, err)
+      }
+      UpstreamDownloadError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      UpstreamDownloadError::PathNotFound(_) => {
+        with_code(ErrorCode::EntryNotFound, err)
+      }
+      UpstreamDownloadError::InternalError(_) => {
+        ApiError::internal(err, "failed to download from upstream")
+      }
+    }
+  }
+}
+
+impl From<UploadPlanningError> for ApiError {
+  fn from(err: UploadPlanningError) -> Self {
+    match err {
+      UploadPlanningError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      UploadPlanningError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      UploadPlanningError::TargetStoreNotFound(_) => {
+        with_code(ErrorCode::StoreNotFound, err)
+      }
+      UploadPlanningError::DuplicateEntryInStore(_)
+      | UploadPlanningError::DuplicateEntryInCache { .. } => {
+        with_code(ErrorCode::DuplicateEntry, err)
+      }
+      UploadPlanningError::TargetStoreAmbiguous(..)
+      | UploadPlanningError::MismatchedStoreDirs(_) => {
+        with_code(ErrorCode::Conflict, err)
+      }
+      UploadPlanningError::MissingTrustedSignature(_) => {
+        with_code(ErrorCode::InvalidSignature, err)
+      }
+      UploadPlanningError::InternalError(_) => {
+        ApiError::internal(err, "failed to plan upload")
+      }
+    }
+  }
+}
+
+impl From<UploadExecutionError> for ApiError {
+  fn from(err: UploadExecutionError) -> Self {
+    match err {
+      UploadExecutionError::NarValidationError(
+        InterrogatorError::TaskFailure(_),
+      )
+      | UploadExecutionError::StorageFailure(_)
+      | UploadExecutionError::InternalError(_) => {
+        ApiError::internal(err, "failed to execute upload")
+      }
+      UploadExecutionError::InputDataError(_)
+      | UploadExecutionError::NarValidationError(_)
+      | UploadExecutionError::NarHashMismatch
+      | UploadExecutionError::NarSizeMismatch
+      | UploadExecutionError::ReferenceNotFound(_) => {
+        with_code(ErrorCode::InvalidUpload, err)
+      }
+      UploadExecutionError::InvalidSignature(_) => {
+        with_code(ErrorCode::InvalidSignature, err)
+      }
+    }
+  }
+}
+
+impl From<UploadSessionError> for ApiError {
+  fn from(err: UploadSessionError) -> Self {
+    match err {
+      UploadSessionError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      UploadSessionError::SessionNotFound(_) => {
+        with_code(ErrorCode::SessionNotFound, err)
+      }
+      UploadSessionError::MissingParts(_) => {
+        with_code(ErrorCode::Conflict, err)
+      }
+      UploadSessionError::InvalidPart(_) => {
+        with_code(ErrorCode::InvalidUpload, err)
+      }
+      UploadSessionError::Planning(err) => err.into(),
+      UploadSessionError::Execution(err) => err.into(),
+      UploadSessionError::StorageFailure(_)
+      | UploadSessionError::InternalError(_) => {
+        ApiError::internal(err, "upload session request failed")
+      }
+    }
+  }
+}
+
+impl From<BinaryCacheUploadError> for ApiError {
+  fn from(err: BinaryCacheUploadError) -> Self {
+    match err {
+      BinaryCacheUploadError::Unauthorized => {
+        with_code(ErrorCode::Forbidden, err)
+      }
+      BinaryCacheUploadError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      BinaryCacheUploadError::NarNotFound(_) => {
+        with_code(ErrorCode::EntryNotFound, err)
+      }
+      BinaryCacheUploadError::NoDefaultStore(_) => {
+        with_code(ErrorCode::Conflict, err)
+      }
+      BinaryCacheUploadError::InvalidNarFileName(_) => {
+        with_code(ErrorCode::MalformedRequest, err)
+      }
+      BinaryCacheUploadError::InvalidNarinfo(_)
+      | BinaryCacheUploadError::UnsupportedCompression(_) => {
+        with_code(ErrorCode::InvalidUpload, err)
+      }
+      BinaryCacheUploadError::Planning(err) => err.into(),
+      BinaryCacheUploadError::Execution(err) => err.into(),
+      BinaryCacheUploadError::StorageFailure(_)
+      | BinaryCacheUploadError::InternalError(_) => {
+        ApiError::internal(err, "binary cache upload failed")
+      }
+    }
+  }
+}
+
+impl From<MissingPathsError> for ApiError {
+  fn from(err: MissingPathsError) -> Self {
+    match err {
+      MissingPathsError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      MissingPathsError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      MissingPathsError::TooManyPaths(_) => {
+        with_code(ErrorCode::PayloadTooLarge, err)
+      }
+      MissingPathsError::InternalError(_) => {
+        ApiError::internal(err, "failed to find missing paths")
+      }
+    }
+  }
+}
+
+impl From<StoreEntriesError> for ApiError {
+  fn from(err: StoreEntriesError) -> Self {
+    match err {
+      StoreEntriesError::StoreNotFound(_) => {
+        with_code(ErrorCode::StoreNotFound, err)
+      }
+      StoreEntriesError::StoreAmbiguous(..) => {
+        with_code(ErrorCode::Conflict, err)
+      }
+      StoreEntriesError::TooManyPaths(_) => {
+        with_code(ErrorCode::PayloadTooLarge, err)
+      }
+      StoreEntriesError::InternalError(_) => {
+        ApiError::internal(err, "failed to look up store entries")
+      }
+    }
+  }
+}
+
+impl From<CachePublicKeyError> for ApiError {
+  fn from(err: CachePublicKeyError) -> Self {
+    match err {
+      CachePublicKeyError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      CachePublicKeyError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      CachePublicKeyError::InternalError(_) => {
+        ApiError::internal(err, "failed to fetch cache public key")
+      }
+    }
+  }
+}
+
+impl From<NixCacheInfoError> for ApiError {
+  fn from(err: NixCacheInfoError) -> Self {
+    match err {
+      NixCacheInfoError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      NixCacheInfoError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      NixCacheInfoError::InternalError(_) => {
+        ApiError::internal(err, "failed to build nix-cache-info")
+      }
+    }
+  }
+}
+
+impl From<EntryCacheError> for ApiError {
+  fn from(err: EntryCacheError) -> Self {
+    match err {
+      EntryCacheError::Unauthorized => with_code(ErrorCode::Forbidden, err),
+      EntryCacheError::EntryNotFound(_)
+      | EntryCacheError::EntryNotInCache { .. } => {
+        with_code(ErrorCode::EntryNotFound, err)
+      }
+      EntryCacheError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      EntryCacheError::DuplicateEntryInCache { .. } => {
+        with_code(ErrorCode::DuplicateEntry, err)
+      }
+      EntryCacheError::StoreDirMismatch { .. } => {
+        with_code(ErrorCode::Conflict, err)
+      }
+      EntryCacheError::InternalError(_) => {
+        ApiError::internal(err, "failed to update entry caches")
+      }
+    }
+  }
+}
+
+impl From<UpdateCacheSettingsError> for ApiError {
+  fn from(err: UpdateCacheSettingsError) -> Self {
+    match err {
+      UpdateCacheSettingsError::Unauthorized => {
+        with_code(ErrorCode::Forbidden, err)
+      }
+      UpdateCacheSettingsError::CacheNotFound(_) => {
+        with_code(ErrorCode::CacheNotFound, err)
+      }
+      UpdateCacheSettingsError::StoreNotFound(_) => {
+        with_code(ErrorCode::StoreNotFound, err)
+      }
+      UpdateCacheSettingsError::InvalidPublicKey(_)
+      | UpdateCacheSettingsError::InvalidUpstreamUrl(_)
+      | UpdateCacheSettingsError::MissingUpstreamPublicKeys(_) => {
+        with_code(ErrorCode::InvalidSettings, err)
+      }
+      UpdateCacheSettingsError::CacheNotEmpty(_) => {
+        with_code(ErrorCode::Conflict, err)
+      }
+      UpdateCacheSettingsError::InternalError(_) => {
+        ApiError::internal(err, "failed to update cache settings")
+      }
+    }
+  }
+}
+
+impl From<CreateUserError> for ApiError {
+  fn from(err: CreateUserError) -> Self {
+    match err {
+      CreateUserError::EmailAlreadyUsed(_) => {
+        with_code(ErrorCode::Conflict, err)
+      }
+      CreateUserError::InternalError(_) => {
+        ApiError::internal(err, "failed to sign up")
+      }
+    }
+  }
+}
@@ -1,12 +1,19 @@
+mod ca_hash;
 mod cache_list;
 mod cache_name;
+mod compression;
 mod deriver_store_path;
+mod entry_id;
 mod generic;
+mod nar_hash;
+mod reference_list;
+mod signature_list;
 mod store_path;
 mod target_store;
 mod user_id;
 
 pub use self::{
-  cache_list::*, cache_name::*, deriver_store_path::*, store_path::*,
-  target_store::*, user_id::*,
+  ca_hash::*, cache_list::*, cache_name::*, compression::*,
+  deriver_store_path::*, entry_id::*, nar_hash::*, reference_list::*,
+  signature_list::*, store_path::*, target_store::*, user_id::*,
 };
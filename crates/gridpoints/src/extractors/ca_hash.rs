@@ -0,0 +1,45 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+use domain::models::CAHash;
+use serde::{
+  Deserialize,
+  de::{IntoDeserializer, value::StrDeserializer},
+};
+
+use super::generic::parse_query;
+use crate::error::ApiError;
+
+const CA_HASH_QUERY_PARAM: &str = "ca";
+
+/// Extracts an optional content-addressed hash, in the form used by the `CA`
+/// field of a narinfo.
+pub struct CaHashExtractor(pub Option<CAHash>);
+
+impl<S: Sync> FromRequestParts<S> for CaHashExtractor {
+  type Rejection = ApiError;
+
+  async fn from_request_parts(
+    parts: &mut Parts,
+    _state: &S,
+  ) -> Result<Self, Self::Rejection> {
+    let query = parse_query(parts)?;
+
+    let Some(value) = query.get(CA_HASH_QUERY_PARAM) else {
+      return Ok(Self(None));
+    };
+    if value.is_empty() {
+      return Ok(Self(None));
+    }
+
+    // the serde representation of `CAHash` is the narinfo form
+    let deserializer: StrDeserializer<'_, serde::de::value::Error> =
+      value.as_str().into_deserializer();
+    let ca_hash = CAHash::deserialize(deserializer).map_err(|_| {
+      ApiError::malformed(format!(
+        "Content-addressed hash is malformed: `{value}` (query param \
+         `{CA_HASH_QUERY_PARAM}`)"
+      ))
+    })?;
+
+    Ok(Self(Some(ca_hash)))
+  }
+}
@@ -1,56 +1,45 @@
-use std::collections::HashMap;
-
-use axum::{
-  extract::{FromRequestParts, Query},
-  http::{StatusCode, request::Parts},
-};
+use axum::{extract::FromRequestParts, http::request::Parts};
 use domain::models::{EntityName, Slug};
 
+use super::generic::parse_query;
+use crate::error::ApiError;
+
 const CACHE_LIST_QUERY_PARAM: &str = "caches";
 
 pub struct CacheListExtractor(pub Vec<EntityName>);
 
 impl<S: Sync> FromRequestParts<S> for CacheListExtractor {
-  type Rejection = (StatusCode, String);
+  type Rejection = ApiError;
 
   async fn from_request_parts(
     parts: &mut Parts,
     _state: &S,
   ) -> Result<Self, Self::Rejection> {
-    let query =
-      Query::<HashMap<String, String>>::try_from_uri(&parts.uri).unwrap();
+    let query = parse_query(parts)?;
 
     let Some(value) = query.get("caches") else {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!(
-          "Cache list is missing (query param `{CACHE_LIST_QUERY_PARAM}`)"
-        ),
-      ));
+      return Err(ApiError::malformed(format!(
+        "Cache list is missing (query param `{CACHE_LIST_QUERY_PARAM}`)"
+      )));
     };
 
     if value.is_empty() {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!("Cache list is empty (query param `{CACHE_LIST_QUERY_PARAM}`)"),
-      ));
+      return Err(ApiError::malformed(format!(
+        "Cache list is empty (query param `{CACHE_LIST_QUERY_PARAM}`)"
+      )));
     }
 
     let caches = value
       .split(",")
       .map(|c| {
         if c.is_empty() {
-          return Err((
-            StatusCode::BAD_REQUEST,
-            "Empty cache name found".to_owned(),
-          ));
+          return Err(ApiError::malformed("Empty cache name found"));
         }
         match Slug::new(c).as_ref() == c {
           true => Ok(EntityName::new(c)),
-          false => Err((
-            StatusCode::BAD_REQUEST,
-            format!("Cache name is malformed: `{c}`"),
-          )),
+          false => Err(ApiError::malformed(format!(
+            "Cache name is malformed: `{c}`"
+          ))),
         }
       })
       .try_collect::<Vec<_>>()?;
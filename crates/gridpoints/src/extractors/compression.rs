@@ -0,0 +1,53 @@
+use axum::{
+  extract::FromRequestParts,
+  http::{header::CONTENT_ENCODING, request::Parts},
+};
+use domain::models::CompressionAlgorithm;
+
+use super::generic::parse_query;
+use crate::error::ApiError;
+
+const COMPRESSION_QUERY_PARAM: &str = "compression";
+
+/// Extracts the optional compression of an uploaded NAR, from the
+/// `compression` query param or else the `Content-Encoding` header. Either
+/// takes the names Nix uses (`zstd`, `xz`, `bzip2` or `none`).
+pub struct CompressionExtractor(pub Option<CompressionAlgorithm>);
+
+impl<S: Sync> FromRequestParts<S> for CompressionExtractor {
+  type Rejection = ApiError;
+
+  async fn from_request_parts(
+    parts: &mut Parts,
+    _state: &S,
+  ) -> Result<Self, Self::Rejection> {
+    let query = parse_query(parts)?;
+
+    let (value, source) = match query.get(COMPRESSION_QUERY_PARAM) {
+      Some(value) => (
+        value.as_str(),
+        format!("query param `{COMPRESSION_QUERY_PARAM}`"),
+      ),
+      None => match parts.headers.get(CONTENT_ENCODING) {
+        Some(value) => (
+          value.to_str().unwrap_or_default(),
+          format!("header `{CONTENT_ENCODING}`"),
+        ),
+        None => return Ok(Self(None)),
+      },
+    };
+
+    // content codings may be given with the legacy `x-` prefix
+    let name = value.trim().trim_start_matches("x-");
+    if name.is_empty() || name == "none" || name == "identity" {
+      return Ok(Self(None));
+    }
+
+    match CompressionAlgorithm::from_nix_name(name) {
+      Some(algorithm) => Ok(Self(Some(algorithm))),
+      None => Err(ApiError::malformed(format!(
+        "Compression is unsupported: `{value}` ({source})"
+      ))),
+    }
+  }
+}
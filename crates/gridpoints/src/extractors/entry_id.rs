@@ -0,0 +1,40 @@
+use std::{collections::HashMap, str::FromStr};
+
+use axum::{
+  extract::{FromRequestParts, Path},
+  http::request::Parts,
+};
+use domain::models::{Entry, RecordId};
+
+use crate::error::ApiError;
+
+const ENTRY_ID_PATH_PARAM: &str = "entry_id";
+
+/// Extracts an [`Entry`] ID from the path.
+pub struct EntryIdExtractor(pub RecordId<Entry>);
+
+impl<S: Send + Sync> FromRequestParts<S> for EntryIdExtractor {
+  type Rejection = ApiError;
+
+  async fn from_request_parts(
+    parts: &mut Parts,
+    state: &S,
+  ) -> Result<Self, Self::Rejection> {
+    let path =
+      <Path<HashMap<String, String>> as FromRequestParts<S>>::from_request_parts(parts, state)
+        .await
+        .unwrap();
+
+    let Some(value) = path.get(ENTRY_ID_PATH_PARAM) else {
+      return Err(ApiError::malformed(format!(
+        "Entry ID is missing (path param `{ENTRY_ID_PATH_PARAM}`)"
+      )));
+    };
+
+    let entry_id = RecordId::from_str(value).map_err(|_| {
+      ApiError::malformed(format!("Entry ID is malformed: `{value}`"))
+    })?;
+
+    Ok(Self(entry_id))
+  }
+}
@@ -1,3 +1,9 @@
+use std::collections::HashMap;
+
+use axum::{extract::Query, http::request::Parts};
+
+use crate::error::ApiError;
+
 mod entity_name_from_path;
 mod entity_name_from_query;
 mod store_path_from_query;
@@ -15,3 +21,11 @@ pub trait PathParameter {
   const PARAM_NAME: &'static str;
   const DESCRIPTION: &'static str;
 }
+
+/// Parses the query parameters of a request, rejecting a malformed query
+/// string.
+pub fn parse_query(parts: &Parts) -> Result<HashMap<String, String>, ApiError> {
+  Query::<HashMap<String, String>>::try_from_uri(&parts.uri)
+    .map(|Query(query)| query)
+    .map_err(|e| ApiError::malformed(format!("Query string is malformed: {e}")))
+}
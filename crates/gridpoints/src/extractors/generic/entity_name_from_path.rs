@@ -2,11 +2,12 @@ use std::{collections::HashMap, marker::PhantomData};
 
 use axum::{
   extract::{FromRequestParts, Path},
-  http::{StatusCode, request::Parts},
+  http::request::Parts,
 };
 use domain::models::{EntityName, Slug};
 
 use super::PathParameter;
+use crate::error::ApiError;
 
 pub struct EntityNameFromPathExtractor<P>(EntityName, PhantomData<P>);
 
@@ -17,7 +18,7 @@ impl<P> EntityNameFromPathExtractor<P> {
 impl<S: Send + Sync, P: PathParameter> FromRequestParts<S>
   for EntityNameFromPathExtractor<P>
 {
-  type Rejection = (StatusCode, String);
+  type Rejection = ApiError;
 
   async fn from_request_parts(
     parts: &mut Parts,
@@ -29,26 +30,23 @@ impl<S: Send + Sync, P: PathParameter> FromRequestParts<S>
         .unwrap();
 
     let Some(value) = path.get(P::PARAM_NAME) else {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!(
-          "{desc} is missing (path param `{p_name}`)",
-          desc = P::DESCRIPTION,
-          p_name = P::PARAM_NAME
-        ),
-      ));
+      return Err(ApiError::malformed(format!(
+        "{desc} is missing (path param `{p_name}`)",
+        desc = P::DESCRIPTION,
+        p_name = P::PARAM_NAME
+      )));
     };
     if value.is_empty() {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!("{desc} is empty", desc = P::DESCRIPTION),
-      ));
+      return Err(ApiError::malformed(format!(
+        "{desc} is empty",
+        desc = P::DESCRIPTION
+      )));
     }
     if Slug::new(value).as_ref() != value {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!("{desc} is malformed: `{value}`", desc = P::DESCRIPTION),
-      ));
+      return Err(ApiError::malformed(format!(
+        "{desc} is malformed: `{value}`",
+        desc = P::DESCRIPTION
+      )));
     }
     let value = EntityName::new(value);
 
@@ -1,12 +1,10 @@
-use std::{collections::HashMap, marker::PhantomData};
+use std::marker::PhantomData;
 
-use axum::{
-  extract::{FromRequestParts, Query},
-  http::{StatusCode, request::Parts},
-};
+use axum::{extract::FromRequestParts, http::request::Parts};
 use domain::models::{EntityName, Slug};
 
-use super::QueryParameter;
+use super::{QueryParameter, parse_query};
+use crate::error::ApiError;
 
 pub struct EntityNameFromQueryExtractor<P>(EntityName, PhantomData<P>);
 
@@ -17,36 +15,32 @@ impl<P> EntityNameFromQueryExtractor<P> {
 impl<S: Sync, P: QueryParameter> FromRequestParts<S>
   for EntityNameFromQueryExtractor<P>
 {
-  type Rejection = (StatusCode, String);
+  type Rejection = ApiError;
 
   async fn from_request_parts(
     parts: &mut Parts,
     _state: &S,
   ) -> Result<Self, Self::Rejection> {
-    let query =
-      Query::<HashMap<String, String>>::try_from_uri(&parts.uri).unwrap();
+    let query = parse_query(parts)?;
 
     let Some(value) = query.get(P::PARAM_NAME) else {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!(
-          "{desc} is missing (query param `{p_name}`)",
-          desc = P::DESCRIPTION,
-          p_name = P::PARAM_NAME
-        ),
-      ));
+      return Err(ApiError::malformed(format!(
+        "{desc} is missing (query param `{p_name}`)",
+        desc = P::DESCRIPTION,
+        p_name = P::PARAM_NAME
+      )));
     };
     if value.is_empty() {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!("{desc} is empty", desc = P::DESCRIPTION),
-      ));
+      return Err(ApiError::malformed(format!(
+        "{desc} is empty",
+        desc = P::DESCRIPTION
+      )));
     }
     if Slug::new(value).as_ref() != value {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!("{desc} is malformed: `{value}`", desc = P::DESCRIPTION),
-      ));
+      return Err(ApiError::malformed(format!(
+        "{desc} is malformed: `{value}`",
+        desc = P::DESCRIPTION
+      )));
     }
     let value = EntityName::new(value);
 
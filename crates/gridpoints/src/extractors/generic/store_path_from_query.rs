@@ -1,12 +1,13 @@
-use std::{collections::HashMap, marker::PhantomData};
+use std::marker::PhantomData;
 
 use axum::{
-  extract::{FromRequestParts, Query},
-  http::{StatusCode, request::Parts},
+  extract::{FromRequestParts, OptionalFromRequestParts},
+  http::request::Parts,
 };
 use domain::models::StorePath;
 
-use super::QueryParameter;
+use super::{QueryParameter, parse_query};
+use crate::error::ApiError;
 
 pub struct StorePathFromQueryExtractor<P>(StorePath<String>, PhantomData<P>);
 
@@ -17,38 +18,57 @@ impl<P> StorePathFromQueryExtractor<P> {
 impl<S: Sync, P: QueryParameter> FromRequestParts<S>
   for StorePathFromQueryExtractor<P>
 {
-  type Rejection = (StatusCode, String);
+  type Rejection = ApiError;
 
   async fn from_request_parts(
     parts: &mut Parts,
     _state: &S,
   ) -> Result<Self, Self::Rejection> {
-    let query =
-      Query::<HashMap<String, String>>::try_from_uri(&parts.uri).unwrap();
+    let query = parse_query(parts)?;
 
     let Some(value) = query.get(P::PARAM_NAME) else {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!(
-          "{desc} is missing (query param `{p_name}`)",
-          desc = P::DESCRIPTION,
-          p_name = P::PARAM_NAME
-        ),
-      ));
+      return Err(ApiError::malformed(format!(
+        "{desc} is missing (query param `{p_name}`)",
+        desc = P::DESCRIPTION,
+        p_name = P::PARAM_NAME
+      )));
     };
     if value.is_empty() {
-      return Err((
-        StatusCode::BAD_REQUEST,
-        format!("{desc} is empty", desc = P::DESCRIPTION),
-      ));
+      return Err(ApiError::malformed(format!(
+        "{desc} is empty",
+        desc = P::DESCRIPTION
+      )));
     }
     let value = StorePath::from_bytes(value.as_bytes()).map_err(|_| {
-      (
-        StatusCode::BAD_REQUEST,
-        format!("{desc} is malformed: `{value}`", desc = P::DESCRIPTION),
-      )
+      ApiError::malformed(format!(
+        "{desc} is malformed: `{value}`",
+        desc = P::DESCRIPTION
+      ))
     })?;
 
     Ok(Self(value, PhantomData))
   }
 }
+
+impl<S: Sync, P: QueryParameter> OptionalFromRequestParts<S>
+  for StorePathFromQueryExtractor<P>
+{
+  type Rejection = ApiError;
+
+  async fn from_request_parts(
+    parts: &mut Parts,
+    state: &S,
+  ) -> Result<Option<Self>, Self::Rejection> {
+    // a missing or empty parameter is `None`, but a malformed one is rejected
+    let query = parse_query(parts)?;
+    match query.get(P::PARAM_NAME) {
+      None => Ok(None),
+      Some(value) if value.is_empty() => Ok(None),
+      Some(_) => {
+        <Self as FromRequestParts<S>>::from_request_parts(parts, state)
+          .await
+          .map(Some)
+      }
+    }
+  }
+}
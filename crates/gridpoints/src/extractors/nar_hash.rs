@@ -0,0 +1,36 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+use domain::models::nix_compat::nixhash::{self, NixHash};
+
+use super::generic::parse_query;
+use crate::error::ApiError;
+
+const NAR_HASH_QUERY_PARAM: &str = "nar_hash";
+
+/// Extracts an optional SHA-256 NAR hash, in any form Nix prints.
+pub struct NarHashExtractor(pub Option<[u8; 32]>);
+
+impl<S: Sync> FromRequestParts<S> for NarHashExtractor {
+  type Rejection = ApiError;
+
+  async fn from_request_parts(
+    parts: &mut Parts,
+    _state: &S,
+  ) -> Result<Self, Self::Rejection> {
+    let query = parse_query(parts)?;
+
+    let Some(value) = query.get(NAR_HASH_QUERY_PARAM) else {
+      return Ok(Self(None));
+    };
+    if value.is_empty() {
+      return Ok(Self(None));
+    }
+
+    match nixhash::from_str(value, Some("sha256")) {
+      Ok(NixHash::Sha256(hash)) => Ok(Self(Some(hash))),
+      _ => Err(ApiError::malformed(format!(
+        "NAR hash is malformed: `{value}` (query param \
+         `{NAR_HASH_QUERY_PARAM}`)"
+      ))),
+    }
+  }
+}
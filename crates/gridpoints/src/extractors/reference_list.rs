@@ -0,0 +1,45 @@
+use std::collections::HashSet;
+
+use axum::{extract::FromRequestParts, http::request::Parts};
+use domain::models::StorePath;
+
+use super::generic::parse_query;
+use crate::error::ApiError;
+
+const REFERENCE_LIST_QUERY_PARAM: &str = "references";
+
+/// Extracts an optional comma-separated list of claimed references, as store
+/// path base names. A present but empty list claims that there are no
+/// references, while a missing one leaves them to be discovered.
+pub struct ReferenceListExtractor(pub Option<HashSet<StorePath<String>>>);
+
+impl<S: Sync> FromRequestParts<S> for ReferenceListExtractor {
+  type Rejection = ApiError;
+
+  async fn from_request_parts(
+    parts: &mut Parts,
+    _state: &S,
+  ) -> Result<Self, Self::Rejection> {
+    let query = parse_query(parts)?;
+
+    let Some(value) = query.get(REFERENCE_LIST_QUERY_PARAM) else {
+      return Ok(Self(None));
+    };
+    if value.is_empty() {
+      return Ok(Self(Some(HashSet::new())));
+    }
+
+    let references = value
+      .split(",")
+      .map(|r| {
+        StorePath::from_bytes(r.as_bytes()).map_err(|_| {
+          ApiError::malformed(format!(
+            "Store path is malformed: `{r}` (query param \
+             `{REFERENCE_LIST_QUERY_PARAM}`)"
+          ))
+        })
+      })
+      .try_collect::<HashSet<_>>()?;
+    Ok(Self(Some(references)))
+  }
+}
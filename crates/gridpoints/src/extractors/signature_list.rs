@@ -0,0 +1,43 @@
+use axum::{extract::FromRequestParts, http::request::Parts};
+use domain::models::Signature;
+
+use super::generic::parse_query;
+use crate::error::ApiError;
+
+const SIGNATURE_LIST_QUERY_PARAM: &str = "signatures";
+
+/// Extracts an optional comma-separated list of narinfo signatures.
+pub struct SignatureListExtractor(pub Vec<Signature<String>>);
+
+impl<S: Sync> FromRequestParts<S> for SignatureListExtractor {
+  type Rejection = ApiError;
+
+  async fn from_request_parts(
+    parts: &mut Parts,
+    _state: &S,
+  ) -> Result<Self, Self::Rejection> {
+    let query = parse_query(parts)?;
+
+    let Some(value) = query.get(SIGNATURE_LIST_QUERY_PARAM) else {
+      return Ok(Self(Vec::new()));
+    };
+    if value.is_empty() {
+      return Ok(Self(Vec::new()));
+    }
+
+    let signatures = value
+      .split(",")
+      .map(|s| {
+        Signature::<&str>::parse(s)
+          .map(|s| s.to_owned())
+          .map_err(|_| {
+            ApiError::malformed(format!(
+              "Signature is malformed: `{s}` (query param \
+               `{SIGNATURE_LIST_QUERY_PARAM}`)"
+            ))
+          })
+      })
+      .try_collect::<Vec<_>>()?;
+    Ok(Self(signatures))
+  }
+}
@@ -1,16 +1,23 @@
 use auth_domain::AuthSession;
 use axum::{
   extract::{FromRequestParts, OptionalFromRequestParts},
-  http::StatusCode,
+  http::header::AUTHORIZATION,
 };
-use domain::models::AuthUser;
+use data_encoding::BASE64;
+use domain::models::{AuthUser, EmailAddress, UserSubmittedAuthCredentials};
+
+use crate::error::{ApiError, ErrorCode};
 
 /// Uses [`AuthSession`] to extract [`AuthUser`]. This extra indirection is here
 /// so that we can extract user auth from multiple sources in the future.
+///
+/// Requests without a session may authenticate with HTTP basic auth instead,
+/// which is what Nix sends for credentials in a netrc file. Those are verified
+/// through a short-lived cache, since they come with every request.
 pub struct UserAuthExtractor(pub AuthUser);
 
 impl<S: Send + Sync> FromRequestParts<S> for UserAuthExtractor {
-  type Rejection = (StatusCode, &'static str);
+  type Rejection = ApiError;
 
   async fn from_request_parts(
     parts: &mut http::request::Parts,
@@ -19,23 +26,57 @@ impl<S: Send + Sync> FromRequestParts<S> for UserAuthExtractor {
     // extract using the optional form and then throw an error on None
     <Self as OptionalFromRequestParts<S>>::from_request_parts(parts, state)
       .await?
-      .ok_or((
-        StatusCode::UNAUTHORIZED,
-        "UNAUTHORIZED: session header missing",
-      ))
+      .ok_or_else(|| {
+        ApiError::new(ErrorCode::Unauthenticated, "credentials are missing")
+      })
   }
 }
 
 impl<S: Send + Sync> OptionalFromRequestParts<S> for UserAuthExtractor {
-  type Rejection = (StatusCode, &'static str);
+  type Rejection = ApiError;
 
   async fn from_request_parts(
     parts: &mut http::request::Parts,
     state: &S,
   ) -> Result<Option<Self>, Self::Rejection> {
     // extract AuthSession straight from the request and pull the user field
-    AuthSession::from_request_parts(parts, state)
+    let auth_session = AuthSession::from_request_parts(parts, state)
+      .await
+      .map_err(|(_, err)| {
+        ApiError::internal(err, "failed to extract session")
+      })?;
+    if let Some(user) = auth_session.user {
+      return Ok(Some(Self(user)));
+    }
+
+    let Some(creds) = basic_auth_credentials(parts) else {
+      return Ok(None);
+    };
+    auth_session
+      .backend
+      .authenticate_per_request(creds)
       .await
-      .map(|s| s.user.map(Self))
+      .map(|u| u.map(Self))
+      .map_err(|e| {
+        ApiError::internal(e, "failed to authenticate with basic auth")
+      })
   }
 }
+
+/// Parses the email and password from a basic auth header, if there is one.
+fn basic_auth_credentials(
+  parts: &http::request::Parts,
+) -> Option<(EmailAddress, UserSubmittedAuthCredentials)> {
+  let header = parts.headers.get(AUTHORIZATION)?.to_str().ok()?;
+  let encoded = header.strip_prefix("Basic ")?;
+  let decoded =
+    String::from_utf8(BASE64.decode(encoded.as_bytes()).ok()?).ok()?;
+  let (email, password) = decoded.split_once(':')?;
+
+  Some((
+    EmailAddress::try_new(email).ok()?,
+    UserSubmittedAuthCredentials::Password {
+      password: password.to_owned(),
+    },
+  ))
+}
@@ -3,30 +3,62 @@
 #![feature(iterator_try_collect)]
 
 mod authenticate;
+mod binary_cache;
+mod build_log;
+mod debug_info;
+mod default_store;
 mod download;
+mod entry_caches;
+mod error;
 mod extractors;
+mod missing;
+mod nar_listing;
 mod narinfo;
 mod nix_cache_info;
+mod public_key;
+mod range;
+mod realisation;
 mod signup;
+mod store_dir;
+mod store_entries;
+mod trusted_keys;
 mod upload;
+mod upload_session;
+mod upstreams;
 mod util_traits;
 
 use axum::{
   Json, Router,
-  http::StatusCode,
   response::IntoResponse,
-  routing::{get, post},
+  routing::{get, post, put},
 };
 use grid_state::AppState;
 
 pub use self::util_traits::*;
 use self::{
   authenticate::{authenticate, deauthenticate},
-  download::download,
+  binary_cache::{put_nar, put_narinfo, put_nix_cache_info},
+  build_log::{build_log, put_build_log},
+  debug_info::debug_info,
+  default_store::update_default_store,
+  download::{download, download_nar, download_upstream},
+  entry_caches::{link_entry, unlink_entry},
+  error::{ApiError, ErrorCode},
+  missing::missing,
   narinfo::narinfo,
   nix_cache_info::nix_cache_info,
+  public_key::public_key,
+  realisation::{put_realisation, realisation},
   signup::signup,
+  store_dir::update_store_dir,
+  store_entries::store_entries,
+  trusted_keys::update_trusted_keys,
   upload::upload,
+  upload_session::{
+    abort_upload_session, finalize_upload_session, start_upload_session,
+    upload_session_part, upload_session_status,
+  },
+  upstreams::update_upstreams,
 };
 
 #[axum::debug_handler]
@@ -40,7 +72,7 @@ async fn health() -> impl IntoResponse { Json(true) }
 
 #[axum::debug_handler]
 async fn fallback() -> impl IntoResponse {
-  (StatusCode::NOT_FOUND, "endpoint not found")
+  ApiError::new(ErrorCode::EndpointNotFound, "endpoint not found")
 }
 
 /// Builds the grid router.
@@ -52,8 +84,52 @@ pub fn router() -> Router<AppState> {
     .route("/authenticate", post(authenticate))
     .route("/deauthenticate", post(deauthenticate))
     .route("/upload", post(upload))
-    .route("/c/{cache_name}/nix-cache-info", get(nix_cache_info))
+    .route("/upload/sessions", post(start_upload_session))
+    .route(
+      "/upload/sessions/{session_id}",
+      get(upload_session_status).delete(abort_upload_session),
+    )
+    .route(
+      "/upload/sessions/{session_id}/parts/{part_number}",
+      put(upload_session_part),
+    )
+    .route(
+      "/upload/sessions/{session_id}/finalize",
+      post(finalize_upload_session),
+    )
+    .route("/entries/lookup", post(store_entries))
+    .route(
+      "/entry/{entry_id}/caches/{cache_name}",
+      put(link_entry).delete(unlink_entry),
+    )
+    .route(
+      "/c/{cache_name}/nix-cache-info",
+      get(nix_cache_info).put(put_nix_cache_info),
+    )
+    .route("/c/{cache_name}/public-key", get(public_key))
+    .route("/c/{cache_name}/missing", post(missing))
+    .route("/c/{cache_name}/trusted-keys", put(update_trusted_keys))
+    .route("/c/{cache_name}/default-store", put(update_default_store))
+    .route("/c/{cache_name}/upstreams", put(update_upstreams))
+    .route("/c/{cache_name}/store-dir", put(update_store_dir))
     .route("/c/{cache_name}/download/{store_path}", get(download))
-    .route("/c/{cache_name}/{digest_with_suffix}", get(narinfo))
+    .route(
+      "/c/{cache_name}/nar/{file_name}",
+      get(download_nar).put(put_nar),
+    )
+    .route("/c/{cache_name}/upstream/{digest}", get(download_upstream))
+    .route(
+      "/c/{cache_name}/log/{drv}",
+      get(build_log).put(put_build_log),
+    )
+    .route("/c/{cache_name}/debuginfo/{build_id}", get(debug_info))
+    .route(
+      "/c/{cache_name}/realisations/{file_name}",
+      get(realisation).put(put_realisation),
+    )
+    .route(
+      "/c/{cache_name}/{digest_with_suffix}",
+      get(narinfo).put(put_narinfo),
+    )
     .fallback(fallback)
 }
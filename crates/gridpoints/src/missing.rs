@@ -0,0 +1,68 @@
+use std::{collections::HashSet, str::FromStr};
+
+use axum::{Json, extract::State, response::IntoResponse};
+use domain::{missing_paths::MissingPathsRequest, models::Digest};
+use grid_state::AppState;
+use serde::Deserialize;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[derive(Deserialize)]
+pub struct MissingParams {
+  /// Store paths, or their digests.
+  paths: Vec<String>,
+}
+
+/// Parses the digest from a store path, a store path's base name, or a bare
+/// digest.
+fn parse_digest(path: &str) -> Option<Digest> {
+  let base_name = path.rsplit('/').next()?;
+  let digest = base_name.split('-').next()?;
+  Digest::from_str(digest).ok()
+}
+
+#[axum::debug_handler]
+pub async fn missing(
+  cache_name: CacheNameExtractor,
+  user: Option<UserAuthExtractor>,
+  State(app_state): State<AppState>,
+  Json(params): Json<MissingParams>,
+) -> impl IntoResponse {
+  let mut digests = Vec::with_capacity(params.paths.len());
+  for path in params.paths.iter() {
+    match parse_digest(path) {
+      Some(digest) => digests.push(digest),
+      None => {
+        return ApiError::malformed(format!(
+          "Store path or digest is malformed: `{path}`"
+        ))
+        .into_response();
+      }
+    }
+  }
+
+  let anonymous = user.is_none();
+  let req = MissingPathsRequest {
+    auth:       user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+    digests:    digests.clone(),
+  };
+
+  match app_state.domain.missing_paths(req).await {
+    Ok(missing) => {
+      let missing = missing.into_iter().collect::<HashSet<_>>();
+      let missing_paths = params
+        .paths
+        .into_iter()
+        .zip(digests)
+        .filter(|(_, d)| missing.contains(d))
+        .map(|(p, _)| p)
+        .collect::<Vec<_>>();
+      Json(serde_json::json!({ "missing": missing_paths })).into_response()
+    }
+    Err(err) => ApiError::from(err).for_anonymous(anonymous).into_response(),
+  }
+}
@@ -0,0 +1,34 @@
+use axum::{
+  http::header::CONTENT_TYPE,
+  response::{IntoResponse, Response},
+};
+use domain::{models::Digest, nar_listing::NarListingRequest};
+use grid_state::AppState;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+/// Serves the JSON listing of a NAR, requested at `<digest>.ls` next to its
+/// narinfo.
+pub async fn nar_listing(
+  app_state: &AppState,
+  cache_name: CacheNameExtractor,
+  digest: Digest,
+  user: Option<UserAuthExtractor>,
+) -> Response {
+  let anonymous = user.is_none();
+  let req = NarListingRequest {
+    auth: user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+    digest,
+  };
+
+  match app_state.domain.nar_listing(req).await {
+    Ok(listing) => {
+      ([(CONTENT_TYPE, "application/json")], listing).into_response()
+    }
+    Err(err) => ApiError::from(err).for_anonymous(anonymous).into_response(),
+  }
+}
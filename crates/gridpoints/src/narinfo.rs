@@ -2,13 +2,16 @@ use std::{collections::HashMap, str::FromStr};
 
 use axum::{
   extract::{Path, State},
-  http::StatusCode,
   response::IntoResponse,
 };
 use domain::{models::Digest, narinfo::NarinfoRequest};
 use grid_state::AppState;
 
-use super::extractors::{CacheNameExtractor, UserAuthExtractor};
+use super::{
+  error::{ApiError, ErrorCode},
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+  nar_listing::nar_listing,
+};
 
 #[axum::debug_handler]
 pub async fn narinfo(
@@ -17,31 +20,18 @@ pub async fn narinfo(
   user: Option<UserAuthExtractor>,
   State(app_state): State<AppState>,
 ) -> impl IntoResponse {
-  let digest = match params
+  let digest_with_suffix = params
     .get("digest_with_suffix")
-    .expect("upload route param names are malformed")
-    .strip_suffix(".narinfo")
-  {
-    Some(d) => d,
-    None => {
-      return (
-        StatusCode::NOT_FOUND,
-        "Expected a digest ending in \".narinfo\"",
-      )
-        .into_response();
-    }
-  };
-  let digest = match Digest::from_str(digest) {
-    Ok(digest) => digest,
-    Err(_) => {
-      return (
-        StatusCode::BAD_REQUEST,
-        format!("Digest is malformed: `{digest}`"),
-      )
-        .into_response();
+    .expect("upload route param names are malformed");
+  let digest = match DigestFile::parse(digest_with_suffix) {
+    Ok(DigestFile::Narinfo(digest)) => digest,
+    Ok(DigestFile::Listing(digest)) => {
+      return nar_listing(&app_state, cache_name, digest, user).await;
     }
+    Err(err) => return err.into_response(),
   };
 
+  let anonymous = user.is_none();
   let narinfo_req = NarinfoRequest {
     auth: user.map(|e| e.0.id),
     cache_name: cache_name.value().clone(),
@@ -51,7 +41,67 @@ pub async fn narinfo(
   let narinfo_resp = app_state.domain.narinfo(narinfo_req).await;
 
   match narinfo_resp {
-    Ok(resp) => resp.narinfo().to_string().into_response(),
-    Err(err) => format!("{err:#?}").into_response(),
+    Ok(resp) => resp.to_string().into_response(),
+    // nix checks for a path before uploading it, and needs a 404 to know
+    // it's missing
+    Err(err) => ApiError::from(err).for_anonymous(anonymous).into_response(),
+  }
+}
+
+/// A file named by a store path's digest, next to the path's narinfo.
+#[derive(Debug, PartialEq)]
+pub(crate) enum DigestFile {
+  /// The path's narinfo, at `<digest>.narinfo`.
+  Narinfo(Digest),
+  /// The listing of the path's NAR, at `<digest>.ls`.
+  Listing(Digest),
+}
+
+impl DigestFile {
+  /// Parses the name of a file next to a narinfo.
+  pub(crate) fn parse(file_name: &str) -> Result<Self, ApiError> {
+    let (digest, listing) = match (
+      file_name.strip_suffix(".narinfo"),
+      file_name.strip_suffix(".ls"),
+    ) {
+      (Some(d), _) => (d, false),
+      (_, Some(d)) => (d, true),
+      _ => {
+        return Err(ApiError::new(
+          ErrorCode::EndpointNotFound,
+          "Expected a digest ending in \".narinfo\" or \".ls\"",
+        ));
+      }
+    };
+    let digest = Digest::from_str(digest).map_err(|_| {
+      ApiError::malformed(format!("Digest is malformed: `{digest}`"))
+    })?;
+
+    Ok(match listing {
+      true => Self::Listing(digest),
+      false => Self::Narinfo(digest),
+    })
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn narinfos_and_listings_are_parsed() {
+    let digest = "ky2wzr68im63ibgzksbsar19iyk861x6";
+    let expected = Digest::from_str(digest).unwrap();
+
+    assert_eq!(
+      DigestFile::parse(&format!("{digest}.narinfo")).ok(),
+      Some(DigestFile::Narinfo(expected))
+    );
+    assert_eq!(
+      DigestFile::parse(&format!("{digest}.ls")).ok(),
+      Some(DigestFile::Listing(expected))
+    );
+    assert!(DigestFile::parse(&format!("{digest}.nar")).is_err());
+    assert!(DigestFile::parse("not-a-digest.ls").is_err());
   }
 }
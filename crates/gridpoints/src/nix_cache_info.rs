@@ -1,5 +1,26 @@
-use axum::response::IntoResponse;
+use axum::{extract::State, response::IntoResponse};
+use domain::nix_cache_info::NixCacheInfoRequest;
+use grid_state::AppState;
 
-pub async fn nix_cache_info() -> impl IntoResponse {
-  "StoreDir: /nix/store\nWantMassQuery: 1\nPriority: 30"
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[axum::debug_handler]
+pub async fn nix_cache_info(
+  cache_name: CacheNameExtractor,
+  user: Option<UserAuthExtractor>,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let anonymous = user.is_none();
+  let req = NixCacheInfoRequest {
+    auth:       user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+  };
+
+  match app_state.domain.nix_cache_info(req).await {
+    Ok(resp) => resp.to_string().into_response(),
+    Err(err) => ApiError::from(err).for_anonymous(anonymous).into_response(),
+  }
 }
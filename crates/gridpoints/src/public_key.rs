@@ -0,0 +1,26 @@
+use axum::{extract::State, response::IntoResponse};
+use domain::signing::CachePublicKeyRequest;
+use grid_state::AppState;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[axum::debug_handler]
+pub async fn public_key(
+  cache_name: CacheNameExtractor,
+  user: Option<UserAuthExtractor>,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let anonymous = user.is_none();
+  let req = CachePublicKeyRequest {
+    auth:       user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+  };
+
+  match app_state.domain.cache_public_key(req).await {
+    Ok(public_key) => public_key.into_response(),
+    Err(err) => ApiError::from(err).for_anonymous(anonymous).into_response(),
+  }
+}
@@ -0,0 +1,126 @@
+use axum::http::{
+  HeaderMap,
+  header::{IF_RANGE, RANGE},
+};
+use domain::download::ByteRange;
+
+/// What part of a file to serve for a request.
+#[derive(Debug, PartialEq, Eq)]
+pub enum RangeOutcome {
+  /// Serve the whole file.
+  Full,
+  /// Serve just this range.
+  Partial(ByteRange),
+  /// The requested range doesn't overlap the file.
+  Unsatisfiable,
+}
+
+/// Decides what part of a file to serve from the `Range` and `If-Range`
+/// headers of a request. Like the spec allows, malformed and multi-part
+/// ranges are ignored, and the whole file is served instead.
+pub fn resolve_range(
+  headers: &HeaderMap,
+  file_size: u64,
+  etag: &str,
+) -> RangeOutcome {
+  let Some(range) = headers.get(RANGE).and_then(|v| v.to_str().ok()) else {
+    return RangeOutcome::Full;
+  };
+
+  // only a matching strong validator allows a partial response. we don't send
+  // `Last-Modified`, so dates never match.
+  if headers
+    .get(IF_RANGE)
+    .is_some_and(|v| v.as_bytes() != etag.as_bytes())
+  {
+    return RangeOutcome::Full;
+  }
+
+  parse_range(range, file_size)
+}
+
+/// Parses a single `bytes=` range against the size of the file.
+fn parse_range(range: &str, file_size: u64) -> RangeOutcome {
+  let Some(spec) = range.trim().strip_prefix("bytes=") else {
+    return RangeOutcome::Full;
+  };
+  let Some((first, last)) = spec.trim().split_once('-') else {
+    return RangeOutcome::Full;
+  };
+  let parse = |s: &str| s.trim().parse::<u64>().ok();
+
+  let (start, end) = match (first.trim(), last.trim()) {
+    // `bytes=-<len>` is the last `len` bytes
+    ("", len) => match parse(len) {
+      Some(0) => return RangeOutcome::Unsatisfiable,
+      Some(len) => (file_size.saturating_sub(len), file_size.saturating_sub(1)),
+      None => return RangeOutcome::Full,
+    },
+    // `bytes=<start>-` runs to the end of the file
+    (start, "") => match parse(start) {
+      Some(start) => (start, file_size.saturating_sub(1)),
+      None => return RangeOutcome::Full,
+    },
+    (start, end) => match (parse(start), parse(end)) {
+      (Some(start), Some(end)) if start <= end => {
+        (start, end.min(file_size.saturating_sub(1)))
+      }
+      _ => return RangeOutcome::Full,
+    },
+  };
+
+  if file_size == 0 || start >= file_size {
+    return RangeOutcome::Unsatisfiable;
+  }
+  RangeOutcome::Partial(ByteRange { start, end })
+}
+
+#[cfg(test)]
+mod tests {
+  use axum::http::HeaderValue;
+
+  use super::*;
+
+  fn partial(start: u64, end: u64) -> RangeOutcome {
+    RangeOutcome::Partial(ByteRange { start, end })
+  }
+
+  #[test]
+  fn parses_single_ranges() {
+    assert_eq!(parse_range("bytes=0-99", 1000), partial(0, 99));
+    assert_eq!(parse_range("bytes=500-", 1000), partial(500, 999));
+    assert_eq!(parse_range("bytes=-100", 1000), partial(900, 999));
+    assert_eq!(parse_range("bytes=-5000", 1000), partial(0, 999));
+    assert_eq!(parse_range("bytes=900-5000", 1000), partial(900, 999));
+  }
+
+  #[test]
+  fn rejects_ranges_outside_the_file() {
+    assert_eq!(
+      parse_range("bytes=1000-", 1000),
+      RangeOutcome::Unsatisfiable
+    );
+    assert_eq!(parse_range("bytes=-0", 1000), RangeOutcome::Unsatisfiable);
+    assert_eq!(parse_range("bytes=0-", 0), RangeOutcome::Unsatisfiable);
+  }
+
+  #[test]
+  fn ignores_malformed_and_multipart_ranges() {
+    for range in ["items=0-1", "bytes=5-1", "bytes=a-b", "bytes=0-1,5-6"] {
+      assert_eq!(parse_range(range, 1000), RangeOutcome::Full, "{range}");
+    }
+  }
+
+  #[test]
+  fn honors_ranges_only_for_matching_validators() {
+    let mut headers = HeaderMap::new();
+    headers.insert(RANGE, HeaderValue::from_static("bytes=10-19"));
+    assert_eq!(resolve_range(&headers, 100, "\"abc\""), partial(10, 19));
+
+    headers.insert(IF_RANGE, HeaderValue::from_static("\"abc\""));
+    assert_eq!(resolve_range(&headers, 100, "\"abc\""), partial(10, 19));
+
+    headers.insert(IF_RANGE, HeaderValue::from_static("\"def\""));
+    assert_eq!(resolve_range(&headers, 100, "\"abc\""), RangeOutcome::Full);
+  }
+}
@@ -0,0 +1,92 @@
+use std::collections::HashMap;
+
+use axum::{
+  extract::{Path, State},
+  http::{StatusCode, header::CONTENT_TYPE},
+  response::IntoResponse,
+};
+use domain::{
+  models::DrvOutput,
+  realisation::{RealisationRequest, RealisationUploadRequest},
+};
+use grid_state::AppState;
+
+use super::{
+  error::{ApiError, ErrorCode},
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[axum::debug_handler]
+pub async fn realisation(
+  cache_name: CacheNameExtractor,
+  Path(params): Path<HashMap<String, String>>,
+  user: Option<UserAuthExtractor>,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let drv_output = match drv_output_from_params(&params) {
+    Ok(drv_output) => drv_output,
+    Err(err) => return err.into_response(),
+  };
+
+  let anonymous = user.is_none();
+  let req = RealisationRequest {
+    auth: user.map(|e| e.0.id),
+    cache_name: cache_name.value().clone(),
+    drv_output,
+  };
+
+  match app_state.domain.realisation(req).await {
+    Ok(document) => {
+      ([(CONTENT_TYPE, "application/json")], document).into_response()
+    }
+    // like narinfos, nix needs a 404 to know a realisation is missing
+    Err(err) => ApiError::from(err).for_anonymous(anonymous).into_response(),
+  }
+}
+
+#[axum::debug_handler]
+pub async fn put_realisation(
+  cache_name: CacheNameExtractor,
+  Path(params): Path<HashMap<String, String>>,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  document: String,
+) -> impl IntoResponse {
+  let drv_output = match drv_output_from_params(&params) {
+    Ok(drv_output) => drv_output,
+    Err(err) => return err.into_response(),
+  };
+
+  let req = RealisationUploadRequest {
+    auth: user.id,
+    cache_name: cache_name.value().clone(),
+    drv_output,
+    document,
+  };
+
+  match app_state.domain.upload_realisation(req).await {
+    Ok(_) => StatusCode::OK.into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+/// Parses the derivation output from the route, which Nix gives as
+/// `<drv-output>.doi`.
+fn drv_output_from_params(
+  params: &HashMap<String, String>,
+) -> Result<DrvOutput, ApiError> {
+  let file_name = params
+    .get("file_name")
+    .expect("realisation route param names are malformed");
+  let Some(drv_output) = file_name.strip_suffix(".doi") else {
+    return Err(ApiError::new(
+      ErrorCode::EndpointNotFound,
+      "Expected a derivation output ending in \".doi\"",
+    ));
+  };
+  drv_output.parse().map_err(|_| {
+    ApiError::malformed(format!(
+      "Derivation output is malformed: `{drv_output}`"
+    ))
+  })
+}
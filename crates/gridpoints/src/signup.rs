@@ -6,7 +6,10 @@ use domain::{
 };
 use serde::Deserialize;
 
-use crate::util_traits::InternalError;
+use crate::{
+  error::{ApiError, ErrorCode},
+  util_traits::InternalError,
+};
 
 #[derive(Deserialize)]
 pub struct SignupParams {
@@ -22,39 +25,36 @@ pub async fn signup(
   Json(params): Json<SignupParams>,
 ) -> impl IntoResponse {
   let Some(name) = params.name else {
-    return (StatusCode::BAD_REQUEST, "Missing `name` field").into_response();
+    return ApiError::malformed("Missing `name` field").into_response();
   };
   if name.is_empty() {
-    return (StatusCode::BAD_REQUEST, "Empty `name` field").into_response();
+    return ApiError::malformed("Empty `name` field").into_response();
   }
   let name = match HumanName::try_new(name) {
     Ok(name) => name,
     Err(_) => {
-      return (StatusCode::BAD_REQUEST, "Malformed `name` field")
-        .into_response();
+      return ApiError::malformed("Malformed `name` field").into_response();
     }
   };
 
   let Some(email) = params.email else {
-    return (StatusCode::BAD_REQUEST, "Missing `email` field").into_response();
+    return ApiError::malformed("Missing `email` field").into_response();
   };
   if email.is_empty() {
-    return (StatusCode::BAD_REQUEST, "Empty `email` field").into_response();
+    return ApiError::malformed("Empty `email` field").into_response();
   }
   let email = match EmailAddress::try_new(email) {
     Ok(email) => email,
     Err(_) => {
-      return (StatusCode::BAD_REQUEST, "Malformed `email` field")
-        .into_response();
+      return ApiError::malformed("Malformed `email` field").into_response();
     }
   };
 
   let Some(password) = params.password else {
-    return (StatusCode::BAD_REQUEST, "Missing `password` field")
-      .into_response();
+    return ApiError::malformed("Missing `password` field").into_response();
   };
   if password.is_empty() {
-    return (StatusCode::BAD_REQUEST, "Empty `password` field").into_response();
+    return ApiError::malformed("Empty `password` field").into_response();
   }
 
   let creds = UserSubmittedAuthCredentials::Password { password };
@@ -63,13 +63,17 @@ pub async fn signup(
     .user_signup(name, email.clone(), creds.clone())
     .await
   {
-    return e.internal("failed to sign up");
+    return ApiError::from(e).into_response();
   };
 
   let user = match auth_session.authenticate((email, creds)).await {
     Ok(Some(user)) => user,
     Ok(None) => {
-      return (StatusCode::UNAUTHORIZED, Json(())).into_response();
+      return ApiError::new(
+        ErrorCode::Unauthenticated,
+        "invalid email or password",
+      )
+      .into_response();
     }
     Err(e) => {
       return e.internal("failed to authenticate");
@@ -0,0 +1,41 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use domain::{cache_settings::UpdateStoreDirRequest, models::StoreDir};
+use grid_state::AppState;
+use serde::Deserialize;
+
+use super::{
+  error::{ApiError, ErrorCode},
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[derive(Deserialize)]
+pub struct StoreDirParams {
+  store_dir: String,
+}
+
+#[axum::debug_handler]
+pub async fn update_store_dir(
+  cache_name: CacheNameExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  Json(params): Json<StoreDirParams>,
+) -> impl IntoResponse {
+  let store_dir = match StoreDir::new(params.store_dir) {
+    Ok(store_dir) => store_dir,
+    Err(err) => {
+      return ApiError::new(ErrorCode::InvalidSettings, err.to_string())
+        .into_response();
+    }
+  };
+
+  let req = UpdateStoreDirRequest {
+    auth: user.id,
+    cache_name: cache_name.value().clone(),
+    store_dir,
+  };
+
+  match app_state.domain.update_cache_store_dir(req).await {
+    Ok(()) => Json(()).into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
@@ -0,0 +1,57 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use domain::{models::StorePath, store_entries::StoreEntriesRequest};
+use grid_state::AppState;
+use serde::Deserialize;
+
+use super::{
+  error::ApiError,
+  extractors::{TargetStoreExtractor, UserAuthExtractor},
+};
+
+#[derive(Deserialize)]
+pub struct StoreEntriesParams {
+  /// Store paths, absolute or as base names.
+  paths: Vec<String>,
+}
+
+#[axum::debug_handler]
+pub async fn store_entries(
+  target_store: TargetStoreExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  Json(params): Json<StoreEntriesParams>,
+) -> impl IntoResponse {
+  let mut store_paths = Vec::with_capacity(params.paths.len());
+  for path in params.paths.iter() {
+    let base_name = path.rsplit('/').next().unwrap_or_default();
+    match StorePath::<String>::from_bytes(base_name.as_bytes()) {
+      Ok(store_path) => store_paths.push(store_path),
+      Err(_) => {
+        return ApiError::malformed(format!(
+          "Store path is malformed: `{path}`"
+        ))
+        .into_response();
+      }
+    }
+  }
+
+  let req = StoreEntriesRequest {
+    auth:        user.id,
+    store_name:  target_store.value().clone(),
+    store_paths: store_paths.clone(),
+  };
+
+  match app_state.domain.store_entries(req).await {
+    Ok(entries) => {
+      // answer with the paths as they were given
+      let entries = params
+        .paths
+        .into_iter()
+        .zip(store_paths)
+        .filter_map(|(p, s)| Some((p, entries.get(&s)?.to_string().into())))
+        .collect::<serde_json::Map<_, _>>();
+      Json(serde_json::json!({ "entries": entries })).into_response()
+    }
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
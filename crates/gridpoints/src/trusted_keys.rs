@@ -0,0 +1,36 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use domain::cache_settings::UpdateTrustedKeysRequest;
+use grid_state::AppState;
+use serde::Deserialize;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[derive(Deserialize)]
+pub struct TrustedKeysParams {
+  trusted_public_keys:       Vec<String>,
+  #[serde(default)]
+  require_trusted_signature: bool,
+}
+
+#[axum::debug_handler]
+pub async fn update_trusted_keys(
+  cache_name: CacheNameExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  Json(params): Json<TrustedKeysParams>,
+) -> impl IntoResponse {
+  let req = UpdateTrustedKeysRequest {
+    auth: user.id,
+    cache_name: cache_name.value().clone(),
+    trusted_public_keys: params.trusted_public_keys,
+    require_trusted_signature: params.require_trusted_signature,
+  };
+
+  match app_state.domain.update_cache_trusted_keys(req).await {
+    Ok(()) => Json(()).into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
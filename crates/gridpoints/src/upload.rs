@@ -4,16 +4,20 @@ use axum::{
   Json,
   body::Body,
   extract::{Query, State},
-  http::StatusCode,
   response::IntoResponse,
 };
 use domain::{belt::Belt, models::NarDeriverData, upload::UploadRequest};
 use grid_state::AppState;
 use http_body_util::BodyExt;
 
-use super::extractors::{
-  CacheListExtractor, DeriverStorePathExtractor, StorePathExtractor,
-  TargetStoreExtractor, UserAuthExtractor,
+use super::{
+  error::ApiError,
+  extractors::{
+    CaHashExtractor, CacheListExtractor, CompressionExtractor,
+    DeriverStorePathExtractor, NarHashExtractor, ReferenceListExtractor,
+    SignatureListExtractor, StorePathExtractor, TargetStoreExtractor,
+    UserAuthExtractor,
+  },
 };
 
 #[allow(clippy::too_many_arguments)]
@@ -22,25 +26,28 @@ pub async fn upload(
   Query(query): Query<HashMap<String, String>>,
   CacheListExtractor(caches): CacheListExtractor,
   store_path: StorePathExtractor,
-  deriver_store_path: DeriverStorePathExtractor,
+  deriver_store_path: Option<DeriverStorePathExtractor>,
   target_store: TargetStoreExtractor,
+  CaHashExtractor(ca_hash): CaHashExtractor,
+  NarHashExtractor(nar_hash): NarHashExtractor,
+  CompressionExtractor(compression): CompressionExtractor,
+  ReferenceListExtractor(references): ReferenceListExtractor,
+  SignatureListExtractor(signatures): SignatureListExtractor,
   UserAuthExtractor(user): UserAuthExtractor,
   State(app_state): State<AppState>,
   body: Body,
 ) -> impl IntoResponse {
   let Some(deriver_system) = query.get("deriver_system") else {
-    return (StatusCode::BAD_REQUEST, "Deriver system is missing")
-      .into_response();
+    return ApiError::malformed("Deriver system is missing").into_response();
   };
   if deriver_system.is_empty() {
-    return (StatusCode::BAD_REQUEST, "Deriver system is missing")
-      .into_response();
+    return ApiError::malformed("Deriver system is missing").into_response();
   }
 
   // WARNING: the system field is totally unvalidated at this point.
   let deriver_data = NarDeriverData {
     system:  Some(deriver_system.clone()),
-    deriver: Some(deriver_store_path.value().clone()),
+    deriver: deriver_store_path.map(|d| d.value().clone()),
   };
 
   let nar_contents = Belt::new(
@@ -53,16 +60,20 @@ pub async fn upload(
     auth: user.id,
     target_store: target_store.value().clone(),
     nar_contents,
+    compression,
     caches,
     store_path: store_path.value().clone(),
     deriver_data,
+    ca_hash,
+    nar_hash,
+    nar_size: None,
+    references,
+    signatures,
   };
 
   let upload_plan = match app_state.domain.plan_upload(upload_req).await {
     Ok(plan) => plan,
-    Err(err) => {
-      return format!("{err:?}").into_response();
-    }
+    Err(err) => return ApiError::from(err).into_response(),
   };
   match app_state.domain.execute_upload(upload_plan).await {
     Ok(resp) => {
@@ -75,6 +86,6 @@ pub async fn upload(
       }))
       .into_response()
     }
-    Err(err) => format!("{err:?}").into_response(),
+    Err(err) => ApiError::from(err).into_response(),
   }
 }
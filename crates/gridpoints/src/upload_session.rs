@@ -0,0 +1,206 @@
+use std::{collections::HashMap, io, str::FromStr};
+
+use axum::{
+  Json,
+  body::Body,
+  extract::{Path, Query, State},
+  response::{IntoResponse, Response},
+};
+use domain::{
+  belt::Belt,
+  models::{NarDeriverData, RecordId, UploadSession, UploadSessionPart},
+  upload_session::StartUploadSessionRequest,
+};
+use grid_state::AppState;
+use http_body_util::BodyExt;
+
+use super::{
+  error::ApiError,
+  extractors::{
+    CaHashExtractor, CacheListExtractor, CompressionExtractor,
+    DeriverStorePathExtractor, NarHashExtractor, ReferenceListExtractor,
+    SignatureListExtractor, StorePathExtractor, TargetStoreExtractor,
+    UserAuthExtractor,
+  },
+};
+
+fn parse_session_id(
+  params: &HashMap<String, String>,
+) -> Result<RecordId<UploadSession>, Response> {
+  let value = params
+    .get("session_id")
+    .expect("upload session route param names are malformed");
+  RecordId::from_str(value).map_err(|_| {
+    ApiError::malformed(format!("Upload session ID is malformed: `{value}`"))
+      .into_response()
+  })
+}
+
+fn part_json(part: &UploadSessionPart) -> serde_json::Value {
+  serde_json::json!({
+    "number": part.number,
+    "size": part.size,
+  })
+}
+
+#[allow(clippy::too_many_arguments)]
+#[axum::debug_handler]
+pub async fn start_upload_session(
+  Query(query): Query<HashMap<String, String>>,
+  CacheListExtractor(caches): CacheListExtractor,
+  store_path: StorePathExtractor,
+  deriver_store_path: Option<DeriverStorePathExtractor>,
+  target_store: TargetStoreExtractor,
+  CaHashExtractor(ca_hash): CaHashExtractor,
+  NarHashExtractor(nar_hash): NarHashExtractor,
+  CompressionExtractor(compression): CompressionExtractor,
+  ReferenceListExtractor(references): ReferenceListExtractor,
+  SignatureListExtractor(signatures): SignatureListExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let Some(deriver_system) = query.get("deriver_system") else {
+    return ApiError::malformed("Deriver system is missing").into_response();
+  };
+  if deriver_system.is_empty() {
+    return ApiError::malformed("Deriver system is missing").into_response();
+  }
+
+  // WARNING: the system field is totally unvalidated at this point.
+  let deriver_data = NarDeriverData {
+    system:  Some(deriver_system.clone()),
+    deriver: deriver_store_path.map(|d| d.value().clone()),
+  };
+
+  let req = StartUploadSessionRequest {
+    auth: user.id,
+    caches,
+    target_store: target_store.value().clone(),
+    store_path: store_path.value().clone(),
+    deriver_data,
+    ca_hash,
+    nar_hash,
+    compression,
+    references,
+    signatures,
+  };
+
+  match app_state.domain.start_upload_session(req).await {
+    Ok(session) => Json(serde_json::json!({
+      "session_id": session.id,
+      "expires_at": session.expires_at,
+    }))
+    .into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+#[axum::debug_handler]
+pub async fn upload_session_status(
+  Path(params): Path<HashMap<String, String>>,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let session_id = match parse_session_id(&params) {
+    Ok(id) => id,
+    Err(resp) => return resp,
+  };
+
+  match app_state.domain.upload_session(user.id, session_id).await {
+    Ok(status) => Json(serde_json::json!({
+      "session_id": status.session.id,
+      "parts": status.parts.iter().map(part_json).collect::<Vec<_>>(),
+      "expires_at": status.session.expires_at,
+    }))
+    .into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+#[axum::debug_handler]
+pub async fn upload_session_part(
+  Path(params): Path<HashMap<String, String>>,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  body: Body,
+) -> impl IntoResponse {
+  let session_id = match parse_session_id(&params) {
+    Ok(id) => id,
+    Err(resp) => return resp,
+  };
+  let part_number = params
+    .get("part_number")
+    .expect("upload session route param names are malformed");
+  let Ok(part_number) = u32::from_str(part_number) else {
+    return ApiError::malformed(format!(
+      "Part number is malformed: `{part_number}`"
+    ))
+    .into_response();
+  };
+
+  let data = Belt::new(
+    body
+      .map_err(|e| io::Error::other(e.to_string()))
+      .into_data_stream(),
+  );
+
+  match app_state
+    .domain
+    .upload_session_part(user.id, session_id, part_number, data)
+    .await
+  {
+    Ok(part) => Json(part_json(&part)).into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+#[axum::debug_handler]
+pub async fn finalize_upload_session(
+  Path(params): Path<HashMap<String, String>>,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let session_id = match parse_session_id(&params) {
+    Ok(id) => id,
+    Err(resp) => return resp,
+  };
+
+  match app_state
+    .domain
+    .finalize_upload_session(user.id, session_id)
+    .await
+  {
+    Ok(resp) => {
+      app_state
+        .metrics_domain
+        .send_event(resp.compute_event)
+        .await;
+      Json(serde_json::json!({
+        "entry_id": resp.entry_id,
+      }))
+      .into_response()
+    }
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
+
+#[axum::debug_handler]
+pub async fn abort_upload_session(
+  Path(params): Path<HashMap<String, String>>,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+) -> impl IntoResponse {
+  let session_id = match parse_session_id(&params) {
+    Ok(id) => id,
+    Err(resp) => return resp,
+  };
+
+  match app_state
+    .domain
+    .abort_upload_session(user.id, session_id)
+    .await
+  {
+    Ok(()) => Json(()).into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
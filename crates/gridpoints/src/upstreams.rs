@@ -0,0 +1,52 @@
+use axum::{Json, extract::State, response::IntoResponse};
+use domain::{
+  cache_settings::{UpdateUpstreamsRequest, UpstreamSettings},
+  models::EntityName,
+};
+use grid_state::AppState;
+use serde::Deserialize;
+
+use super::{
+  error::ApiError,
+  extractors::{CacheNameExtractor, UserAuthExtractor},
+};
+
+#[derive(Deserialize)]
+pub struct UpstreamsParams {
+  upstreams: Vec<UpstreamParams>,
+}
+
+#[derive(Deserialize)]
+pub struct UpstreamParams {
+  url:           String,
+  public_keys:   Vec<String>,
+  #[serde(default)]
+  persist_store: Option<String>,
+}
+
+#[axum::debug_handler]
+pub async fn update_upstreams(
+  cache_name: CacheNameExtractor,
+  UserAuthExtractor(user): UserAuthExtractor,
+  State(app_state): State<AppState>,
+  Json(params): Json<UpstreamsParams>,
+) -> impl IntoResponse {
+  let req = UpdateUpstreamsRequest {
+    auth:       user.id,
+    cache_name: cache_name.value().clone(),
+    upstreams:  params
+      .upstreams
+      .into_iter()
+      .map(|u| UpstreamSettings {
+        url:           u.url,
+        public_keys:   u.public_keys,
+        persist_store: u.persist_store.map(EntityName::new),
+      })
+      .collect(),
+  };
+
+  match app_state.domain.update_cache_upstreams(req).await {
+    Ok(()) => Json(()).into_response(),
+    Err(err) => ApiError::from(err).into_response(),
+  }
+}
@@ -1,5 +1,6 @@
 use axum::response::{IntoResponse, Response};
-use http::StatusCode;
+
+use crate::error::ApiError;
 
 /// A trait for obfuscating internal errors for public responses.
 pub trait InternalError<R> {
@@ -9,7 +10,6 @@ pub trait InternalError<R> {
 
 impl<E: std::fmt::Debug> InternalError<Response> for E {
   fn internal(self, desc: &str) -> Response {
-    tracing::error!("{desc}: {self:#?}");
-    (StatusCode::INTERNAL_SERVER_ERROR, "internal error").into_response()
+    ApiError::internal(self, desc).into_response()
   }
 }
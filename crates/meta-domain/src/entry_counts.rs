@@ -1,5 +1,9 @@
+use std::path::Path;
+
 use db::DatabaseError;
-use models::{Cache, EntryIndexSelector, RecordId, Store, model::IndexValue};
+use models::{
+  Cache, Entry, EntryIndexSelector, RecordId, Store, model::IndexValue,
+};
 
 use super::MetaService;
 
@@ -33,4 +37,21 @@ impl MetaService {
       )
       .await
   }
+
+  /// Counts the number of [`Entry`]s referencing a storage path in a
+  /// [`Store`]. This is the reference count of the NAR blob at that path.
+  #[tracing::instrument(skip(self))]
+  pub async fn count_entries_by_store_id_and_storage_path(
+    &self,
+    store: RecordId<Store>,
+    storage_path: &Path,
+  ) -> Result<u64, DatabaseError> {
+    self
+      .entry_repo
+      .count_by_index(
+        EntryIndexSelector::StoreIdAndStoragePath,
+        &Entry::index_store_id_and_storage_path(store, storage_path),
+      )
+      .await
+  }
 }
@@ -0,0 +1,23 @@
+use db::DatabaseError;
+use models::{BuildLog, BuildLogIndexSelector, Cache, RecordId, StorePath};
+
+use super::MetaService;
+
+impl MetaService {
+  /// Fetches a [`BuildLog`] by its
+  /// [cache-id-and-drv-path](BuildLogIndexSelector::CacheIdAndDrvPath).
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_build_log_by_cache_id_and_drv_path(
+    &self,
+    cache_id: RecordId<Cache>,
+    drv_path: &StorePath<String>,
+  ) -> Result<Option<BuildLog>, DatabaseError> {
+    self
+      .build_log_repo
+      .find_by_unique_index(
+        BuildLogIndexSelector::CacheIdAndDrvPath,
+        &BuildLog::unique_index_cache_id_and_drv_path(cache_id, drv_path),
+      )
+      .await
+  }
+}
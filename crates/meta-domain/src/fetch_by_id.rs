@@ -1,5 +1,8 @@
 use db::DatabaseError;
-use models::{Cache, Entry, Org, RecordId, Store, User};
+use models::{
+  BuildLog, Cache, Entry, Org, Realisation, RecordId, Store, UploadSession,
+  User,
+};
 
 use super::MetaService;
 
@@ -25,5 +28,8 @@ impl MetaService {
     fetch_store_by_id, Store, store_repo;
     fetch_entry_by_id, Entry, entry_repo;
     fetch_cache_by_id, Cache, cache_repo;
+    fetch_upload_session_by_id, UploadSession, upload_session_repo;
+    fetch_build_log_by_id, BuildLog, build_log_repo;
+    fetch_realisation_by_id, Realisation, realisation_repo;
   }
 }
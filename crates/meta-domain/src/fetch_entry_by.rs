@@ -1,10 +1,18 @@
+use std::collections::{HashMap, HashSet};
+
 use db::DatabaseError;
+use futures::future::try_join_all;
 use models::{
   Cache, Digest, Entry, EntryIndexSelector, RecordId, Store, StorePath,
+  model::IndexValue,
 };
 
 use super::MetaService;
 
+/// The number of lookups run concurrently by
+/// [`fetch_digests_present_in_cache`](MetaService::fetch_digests_present_in_cache).
+const PRESENCE_LOOKUP_BATCH_SIZE: usize = 64;
+
 impl MetaService {
   /// Fetches a [`Entry`] by its
   /// [cache-id-and-entry-digest](EntryIndexSelector::CacheIdAndEntryDigest).
@@ -42,4 +50,255 @@ impl MetaService {
       )
       .await
   }
+
+  /// Fetches an [`Entry`] in a store with the given NAR hash, through its
+  /// [store-id-and-nar-hash](EntryIndexSelector::StoreIdAndNarHash) index.
+  /// If multiple entries share the NAR hash, any one of them is returned.
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_entry_by_store_id_and_nar_hash(
+    &self,
+    store_id: RecordId<Store>,
+    nar_hash: &[u8; 32],
+  ) -> Result<Option<Entry>, DatabaseError> {
+    Ok(
+      self
+        .entry_repo
+        .find_by_index(
+          EntryIndexSelector::StoreIdAndNarHash,
+          &Entry::index_store_id_and_nar_hash(store_id, nar_hash),
+        )
+        .await?
+        .into_iter()
+        .next(),
+    )
+  }
+
+  /// Fetches an [`Entry`] in a cache whose NAR file has the given hash,
+  /// through its
+  /// [cache-id-and-file-hash](EntryIndexSelector::CacheIdAndFileHash)
+  /// index. If multiple entries share the file, any one of them is returned.
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_entry_by_cache_id_and_file_hash(
+    &self,
+    cache_id: RecordId<Cache>,
+    file_hash: &[u8; 32],
+  ) -> Result<Option<Entry>, DatabaseError> {
+    Ok(
+      self
+        .entry_repo
+        .find_by_index(
+          EntryIndexSelector::CacheIdAndFileHash,
+          &Entry::index_cache_id_and_file_hash_single(cache_id, file_hash),
+        )
+        .await?
+        .into_iter()
+        .next(),
+    )
+  }
+
+  /// Fetches an [`Entry`] in a cache holding a debug info file with the given
+  /// ELF build ID, through its
+  /// [cache-id-and-build-id](EntryIndexSelector::CacheIdAndBuildId) index.
+  /// If multiple entries hold the file, any one of them is returned.
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_entry_by_cache_id_and_build_id(
+    &self,
+    cache_id: RecordId<Cache>,
+    build_id: &str,
+  ) -> Result<Option<Entry>, DatabaseError> {
+    Ok(
+      self
+        .entry_repo
+        .find_by_index(
+          EntryIndexSelector::CacheIdAndBuildId,
+          &Entry::index_cache_id_and_build_id_single(cache_id, build_id),
+        )
+        .await?
+        .into_iter()
+        .next(),
+    )
+  }
+
+  /// Fetches the IDs of all [`Entry`]s in a [`Cache`], through its
+  /// [caches](EntryIndexSelector::Caches) index.
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_entries_by_cache(
+    &self,
+    cache_id: RecordId<Cache>,
+  ) -> Result<Vec<RecordId<Entry>>, DatabaseError> {
+    Ok(
+      self
+        .entry_repo
+        .find_by_index(
+          EntryIndexSelector::Caches,
+          &IndexValue::new_single(cache_id.to_string()),
+        )
+        .await?
+        .into_iter()
+        .map(|e| e.id)
+        .collect(),
+    )
+  }
+
+  /// Returns which of the given digests have an [`Entry`] in a [`Cache`].
+  ///
+  /// Digests are looked up a whole
+  /// [digest bucket](EntryIndexSelector::CacheIdAndDigestBucket) at a time,
+  /// or one by one if the cache's entries aren't all indexed yet. Lookups are
+  /// run concurrently in batches.
+  #[tracing::instrument(
+    skip(self, cache, digests),
+    fields(cache_id = %cache.id, count = digests.len())
+  )]
+  pub async fn fetch_digests_present_in_cache(
+    &self,
+    cache: &Cache,
+    digests: &[Digest],
+  ) -> Result<HashSet<Digest>, DatabaseError> {
+    let lookups = plan_presence_lookups(digests, cache.entries_indexed());
+
+    let mut present = HashSet::new();
+    for batch in lookups.chunks(PRESENCE_LOOKUP_BATCH_SIZE) {
+      let found = try_join_all(
+        batch
+          .iter()
+          .map(|lookup| self.run_presence_lookup(cache.id, lookup)),
+      )
+      .await?;
+      present.extend(found.into_iter().flatten());
+    }
+    Ok(present)
+  }
+
+  /// Runs one lookup for
+  /// [`fetch_digests_present_in_cache`](Self::fetch_digests_present_in_cache),
+  /// returning the digests it found.
+  async fn run_presence_lookup(
+    &self,
+    cache_id: RecordId<Cache>,
+    lookup: &PresenceLookup,
+  ) -> Result<Vec<Digest>, DatabaseError> {
+    match lookup {
+      PresenceLookup::Bucket(bucket, digests) => {
+        let wanted = digests.iter().collect::<HashSet<_>>();
+        let entries = self
+          .entry_repo
+          .find_by_index(
+            EntryIndexSelector::CacheIdAndDigestBucket,
+            &Entry::index_cache_id_and_digest_bucket_single(cache_id, bucket),
+          )
+          .await?;
+        Ok(
+          entries
+            .into_iter()
+            .map(|e| Digest::from_bytes(*e.store_path.digest()))
+            .filter(|d| wanted.contains(d))
+            .collect(),
+        )
+      }
+      PresenceLookup::Digest(digest) => Ok(
+        self
+          .fetch_entry_by_cache_id_and_entry_digest(cache_id, *digest)
+          .await?
+          .map(|_| *digest)
+          .into_iter()
+          .collect(),
+      ),
+    }
+  }
+}
+
+/// Plans the lookups for
+/// [`fetch_digests_present_in_cache`](MetaService::fetch_digests_present_in_cache).
+///
+/// Digests are grouped by their
+/// [cache-id-and-digest-bucket](EntryIndexSelector::CacheIdAndDigestBucket),
+/// and each bucket is fetched whole, so a query needs at most
+/// [`DIGEST_BUCKET_COUNT`](models::DIGEST_BUCKET_COUNT) lookups however many
+/// digests it holds. Buckets are always fetched, even when a point lookup would
+/// read fewer entries: a query for a closure is bounded by the number of round
+/// trips, not by the size of the entries read.
+///
+/// A cache whose entries aren't all indexed yet (see
+/// [`Cache::entries_indexed`]) has incomplete buckets, so its digests are
+/// looked up one by one through the
+/// [cache-id-and-entry-digest](EntryIndexSelector::CacheIdAndEntryDigest)
+/// index instead.
+fn plan_presence_lookups(
+  digests: &[Digest],
+  by_bucket: bool,
+) -> Vec<PresenceLookup> {
+  if !by_bucket {
+    return digests
+      .iter()
+      .copied()
+      .map(PresenceLookup::Digest)
+      .collect();
+  }
+
+  let mut buckets: HashMap<String, Vec<Digest>> = HashMap::new();
+  for digest in digests {
+    buckets.entry(digest.bucket()).or_default().push(*digest);
+  }
+  buckets
+    .into_iter()
+    .map(|(bucket, digests)| PresenceLookup::Bucket(bucket, digests))
+    .collect()
+}
+
+/// A lookup run by
+/// [`fetch_digests_present_in_cache`](MetaService::fetch_digests_present_in_cache).
+#[derive(Debug, PartialEq)]
+enum PresenceLookup {
+  /// Fetches a whole digest bucket, and checks the digests against it.
+  Bucket(String, Vec<Digest>),
+  /// Looks up a single digest.
+  Digest(Digest),
+}
+
+#[cfg(test)]
+mod tests {
+  use models::DIGEST_BUCKET_COUNT;
+
+  use super::*;
+
+  fn digest(byte: u8) -> Digest { Digest::from_bytes([byte; 20]) }
+
+  #[test]
+  fn indexed_caches_fetch_one_lookup_per_bucket() {
+    let mut digests = (0..=255).map(digest).collect::<Vec<_>>();
+    digests.extend((0..=255).map(digest));
+
+    let lookups = plan_presence_lookups(&digests, true);
+
+    let buckets = digests.iter().map(Digest::bucket).collect::<HashSet<_>>();
+    assert_eq!(lookups.len(), buckets.len());
+    assert!(lookups.len() as u64 <= DIGEST_BUCKET_COUNT);
+    for lookup in &lookups {
+      let PresenceLookup::Bucket(bucket, digests) = lookup else {
+        panic!("expected a bucket lookup, got {lookup:?}");
+      };
+      assert!(digests.iter().all(|d| &d.bucket() == bucket));
+    }
+    let planned = lookups
+      .iter()
+      .map(|l| match l {
+        PresenceLookup::Bucket(_, digests) => digests.len(),
+        PresenceLookup::Digest(_) => 1,
+      })
+      .sum::<usize>();
+    assert_eq!(planned, digests.len());
+  }
+
+  #[test]
+  fn unindexed_caches_look_up_each_digest() {
+    let digests = vec![digest(1), digest(2)];
+
+    let lookups = plan_presence_lookups(&digests, false);
+
+    assert_eq!(lookups, vec![
+      PresenceLookup::Digest(digest(1)),
+      PresenceLookup::Digest(digest(2)),
+    ]);
+  }
 }
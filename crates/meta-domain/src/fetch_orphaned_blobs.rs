@@ -0,0 +1,18 @@
+use db::DatabaseError;
+use models::{OrphanedBlob, OrphanedBlobIndexSelector};
+
+use super::MetaService;
+
+impl MetaService {
+  /// Fetches every [`OrphanedBlob`], through the
+  /// [all](OrphanedBlobIndexSelector::All) index.
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_all_orphaned_blobs(
+    &self,
+  ) -> Result<Vec<OrphanedBlob>, DatabaseError> {
+    self
+      .orphaned_blob_repo
+      .find_by_index(OrphanedBlobIndexSelector::All, &OrphanedBlob::index_all())
+      .await
+  }
+}
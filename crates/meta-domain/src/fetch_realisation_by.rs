@@ -0,0 +1,27 @@
+use db::DatabaseError;
+use models::{
+  Cache, DrvOutput, Realisation, RealisationIndexSelector, RecordId,
+};
+
+use super::MetaService;
+
+impl MetaService {
+  /// Fetches a [`Realisation`] by its
+  /// [cache-id-and-drv-output](RealisationIndexSelector::CacheIdAndDrvOutput).
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_realisation_by_cache_id_and_drv_output(
+    &self,
+    cache_id: RecordId<Cache>,
+    drv_output: &DrvOutput,
+  ) -> Result<Option<Realisation>, DatabaseError> {
+    self
+      .realisation_repo
+      .find_by_unique_index(
+        RealisationIndexSelector::CacheIdAndDrvOutput,
+        &Realisation::unique_index_cache_id_and_drv_output(
+          cache_id, drv_output,
+        ),
+      )
+      .await
+  }
+}
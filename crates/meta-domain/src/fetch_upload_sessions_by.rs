@@ -0,0 +1,72 @@
+use db::DatabaseError;
+use models::{
+  RecordId, Store, UploadSession, UploadSessionIndexSelector,
+  UploadSessionPart, UploadSessionPartIndexSelector, model::IndexValue,
+};
+
+use super::MetaService;
+
+impl MetaService {
+  /// Fetches all [`UploadSession`]s which store their parts in a [`Store`].
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_upload_sessions_by_store(
+    &self,
+    store: RecordId<Store>,
+  ) -> Result<Vec<UploadSession>, DatabaseError> {
+    self
+      .upload_session_repo
+      .find_by_index(
+        UploadSessionIndexSelector::Store,
+        &IndexValue::new_single(store.to_string()),
+      )
+      .await
+  }
+
+  /// Fetches every [`UploadSession`], through the
+  /// [all](UploadSessionIndexSelector::All) index.
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_all_upload_sessions(
+    &self,
+  ) -> Result<Vec<UploadSession>, DatabaseError> {
+    self
+      .upload_session_repo
+      .find_by_index(
+        UploadSessionIndexSelector::All,
+        &UploadSession::index_all(),
+      )
+      .await
+  }
+
+  /// Fetches the [`UploadSessionPart`]s of an [`UploadSession`], in no
+  /// particular order.
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_upload_session_parts_by_session(
+    &self,
+    session: RecordId<UploadSession>,
+  ) -> Result<Vec<UploadSessionPart>, DatabaseError> {
+    self
+      .upload_session_part_repo
+      .find_by_index(
+        UploadSessionPartIndexSelector::Session,
+        &IndexValue::new_single(session.to_string()),
+      )
+      .await
+  }
+
+  /// Fetches an [`UploadSessionPart`] by its
+  /// [session-and-number](UploadSessionPartIndexSelector::SessionAndNumber).
+  #[tracing::instrument(skip(self))]
+  pub async fn fetch_upload_session_part_by_session_and_number(
+    &self,
+    session: RecordId<UploadSession>,
+    number: u32,
+  ) -> Result<Option<UploadSessionPart>, DatabaseError> {
+    self
+      .upload_session_part_repo
+      .find_by_unique_index(
+        UploadSessionPartIndexSelector::SessionAndNumber,
+        &UploadSessionPart::unique_index_session_and_number(session, number),
+      )
+      .await
+  }
+}
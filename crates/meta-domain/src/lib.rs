@@ -1,26 +1,38 @@
 //! Provides [`MetaService`] for read-only only operations on models.
 
 mod entry_counts;
+mod fetch_build_log_by;
 mod fetch_by_id;
 mod fetch_by_name;
 mod fetch_by_org;
 mod fetch_entry_by;
+mod fetch_orphaned_blobs;
+mod fetch_realisation_by;
+mod fetch_upload_sessions_by;
 mod fetch_user_by;
 mod search_stores_by_user;
 
 use db::Database;
-use models::{Cache, Entry, Org, Store, User};
+use models::{
+  BuildLog, Cache, Entry, Org, OrphanedBlob, Realisation, Store, UploadSession,
+  UploadSessionPart, User,
+};
 
 pub use self::search_stores_by_user::SearchByUserError;
 
 /// Service for read-only operations on models.
 #[derive(Debug, Clone)]
 pub struct MetaService {
-  org_repo:   Database<Org>,
-  user_repo:  Database<User>,
-  store_repo: Database<Store>,
-  entry_repo: Database<Entry>,
-  cache_repo: Database<Cache>,
+  org_repo:                 Database<Org>,
+  user_repo:                Database<User>,
+  store_repo:               Database<Store>,
+  entry_repo:               Database<Entry>,
+  cache_repo:               Database<Cache>,
+  upload_session_repo:      Database<UploadSession>,
+  upload_session_part_repo: Database<UploadSessionPart>,
+  build_log_repo:           Database<BuildLog>,
+  realisation_repo:         Database<Realisation>,
+  orphaned_blob_repo:       Database<OrphanedBlob>,
 }
 
 impl MetaService {
@@ -31,6 +43,11 @@ impl MetaService {
     store_repo: Database<Store>,
     entry_repo: Database<Entry>,
     cache_repo: Database<Cache>,
+    upload_session_repo: Database<UploadSession>,
+    upload_session_part_repo: Database<UploadSessionPart>,
+    build_log_repo: Database<BuildLog>,
+    realisation_repo: Database<Realisation>,
+    orphaned_blob_repo: Database<OrphanedBlob>,
   ) -> Self {
     Self {
       org_repo,
@@ -38,17 +55,27 @@ impl MetaService {
       store_repo,
       entry_repo,
       cache_repo,
+      upload_session_repo,
+      upload_session_part_repo,
+      build_log_repo,
+      realisation_repo,
+      orphaned_blob_repo,
     }
   }
 
   /// Creates a mocked-up [`MetaService`].
   pub fn new_mock() -> Self {
     Self {
-      org_repo:   Database::new_mock(),
-      user_repo:  Database::new_mock(),
-      store_repo: Database::new_mock(),
-      entry_repo: Database::new_mock(),
-      cache_repo: Database::new_mock(),
+      org_repo:                 Database::new_mock(),
+      user_repo:                Database::new_mock(),
+      store_repo:               Database::new_mock(),
+      entry_repo:               Database::new_mock(),
+      cache_repo:               Database::new_mock(),
+      upload_session_repo:      Database::new_mock(),
+      upload_session_part_repo: Database::new_mock(),
+      build_log_repo:           Database::new_mock(),
+      realisation_repo:         Database::new_mock(),
+      orphaned_blob_repo:       Database::new_mock(),
     }
   }
 }
@@ -5,15 +5,17 @@ use crate::FileSize;
 /// Represents the compression status of a file.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub enum CompressionStatus {
-  // /// The file is compressed.
-  // Compressed {
-  //   /// The compressed size of the file.
-  //   compressed_size:   FileSize,
-  //   /// The uncompressed size of the file.
-  //   uncompressed_size: FileSize,
-  //   /// The compression algorithm used to compress the file.
-  //   algorithm:         CompressionAlgorithm,
-  // },
+  /// The file is compressed.
+  Compressed {
+    /// The compressed size of the file.
+    compressed_size:   FileSize,
+    /// The uncompressed size of the file.
+    uncompressed_size: FileSize,
+    /// The SHA-256 digest of the compressed file.
+    compressed_hash:   [u8; 32],
+    /// The compression algorithm used to compress the file.
+    algorithm:         CompressionAlgorithm,
+  },
   /// The file is not compressed.
   Uncompressed {
     /// The uncompressed size of the file.
@@ -25,14 +27,38 @@ impl CompressionStatus {
   /// Returns the compression algorithm used to compress the file.
   pub fn algorithm(&self) -> Option<CompressionAlgorithm> {
     match self {
-      // Self::Compressed { algorithm, .. } => Some(*algorithm),
+      Self::Compressed { algorithm, .. } => Some(*algorithm),
       Self::Uncompressed { .. } => None,
     }
   }
+
+  /// Returns the size of the file as it is stored.
+  pub fn stored_size(&self) -> FileSize {
+    match self {
+      Self::Compressed {
+        compressed_size, ..
+      } => *compressed_size,
+      Self::Uncompressed { size } => *size,
+    }
+  }
+
+  /// Returns the size of the file once decompressed.
+  pub fn uncompressed_size(&self) -> FileSize {
+    match self {
+      Self::Compressed {
+        uncompressed_size, ..
+      } => *uncompressed_size,
+      Self::Uncompressed { size } => *size,
+    }
+  }
 }
 
 /// Represents a configuration for compression.
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+///
+/// The default is no compression, which is what stores persisted before
+/// compression was configurable use. New stores are created with
+/// [`recommended`](Self::recommended) instead.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
 pub struct CompressionConfig {
   algorithm: Option<CompressionAlgorithm>,
 }
@@ -43,13 +69,58 @@ impl CompressionConfig {
     Self { algorithm }
   }
 
+  /// The configuration new stores are created with, which compresses with
+  /// [`Zstd`](CompressionAlgorithm::Zstd).
+  pub fn recommended() -> Self { Self::new(Some(CompressionAlgorithm::Zstd)) }
+
   /// Returns the compression algorithm.
   pub fn algorithm(&self) -> Option<CompressionAlgorithm> { self.algorithm }
 }
 
 /// Represents a compression algorithm.
-#[derive(Copy, Clone, Debug, PartialEq, Serialize, Deserialize)]
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
 pub enum CompressionAlgorithm {
   /// The Zstandard compression algorithm.
   Zstd,
+  /// The XZ compression algorithm, which Nix uses by default.
+  Xz,
+  /// The bzip2 compression algorithm.
+  Bzip2,
+}
+
+impl CompressionAlgorithm {
+  /// Returns the name Nix uses for the algorithm in the `Compression` field of
+  /// a narinfo.
+  pub fn nix_name(&self) -> &'static str {
+    match self {
+      Self::Zstd => "zstd",
+      Self::Xz => "xz",
+      Self::Bzip2 => "bzip2",
+    }
+  }
+
+  /// Returns the file extension Nix gives NARs compressed with the algorithm,
+  /// without the leading dot.
+  pub fn nix_extension(&self) -> &'static str {
+    match self {
+      Self::Zstd => "zst",
+      Self::Xz => "xz",
+      Self::Bzip2 => "bz2",
+    }
+  }
+
+  /// Parses an algorithm from the name Nix uses for it.
+  pub fn from_nix_name(name: &str) -> Option<Self> {
+    [Self::Zstd, Self::Xz, Self::Bzip2]
+      .into_iter()
+      .find(|a| a.nix_name() == name)
+  }
+
+  /// Parses an algorithm from the file extension Nix gives NARs compressed
+  /// with it.
+  pub fn from_nix_extension(extension: &str) -> Option<Self> {
+    [Self::Zstd, Self::Xz, Self::Bzip2]
+      .into_iter()
+      .find(|a| a.nix_extension() == extension)
+  }
 }
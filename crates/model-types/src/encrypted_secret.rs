@@ -0,0 +1,18 @@
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+
+/// A secret encrypted at rest with a server-side key.
+#[derive(Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct EncryptedSecret {
+  /// The nonce used to encrypt the secret.
+  pub nonce:      Vec<u8>,
+  /// The encrypted secret, including its authentication tag.
+  pub ciphertext: Vec<u8>,
+}
+
+impl fmt::Debug for EncryptedSecret {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.debug_struct("EncryptedSecret").finish_non_exhaustive()
+  }
+}
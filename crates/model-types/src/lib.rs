@@ -2,6 +2,7 @@
 
 mod compression_status;
 mod email_address;
+mod encrypted_secret;
 mod entity_name;
 mod file_size;
 mod human_name;
@@ -11,6 +12,6 @@ mod visibility;
 pub use slug::*;
 
 pub use self::{
-  compression_status::*, email_address::*, entity_name::*, file_size::*,
-  human_name::*, paddle::*, visibility::*,
+  compression_status::*, email_address::*, encrypted_secret::*, entity_name::*,
+  file_size::*, human_name::*, paddle::*, visibility::*,
 };
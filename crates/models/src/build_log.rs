@@ -0,0 +1,59 @@
+use std::path::PathBuf;
+
+use model::{IndexValue, Model, RecordId};
+use model_types::FileSize;
+use nix_compat::store_path::StorePath;
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+
+use crate::{Cache, Org, Store, User};
+
+/// The build log of a derivation, uploaded to a [`Cache`].
+///
+/// The log is stored zstd-compressed as a blob in a [`Store`], and is served
+/// to Nix by the derivation's path. Each cache holds at most one log per
+/// derivation, which later uploads replace.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Model)]
+#[model(
+  table = "build_log",
+  index(name = "org", extract = |m| vec![IndexValue::new_single(m.org.to_string())]),
+  index(name = "store", extract = |m| vec![IndexValue::new_single(m.store.to_string())]),
+  index(name = "cache_id_and_drv_path", unique, extract =
+    |m| vec![BuildLog::unique_index_cache_id_and_drv_path(m.cache, &m.drv_path)]
+  ),
+)]
+pub struct BuildLog {
+  /// The log's ID.
+  #[model(id)]
+  pub id:          RecordId<BuildLog>,
+  /// The log's org.
+  pub org:         RecordId<Org>,
+  /// The cache the log was uploaded to.
+  pub cache:       RecordId<Cache>,
+  /// The path of the derivation whose build produced the log.
+  pub drv_path:    StorePath<String>,
+  /// The store that the log is stored in.
+  pub store:       RecordId<Store>,
+  /// The size of the log as it is stored, compressed.
+  pub stored_size: FileSize,
+  /// The user who uploaded the log.
+  pub uploaded_by: RecordId<User>,
+  /// When the log was uploaded.
+  pub uploaded_at: UtcDateTime,
+}
+
+impl BuildLog {
+  /// The path within the store where the log is stored.
+  pub fn storage_path(&self) -> PathBuf {
+    PathBuf::from(format!("build-logs/{id}.zst", id = self.id))
+  }
+
+  /// Generates the value of the unique [`BuildLog`] index
+  /// `cache-id-and-drv-path`.
+  pub fn unique_index_cache_id_and_drv_path(
+    cache_id: RecordId<Cache>,
+    drv_path: &StorePath<String>,
+  ) -> IndexValue {
+    IndexValue::new([cache_id.to_string(), drv_path.to_string()])
+  }
+}
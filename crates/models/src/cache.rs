@@ -1,8 +1,13 @@
 use model::{IndexValue, Model, RecordId};
-use model_types::{EntityName, Visibility};
+use model_types::{EncryptedSecret, EntityName, Visibility};
 use serde::{Deserialize, Serialize};
 
-use crate::Org;
+use crate::{Org, Store, StoreDir};
+
+/// The version of the [`Entry`](crate::Entry) indexes. It's bumped whenever
+/// an entry index is added, since indexes are only computed when a record is
+/// written, so entries persisted before then aren't in the new index.
+pub const ENTRY_INDEX_VERSION: u32 = 1;
 
 /// A cache.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Model)]
@@ -18,35 +23,128 @@ use crate::Org;
 pub struct Cache {
   /// The cache's ID.
   #[model(id)]
-  pub id:         RecordId<Cache>,
+  pub id: RecordId<Cache>,
   /// The cache's org.
-  pub org:        RecordId<Org>,
+  pub org: RecordId<Org>,
   /// The cache's name.
-  pub name:       EntityName,
+  pub name: EntityName,
   /// The cache's base visibility.
   pub visibility: Visibility,
+  /// The key used to sign entries added to the cache. Caches created before
+  /// signing was introduced have none.
+  #[serde(default)]
+  pub signing_key: Option<CacheSigningKey>,
+  /// Public keys, in `name:base64` form, whose signatures are accepted on
+  /// uploads to the cache.
+  #[serde(default)]
+  pub trusted_public_keys: Vec<String>,
+  /// Whether uploads to the cache must carry a signature from one of its
+  /// trusted public keys.
+  #[serde(default)]
+  pub require_trusted_signature: bool,
+  /// The store that NARs are stored in when they're uploaded through the
+  /// binary cache protocol, which has no way to name a store.
+  #[serde(default)]
+  pub default_store: Option<RecordId<Store>>,
+  /// Binary caches that paths missing from the cache are fetched from, in
+  /// order of preference.
+  #[serde(default)]
+  pub upstreams: Vec<CacheUpstream>,
+  /// The settings the cache advertises to Nix in its `nix-cache-info`.
+  #[serde(default)]
+  pub nix_cache_info: NixCacheInfoSettings,
+  /// The directory of the Nix store the cache's paths are in.
+  #[serde(default)]
+  pub store_dir: StoreDir,
+  /// The [`ENTRY_INDEX_VERSION`] the cache's entries were last all written
+  /// with. Caches persisted before it was tracked have version 0.
+  #[serde(default)]
+  pub entry_index_version: u32,
+}
+
+impl Cache {
+  /// Whether all of the cache's entries are in the current entry indexes.
+  pub fn entries_indexed(&self) -> bool {
+    self.entry_index_version >= ENTRY_INDEX_VERSION
+  }
+}
+
+/// The settings a [`Cache`] advertises to Nix in its `nix-cache-info`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NixCacheInfoSettings {
+  /// The cache's priority among substituters. Lower values are preferred,
+  /// and `cache.nixos.org` uses 40.
+  pub priority:        u32,
+  /// Whether Nix should query the cache for many paths at once, e.g. when
+  /// evaluating what can be substituted.
+  pub want_mass_query: bool,
+}
+
+impl Default for NixCacheInfoSettings {
+  fn default() -> Self {
+    Self {
+      priority:        30,
+      want_mass_query: true,
+    }
+  }
+}
+
+/// An upstream binary cache that a [`Cache`] falls through to for paths it
+/// doesn't have.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CacheUpstream {
+  /// The base URL of the binary cache, e.g. `https://cache.nixos.org`, or
+  /// another Rambit cache's `/c/{cache_name}` URL.
+  pub url:           String,
+  /// Public keys, in `name:base64` form, one of which must have signed a path
+  /// for it to be served from the upstream.
+  pub public_keys:   Vec<String>,
+  /// The store that paths fetched from the upstream are persisted into, if
+  /// any. Persisted paths are added to the cache.
+  pub persist_store: Option<RecordId<Store>>,
+}
+
+/// A [`Cache`]'s Ed25519 signing keypair.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct CacheSigningKey {
+  /// The name of the key, used in signatures.
+  pub name:       String,
+  /// The public key, in the `name:base64` form used by
+  /// `trusted-public-keys`.
+  pub public_key: String,
+  /// The secret key, encrypted at rest.
+  pub secret_key: EncryptedSecret,
 }
 
 /// The public view of [`Cache`].
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
 pub struct PvCache {
   /// The cache's ID.
-  pub id:         RecordId<Cache>,
+  pub id:             RecordId<Cache>,
   /// The cache's org.
-  pub org:        RecordId<Org>,
+  pub org:            RecordId<Org>,
   /// The cache's name.
-  pub name:       EntityName,
+  pub name:           EntityName,
   /// The cache's base visibility.
-  pub visibility: Visibility,
+  pub visibility:     Visibility,
+  /// The cache's public key, in `name:base64` form.
+  pub public_key:     Option<String>,
+  /// The settings the cache advertises to Nix in its `nix-cache-info`.
+  pub nix_cache_info: NixCacheInfoSettings,
+  /// The directory of the Nix store the cache's paths are in.
+  pub store_dir:      StoreDir,
 }
 
 impl From<Cache> for PvCache {
   fn from(value: Cache) -> Self {
     PvCache {
-      id:         value.id,
-      org:        value.org,
-      name:       value.name,
-      visibility: value.visibility,
+      id:             value.id,
+      org:            value.org,
+      name:           value.name,
+      visibility:     value.visibility,
+      public_key:     value.signing_key.map(|k| k.public_key),
+      nix_cache_info: value.nix_cache_info,
+      store_dir:      value.store_dir,
     }
   }
 }
@@ -1,14 +1,19 @@
 mod abbreviate;
 mod digest;
 mod nar_data;
+mod store_dir;
+
+use std::path::Path;
 
 use model::{IndexValue, Model, RecordId};
+use model_types::CompressionStatus;
+use nix_compat::nixbase32;
 pub use nix_compat::{
   narinfo::Signature, nixhash::CAHash, store_path::StorePath,
 };
 use serde::{Deserialize, Serialize};
 
-pub use self::{abbreviate::*, digest::*, nar_data::*};
+pub use self::{abbreviate::*, digest::*, nar_data::*, store_dir::*};
 use crate::{Org, Store, cache::Cache};
 
 /// An entry.
@@ -16,6 +21,18 @@ use crate::{Org, Store, cache::Cache};
 /// Entries have a store-and-path unique index to prevent storage collisions,
 /// and a cache-and-path unique index to allow querying and prevent entry
 /// duplication within a cache.
+///
+/// NAR blobs are shared between entries in the same store with the same NAR
+/// hash. The store-and-NAR-hash index finds an existing blob for new content,
+/// and the store-and-storage-path index counts the entries referencing a
+/// blob, so that it's only orphaned when the last one goes away.
+///
+/// The cache-and-file-hash index resolves the `nar/<filehash>.nar` URLs that
+/// narinfos point to, and the cache-and-build-ID index resolves the
+/// `debuginfo/<build-id>` lookups of separate debug info files. The
+/// cache-and-digest-bucket index groups a cache's entries by the first
+/// characters of their digests, so that many digests can be checked for
+/// presence in one lookup per bucket.
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Model)]
 #[model(
   table = "entry",
@@ -25,9 +42,24 @@ use crate::{Org, Store, cache::Cache};
   index(name = "store_id_and_entry_path", unique, extract =
     |m| vec![Entry::unique_index_store_id_and_entry_path(m.storage_data.store, &m.store_path)]
   ),
+  index(name = "store_id_and_nar_hash", extract =
+    |m| vec![Entry::index_store_id_and_nar_hash(m.storage_data.store, &m.intrensic_data.nar_hash)]
+  ),
+  index(name = "store_id_and_storage_path", extract =
+    |m| vec![Entry::index_store_id_and_storage_path(m.storage_data.store, &m.storage_data.storage_path)]
+  ),
   index(name = "cache_id_and_entry_digest", unique, extract =
     Entry::unique_index_cache_id_and_entry_digest_all
   ),
+  index(name = "cache_id_and_digest_bucket", extract =
+    Entry::index_cache_id_and_digest_bucket_all
+  ),
+  index(name = "cache_id_and_file_hash", extract =
+    Entry::index_cache_id_and_file_hash_all
+  ),
+  index(name = "cache_id_and_build_id", extract =
+    Entry::index_cache_id_and_build_id_all
+  ),
 )]
 pub struct Entry {
   /// The entry's ID.
@@ -39,6 +71,9 @@ pub struct Entry {
   pub caches:            Vec<RecordId<Cache>>,
   /// The store path that the entry refers to.
   pub store_path:        StorePath<String>,
+  /// The store directory the entry's store path and references are in.
+  #[serde(default)]
+  pub store_dir:         StoreDir,
   /// Intrensic data about the entry's NAR.
   pub intrensic_data:    NarIntrensicData,
   /// Data about how the NAR exists in the [`Store`](super::Store).
@@ -50,6 +85,17 @@ pub struct Entry {
 }
 
 impl Entry {
+  /// The SHA-256 digest of the entry's NAR file as it is stored, which is the
+  /// NAR hash if it's stored uncompressed.
+  pub fn file_hash(&self) -> [u8; 32] {
+    match &self.storage_data.compression_status {
+      CompressionStatus::Compressed {
+        compressed_hash, ..
+      } => *compressed_hash,
+      CompressionStatus::Uncompressed { .. } => self.intrensic_data.nar_hash,
+    }
+  }
+
   /// Generates the value of the unique [`Entry`] index
   /// `store-id-and-entry-path`.
   pub fn unique_index_store_id_and_entry_path(
@@ -59,6 +105,25 @@ impl Entry {
     IndexValue::new([store_id.to_string(), entry_path.to_string()])
   }
 
+  /// Generates the value of the [`Entry`] index `store-id-and-nar-hash`.
+  pub fn index_store_id_and_nar_hash(
+    store_id: RecordId<Store>,
+    nar_hash: &[u8; 32],
+  ) -> IndexValue {
+    IndexValue::new([store_id.to_string(), nixbase32::encode(nar_hash)])
+  }
+
+  /// Generates the value of the [`Entry`] index `store-id-and-storage-path`.
+  pub fn index_store_id_and_storage_path(
+    store_id: RecordId<Store>,
+    storage_path: &Path,
+  ) -> IndexValue {
+    IndexValue::new([
+      store_id.to_string(),
+      storage_path.to_string_lossy().into_owned(),
+    ])
+  }
+
   /// Generates a single value of the unique [`Entry`] index
   /// `cache-id-and-entry-digest`.
   pub fn unique_index_cache_id_and_entry_digest_single(
@@ -82,4 +147,69 @@ impl Entry {
       })
       .collect()
   }
+
+  /// Generates a single value of the [`Entry`] index
+  /// `cache-id-and-digest-bucket`.
+  /// The bucket is one returned by [`Digest::bucket`].
+  pub fn index_cache_id_and_digest_bucket_single(
+    cache_id: RecordId<Cache>,
+    bucket: &str,
+  ) -> IndexValue {
+    IndexValue::new([cache_id.to_string(), bucket.to_owned()])
+  }
+
+  /// Generates all values of the [`Entry`] index
+  /// `cache-id-and-digest-bucket` for a given [`Entry`].
+  pub fn index_cache_id_and_digest_bucket_all(&self) -> Vec<IndexValue> {
+    let bucket = Digest::from_bytes(*self.store_path.digest()).bucket();
+    self
+      .caches
+      .iter()
+      .map(|c| Self::index_cache_id_and_digest_bucket_single(*c, &bucket))
+      .collect()
+  }
+
+  /// Generates a single value of the [`Entry`] index
+  /// `cache-id-and-file-hash`.
+  pub fn index_cache_id_and_file_hash_single(
+    cache_id: RecordId<Cache>,
+    file_hash: &[u8; 32],
+  ) -> IndexValue {
+    IndexValue::new([cache_id.to_string(), nixbase32::encode(file_hash)])
+  }
+
+  /// Generates all values of the [`Entry`] index `cache-id-and-file-hash` for
+  /// a given [`Entry`].
+  pub fn index_cache_id_and_file_hash_all(&self) -> Vec<IndexValue> {
+    let file_hash = self.file_hash();
+    self
+      .caches
+      .iter()
+      .map(|c| Self::index_cache_id_and_file_hash_single(*c, &file_hash))
+      .collect()
+  }
+
+  /// Generates a single value of the [`Entry`] index `cache-id-and-build-id`.
+  pub fn index_cache_id_and_build_id_single(
+    cache_id: RecordId<Cache>,
+    build_id: &str,
+  ) -> IndexValue {
+    IndexValue::new([cache_id.to_string(), build_id.to_owned()])
+  }
+
+  /// Generates all values of the [`Entry`] index `cache-id-and-build-id` for
+  /// a given [`Entry`], one per cache and debug info file.
+  pub fn index_cache_id_and_build_id_all(&self) -> Vec<IndexValue> {
+    self
+      .caches
+      .iter()
+      .flat_map(|c| {
+        self
+          .intrensic_data
+          .debug_info
+          .iter()
+          .map(|d| Self::index_cache_id_and_build_id_single(*c, &d.build_id))
+      })
+      .collect()
+  }
 }
@@ -3,8 +3,16 @@ use std::{fmt, str::FromStr};
 use nix_compat::{nixbase32, store_path::DIGEST_SIZE};
 use serde::{Deserialize, Serialize};
 
+/// The number of leading characters of a digest's nixbase32 encoding which
+/// name its bucket. Each character picks one of 32 values, so this makes 1024
+/// buckets.
+pub const DIGEST_BUCKET_PREFIX_LEN: usize = 2;
+
+/// The number of buckets digests are divided into.
+pub const DIGEST_BUCKET_COUNT: u64 = 32u64.pow(DIGEST_BUCKET_PREFIX_LEN as u32);
+
 /// A store path digest.
-#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize, PartialEq, Eq, Hash)]
 pub struct Digest([u8; DIGEST_SIZE]);
 
 impl Digest {
@@ -13,6 +21,12 @@ impl Digest {
 
   /// Creates a digest from its bytes.
   pub fn from_bytes(input: [u8; DIGEST_SIZE]) -> Self { Self(input) }
+
+  /// Returns the bucket the digest falls in, which is the first
+  /// [`DIGEST_BUCKET_PREFIX_LEN`] characters of its nixbase32 encoding.
+  pub fn bucket(&self) -> String {
+    self.to_string()[..DIGEST_BUCKET_PREFIX_LEN].to_owned()
+  }
 }
 
 impl fmt::Display for Digest {
@@ -2,9 +2,12 @@ use std::{collections::HashSet, path::PathBuf};
 
 use model::RecordId;
 use model_types::{CompressionStatus, FileSize};
-use nix_compat::{narinfo::Signature, nixhash::CAHash, store_path::StorePath};
+use nix_compat::{
+  narinfo::Signature, nixbase32, nixhash::CAHash, store_path::StorePath,
+};
 use serde::{Deserialize, Serialize};
 
+use super::StoreDir;
 use crate::Store;
 
 /// Data intrensic to the NAR contents of an [`Entry`](super::Entry). This can
@@ -19,6 +22,44 @@ pub struct NarIntrensicData {
   pub references: HashSet<StorePath<String>>,
   /// The content-addressed hash of the entry.
   pub ca_hash:    Option<CAHash>,
+  /// Separate debug info files in the NAR, by their ELF build IDs.
+  #[serde(default)]
+  pub debug_info: Vec<DebugInfoFile>,
+}
+
+/// A separate debug info file in a NAR, under `lib/debug/.build-id`.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DebugInfoFile {
+  /// The ELF build ID of the file, in lowercase hex.
+  pub build_id: String,
+  /// The path of the file within the NAR, like
+  /// `lib/debug/.build-id/ab/cdef.debug`.
+  pub member:   String,
+}
+
+impl NarIntrensicData {
+  /// Computes the fingerprint which narinfo signatures sign, of the form
+  /// `1;path;narHash;narSize;refs`, with paths in the given store directory.
+  pub fn fingerprint(
+    &self,
+    store_dir: &StoreDir,
+    store_path: &StorePath<String>,
+  ) -> String {
+    let mut references = self
+      .references
+      .iter()
+      .map(|r| store_dir.absolute_path(r))
+      .collect::<Vec<_>>();
+    references.sort_unstable();
+
+    format!(
+      "1;{path};sha256:{nar_hash};{nar_size};{references}",
+      path = store_dir.absolute_path(store_path),
+      nar_hash = nixbase32::encode(&self.nar_hash),
+      nar_size = self.nar_size.inner(),
+      references = references.join(","),
+    )
+  }
 }
 
 /// Data about how the NAR exists in the [`Store`](crate::Store).
@@ -37,7 +78,12 @@ pub struct NarStorageData {
 #[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Default)]
 pub struct NarAuthenticityData {
   /// The signatures on the NAR's fingerprint data.
-  pub signatures: Vec<Signature<String>>,
+  pub signatures:            Vec<Signature<String>>,
+  /// Signatures supplied by the uploader from keys that none of the entry's
+  /// caches trust, so they couldn't be verified. They're still served, for
+  /// clients which trust their keys to verify.
+  #[serde(default)]
+  pub unverified_signatures: Vec<Signature<String>>,
 }
 
 /// Data about the NAR's deriver.
@@ -49,3 +95,13 @@ pub struct NarDeriverData {
   /// .drv suffix is stripped.
   pub deriver: Option<StorePath<String>>,
 }
+
+impl NarDeriverData {
+  /// The path of the `.drv` that produced the store entry, with its suffix
+  /// restored.
+  pub fn drv_path(&self) -> Option<StorePath<String>> {
+    self.deriver.as_ref().and_then(|deriver| {
+      StorePath::from_bytes(format!("{deriver}.drv").as_bytes()).ok()
+    })
+  }
+}
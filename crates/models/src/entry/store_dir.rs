@@ -0,0 +1,119 @@
+use std::{error::Error, fmt};
+
+use nix_compat::store_path::StorePath;
+use serde::{Deserialize, Serialize};
+
+/// The store directory used by default, and by almost every Nix installation.
+const DEFAULT_STORE_DIR: &str = "/nix/store";
+
+/// The directory of a Nix store, like `/nix/store`, without a trailing slash.
+///
+/// [`StorePath`]s don't carry their store directory, so anything rendering
+/// them as absolute paths or scanning for them needs to know it.
+#[derive(Clone, Debug, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub struct StoreDir(String);
+
+impl StoreDir {
+  /// Creates a store directory, making sure it's a normalized absolute path.
+  pub fn new(dir: impl Into<String>) -> Result<Self, InvalidStoreDir> {
+    let dir = dir.into();
+    let is_valid = dir.starts_with('/')
+      && dir.len() > 1
+      && dir[1..]
+        .split('/')
+        .all(|c| !c.is_empty() && c != "." && c != "..")
+      && dir
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"/._+-".contains(&b));
+    match is_valid {
+      true => Ok(Self(dir)),
+      false => Err(InvalidStoreDir(dir)),
+    }
+  }
+
+  /// Returns the directory as a string.
+  pub fn as_str(&self) -> &str { &self.0 }
+
+  /// Renders a store path as an absolute path in this store directory.
+  pub fn absolute_path<S: AsRef<str>>(
+    &self,
+    store_path: &StorePath<S>,
+  ) -> String {
+    format!("{}/{store_path}", self.0)
+  }
+
+  /// Parses an absolute path in this store directory as a store path.
+  pub fn parse_absolute_path(&self, path: &[u8]) -> Option<StorePath<String>> {
+    let base_name = path.strip_prefix(self.0.as_bytes())?.strip_prefix(b"/")?;
+    StorePath::from_bytes(base_name).ok()
+  }
+}
+
+impl Default for StoreDir {
+  fn default() -> Self { Self(DEFAULT_STORE_DIR.to_owned()) }
+}
+
+impl fmt::Display for StoreDir {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str(&self.0)
+  }
+}
+
+impl TryFrom<String> for StoreDir {
+  type Error = InvalidStoreDir;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> { Self::new(value) }
+}
+
+impl From<StoreDir> for String {
+  fn from(value: StoreDir) -> Self { value.0 }
+}
+
+/// The error returned when a store directory isn't a normalized absolute path.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidStoreDir(pub String);
+
+impl fmt::Display for InvalidStoreDir {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid store directory: \"{}\"", self.0)
+  }
+}
+
+impl Error for InvalidStoreDir {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  #[test]
+  fn store_dirs_are_validated() {
+    assert!(StoreDir::new("/nix/store").is_ok());
+    assert!(StoreDir::new("/gpfs/nix/store").is_ok());
+
+    assert!(StoreDir::new("nix/store").is_err());
+    assert!(StoreDir::new("/nix/store/").is_err());
+    assert!(StoreDir::new("/nix//store").is_err());
+    assert!(StoreDir::new("/nix/../store").is_err());
+    assert!(StoreDir::new("/").is_err());
+    assert!(StoreDir::new("/nix store").is_err());
+  }
+
+  #[test]
+  fn store_paths_round_trip_through_store_dirs() {
+    let store_dir = StoreDir::new("/gpfs/nix/store").unwrap();
+    let absolute_path =
+      "/gpfs/nix/store/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0";
+
+    let store_path = store_dir
+      .parse_absolute_path(absolute_path.as_bytes())
+      .expect("failed to parse store path");
+    assert_eq!(store_dir.absolute_path(&store_path), absolute_path);
+
+    assert!(
+      StoreDir::default()
+        .parse_absolute_path(absolute_path.as_bytes())
+        .is_none()
+    );
+  }
+}
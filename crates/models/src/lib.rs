@@ -2,12 +2,16 @@
 
 #![feature(never_type)]
 
+mod build_log;
 mod cache;
 mod entry;
 mod org;
+mod orphaned_blob;
+mod realisation;
 #[cfg(feature = "session")]
 mod session;
 mod store;
+mod upload_session;
 mod user;
 
 pub use model::{self, RecordId};
@@ -16,4 +20,7 @@ pub use nix_compat;
 
 #[cfg(feature = "session")]
 pub use self::session::*;
-pub use self::{cache::*, entry::*, org::*, store::*, user::*};
+pub use self::{
+  build_log::*, cache::*, entry::*, org::*, orphaned_blob::*, realisation::*,
+  store::*, upload_session::*, user::*,
+};
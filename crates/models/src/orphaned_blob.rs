@@ -0,0 +1,42 @@
+use std::path::PathBuf;
+
+use model::{IndexValue, Model, RecordId};
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+
+use crate::Store;
+
+/// A NAR blob which no [`Entry`](crate::Entry) references anymore, waiting to
+/// be deleted.
+///
+/// Blobs aren't deleted as soon as their last entry is, since an upload which
+/// found the blob while it was still referenced may be about to share it.
+/// Instead they're swept once they've been orphaned for a grace period, if
+/// nothing references them by then.
+///
+/// NARs uploaded through the binary cache protocol are recorded too while they
+/// wait for their narinfo, so that ones whose narinfo never arrives are swept.
+///
+/// The all index lists every orphaned blob, so that they can be swept.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Model)]
+#[model(
+  table = "orphaned_blob",
+  index(name = "all", extract = |_| vec![OrphanedBlob::index_all()]),
+)]
+pub struct OrphanedBlob {
+  /// The record's ID.
+  #[model(id)]
+  pub id:           RecordId<OrphanedBlob>,
+  /// The store the blob is stored in.
+  pub store:        RecordId<Store>,
+  /// The path within the store where the blob is stored.
+  pub storage_path: PathBuf,
+  /// When the last entry referencing the blob was deleted, or when a pending
+  /// NAR was uploaded.
+  pub orphaned_at:  UtcDateTime,
+}
+
+impl OrphanedBlob {
+  /// Generates the value of the [`OrphanedBlob`] index `all`.
+  pub fn index_all() -> IndexValue { IndexValue::new_single("all".to_owned()) }
+}
@@ -0,0 +1,190 @@
+use std::{collections::BTreeMap, error::Error, fmt, str::FromStr};
+
+use model::{IndexValue, Model, RecordId};
+use nix_compat::{narinfo::Signature, nixbase32, store_path::StorePath};
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+
+use crate::{Cache, Org, User};
+
+/// The realisation of an output of a content-addressed derivation, uploaded to
+/// a [`Cache`].
+///
+/// Realisations map a derivation output to the store path it was built at,
+/// which Nix can't know in advance for content-addressed derivations. Each
+/// cache holds at most one realisation per derivation output.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Model)]
+#[model(
+  table = "realisation",
+  index(name = "org", extract = |m| vec![IndexValue::new_single(m.org.to_string())]),
+  index(name = "cache_id_and_drv_output", unique, extract =
+    |m| vec![Realisation::unique_index_cache_id_and_drv_output(m.cache, &m.drv_output)]
+  ),
+)]
+pub struct Realisation {
+  /// The realisation's ID.
+  #[model(id)]
+  pub id:                     RecordId<Realisation>,
+  /// The realisation's org.
+  pub org:                    RecordId<Org>,
+  /// The cache the realisation was uploaded to.
+  pub cache:                  RecordId<Cache>,
+  /// The derivation output that was realised.
+  pub drv_output:             DrvOutput,
+  /// The store path the output was realised at.
+  pub out_path:               StorePath<String>,
+  /// Signatures on the realisation's fingerprint.
+  pub signatures:             Vec<Signature<String>>,
+  /// The realisations of the outputs that this output depends on.
+  pub dependent_realisations: BTreeMap<DrvOutput, StorePath<String>>,
+  /// The user who uploaded the realisation.
+  pub uploaded_by:            RecordId<User>,
+  /// When the realisation was uploaded.
+  pub uploaded_at:            UtcDateTime,
+}
+
+impl Realisation {
+  /// Generates the value of the unique [`Realisation`] index
+  /// `cache-id-and-drv-output`.
+  pub fn unique_index_cache_id_and_drv_output(
+    cache_id: RecordId<Cache>,
+    drv_output: &DrvOutput,
+  ) -> IndexValue {
+    IndexValue::new([cache_id.to_string(), drv_output.to_string()])
+  }
+}
+
+/// An output of a derivation, identified by the derivation's hash modulo and
+/// the output's name, like `sha256:<hex>!out`.
+#[derive(
+  Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize,
+)]
+#[serde(try_from = "String", into = "String")]
+pub struct DrvOutput {
+  drv_hash:    [u8; 32],
+  output_name: String,
+}
+
+impl DrvOutput {
+  /// Creates a derivation output from the derivation's SHA-256 hash modulo
+  /// and the output's name.
+  pub fn new(
+    drv_hash: [u8; 32],
+    output_name: impl Into<String>,
+  ) -> Result<Self, InvalidDrvOutput> {
+    let output_name = output_name.into();
+    let is_valid = !output_name.is_empty()
+      && output_name
+        .bytes()
+        .all(|b| b.is_ascii_alphanumeric() || b"+-._?=".contains(&b));
+    match is_valid {
+      true => Ok(Self {
+        drv_hash,
+        output_name,
+      }),
+      false => Err(InvalidDrvOutput(output_name)),
+    }
+  }
+
+  /// The derivation's SHA-256 hash modulo.
+  pub fn drv_hash(&self) -> &[u8; 32] { &self.drv_hash }
+
+  /// The name of the output.
+  pub fn output_name(&self) -> &str { &self.output_name }
+}
+
+impl fmt::Display for DrvOutput {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    f.write_str("sha256:")?;
+    for byte in self.drv_hash {
+      write!(f, "{byte:02x}")?;
+    }
+    write!(f, "!{}", self.output_name)
+  }
+}
+
+impl FromStr for DrvOutput {
+  type Err = InvalidDrvOutput;
+
+  /// Parses a derivation output. Nix writes the hash in base16, but reads
+  /// it in nixbase32 as well.
+  fn from_str(s: &str) -> Result<Self, Self::Err> {
+    let invalid = || InvalidDrvOutput(s.to_owned());
+    let (hash, output_name) = s
+      .strip_prefix("sha256:")
+      .and_then(|s| s.split_once('!'))
+      .ok_or_else(invalid)?;
+
+    let drv_hash = match hash.len() {
+      64 if hash.bytes().all(|b| b.is_ascii_hexdigit()) => {
+        let mut drv_hash = [0; 32];
+        for (i, byte) in drv_hash.iter_mut().enumerate() {
+          *byte = u8::from_str_radix(&hash[i * 2..i * 2 + 2], 16)
+            .map_err(|_| invalid())?;
+        }
+        drv_hash
+      }
+      _ => nixbase32::decode_fixed(hash.as_bytes()).map_err(|_| invalid())?,
+    };
+
+    Self::new(drv_hash, output_name).map_err(|_| invalid())
+  }
+}
+
+impl TryFrom<String> for DrvOutput {
+  type Error = InvalidDrvOutput;
+
+  fn try_from(value: String) -> Result<Self, Self::Error> { value.parse() }
+}
+
+impl From<DrvOutput> for String {
+  fn from(value: DrvOutput) -> Self { value.to_string() }
+}
+
+/// The error returned when a derivation output is malformed.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InvalidDrvOutput(pub String);
+
+impl fmt::Display for InvalidDrvOutput {
+  fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+    write!(f, "invalid derivation output: \"{}\"", self.0)
+  }
+}
+
+impl Error for InvalidDrvOutput {}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const HASH: &str =
+    "1b4f0e9851971998e732078544c96b36c3d01cedf7caa332359d6f1d83567014";
+
+  #[test]
+  fn drv_outputs_round_trip() {
+    let input = format!("sha256:{HASH}!out");
+    let drv_output = input.parse::<DrvOutput>().unwrap();
+    assert_eq!(drv_output.output_name(), "out");
+    assert_eq!(drv_output.drv_hash()[0], 0x1b);
+    assert_eq!(drv_output.to_string(), input);
+
+    let nixbase32_input =
+      format!("sha256:{}!out", nixbase32::encode(drv_output.drv_hash()));
+    assert_eq!(nixbase32_input.parse::<DrvOutput>(), Ok(drv_output));
+  }
+
+  #[test]
+  fn malformed_drv_outputs_are_rejected() {
+    for input in [
+      format!("{HASH}!out"),
+      format!("sha1:{HASH}!out"),
+      "sha256:1b4f!out".to_owned(),
+      format!("sha256:+{}!out", &HASH[1..]),
+      format!("sha256:{HASH}"),
+      format!("sha256:{HASH}!"),
+      format!("sha256:{HASH}!a/b"),
+    ] {
+      assert!(input.parse::<DrvOutput>().is_err(), "{input}");
+    }
+  }
+}
@@ -1,7 +1,7 @@
 mod creds;
 
 use model::{IndexValue, Model, RecordId};
-use model_types::EntityName;
+use model_types::{CompressionConfig, EntityName};
 use serde::{Deserialize, Serialize};
 
 pub use self::creds::*;
@@ -69,5 +69,35 @@ impl From<Store> for PvStore {
 }
 
 /// The configuration for a [`Store`].
-#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
-pub struct StoreConfiguration {}
+///
+/// Fields added since stores were first persisted default to how those stores
+/// already behaved, so that existing records keep their meaning.
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct StoreConfiguration {
+  /// How entries are compressed when written to the store.
+  #[serde(default)]
+  pub compression: CompressionConfig,
+}
+
+#[cfg(test)]
+mod tests {
+  use model_types::CompressionAlgorithm;
+
+  use super::*;
+
+  #[test]
+  fn persisted_configurations_stay_uncompressed() {
+    let config: StoreConfiguration = serde_json::from_str("{}").unwrap();
+    assert_eq!(config.compression.algorithm(), None);
+
+    let config = StoreConfiguration {
+      compression: CompressionConfig::recommended(),
+    };
+    let round_tripped: StoreConfiguration =
+      serde_json::from_str(&serde_json::to_string(&config).unwrap()).unwrap();
+    assert_eq!(
+      round_tripped.compression.algorithm(),
+      Some(CompressionAlgorithm::Zstd)
+    );
+  }
+}
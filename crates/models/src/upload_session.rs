@@ -0,0 +1,130 @@
+use std::{collections::HashSet, path::PathBuf};
+
+use model::{IndexValue, Model, RecordId};
+use model_types::{CompressionAlgorithm, EntityName, FileSize};
+use nix_compat::{narinfo::Signature, nixhash::CAHash, store_path::StorePath};
+use serde::{Deserialize, Serialize};
+use time::UtcDateTime;
+
+use crate::{NarDeriverData, Org, Store, User};
+
+/// A resumable upload in progress.
+///
+/// The NAR is uploaded in numbered [`UploadSessionPart`]s. In stores which
+/// support multipart uploads, the parts are sent to one, and are assembled by
+/// the store when the session is finalized. In other stores, each part is
+/// stored as a blob in the target [`Store`] until then.
+///
+/// The all index lists every session, so that expired ones can be swept.
+/// Sessions are deleted when they're finalized or expire, so there are never
+/// many.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Model)]
+#[model(
+  table = "upload_session",
+  index(name = "org", extract = |m| vec![IndexValue::new_single(m.org.to_string())]),
+  index(name = "store", extract = |m| vec![IndexValue::new_single(m.store.to_string())]),
+  index(name = "all", extract = |_| vec![UploadSession::index_all()]),
+)]
+pub struct UploadSession {
+  /// The session's ID.
+  #[model(id)]
+  pub id:                  RecordId<UploadSession>,
+  /// The session's org.
+  pub org:                 RecordId<Org>,
+  /// The user who started the session.
+  pub owner:               RecordId<User>,
+  /// The store that the parts are stored in.
+  pub store:               RecordId<Store>,
+  /// The parameters of the upload.
+  pub params:              UploadSessionParams,
+  /// The ID of the store's multipart upload that the parts are sent to, if
+  /// the store supports them.
+  #[serde(default)]
+  pub multipart_upload_id: Option<String>,
+  /// When the session expires if no more parts are received.
+  pub expires_at:          UtcDateTime,
+}
+
+impl UploadSession {
+  /// Generates the value of the [`UploadSession`] index `all`.
+  pub fn index_all() -> IndexValue { IndexValue::new_single("all".to_owned()) }
+
+  /// The path within the store where a part is stored, if it isn't sent to a
+  /// multipart upload.
+  pub fn part_storage_path(&self, number: u32) -> PathBuf {
+    PathBuf::from(format!("upload-sessions/{id}/{number:06}", id = self.id))
+  }
+
+  /// The path within the store where a multipart upload assembles the parts.
+  pub fn nar_storage_path(&self) -> PathBuf {
+    PathBuf::from(format!("upload-sessions/{id}/nar", id = self.id))
+  }
+
+  /// Whether the session has expired.
+  pub fn is_expired(&self) -> bool { self.expires_at < UtcDateTime::now() }
+}
+
+/// The parameters of an [`UploadSession`], which are used for the upload once
+/// the session is finalized.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct UploadSessionParams {
+  /// The names of the caches to register the entry in.
+  pub caches:       Vec<EntityName>,
+  /// The name of the store to store the data in.
+  pub target_store: EntityName,
+  /// The store path of the entry.
+  pub store_path:   StorePath<String>,
+  /// Data about the NAR's deriver.
+  pub deriver_data: NarDeriverData,
+  /// The claimed content-addressed hash of the entry.
+  pub ca_hash:      Option<CAHash>,
+  /// The NAR hash claimed by the uploader.
+  pub nar_hash:     Option<[u8; 32]>,
+  /// The compression of the assembled parts, if they're compressed.
+  #[serde(default)]
+  pub compression:  Option<CompressionAlgorithm>,
+  /// The references claimed by the uploader.
+  #[serde(default)]
+  pub references:   Option<HashSet<StorePath<String>>>,
+  /// Signatures supplied by the uploader.
+  pub signatures:   Vec<Signature<String>>,
+}
+
+/// A part of an [`UploadSession`].
+///
+/// Parts are records of their own rather than a list in the session, so that
+/// parts uploaded at the same time don't overwrite each other's records. The
+/// session-and-number unique index finds a part which is being re-sent.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize, Model)]
+#[model(
+  table = "upload_session_part",
+  index(name = "session", extract = |m| vec![IndexValue::new_single(m.session.to_string())]),
+  index(name = "session_and_number", unique, extract =
+    |m| vec![UploadSessionPart::unique_index_session_and_number(m.session, m.number)]
+  ),
+)]
+pub struct UploadSessionPart {
+  /// The part's ID.
+  #[model(id)]
+  pub id:      RecordId<UploadSessionPart>,
+  /// The session the part belongs to.
+  pub session: RecordId<UploadSession>,
+  /// The part's number. Parts are assembled in order of their numbers,
+  /// starting from zero.
+  pub number:  u32,
+  /// The size of the part.
+  pub size:    FileSize,
+  /// The ETag the store gave the part, if it was sent to a multipart upload.
+  pub etag:    Option<String>,
+}
+
+impl UploadSessionPart {
+  /// Generates the value of the unique [`UploadSessionPart`] index
+  /// `session-and-number`.
+  pub fn unique_index_session_and_number(
+    session: RecordId<UploadSession>,
+    number: u32,
+  ) -> IndexValue {
+    IndexValue::new([session.to_string(), number.to_string()])
+  }
+}
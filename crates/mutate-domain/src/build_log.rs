@@ -0,0 +1,30 @@
+//! Build log mutation logic.
+
+use db::DatabaseError;
+use models::{BuildLog, RecordId};
+
+use super::MutationService;
+
+impl MutationService {
+  /// Creates a [`BuildLog`].
+  #[tracing::instrument(skip(self))]
+  pub async fn create_build_log(
+    &self,
+    build_log: &BuildLog,
+  ) -> Result<RecordId<BuildLog>, DatabaseError> {
+    self
+      .build_log_repo
+      .insert(build_log)
+      .await
+      .map(|()| build_log.id)
+  }
+
+  /// Patches a [`BuildLog`].
+  #[tracing::instrument(skip(self))]
+  pub async fn patch_build_log(
+    &self,
+    build_log: &BuildLog,
+  ) -> Result<(), DatabaseError> {
+    self.build_log_repo.update(build_log).await
+  }
+}
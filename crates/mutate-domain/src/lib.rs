@@ -1,23 +1,37 @@
 //! Provides [`MutationService`] for mutation operations on models.
 
+mod build_log;
 mod create;
 mod delete_entry;
+mod orphaned_blob;
+mod patch_cache;
+mod patch_entry;
 mod patch_user;
+mod realisation;
+mod upload_session;
 mod user_active_org;
 
 use db::Database;
-use models::{Cache, Entry, Org, Store, User};
+use models::{
+  BuildLog, Cache, Entry, Org, OrphanedBlob, Realisation, Store, UploadSession,
+  UploadSessionPart, User,
+};
 
 pub use self::user_active_org::UpdateActiveOrgError;
 
 /// Service for mutation operations on models.
 #[derive(Debug, Clone)]
 pub struct MutationService {
-  org_repo:   Database<Org>,
-  user_repo:  Database<User>,
-  store_repo: Database<Store>,
-  entry_repo: Database<Entry>,
-  cache_repo: Database<Cache>,
+  org_repo:                 Database<Org>,
+  user_repo:                Database<User>,
+  store_repo:               Database<Store>,
+  entry_repo:               Database<Entry>,
+  cache_repo:               Database<Cache>,
+  upload_session_repo:      Database<UploadSession>,
+  upload_session_part_repo: Database<UploadSessionPart>,
+  build_log_repo:           Database<BuildLog>,
+  realisation_repo:         Database<Realisation>,
+  orphaned_blob_repo:       Database<OrphanedBlob>,
 }
 
 impl MutationService {
@@ -28,6 +42,11 @@ impl MutationService {
     store_repo: Database<Store>,
     entry_repo: Database<Entry>,
     cache_repo: Database<Cache>,
+    upload_session_repo: Database<UploadSession>,
+    upload_session_part_repo: Database<UploadSessionPart>,
+    build_log_repo: Database<BuildLog>,
+    realisation_repo: Database<Realisation>,
+    orphaned_blob_repo: Database<OrphanedBlob>,
   ) -> Self {
     Self {
       org_repo,
@@ -35,17 +54,27 @@ impl MutationService {
       store_repo,
       entry_repo,
       cache_repo,
+      upload_session_repo,
+      upload_session_part_repo,
+      build_log_repo,
+      realisation_repo,
+      orphaned_blob_repo,
     }
   }
 
   /// Creates a mocked-up [`MutationService`].
   pub fn new_mock() -> Self {
     Self {
-      org_repo:   Database::new_mock(),
-      user_repo:  Database::new_mock(),
-      store_repo: Database::new_mock(),
-      entry_repo: Database::new_mock(),
-      cache_repo: Database::new_mock(),
+      org_repo:                 Database::new_mock(),
+      user_repo:                Database::new_mock(),
+      store_repo:               Database::new_mock(),
+      entry_repo:               Database::new_mock(),
+      cache_repo:               Database::new_mock(),
+      upload_session_repo:      Database::new_mock(),
+      upload_session_part_repo: Database::new_mock(),
+      build_log_repo:           Database::new_mock(),
+      realisation_repo:         Database::new_mock(),
+      orphaned_blob_repo:       Database::new_mock(),
     }
   }
 }
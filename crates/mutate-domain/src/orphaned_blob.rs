@@ -0,0 +1,30 @@
+//! Orphaned blob mutation logic.
+
+use db::DatabaseError;
+use models::{OrphanedBlob, RecordId};
+
+use super::MutationService;
+
+impl MutationService {
+  /// Creates an [`OrphanedBlob`].
+  #[tracing::instrument(skip(self))]
+  pub async fn create_orphaned_blob(
+    &self,
+    orphaned_blob: &OrphanedBlob,
+  ) -> Result<RecordId<OrphanedBlob>, DatabaseError> {
+    self
+      .orphaned_blob_repo
+      .insert(orphaned_blob)
+      .await
+      .map(|()| orphaned_blob.id)
+  }
+
+  /// Deletes an [`OrphanedBlob`].
+  #[tracing::instrument(skip(self))]
+  pub async fn delete_orphaned_blob(
+    &self,
+    id: RecordId<OrphanedBlob>,
+  ) -> Result<OrphanedBlob, DatabaseError> {
+    self.orphaned_blob_repo.delete_and_return(id).await
+  }
+}
@@ -0,0 +1,14 @@
+//! Cache mutation logic.
+
+use db::DatabaseError;
+use models::Cache;
+
+use super::MutationService;
+
+impl MutationService {
+  /// Patches a [`Cache`].
+  #[tracing::instrument(skip(self))]
+  pub async fn patch_cache(&self, cache: &Cache) -> Result<(), DatabaseError> {
+    self.cache_repo.update(cache).await
+  }
+}
@@ -0,0 +1,14 @@
+//! Entry mutation logic.
+
+use db::DatabaseError;
+use models::Entry;
+
+use super::MutationService;
+
+impl MutationService {
+  /// Patches an [`Entry`].
+  #[tracing::instrument(skip(self))]
+  pub async fn patch_entry(&self, entry: &Entry) -> Result<(), DatabaseError> {
+    self.entry_repo.update(entry).await
+  }
+}
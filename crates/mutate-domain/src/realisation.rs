@@ -0,0 +1,30 @@
+//! Realisation mutation logic.
+
+use db::DatabaseError;
+use models::{Realisation, RecordId};
+
+use super::MutationService;
+
+impl MutationService {
+  /// Creates a [`Realisation`].
+  #[tracing::instrument(skip(self))]
+  pub async fn create_realisation(
+    &self,
+    realisation: &Realisation,
+  ) -> Result<RecordId<Realisation>, DatabaseError> {
+    self
+      .realisation_repo
+      .insert(realisation)
+      .await
+      .map(|()| realisation.id)
+  }
+
+  /// Patches a [`Realisation`].
+  #[tracing::instrument(skip(self))]
+  pub async fn patch_realisation(
+    &self,
+    realisation: &Realisation,
+  ) -> Result<(), DatabaseError> {
+    self.realisation_repo.update(realisation).await
+  }
+}
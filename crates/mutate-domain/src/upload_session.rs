@@ -0,0 +1,70 @@
+//! Upload session mutation logic.
+
+use db::DatabaseError;
+use models::{RecordId, UploadSession, UploadSessionPart};
+
+use super::MutationService;
+
+impl MutationService {
+  /// Creates an [`UploadSession`].
+  #[tracing::instrument(skip(self))]
+  pub async fn create_upload_session(
+    &self,
+    session: &UploadSession,
+  ) -> Result<RecordId<UploadSession>, DatabaseError> {
+    self
+      .upload_session_repo
+      .insert(session)
+      .await
+      .map(|()| session.id)
+  }
+
+  /// Patches an [`UploadSession`].
+  #[tracing::instrument(skip(self))]
+  pub async fn patch_upload_session(
+    &self,
+    session: &UploadSession,
+  ) -> Result<(), DatabaseError> {
+    self.upload_session_repo.update(session).await
+  }
+
+  /// Deletes an [`UploadSession`].
+  #[tracing::instrument(skip(self))]
+  pub async fn delete_upload_session(
+    &self,
+    id: RecordId<UploadSession>,
+  ) -> Result<UploadSession, DatabaseError> {
+    self.upload_session_repo.delete_and_return(id).await
+  }
+
+  /// Creates an [`UploadSessionPart`].
+  #[tracing::instrument(skip(self))]
+  pub async fn create_upload_session_part(
+    &self,
+    part: &UploadSessionPart,
+  ) -> Result<RecordId<UploadSessionPart>, DatabaseError> {
+    self
+      .upload_session_part_repo
+      .insert(part)
+      .await
+      .map(|()| part.id)
+  }
+
+  /// Patches an [`UploadSessionPart`].
+  #[tracing::instrument(skip(self))]
+  pub async fn patch_upload_session_part(
+    &self,
+    part: &UploadSessionPart,
+  ) -> Result<(), DatabaseError> {
+    self.upload_session_part_repo.update(part).await
+  }
+
+  /// Deletes an [`UploadSessionPart`].
+  #[tracing::instrument(skip(self))]
+  pub async fn delete_upload_session_part(
+    &self,
+    id: RecordId<UploadSessionPart>,
+  ) -> Result<UploadSessionPart, DatabaseError> {
+    self.upload_session_part_repo.delete_and_return(id).await
+  }
+}
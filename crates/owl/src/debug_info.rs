@@ -0,0 +1,374 @@
+//! Finds separate debug info files in NARs by their ELF build IDs, like Nix's
+//! `index-debug-info`.
+
+use std::{
+  io::{self, Read},
+  path::{Component, Path},
+};
+
+/// The directory debug info files are installed under, by build ID.
+const BUILD_ID_DIR: [&str; 3] = ["lib", "debug", ".build-id"];
+/// How much of the start of a file is held to find its build ID in. Notes
+/// come right after the headers, so this is plenty.
+const PREFIX_LEN: usize = 64 * 1024;
+/// The most section header bytes read from the end of a file.
+const MAX_SECTION_HEADERS_LEN: u64 = 64 * 1024;
+
+/// The ELF program header type of notes.
+const PT_NOTE: u32 = 4;
+/// The ELF section header type of notes.
+const SHT_NOTE: u32 = 7;
+/// The ELF note type of build IDs.
+const NT_GNU_BUILD_ID: u32 = 3;
+
+/// Returns the path of a file within a NAR if it's where a debug info file is
+/// installed, like `lib/debug/.build-id/ab/cdef.debug`.
+pub(crate) fn debug_info_member(path: &Path) -> Option<String> {
+  let components = path
+    .components()
+    .map(|c| match c {
+      Component::Normal(c) => c.to_str(),
+      _ => None,
+    })
+    .collect::<Option<Vec<_>>>()?;
+  let [lib, debug, build_id_dir, prefix, file_name] = components[..] else {
+    return None;
+  };
+  let is_hex =
+    |s: &str| !s.is_empty() && s.bytes().all(|b| b.is_ascii_hexdigit());
+
+  let is_member = [lib, debug, build_id_dir] == BUILD_ID_DIR
+    && prefix.len() == 2
+    && is_hex(prefix)
+    && file_name.strip_suffix(".debug").is_some_and(is_hex);
+  is_member.then(|| components.join("/"))
+}
+
+/// Reads a file through, and returns its size and its ELF build ID in
+/// lowercase hex, if it's an ELF file with one.
+///
+/// The file is streamed, so only its start and its section headers are held.
+/// The build ID is looked for in the notes found through the program headers,
+/// and then through the section headers.
+pub(crate) fn read_build_id(
+  mut data: impl Read,
+) -> io::Result<(u64, Option<String>)> {
+  let mut prefix = Vec::with_capacity(PREFIX_LEN);
+  (&mut data)
+    .take(PREFIX_LEN as u64)
+    .read_to_end(&mut prefix)?;
+  let mut size = prefix.len() as u64;
+
+  let build_id = match ElfHeader::parse(&prefix) {
+    Some(header) => match header.build_id_from_segments(&prefix) {
+      Some(build_id) => Some(build_id),
+      None => match header.section_headers_range() {
+        // small files are held whole, section headers included
+        Some((start, len)) if start.saturating_add(len) <= size => {
+          let section_headers = slice(&prefix, start, len).unwrap_or_default();
+          header.build_id_from_sections(section_headers, &prefix)
+        }
+        // the section headers are usually at the end of larger files, so
+        // skip ahead to them
+        Some((start, len))
+          if start >= size && len <= MAX_SECTION_HEADERS_LEN =>
+        {
+          size +=
+            io::copy(&mut (&mut data).take(start - size), &mut io::sink())?;
+          let mut section_headers = Vec::new();
+          (&mut data).take(len).read_to_end(&mut section_headers)?;
+          size += section_headers.len() as u64;
+          header.build_id_from_sections(&section_headers, &prefix)
+        }
+        _ => None,
+      },
+    },
+    None => None,
+  };
+
+  size += io::copy(&mut data, &mut io::sink())?;
+  Ok((size, build_id.map(|b| hex(&b))))
+}
+
+fn hex(bytes: &[u8]) -> String {
+  bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
+
+/// The parts of an ELF header needed to find notes.
+#[derive(Debug)]
+struct ElfHeader {
+  is_64:     bool,
+  is_le:     bool,
+  phoff:     u64,
+  phentsize: u64,
+  phnum:     u64,
+  shoff:     u64,
+  shentsize: u64,
+  shnum:     u64,
+}
+
+impl ElfHeader {
+  fn parse(data: &[u8]) -> Option<Self> {
+    if data.get(..4)? != b"\x7fELF" {
+      return None;
+    }
+    let is_64 = match data.get(4)? {
+      1 => false,
+      2 => true,
+      _ => return None,
+    };
+    let is_le = match data.get(5)? {
+      1 => true,
+      2 => false,
+      _ => return None,
+    };
+    let reader = FieldReader { is_le };
+
+    let header = match is_64 {
+      true => Self {
+        is_64,
+        is_le,
+        phoff: reader.u64(data, 0x20)?,
+        shoff: reader.u64(data, 0x28)?,
+        phentsize: reader.u16(data, 0x36)?.into(),
+        phnum: reader.u16(data, 0x38)?.into(),
+        shentsize: reader.u16(data, 0x3a)?.into(),
+        shnum: reader.u16(data, 0x3c)?.into(),
+      },
+      false => Self {
+        is_64,
+        is_le,
+        phoff: reader.u32(data, 0x1c)?.into(),
+        shoff: reader.u32(data, 0x20)?.into(),
+        phentsize: reader.u16(data, 0x2a)?.into(),
+        phnum: reader.u16(data, 0x2c)?.into(),
+        shentsize: reader.u16(data, 0x2e)?.into(),
+        shnum: reader.u16(data, 0x30)?.into(),
+      },
+    };
+    Some(header)
+  }
+
+  fn reader(&self) -> FieldReader { FieldReader { is_le: self.is_le } }
+
+  /// Looks for a build ID in the note segments, which must be in the data.
+  fn build_id_from_segments(&self, data: &[u8]) -> Option<Vec<u8>> {
+    let reader = self.reader();
+    (0..self.phnum).find_map(|i| {
+      let entry = self.phoff.checked_add(i.checked_mul(self.phentsize)?)?;
+      let entry = usize::try_from(entry).ok()?;
+      if reader.u32(data, entry)? != PT_NOTE {
+        return None;
+      }
+      let (offset, size) = match self.is_64 {
+        true => (reader.u64(data, entry + 8)?, reader.u64(data, entry + 32)?),
+        false => (
+          reader.u32(data, entry + 4)?.into(),
+          reader.u32(data, entry + 16)?.into(),
+        ),
+      };
+      reader.build_id_from_notes(slice(data, offset, size)?)
+    })
+  }
+
+  /// The offset and length of the section header table.
+  fn section_headers_range(&self) -> Option<(u64, u64)> {
+    Some((self.shoff, self.shnum.checked_mul(self.shentsize)?))
+      .filter(|(start, len)| *start > 0 && *len > 0)
+  }
+
+  /// Looks for a build ID in the note sections, given the section header
+  /// table. The notes themselves must be in the data.
+  fn build_id_from_sections(
+    &self,
+    section_headers: &[u8],
+    data: &[u8],
+  ) -> Option<Vec<u8>> {
+    let reader = self.reader();
+    (0..self.shnum).find_map(|i| {
+      let entry = usize::try_from(i.checked_mul(self.shentsize)?).ok()?;
+      if reader.u32(section_headers, entry + 4)? != SHT_NOTE {
+        return None;
+      }
+      let (offset, size) = match self.is_64 {
+        true => (
+          reader.u64(section_headers, entry + 24)?,
+          reader.u64(section_headers, entry + 32)?,
+        ),
+        false => (
+          reader.u32(section_headers, entry + 16)?.into(),
+          reader.u32(section_headers, entry + 20)?.into(),
+        ),
+      };
+      reader.build_id_from_notes(slice(data, offset, size)?)
+    })
+  }
+}
+
+/// Returns the part of the data at the offset, if it's all there.
+fn slice(data: &[u8], offset: u64, size: u64) -> Option<&[u8]> {
+  let start = usize::try_from(offset).ok()?;
+  let end = start.checked_add(usize::try_from(size).ok()?)?;
+  data.get(start..end)
+}
+
+/// Reads fields of an ELF file in its byte order.
+#[derive(Clone, Copy)]
+struct FieldReader {
+  is_le: bool,
+}
+
+impl FieldReader {
+  fn u16(self, data: &[u8], offset: usize) -> Option<u16> {
+    let bytes = data.get(offset..offset.checked_add(2)?)?.try_into().ok()?;
+    Some(match self.is_le {
+      true => u16::from_le_bytes(bytes),
+      false => u16::from_be_bytes(bytes),
+    })
+  }
+
+  fn u32(self, data: &[u8], offset: usize) -> Option<u32> {
+    let bytes = data.get(offset..offset.checked_add(4)?)?.try_into().ok()?;
+    Some(match self.is_le {
+      true => u32::from_le_bytes(bytes),
+      false => u32::from_be_bytes(bytes),
+    })
+  }
+
+  fn u64(self, data: &[u8], offset: usize) -> Option<u64> {
+    let bytes = data.get(offset..offset.checked_add(8)?)?.try_into().ok()?;
+    Some(match self.is_le {
+      true => u64::from_le_bytes(bytes),
+      false => u64::from_be_bytes(bytes),
+    })
+  }
+
+  /// Looks for a GNU build ID note among the notes. Names and descriptions
+  /// are padded to 4 bytes.
+  fn build_id_from_notes(self, mut notes: &[u8]) -> Option<Vec<u8>> {
+    while notes.len() >= 12 {
+      let name_size = self.u32(notes, 0)? as usize;
+      let desc_size = self.u32(notes, 4)? as usize;
+      let note_type = self.u32(notes, 8)?;
+      let desc_start =
+        name_size.checked_next_multiple_of(4)?.checked_add(12)?;
+      let name = notes.get(12..12usize.checked_add(name_size)?)?;
+      let desc = notes.get(desc_start..desc_start.checked_add(desc_size)?)?;
+      let next =
+        desc_start.checked_add(desc_size.checked_next_multiple_of(4)?)?;
+
+      if note_type == NT_GNU_BUILD_ID && name == b"GNU\0" && !desc.is_empty() {
+        return Some(desc.to_vec());
+      }
+      notes = notes.get(next..)?;
+    }
+    None
+  }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+  use super::*;
+
+  const BUILD_ID: [u8; 20] = [
+    0xab, 0xcd, 0xef, 0x01, 0x23, 0x45, 0x67, 0x89, 0x0a, 0xbc, 0xde, 0xf0,
+    0x12, 0x34, 0x56, 0x78, 0x9a, 0xbc, 0xde, 0xf0,
+  ];
+
+  /// A GNU build ID note.
+  fn build_id_note() -> Vec<u8> {
+    let mut note = Vec::new();
+    note.extend_from_slice(&4u32.to_le_bytes());
+    note.extend_from_slice(&(BUILD_ID.len() as u32).to_le_bytes());
+    note.extend_from_slice(&NT_GNU_BUILD_ID.to_le_bytes());
+    note.extend_from_slice(b"GNU\0");
+    note.extend_from_slice(&BUILD_ID);
+    note
+  }
+
+  /// A little-endian 64-bit ELF file with a build ID note, found through a
+  /// program header or through a section header at the end of the file.
+  pub(crate) fn elf_with_build_id(in_segment: bool, padding: usize) -> Vec<u8> {
+    let note = build_id_note();
+    let note_offset = 64 + 56;
+
+    let mut elf = vec![0; 64];
+    elf[..6].copy_from_slice(b"\x7fELF\x02\x01");
+
+    // one note program header, or none
+    let mut phdr = vec![0; 56];
+    phdr[..4].copy_from_slice(&PT_NOTE.to_le_bytes());
+    phdr[8..16].copy_from_slice(&(note_offset as u64).to_le_bytes());
+    phdr[32..40].copy_from_slice(&(note.len() as u64).to_le_bytes());
+    if in_segment {
+      elf[0x20..0x28].copy_from_slice(&64u64.to_le_bytes());
+      elf[0x36..0x38].copy_from_slice(&56u16.to_le_bytes());
+      elf[0x38..0x3a].copy_from_slice(&1u16.to_le_bytes());
+    }
+    elf.extend_from_slice(&phdr);
+    elf.extend_from_slice(&note);
+    elf.resize(elf.len() + padding, 0);
+
+    // a null section header and a note section header
+    let shoff = elf.len() as u64;
+    let mut shdr = vec![0; 64];
+    shdr[4..8].copy_from_slice(&SHT_NOTE.to_le_bytes());
+    shdr[24..32].copy_from_slice(&(note_offset as u64).to_le_bytes());
+    shdr[32..40].copy_from_slice(&(note.len() as u64).to_le_bytes());
+    if !in_segment {
+      elf[0x28..0x30].copy_from_slice(&shoff.to_le_bytes());
+      elf[0x3a..0x3c].copy_from_slice(&64u16.to_le_bytes());
+      elf[0x3c..0x3e].copy_from_slice(&2u16.to_le_bytes());
+    }
+    elf.extend_from_slice(&[0; 64]);
+    elf.extend_from_slice(&shdr);
+    elf
+  }
+
+  #[test]
+  fn finds_build_ids_in_segments_and_sections() {
+    for (in_segment, padding) in [
+      (true, 0),
+      (true, 2 * PREFIX_LEN),
+      (false, 0),
+      (false, 2 * PREFIX_LEN),
+    ] {
+      let elf = elf_with_build_id(in_segment, padding);
+      let (size, build_id) = read_build_id(elf.as_slice()).unwrap();
+      assert_eq!(size, elf.len() as u64);
+      assert_eq!(
+        build_id.as_deref(),
+        Some("abcdef01234567890abcdef0123456789abcdef0"),
+        "in segment: {in_segment}, padding: {padding}"
+      );
+    }
+  }
+
+  #[test]
+  fn ignores_files_without_build_ids() {
+    let data = b"not an ELF file".repeat(10_000);
+    let (size, build_id) = read_build_id(data.as_slice()).unwrap();
+    assert_eq!(size, data.len() as u64);
+    assert_eq!(build_id, None);
+  }
+
+  #[test]
+  fn recognizes_debug_info_members() {
+    let member =
+      "lib/debug/.build-id/ab/cdef01234567890abcdef0123456789abcdef0.debug";
+    assert_eq!(
+      debug_info_member(Path::new(member)).as_deref(),
+      Some(member)
+    );
+
+    for path in [
+      "lib/debug/.build-id/ab/cdef.so",
+      "lib/debug/.build-id/abc/def.debug",
+      "lib/debug/.build-id/ab/.debug",
+      "lib/debug/ab/cdef.debug",
+      "share/debug/.build-id/ab/cdef.debug",
+    ] {
+      assert_eq!(debug_info_member(Path::new(path)), None, "{path}");
+    }
+  }
+}
@@ -0,0 +1,34 @@
+use std::io::{self, Read};
+
+use sha2::{Digest, Sha256};
+
+/// A reader adapter that hashes and counts every byte read through it.
+pub(crate) struct HashingReader<R> {
+  inner:  R,
+  hasher: Sha256,
+  count:  u64,
+}
+
+impl<R> HashingReader<R> {
+  pub(crate) fn new(inner: R) -> Self {
+    Self {
+      inner,
+      hasher: Sha256::new(),
+      count: 0,
+    }
+  }
+
+  /// Returns the SHA-256 digest and the count of all bytes read.
+  pub(crate) fn finalize(self) -> ([u8; 32], u64) {
+    (self.hasher.finalize().into(), self.count)
+  }
+}
+
+impl<R: Read> Read for HashingReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    self.hasher.update(&buf[..n]);
+    self.count += n as u64;
+    Ok(n)
+  }
+}
@@ -1,23 +1,40 @@
 //! Tools to manipulate NARs.
 
-#![feature(slice_pattern)]
+mod debug_info;
+mod hashing_reader;
+mod listing;
+mod scan;
 
-use core::slice::SlicePattern;
 use std::{
-  io::{self, BufRead, BufReader, Read},
-  sync::LazyLock,
+  collections::HashSet,
+  io::{self, BufReader, Read},
+  path::Path,
 };
 
 use belt::Belt;
-use models::{FileSize, NarIntrensicData};
-use nix_compat::store_path::StorePath;
+use models::{DebugInfoFile, FileSize, NarIntrensicData, StoreDir, StorePath};
+use nix_compat::nixhash::{CAHash, NixHash};
 use nix_nar::Content;
-use regex::bytes::Regex as RegexBytes;
+use tokio_util::io::{StreamReader, SyncIoBridge};
 use tracing::{Instrument, info_span};
 
+pub use self::listing::{NarListing, NarListingNode};
+use self::{
+  debug_info::{debug_info_member, read_build_id},
+  hashing_reader::HashingReader,
+  listing::ListingBuilder,
+  scan::{ReferenceScanner, ScanningReader},
+};
+
+/// The size of the reads the NAR is scanned for references in.
+const SCAN_CHUNK_SIZE: usize = 64 * 1024;
+
 /// Interrogates a NAR and returns its intrensically known data.
-#[derive(Debug)]
-pub struct NarInterrogator;
+#[derive(Debug, Default)]
+pub struct NarInterrogator {
+  store_dir:  StoreDir,
+  candidates: Option<HashSet<StorePath<String>>>,
+}
 
 /// Possible failures of a NAR interrogation.
 #[derive(Debug, thiserror::Error)]
@@ -28,122 +45,316 @@ pub enum InterrogatorError {
   /// Failed to decode NAR.
   #[error("Failed to decode NAR: {0}")]
   DecodingError(nix_nar::NarError),
+  /// The blocking interrogation task failed to complete.
+  #[error("Interrogation task failed: {0}")]
+  TaskFailure(tokio::task::JoinError),
+  /// The content-addressed hash does not match the NAR.
+  #[error("Content-addressed hash does not match the NAR")]
+  CaHashMismatch,
+  /// The content-addressed hash is of a form that can't be verified.
+  #[error("Unsupported content-addressed hash: {0}")]
+  UnsupportedCaHash(String),
 }
 
-static NIX_STORE_PATH_REGEX: LazyLock<RegexBytes> = LazyLock::new(|| {
-  RegexBytes::new("(/nix/store/[a-z0-9]{32}-[a-zA-Z0-9._+?=-]{0,150})")
-    .expect("failed to build regex engine")
-});
-
 impl NarInterrogator {
+  /// Creates an interrogator which finds references to store paths in the
+  /// given store directory.
+  pub fn new(store_dir: StoreDir) -> Self {
+    Self {
+      store_dir,
+      candidates: None,
+    }
+  }
+
+  /// Only looks for references to the given candidate paths, like Nix does.
+  /// Their hash parts are searched for anywhere in the NAR, including in
+  /// symlink targets, so references are found even without their store
+  /// directory or name.
+  ///
+  /// Without candidates, references are discovered by searching for absolute
+  /// paths in the store directory. That's a heuristic: references which only
+  /// appear as bare hash parts are missed, and text that merely looks like a
+  /// store path is reported as a reference.
+  pub fn with_candidates(
+    mut self,
+    candidates: impl IntoIterator<Item = StorePath<String>>,
+  ) -> Self {
+    self.candidates = Some(candidates.into_iter().collect());
+    self
+  }
+
   /// Interrogate a NAR and return its intrensically known data.
-  #[tracing::instrument]
+  ///
+  /// If a content-addressed hash is given, it's verified against the NAR and
+  /// included in the returned data.
+  ///
+  /// The input is consumed incrementally, so memory use is bounded regardless
+  /// of the size of the NAR.
+  #[tracing::instrument(skip(data))]
   pub async fn interrogate(
     &self,
     data: Belt,
+    ca_hash: Option<CAHash>,
   ) -> Result<NarIntrensicData, InterrogatorError> {
-    let buffered_data = data
-      .collect_bytes()
-      .instrument(info_span!("collect_belt_data"))
+    self
+      .interrogate_with_listing(data, ca_hash)
       .await
-      .map_err(InterrogatorError::InputError)?;
-    let buffered_data_slice = buffered_data.as_slice();
-
-    let nar_size = FileSize::new(
-      buffered_data
-        .len()
-        .try_into()
-        .expect("failed to convert from usize to u64"),
-    );
+      .map(|(data, _)| data)
+  }
+
+  /// Interrogate a NAR like [`interrogate`](Self::interrogate), and list its
+  /// contents too.
+  #[tracing::instrument(skip(data))]
+  pub async fn interrogate_with_listing(
+    &self,
+    data: Belt,
+    ca_hash: Option<CAHash>,
+  ) -> Result<(NarIntrensicData, NarListing), InterrogatorError> {
+    // the NAR decoder is synchronous, so bridge the stream into a blocking
+    // reader and run the decoder on the blocking pool
+    let reader = SyncIoBridge::new(StreamReader::new(data));
+    let scanner = match &self.candidates {
+      Some(candidates) => {
+        ReferenceScanner::with_candidates(candidates.iter().cloned())
+      }
+      None => ReferenceScanner::discovering(&self.store_dir),
+    };
+    tokio::task::spawn_blocking(move || {
+      interrogate_reader(reader, scanner, ca_hash)
+    })
+    .instrument(info_span!("interrogate_nar"))
+    .await
+    .map_err(InterrogatorError::TaskFailure)?
+  }
+}
 
-    let nar_hash_string = info_span!("calculate_nar_hash")
-      .in_scope(|| sha256::digest(buffered_data_slice));
-    let nar_hash: [u8; 32] = hex::decode(nar_hash_string)
-      .expect("sha256 crate produced malformed hex string")
-      .try_into()
-      .expect("failed to squash sha256 digest bytes into 32 byte array");
+fn interrogate_reader<R: Read>(
+  reader: R,
+  scanner: ReferenceScanner,
+  ca_hash: Option<CAHash>,
+) -> Result<(NarIntrensicData, NarListing), InterrogatorError> {
+  // like Nix, the whole NAR is scanned rather than just file contents, which
+  // covers symlink targets. reads are buffered so the scanner sees large
+  // chunks rather than the decoder's small reads.
+  let mut scanning_reader = ScanningReader::new(reader, scanner);
+  let mut reader = HashingReader::new(BufReader::with_capacity(
+    SCAN_CHUNK_SIZE,
+    &mut scanning_reader,
+  ));
 
-    let span = info_span!("collect_nar_references");
-    let _guard = span.enter();
-    let decoder = nix_nar::Decoder::new(io::Cursor::new(buffered_data_slice))
+  // the hash of the file contents, if the NAR is a single regular file
+  let mut root_file_hash = None;
+  let mut debug_info = Vec::new();
+  let mut listing = ListingBuilder::default();
+  {
+    let decoder = nix_nar::Decoder::new(&mut reader)
       .map_err(InterrogatorError::DecodingError)?;
-    let mut captures = Vec::new();
     for entry in decoder
       .entries()
       .map_err(InterrogatorError::DecodingError)?
     {
       let entry = entry.map_err(InterrogatorError::DecodingError)?;
+      let path = entry.path.as_ref().map(|p| -> &Path { p.as_ref() });
 
-      let Content::File { data, .. } = entry.content else {
-        continue;
+      let node = match entry.content {
+        Content::Directory => NarListingNode::Directory {
+          entries: Default::default(),
+        },
+        Content::Symlink { target } => NarListingNode::Symlink {
+          target: <_ as AsRef<Path>>::as_ref(&target)
+            .to_string_lossy()
+            .into_owned(),
+        },
+        // file contents are read through so the decoder can move on
+        Content::File {
+          executable,
+          mut data,
+          ..
+        } => {
+          let size = if path.is_none() {
+            let mut data = HashingReader::new(data);
+            io::copy(&mut data, &mut io::sink())
+              .map_err(InterrogatorError::InputError)?;
+            let (hash, size) = data.finalize();
+            root_file_hash = Some(hash);
+            size
+          } else if let Some(member) = path.and_then(debug_info_member) {
+            // separate debug info files are indexed by their build IDs
+            let (size, build_id) =
+              read_build_id(data).map_err(InterrogatorError::InputError)?;
+            if let Some(build_id) = build_id {
+              debug_info.push(DebugInfoFile { build_id, member });
+            }
+            size
+          } else {
+            io::copy(&mut data, &mut io::sink())
+              .map_err(InterrogatorError::InputError)?
+          };
+          NarListingNode::Regular {
+            size,
+            executable,
+            nar_offset: 0,
+          }
+        }
       };
-      let mut entry_captures = extract_store_paths_from_reader(data)
-        .expect("failed to read from memory-backed reader");
-      captures.append(&mut entry_captures);
+      listing.add(path, node);
     }
-    let references = captures
-      .into_iter()
-      .filter_map(|p| {
-        StorePath::<String>::from_absolute_path(p.as_bytes()).ok()
-      })
-      .collect();
-    drop(_guard);
-
-    Ok(NarIntrensicData {
-      nar_hash,
-      nar_size,
-      references,
-      ca_hash: None,
-    })
   }
+
+  // drain anything the decoder didn't consume so it's counted and hashed
+  io::copy(&mut reader, &mut io::sink())
+    .map_err(InterrogatorError::InputError)?;
+
+  let (nar_hash, nar_size) = reader.finalize();
+
+  if let Some(ca_hash) = &ca_hash {
+    verify_ca_hash(ca_hash, &nar_hash, root_file_hash.as_ref())?;
+  }
+
+  let references = scanning_reader.finish();
+
+  let data = NarIntrensicData {
+    nar_hash,
+    nar_size: FileSize::new(nar_size),
+    references,
+    ca_hash,
+    debug_info,
+  };
+  Ok((data, listing.finish()))
 }
 
-fn extract_store_paths_from_reader<R: Read>(
-  reader: R,
-) -> Result<Vec<String>, io::Error> {
-  let re = &NIX_STORE_PATH_REGEX;
-  let mut buf_reader = BufReader::new(reader);
-
-  let mut captures = Vec::new();
-
-  loop {
-    let mut line = Vec::new();
-    match buf_reader.read_until(b'\n', &mut line) {
-      Ok(0) => break, // EOF
-      Ok(_) => {
-        let line_captures = re.captures_iter(&line).map(|c| {
-          c.get(0)
-            .expect("no zeroth capture group")
-            .as_bytes()
-            .utf8_chunks()
-            .map(|c| c.valid())
-            .collect::<Vec<_>>()
-            .join("")
-        });
-        captures.extend(line_captures);
-      }
-      Err(e) => return Err(e),
+/// Checks that a content-addressed hash matches the NAR. Only SHA-256 hashes
+/// can be verified.
+fn verify_ca_hash(
+  ca_hash: &CAHash,
+  nar_hash: &[u8; 32],
+  root_file_hash: Option<&[u8; 32]>,
+) -> Result<(), InterrogatorError> {
+  let matches = match ca_hash {
+    CAHash::Nar(NixHash::Sha256(hash)) => hash == nar_hash,
+    CAHash::Flat(NixHash::Sha256(hash)) | CAHash::Text(hash) => {
+      root_file_hash == Some(hash)
     }
-  }
+    _ => {
+      return Err(InterrogatorError::UnsupportedCaHash(
+        ca_hash.to_nix_nixbase32_string(),
+      ));
+    }
+  };
 
-  Ok(captures)
+  match matches {
+    true => Ok(()),
+    false => Err(InterrogatorError::CaHashMismatch),
+  }
 }
 
 #[cfg(test)]
 mod test {
-  use crate::NarInterrogator;
+  use std::collections::HashSet;
+
+  use models::{DebugInfoFile, StorePath};
+  use nix_compat::nixhash::{CAHash, NixHash};
+  use sha2::Digest;
+
+  use crate::{InterrogatorError, NarInterrogator, NarListingNode};
+
+  /// Serializes a NAR holding a single symlink.
+  fn symlink_nar(target: &str) -> Vec<u8> {
+    let mut nar = Vec::new();
+    for token in [
+      "nix-archive-1",
+      "(",
+      "type",
+      "symlink",
+      "target",
+      target,
+      ")",
+    ] {
+      nar.extend_from_slice(&(token.len() as u64).to_le_bytes());
+      nar.extend_from_slice(token.as_bytes());
+      nar.resize(nar.len().next_multiple_of(8), 0);
+    }
+    nar
+  }
+
+  /// Serializes a NAR holding a directory with a single regular file at the
+  /// given path.
+  fn file_nar(path: &[&str], contents: &[u8]) -> Vec<u8> {
+    fn push(nar: &mut Vec<u8>, token: &[u8]) {
+      nar.extend_from_slice(&(token.len() as u64).to_le_bytes());
+      nar.extend_from_slice(token);
+      nar.resize(nar.len().next_multiple_of(8), 0);
+    }
+
+    let mut nar = Vec::new();
+    push(&mut nar, b"nix-archive-1");
+    for name in path {
+      for token in
+        ["(", "type", "directory", "entry", "(", "name", name, "node"]
+      {
+        push(&mut nar, token.as_bytes());
+      }
+    }
+    for token in ["(", "type", "regular", "contents"] {
+      push(&mut nar, token.as_bytes());
+    }
+    push(&mut nar, contents);
+    push(&mut nar, b")");
+    for _ in path {
+      push(&mut nar, b")");
+      push(&mut nar, b")");
+    }
+    nar
+  }
+
+  #[tokio::test]
+  async fn test_debug_info() {
+    let elf = crate::debug_info::tests::elf_with_build_id(true, 0);
+    let path = [
+      "lib",
+      "debug",
+      ".build-id",
+      "ab",
+      "cdef01234567890abcdef0123456789abcdef0.debug",
+    ];
+
+    let data = NarInterrogator::default()
+      .interrogate(bytes::Bytes::from(file_nar(&path, &elf)).into(), None)
+      .await
+      .unwrap();
+    assert_eq!(data.debug_info, vec![DebugInfoFile {
+      build_id: "abcdef01234567890abcdef0123456789abcdef0".to_owned(),
+      member:   path.join("/"),
+    }]);
+
+    // ELF files elsewhere aren't indexed
+    let data = NarInterrogator::default()
+      .interrogate(
+        bytes::Bytes::from(file_nar(&["lib", "libfoo.so"], &elf)).into(),
+        None,
+      )
+      .await
+      .unwrap();
+    assert!(data.debug_info.is_empty());
+  }
 
   #[tokio::test]
   async fn test_bat_nar() {
     let bat_nar =
       include_bytes!("../test/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0");
 
-    let interrogator = NarInterrogator;
+    let interrogator = NarInterrogator::default();
     let data = interrogator
-      .interrogate(bytes::Bytes::from(bat_nar.as_slice()).into())
+      .interrogate(bytes::Bytes::from(bat_nar.as_slice()).into(), None)
       .await
       .unwrap();
 
+    assert_eq!(data.nar_size.inner(), bat_nar.len() as u64);
+    assert_eq!(
+      data.nar_hash,
+      <[u8; 32]>::from(sha2::Sha256::digest(bat_nar.as_slice()))
+    );
+
     println!("{data:?}");
     println!(
       "{:?}",
@@ -154,4 +365,155 @@ mod test {
         .collect::<Vec<_>>()
     );
   }
+
+  #[tokio::test]
+  async fn test_nar_ca_hash() {
+    let bat_nar =
+      include_bytes!("../test/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0");
+    let nar_hash = <[u8; 32]>::from(sha2::Sha256::digest(bat_nar.as_slice()));
+
+    let interrogator = NarInterrogator::default();
+    let data = interrogator
+      .interrogate(
+        bytes::Bytes::from(bat_nar.as_slice()).into(),
+        Some(CAHash::Nar(NixHash::Sha256(nar_hash))),
+      )
+      .await
+      .unwrap();
+    assert_eq!(data.ca_hash, Some(CAHash::Nar(NixHash::Sha256(nar_hash))));
+
+    let err = interrogator
+      .interrogate(
+        bytes::Bytes::from(bat_nar.as_slice()).into(),
+        Some(CAHash::Nar(NixHash::Sha256([0; 32]))),
+      )
+      .await
+      .unwrap_err();
+    assert!(matches!(err, InterrogatorError::CaHashMismatch));
+
+    // the bat NAR is a directory, so it can't be text-addressed
+    let err = interrogator
+      .interrogate(
+        bytes::Bytes::from(bat_nar.as_slice()).into(),
+        Some(CAHash::Text(nar_hash)),
+      )
+      .await
+      .unwrap_err();
+    assert!(matches!(err, InterrogatorError::CaHashMismatch));
+  }
+
+  #[tokio::test]
+  async fn test_candidate_references() {
+    let bat_nar =
+      include_bytes!("../test/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0");
+
+    let discovered = NarInterrogator::default()
+      .interrogate(bytes::Bytes::from(bat_nar.as_slice()).into(), None)
+      .await
+      .unwrap()
+      .references;
+    assert!(!discovered.is_empty());
+
+    // every discovered path contains its hash part, so the candidate scan
+    // finds all of them, and nothing that wasn't a candidate
+    let unrelated =
+      StorePath::from_bytes(b"4yz8qa58nmysad5w88rgdhq15rkssqr6-other").unwrap();
+    let data = NarInterrogator::default()
+      .with_candidates(discovered.iter().cloned().chain([unrelated]))
+      .interrogate(bytes::Bytes::from(bat_nar.as_slice()).into(), None)
+      .await
+      .unwrap();
+    assert_eq!(data.references, discovered);
+  }
+
+  /// Calls the function with every regular file in the listing node.
+  fn for_each_file(node: &NarListingNode, f: &mut impl FnMut(u64, u64)) {
+    match node {
+      NarListingNode::Regular {
+        size, nar_offset, ..
+      } => f(*size, *nar_offset),
+      NarListingNode::Directory { entries } => {
+        entries.values().for_each(|n| for_each_file(n, f))
+      }
+      NarListingNode::Symlink { .. } => (),
+    }
+  }
+
+  #[tokio::test]
+  async fn test_listing() {
+    let bat_nar =
+      include_bytes!("../test/ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0");
+
+    let (_, listing) = NarInterrogator::default()
+      .interrogate_with_listing(
+        bytes::Bytes::from(bat_nar.as_slice()).into(),
+        None,
+      )
+      .await
+      .unwrap();
+
+    let NarListingNode::Directory { entries } = listing.root() else {
+      panic!("bat NAR root is not a directory");
+    };
+    let Some(NarListingNode::Directory { entries }) = entries.get("bin") else {
+      panic!("bat NAR has no bin directory");
+    };
+    let Some(NarListingNode::Regular {
+      executable: true,
+      nar_offset,
+      ..
+    }) = entries.get("bat")
+    else {
+      panic!("bat NAR has no bat executable");
+    };
+    let nar_offset = *nar_offset as usize;
+    assert_eq!(&bat_nar[nar_offset..nar_offset + 4], b"\x7fELF");
+
+    // every file's contents are preceded by their size
+    let mut files = 0;
+    for_each_file(listing.root(), &mut |size, nar_offset| {
+      let nar_offset = nar_offset as usize;
+      let size_bytes = &bat_nar[nar_offset - 8..nar_offset];
+      assert_eq!(u64::from_le_bytes(size_bytes.try_into().unwrap()), size);
+      files += 1;
+    });
+    assert!(files > 1);
+  }
+
+  #[tokio::test]
+  async fn test_symlink_listing() {
+    let nar = symlink_nar("../lib/libfoo.so");
+    let (_, listing) = NarInterrogator::default()
+      .interrogate_with_listing(bytes::Bytes::from(nar).into(), None)
+      .await
+      .unwrap();
+    assert_eq!(
+      serde_json::from_slice::<serde_json::Value>(&listing.to_json()).unwrap(),
+      serde_json::json!({
+        "version": 1,
+        "root": { "type": "symlink", "target": "../lib/libfoo.so" },
+      })
+    );
+  }
+
+  #[tokio::test]
+  async fn test_symlink_references() {
+    let bat =
+      StorePath::from_bytes(b"ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0")
+        .unwrap();
+    let nar = symlink_nar(&format!("/nix/store/{bat}/bin/bat"));
+
+    let data = NarInterrogator::default()
+      .with_candidates([bat.clone()])
+      .interrogate(bytes::Bytes::from(nar.clone()).into(), None)
+      .await
+      .unwrap();
+    assert_eq!(data.references, HashSet::from([bat.clone()]));
+
+    let data = NarInterrogator::default()
+      .interrogate(bytes::Bytes::from(nar).into(), None)
+      .await
+      .unwrap();
+    assert_eq!(data.references, HashSet::from([bat]));
+  }
 }
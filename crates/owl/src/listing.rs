@@ -0,0 +1,157 @@
+use std::{collections::BTreeMap, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+/// The version of the listing format, as Nix writes it.
+const LISTING_VERSION: u32 = 1;
+
+/// A listing of the contents of a NAR, in the JSON format of the `.ls` files
+/// Nix reads from binary caches.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct NarListing {
+  version: u32,
+  root:    NarListingNode,
+}
+
+/// A node in a [`NarListing`].
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "lowercase")]
+pub enum NarListingNode {
+  /// A regular file.
+  Regular {
+    /// The size of the file's contents.
+    size:       u64,
+    /// Whether the file is executable.
+    #[serde(default, skip_serializing_if = "std::ops::Not::not")]
+    executable: bool,
+    /// The offset of the file's contents in the NAR.
+    #[serde(rename = "narOffset")]
+    nar_offset: u64,
+  },
+  /// A directory.
+  Directory {
+    /// The directory's entries, by name.
+    entries: BTreeMap<String, NarListingNode>,
+  },
+  /// A symlink.
+  Symlink {
+    /// The symlink's target.
+    target: String,
+  },
+}
+
+impl NarListing {
+  /// The root node of the listing.
+  pub fn root(&self) -> &NarListingNode { &self.root }
+
+  /// Serializes the listing as JSON.
+  pub fn to_json(&self) -> Vec<u8> {
+    serde_json::to_vec(self).expect("listings always serialize")
+  }
+}
+
+/// Builds a [`NarListing`] from the entries of a NAR, in the order they're
+/// decoded.
+#[derive(Debug)]
+pub(crate) struct ListingBuilder {
+  root: NarListingNode,
+}
+
+impl Default for ListingBuilder {
+  fn default() -> Self {
+    Self {
+      root: NarListingNode::Directory {
+        entries: BTreeMap::new(),
+      },
+    }
+  }
+}
+
+impl ListingBuilder {
+  /// Adds a node at the given path, or at the root if there is none. The
+  /// decoder yields directories before their entries, so the parent is
+  /// always there already.
+  pub(crate) fn add(&mut self, path: Option<&Path>, node: NarListingNode) {
+    let Some(path) = path else {
+      self.root = node;
+      return;
+    };
+
+    let mut names = path
+      .components()
+      .map(|c| c.as_os_str().to_string_lossy().into_owned())
+      .collect::<Vec<_>>();
+    let Some(name) = names.pop() else {
+      self.root = node;
+      return;
+    };
+
+    let mut parent = &mut self.root;
+    for name in names {
+      let NarListingNode::Directory { entries } = parent else {
+        return;
+      };
+      let Some(child) = entries.get_mut(&name) else {
+        return;
+      };
+      parent = child;
+    }
+    if let NarListingNode::Directory { entries } = parent {
+      entries.insert(name, node);
+    }
+  }
+
+  /// Finishes the listing, working out where each file's contents are in the
+  /// NAR from its structure.
+  pub(crate) fn finish(mut self) -> NarListing {
+    let mut offset = token_len("nix-archive-1");
+    assign_offsets(&mut self.root, &mut offset);
+    NarListing {
+      version: LISTING_VERSION,
+      root:    self.root,
+    }
+  }
+}
+
+/// The length of a string serialized in a NAR, which is its length as a
+/// 64-bit integer followed by the string padded to 8 bytes.
+fn token_len(token: &str) -> u64 { 8 + token.len().next_multiple_of(8) as u64 }
+
+/// Assigns the offsets of the files in a node, given the node's offset in the
+/// NAR, and advances the offset past the node.
+fn assign_offsets(node: &mut NarListingNode, offset: &mut u64) {
+  *offset += token_len("(") + token_len("type");
+  match node {
+    NarListingNode::Regular {
+      size,
+      executable,
+      nar_offset,
+    } => {
+      *offset += token_len("regular");
+      if *executable {
+        *offset += token_len("executable") + token_len("");
+      }
+      // the contents are preceded by their length
+      *offset += token_len("contents") + 8;
+      *nar_offset = *offset;
+      *offset += size.next_multiple_of(8);
+    }
+    NarListingNode::Directory { entries } => {
+      *offset += token_len("directory");
+      // entries are in the same order as in the NAR, sorted by name
+      for (name, child) in entries {
+        *offset += token_len("entry")
+          + token_len("(")
+          + token_len("name")
+          + token_len(name)
+          + token_len("node");
+        assign_offsets(child, offset);
+        *offset += token_len(")");
+      }
+    }
+    NarListingNode::Symlink { target } => {
+      *offset += token_len("symlink") + token_len("target") + token_len(target);
+    }
+  }
+  *offset += token_len(")");
+}
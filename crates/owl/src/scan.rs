@@ -0,0 +1,322 @@
+use std::{
+  collections::{HashMap, HashSet},
+  io::{self, Read},
+};
+
+use models::{StoreDir, StorePath};
+use nix_compat::nixbase32;
+use regex::bytes::Regex as RegexBytes;
+
+/// The length of the nixbase32 hash part of a store path.
+const HASH_PART_LEN: usize = 32;
+/// The longest store path name matched when discovering references.
+const MAX_NAME_LEN: usize = 150;
+
+/// Scans a stream of bytes for references to store paths, fed in arbitrarily
+/// sized pieces, so memory use is bounded regardless of the size of the input.
+pub(crate) struct ReferenceScanner {
+  mode:  ScanMode,
+  /// The end of the input fed so far, which is carried over so that matches
+  /// can straddle the boundaries between pieces.
+  tail:  Vec<u8>,
+  found: HashSet<StorePath<String>>,
+}
+
+enum ScanMode {
+  /// Looks for the hash parts of a known set of candidate paths, like Nix's
+  /// refscanner does.
+  Candidates(HashMap<[u8; HASH_PART_LEN], StorePath<String>>),
+  /// Looks for absolute paths in a store directory, for when no candidates
+  /// are known.
+  Discovery {
+    store_dir:     StoreDir,
+    regex:         RegexBytes,
+    /// The longest possible match of `regex`.
+    max_match_len: usize,
+  },
+}
+
+impl ReferenceScanner {
+  /// Creates a scanner which reports the candidates whose hash parts appear
+  /// in the input.
+  pub(crate) fn with_candidates(
+    candidates: impl IntoIterator<Item = StorePath<String>>,
+  ) -> Self {
+    let candidates = candidates
+      .into_iter()
+      .map(|path| (hash_part(&path), path))
+      .collect();
+    Self {
+      mode:  ScanMode::Candidates(candidates),
+      tail:  Vec::new(),
+      found: HashSet::new(),
+    }
+  }
+
+  /// Creates a scanner which reports every absolute path in the store
+  /// directory that appears in the input.
+  pub(crate) fn discovering(store_dir: &StoreDir) -> Self {
+    let regex = RegexBytes::new(&format!(
+      "({}/[a-z0-9]{{32}}-[a-zA-Z0-9._+?=-]{{0,{MAX_NAME_LEN}}})",
+      regex::escape(store_dir.as_str())
+    ))
+    .expect("failed to build regex engine");
+    let max_match_len =
+      store_dir.as_str().len() + 1 + HASH_PART_LEN + 1 + MAX_NAME_LEN;
+
+    Self {
+      mode:  ScanMode::Discovery {
+        store_dir: store_dir.clone(),
+        regex,
+        max_match_len,
+      },
+      tail:  Vec::new(),
+      found: HashSet::new(),
+    }
+  }
+
+  /// Scans the next piece of the input.
+  pub(crate) fn feed(&mut self, data: &[u8]) {
+    match &self.mode {
+      ScanMode::Candidates(candidates) => {
+        // matches straddling the boundary are found in the end of the last
+        // piece joined with the start of this one
+        let head = &data[..data.len().min(HASH_PART_LEN - 1)];
+        self.tail.extend_from_slice(head);
+        search_hash_parts(&self.tail, candidates, &mut self.found);
+        self.tail.truncate(self.tail.len() - head.len());
+
+        search_hash_parts(data, candidates, &mut self.found);
+        keep_tail(&mut self.tail, data, HASH_PART_LEN - 1);
+      }
+      ScanMode::Discovery {
+        store_dir,
+        regex,
+        max_match_len,
+      } => {
+        self.tail.extend_from_slice(data);
+        for m in regex.find_iter(&self.tail) {
+          // a match touching the end of the input so far may continue into
+          // the next piece, so leave it for the next pass
+          if m.end() == self.tail.len() {
+            continue;
+          }
+          if let Some(path) = store_dir.parse_absolute_path(m.as_bytes()) {
+            self.found.insert(path);
+          }
+        }
+        let keep_from = self.tail.len().saturating_sub(*max_match_len);
+        self.tail.drain(..keep_from);
+      }
+    }
+  }
+
+  /// Finishes the scan and returns the references found.
+  pub(crate) fn finish(mut self) -> HashSet<StorePath<String>> {
+    if let ScanMode::Discovery {
+      store_dir, regex, ..
+    } = &self.mode
+    {
+      // the end of the input is known now, so a match touching it is whole
+      let last_match = regex
+        .find_iter(&self.tail)
+        .last()
+        .filter(|m| m.end() == self.tail.len())
+        .and_then(|m| store_dir.parse_absolute_path(m.as_bytes()));
+      self.found.extend(last_match);
+    }
+    self.found
+  }
+}
+
+/// A reader adapter that feeds every byte read through it to a
+/// [`ReferenceScanner`].
+pub(crate) struct ScanningReader<R> {
+  inner:   R,
+  scanner: ReferenceScanner,
+}
+
+impl<R> ScanningReader<R> {
+  pub(crate) fn new(inner: R, scanner: ReferenceScanner) -> Self {
+    Self { inner, scanner }
+  }
+
+  /// Returns the references found in everything read.
+  pub(crate) fn finish(self) -> HashSet<StorePath<String>> {
+    self.scanner.finish()
+  }
+}
+
+impl<R: Read> Read for ScanningReader<R> {
+  fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+    let n = self.inner.read(buf)?;
+    self.scanner.feed(&buf[..n]);
+    Ok(n)
+  }
+}
+
+/// Returns the nixbase32 hash part of a store path.
+fn hash_part(path: &StorePath<String>) -> [u8; HASH_PART_LEN] {
+  nixbase32::encode(path.digest())
+    .into_bytes()
+    .try_into()
+    .expect("store path digests encode to 32 characters")
+}
+
+/// Whether a byte is in the nixbase32 alphabet.
+fn is_base32(byte: u8) -> bool {
+  matches!(byte, b'0'..=b'9' | b'a'..=b'z')
+    && !matches!(byte, b'e' | b'o' | b'u' | b't')
+}
+
+/// Finds the candidates whose hash parts appear in the data. Like Nix, each
+/// window is checked from its end, so a byte outside the nixbase32 alphabet
+/// skips every window containing it.
+fn search_hash_parts(
+  data: &[u8],
+  candidates: &HashMap<[u8; HASH_PART_LEN], StorePath<String>>,
+  found: &mut HashSet<StorePath<String>>,
+) {
+  let mut i = 0;
+  while i + HASH_PART_LEN <= data.len() {
+    let window: &[u8; HASH_PART_LEN] = data[i..i + HASH_PART_LEN]
+      .try_into()
+      .expect("window is the length of a hash part");
+    match window.iter().rposition(|b| !is_base32(*b)) {
+      Some(j) => i += j + 1,
+      None => {
+        if let Some(path) = candidates.get(window) {
+          found.insert(path.clone());
+        }
+        i += 1;
+      }
+    }
+  }
+}
+
+/// Keeps the last `len` bytes of the input fed so far in the tail.
+fn keep_tail(tail: &mut Vec<u8>, data: &[u8], len: usize) {
+  if data.len() >= len {
+    tail.clear();
+    tail.extend_from_slice(&data[data.len() - len..]);
+  } else {
+    tail.extend_from_slice(data);
+    let keep_from = tail.len().saturating_sub(len);
+    tail.drain(..keep_from);
+  }
+}
+
+#[cfg(test)]
+mod tests {
+  use super::*;
+
+  const BAT: &str = "ky2wzr68im63ibgzksbsar19iyk861x6-bat-0.25.0";
+  const OTHER: &str = "4yz8qa58nmysad5w88rgdhq15rkssqr6-other";
+
+  fn store_path(base_name: &str) -> StorePath<String> {
+    StorePath::from_bytes(base_name.as_bytes()).unwrap()
+  }
+
+  /// Feeds the data to the scanner in pieces of the given size.
+  fn scan_in_pieces(
+    mut scanner: ReferenceScanner,
+    data: &[u8],
+    piece_len: usize,
+  ) -> HashSet<StorePath<String>> {
+    for piece in data.chunks(piece_len) {
+      scanner.feed(piece);
+    }
+    scanner.finish()
+  }
+
+  #[test]
+  fn finds_bare_hash_parts_of_candidates() {
+    let data = format!("prefix{}suffix", &BAT[..32]);
+    let found = scan_in_pieces(
+      ReferenceScanner::with_candidates([store_path(BAT), store_path(OTHER)]),
+      data.as_bytes(),
+      usize::MAX,
+    );
+    assert_eq!(found, HashSet::from([store_path(BAT)]));
+  }
+
+  #[test]
+  fn finds_hash_parts_straddling_pieces() {
+    let data = format!("/nix/store/{BAT}/bin/bat:/nix/store/{OTHER}");
+    for piece_len in 1..=data.len() {
+      let found = scan_in_pieces(
+        ReferenceScanner::with_candidates([store_path(BAT), store_path(OTHER)]),
+        data.as_bytes(),
+        piece_len,
+      );
+      assert_eq!(
+        found,
+        HashSet::from([store_path(BAT), store_path(OTHER)]),
+        "failed with pieces of {piece_len} bytes"
+      );
+    }
+  }
+
+  #[test]
+  fn finds_hash_parts_inside_longer_base32_runs() {
+    let data = format!("0{}0", &BAT[..32]);
+    let found = scan_in_pieces(
+      ReferenceScanner::with_candidates([store_path(BAT)]),
+      data.as_bytes(),
+      usize::MAX,
+    );
+    assert_eq!(found, HashSet::from([store_path(BAT)]));
+  }
+
+  #[test]
+  fn ignores_truncated_hash_parts() {
+    let data = format!("{}-bat", &BAT[..31]);
+    let found = scan_in_pieces(
+      ReferenceScanner::with_candidates([store_path(BAT)]),
+      data.as_bytes(),
+      usize::MAX,
+    );
+    assert!(found.is_empty());
+  }
+
+  #[test]
+  fn discovers_paths_straddling_pieces() {
+    let path = format!("/nix/store/{BAT}");
+    let mut data = vec![0u8; 1000];
+    data.extend_from_slice(path.as_bytes());
+    data.push(0);
+
+    for piece_len in [1, 7, 64, 1024] {
+      let found = scan_in_pieces(
+        ReferenceScanner::discovering(&StoreDir::default()),
+        &data,
+        piece_len,
+      );
+      assert_eq!(found, HashSet::from([store_path(BAT)]));
+    }
+  }
+
+  #[test]
+  fn discovers_paths_in_relocated_store() {
+    let data = format!("#!/gpfs/nix/store/{BAT}/bin/bat\n/nix/store/{OTHER}");
+
+    let store_dir = StoreDir::new("/gpfs/nix/store").unwrap();
+    let found = scan_in_pieces(
+      ReferenceScanner::discovering(&store_dir),
+      data.as_bytes(),
+      usize::MAX,
+    );
+    assert_eq!(found, HashSet::from([store_path(BAT)]));
+  }
+
+  #[test]
+  fn discovers_paths_at_end_of_input() {
+    let data = format!("/nix/store/{OTHER}");
+    let found = scan_in_pieces(
+      ReferenceScanner::discovering(&StoreDir::default()),
+      data.as_bytes(),
+      5,
+    );
+    assert_eq!(found, HashSet::from([store_path(OTHER)]));
+  }
+}
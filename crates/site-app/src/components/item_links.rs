@@ -20,7 +20,9 @@ pub fn CacheItemLink(
     <Suspense fallback=|| view! { <LoadingItemLink /> }>
       { move || match cache.get() {
         Some(Ok(Some(cache))) => {
-          view! { <a class=class>{ cache.name.to_string() }</a> }.into_any()
+          let href = format!("/org/{}/cache/{}", cache.org, cache.id);
+          view! { <a href=href class=class>{ cache.name.to_string() }</a> }
+            .into_any()
         }
         Some(Ok(None) | Err(_)) => view! { <UnknownItemLink /> }.into_any(),
         None => view! { <LoadingItemLink /> }.into_any(),
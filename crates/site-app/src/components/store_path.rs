@@ -1,6 +1,6 @@
 use leptos::prelude::*;
 use leptos_use::{use_clipboard, UseClipboardReturn};
-use models::{Abbreviate, StorePath};
+use models::{Abbreviate, StoreDir, StorePath};
 
 use crate::{components::HashtagHeroIcon, join_classes::JoinClasses};
 
@@ -10,8 +10,11 @@ pub fn StorePathAbbreviated(sp: StorePath<String>) -> impl IntoView {
 }
 
 #[island]
-pub fn StorePathCopyButton(sp: StorePath<String>) -> impl IntoView {
-  let absolute = sp.to_absolute_path();
+pub fn StorePathCopyButton(
+  sp: StorePath<String>,
+  store_dir: StoreDir,
+) -> impl IntoView {
+  let absolute = store_dir.absolute_path(&sp);
 
   let UseClipboardReturn { copy, copied, .. } = use_clipboard();
   let copy = {
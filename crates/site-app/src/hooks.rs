@@ -8,9 +8,11 @@ mod entry_hook;
 mod login_hook;
 mod org_hook;
 mod signup_hook;
+mod update_nix_cache_info_hook;
 
 // pub use self::cache_hook::*;
 pub use self::{
   create_cache_hook::*, create_org_hook::*, delete_entry_hook::*,
   entry_hook::*, login_hook::*, org_hook::*, signup_hook::*,
+  update_nix_cache_info_hook::*,
 };
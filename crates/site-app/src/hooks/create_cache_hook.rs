@@ -1,6 +1,8 @@
 use leptos::{ev::Event, prelude::*};
 use leptos_fetch::QueryClient;
-use models::{Cache, EntityName, Org, RecordId, Visibility};
+use models::{
+  Cache, ENTRY_INDEX_VERSION, EntityName, Org, RecordId, Visibility,
+};
 
 use super::OrgHook;
 use crate::{
@@ -187,6 +189,14 @@ pub async fn create_cache(
     org,
     name: sanitized_name,
     visibility,
+    signing_key: None,
+    trusted_public_keys: Vec::new(),
+    require_trusted_signature: false,
+    default_store: None,
+    upstreams: vec![],
+    nix_cache_info: Default::default(),
+    store_dir: Default::default(),
+    entry_index_version: ENTRY_INDEX_VERSION,
   };
 
   domain_service.create_cache(&cache).await.map_err(|e| {
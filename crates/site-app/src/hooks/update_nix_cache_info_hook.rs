@@ -0,0 +1,122 @@
+use leptos::{ev::Event, prelude::*, server::ServerAction};
+use models::{Cache, NixCacheInfoSettings, RecordId};
+
+use crate::reactive_utils::touched_input_bindings;
+
+pub struct UpdateNixCacheInfoHook {
+  key:                    Callback<(), RecordId<Cache>>,
+  priority_signal:        RwSignal<String>,
+  want_mass_query_signal: RwSignal<bool>,
+  parsed_priority_memo:   Memo<Option<u32>>,
+  action:                 ServerAction<UpdateNixCacheInfo>,
+}
+
+impl UpdateNixCacheInfoHook {
+  pub fn new(
+    key: impl Fn() -> RecordId<Cache> + Copy + Send + Sync + 'static,
+    initial: NixCacheInfoSettings,
+  ) -> Self {
+    let priority_signal = RwSignal::new(initial.priority.to_string());
+    let want_mass_query_signal = RwSignal::new(initial.want_mass_query);
+    let parsed_priority_memo =
+      Memo::new(move |_| priority_signal().trim().parse::<u32>().ok());
+
+    Self {
+      key: Callback::new(move |()| key()),
+      priority_signal,
+      want_mass_query_signal,
+      parsed_priority_memo,
+      action: ServerAction::new(),
+    }
+  }
+
+  pub fn priority_bindings(&self) -> (Callback<(), String>, Callback<Event>) {
+    touched_input_bindings(self.priority_signal)
+  }
+
+  pub fn priority_error_hint(&self) -> Signal<Option<String>> {
+    let parsed_priority_memo = self.parsed_priority_memo;
+    Signal::derive(move || match parsed_priority_memo() {
+      Some(_) => None,
+      None => Some("The priority must be a whole number.".to_owned()),
+    })
+  }
+
+  pub fn want_mass_query_signal(&self) -> RwSignal<bool> {
+    self.want_mass_query_signal
+  }
+
+  pub fn show_spinner(&self) -> Signal<bool> {
+    let pending = self.action.pending();
+    Signal::derive(move || pending())
+  }
+
+  pub fn button_text(&self) -> Signal<&'static str> {
+    let (pending, value) = (self.action.pending(), self.action.value());
+    Signal::derive(move || match (value.get(), pending()) {
+      // if the action is loading at all
+      (_, true) => "Saving...",
+      // if it's completed successfully
+      (Some(Ok(_)), _) => "Saved",
+      // any other state
+      _ => "Save",
+    })
+  }
+
+  pub fn action_trigger(&self) -> Callback<()> {
+    let (key, want_mass_query_signal, parsed_priority_memo, action) = (
+      self.key,
+      self.want_mass_query_signal,
+      self.parsed_priority_memo,
+      self.action,
+    );
+    Callback::new(move |()| {
+      // the priority has been checked and is valid
+      if let Some(priority) = parsed_priority_memo() {
+        action.dispatch(UpdateNixCacheInfo {
+          cache: key.run(()),
+          priority,
+          want_mass_query: want_mass_query_signal(),
+        });
+      }
+    })
+  }
+}
+
+#[server(prefix = "/api/sfn")]
+pub async fn update_nix_cache_info(
+  cache: RecordId<Cache>,
+  priority: u32,
+  want_mass_query: bool,
+) -> Result<(), ServerFnError> {
+  use domain::{cache_settings::UpdateNixCacheInfoRequest, DomainService};
+
+  let domain_service: DomainService = expect_context();
+
+  let cache = domain_service
+    .meta()
+    .fetch_cache_by_id(cache)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to fetch cache: {e}");
+      ServerFnError::new("internal error")
+    })?
+    .ok_or(ServerFnError::new("cache does not exist"))?;
+
+  let auth_user = crate::resources::authorize_for_org(cache.org)?;
+
+  domain_service
+    .update_cache_nix_cache_info(UpdateNixCacheInfoRequest {
+      auth:       auth_user.id,
+      cache_name: cache.name,
+      settings:   NixCacheInfoSettings {
+        priority,
+        want_mass_query,
+      },
+    })
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to update nix-cache-info settings: {e}");
+      ServerFnError::new("internal error")
+    })
+}
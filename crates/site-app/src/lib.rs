@@ -86,6 +86,7 @@ pub fn App() -> impl IntoView {
               <Route path=path!("") view=HomePage/>
               <Route path=path!("/org/:org/dash") view=protect_by_org(DashboardPage) />
               <Route path=path!("/org/:org/entry/:entry") view=protect_by_org(EntryPage) />
+              <Route path=path!("/org/:org/cache/:cache") view=protect_by_org(CachePage) />
               <Route path=path!("/org/create_org") view=protect(CreateOrgPage) />
               <ParentRoute path=path!("/org/:org/settings") view=protect_by_org_owner(OrgSettingsPage)>
                 <Route path=path!("/") view=OrgSettingsSubPageOverview />
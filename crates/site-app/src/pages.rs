@@ -1,3 +1,4 @@
+mod cache;
 mod create_cache;
 mod create_org;
 mod create_store;
@@ -13,7 +14,7 @@ mod signup;
 mod unauthorized;
 
 pub use self::{
-  create_cache::*, create_org::*, create_store::*, dashboard::*, entry::*,
-  homepage::*, login::*, logout::*, org_settings::*, payment_link::*,
+  cache::*, create_cache::*, create_org::*, create_store::*, dashboard::*,
+  entry::*, homepage::*, login::*, logout::*, org_settings::*, payment_link::*,
   protected::*, signup::*, unauthorized::*,
 };
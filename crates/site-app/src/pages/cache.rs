@@ -0,0 +1,81 @@
+mod nix_cache_info_tile;
+
+use leptos::prelude::*;
+use leptos_router::hooks::use_params_map;
+use models::{PvCache, RecordId};
+
+use self::nix_cache_info_tile::NixCacheInfoTile;
+use crate::pages::UnauthorizedPage;
+
+#[component]
+pub fn CachePage() -> impl IntoView {
+  let params = use_params_map();
+  let requested_cache = params()
+    .get("cache")
+    .expect("missing cache path param")
+    .parse::<RecordId<_>>()
+    .ok();
+
+  let Some(requested_cache) = requested_cache else {
+    return view! { <UnauthorizedPage /> }.into_any();
+  };
+
+  let cache = crate::resources::cache::cache(move || requested_cache);
+  let cache_suspend = move || {
+    Suspend::new(async move {
+      match cache.await {
+        Ok(Some(cache)) => view! { <CacheInner cache=cache /> }.into_any(),
+        Ok(None) => view! { <MissingCachePage /> }.into_any(),
+        Err(_) => view! { <ErrorCachePage /> }.into_any(),
+      }
+    })
+  };
+
+  view! {
+    <Suspense fallback=|| ()>{ cache_suspend }</Suspense>
+  }
+  .into_any()
+}
+
+#[component]
+fn CacheInner(cache: PvCache) -> impl IntoView {
+  view! {
+    <div class="flex flex-col gap-4">
+      <div class="p-6 elevation-flat flex flex-row gap-2 items-center">
+        <p class="text-base-12 text-xl">
+          <span class="font-bold">
+            "Cache: "
+          </span>
+          { cache.name.to_string() }
+        </p>
+      </div>
+      <NixCacheInfoTile cache_id=cache.id settings=cache.nix_cache_info />
+    </div>
+  }
+}
+
+#[component]
+fn MissingCachePage() -> impl IntoView {
+  view! {
+    <div class="p-6 elevation-flat flex flex-col gap-4 items-center">
+      <p class="title">
+        "( ˶°ㅁ°) !!"
+        " We don't have that cache"
+      </p>
+      <p>"Looks like we don't have that cache! Try creating it from the dashboard."</p>
+    </div>
+  }
+}
+
+#[component]
+fn ErrorCachePage() -> impl IntoView {
+  view! {
+    <div class="p-6 elevation-flat flex flex-col gap-4 items-center">
+      <p class="title">
+        "ヽ(°〇°)ﾉ"
+        " Something went wrong"
+      </p>
+      <p>"Looks like something went wrong when finding your cache. We apologize!"</p>
+    </div>
+  }
+}
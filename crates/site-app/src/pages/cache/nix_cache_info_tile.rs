@@ -0,0 +1,65 @@
+use leptos::prelude::*;
+use models::{Cache, NixCacheInfoSettings, RecordId};
+
+use crate::{
+  components::{InputField, LoadingCircle},
+  hooks::UpdateNixCacheInfoHook,
+};
+
+#[island]
+pub(crate) fn NixCacheInfoTile(
+  cache_id: RecordId<Cache>,
+  settings: NixCacheInfoSettings,
+) -> impl IntoView {
+  let hook = UpdateNixCacheInfoHook::new(move || cache_id, settings);
+
+  let priority_bindings = hook.priority_bindings();
+  let priority_error_hint = hook.priority_error_hint();
+  let want_mass_query_signal = hook.want_mass_query_signal();
+
+  let action_trigger = hook.action_trigger();
+  let button_text = hook.button_text();
+  let show_spinner = hook.show_spinner();
+
+  view! {
+    <div class="p-6 elevation-flat flex flex-col gap-4">
+      <div class="flex flex-col gap-1">
+        <p class="subtitle">"Nix Settings"</p>
+        <p class="max-w-prose">
+          "These are advertised to Nix in the cache's "
+          <code>"nix-cache-info"</code>
+          ". Nix prefers substituters with a lower priority; "
+          <code>"cache.nixos.org"</code>
+          " uses 40."
+        </p>
+      </div>
+
+      <InputField
+        id="priority" label_text="Priority" input_type="number"
+        input_signal=priority_bindings.0 output_signal=priority_bindings.1
+        error_hint=priority_error_hint
+      />
+
+      <label class="flex flex-row items-center gap-2">
+        <input
+          type="checkbox" id="want_mass_query"
+          prop:checked=want_mass_query_signal
+          on:change=move |ev| want_mass_query_signal.set(event_target_checked(&ev))
+        />
+        "Allow Nix to query many paths at once"
+      </label>
+
+      <button
+        class="btn btn-primary w-full max-w-80 justify-between"
+        on:click=move |_| action_trigger.run(())
+      >
+        <div class="size-4" />
+        { button_text }
+        <LoadingCircle {..}
+          class="size-4 transition-opacity"
+          class=("opacity-0", move || { !show_spinner() })
+        />
+      </button>
+    </div>
+  }
+}
@@ -1,12 +1,17 @@
+mod compression_selector;
 mod credentials_input;
 
 use leptos::{prelude::*, server_fn::codec::Json};
 use leptos_fetch::QueryClient;
 use models::{
-  EntityName, Org, R2StorageCredentials, RecordId, Store, StoreConfiguration,
+  CompressionConfig, EntityName, Org, R2StorageCredentials, RecordId, Store,
+  StoreConfiguration,
 };
 
-use self::credentials_input::CredentialsInput;
+use self::{
+  compression_selector::CompressionSelector,
+  credentials_input::CredentialsInput,
+};
 use crate::{
   components::{form_layout::*, InputField, InputIcon, LoadingCircle},
   hooks::OrgHook,
@@ -34,6 +39,7 @@ pub fn CreateStorePage() -> impl IntoView {
   });
   let (read_name, write_name) = touched_input_bindings(name);
   let credentials = RwSignal::<Option<R2StorageCredentials>>::new(None);
+  let compression = RwSignal::new(CompressionConfig::recommended());
   let submit_touched = RwSignal::new(false);
 
   let is_available_query_scope =
@@ -93,7 +99,9 @@ pub fn CreateStorePage() -> impl IntoView {
       org: org(),
       name: sanitized_name().unwrap().to_string(),
       credentials,
-      configuration: StoreConfiguration {},
+      configuration: StoreConfiguration {
+        compression: compression(),
+      },
     });
   };
 
@@ -142,6 +150,14 @@ pub fn CreateStorePage() -> impl IntoView {
         <CredentialsInput signal=credentials show_hints={ move || submit_touched() } />
       </GridRow>
 
+      <GridRow>
+        <GridRowLabel
+          title="Compression"
+          desc="How entries are encoded at rest."
+        />
+        <CompressionSelector signal=compression />
+      </GridRow>
+
       <GridRow>
         <div />
         <label>
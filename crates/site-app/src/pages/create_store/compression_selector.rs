@@ -0,0 +1,83 @@
+use leptos::prelude::*;
+use models::{CompressionAlgorithm, CompressionConfig};
+
+#[component]
+pub(super) fn CompressionSelector(
+  signal: RwSignal<CompressionConfig>,
+) -> impl IntoView {
+  let is_zstd = move || {
+    matches!(signal().algorithm(), Some(CompressionAlgorithm::Zstd))
+  };
+  let is_none = move || signal().algorithm().is_none();
+  let set_zstd = move |_| {
+    signal.set(CompressionConfig::new(Some(CompressionAlgorithm::Zstd)))
+  };
+  let set_none = move |_| signal.set(CompressionConfig::new(None));
+
+  const OUTER_CLASS: &str =
+    "flex-1 flex flex-col gap-2 px-4 py-3 max-w-80 hover:elevation-lv1 \
+     cursor-pointer transition rounded border-2 border-base-7 \
+     hover:border-base-8 bg-gradient-to-tr to-transparent to-50%";
+  const OUTER_ACTIVE_CLASS: &str =
+    "border-product-7 hover:border-product-8 from-product-3";
+  const OUTER_INACTIVE_CLASS: &str = "from-transparent";
+  const TITLE_CLASS: &str = "text-base-12 text-lg font-semibold leading-none";
+  const DESCRIPTION_CLASS: &str = "text-sm leading-[1.1]";
+
+  let outer_zstd_class = move || {
+    format!(
+      "{OUTER_CLASS} {}",
+      if is_zstd() {
+        OUTER_ACTIVE_CLASS
+      } else {
+        OUTER_INACTIVE_CLASS
+      }
+    )
+  };
+  let outer_none_class = move || {
+    format!(
+      "{OUTER_CLASS} {}",
+      if is_none() {
+        OUTER_ACTIVE_CLASS
+      } else {
+        OUTER_INACTIVE_CLASS
+      }
+    )
+  };
+
+  view! {
+    <div class="flex flex-col gap-4">
+      <div
+        class=outer_zstd_class
+        on:click=set_zstd
+      >
+        <div class="flex flex-row justify-between items-center">
+          <p class=TITLE_CLASS>"Zstandard"</p>
+          <div class="size-5 bg-base-2 border border-base-6 rounded-full flex flex-row justify-center items-center">
+            <div
+              class="size-3 bg-product-9 rounded-full transition-opacity"
+              class=("opacity-0", move || !is_zstd())
+            />
+          </div>
+        </div>
+        <p class=DESCRIPTION_CLASS>"Entries are compressed before they are stored. Recommended."</p>
+      </div>
+
+      <div
+        class=outer_none_class
+        on:click=set_none
+      >
+        <div class="flex flex-row justify-between items-center">
+          <p class=TITLE_CLASS>"Uncompressed"</p>
+          <div class="size-5 bg-base-2 border border-base-6 rounded-full flex flex-row justify-center items-center">
+            <div
+              class="size-3 bg-product-9 rounded-full transition-opacity"
+              class=("opacity-0", move || !is_none())
+            />
+          </div>
+        </div>
+        <p class=DESCRIPTION_CLASS>"Entries are stored exactly as they were uploaded."</p>
+      </div>
+    </div>
+  }
+}
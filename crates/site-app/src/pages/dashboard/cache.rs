@@ -4,7 +4,7 @@ use models::{PvCache, Visibility};
 
 use crate::{
   components::{
-    CacheItemLink, CreateCacheButton, DataTableRefreshButton,
+    CacheItemLink, CopyButton, CreateCacheButton, DataTableRefreshButton,
     LockClosedHeroIcon, TableEmptyBody,
   },
   formatting_utils::ThousandsSeparated,
@@ -60,6 +60,7 @@ pub(super) fn CacheTable() -> impl IntoView {
           <th>"Name"</th>
           <th>"Visibility"</th>
           <th>"Entry Count"</th>
+          <th>"Public Key"</th>
         </thead>
         <Transition fallback=|| ()>
           { suspend }
@@ -102,6 +103,17 @@ fn CacheDataRow(cache: PvCache) -> impl IntoView {
     })
   };
 
+  let public_key_view = match cache.public_key {
+    Some(public_key) => view! {
+      <div class="flex flex-row items-center gap-1">
+        <code class="max-w-48 truncate">{ public_key.clone() }</code>
+        <CopyButton copy_content=public_key {..} class="size-4" />
+      </div>
+    }
+    .into_any(),
+    None => "None".into_any(),
+  };
+
   view! {
     <tr>
       <th scope="row"><CacheItemLink id=cache.id extra_class="text-link-primary"/></th>
@@ -114,6 +126,7 @@ fn CacheDataRow(cache: PvCache) -> impl IntoView {
       <td><Transition fallback=|| "[loading]">
         { entry_count_suspend }
       </Transition></td>
+      <td>{ public_key_view }</td>
     </tr>
   }
 }
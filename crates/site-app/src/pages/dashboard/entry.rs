@@ -110,7 +110,7 @@ fn EntryDataRow(entry: Entry) -> impl IntoView {
         <a class="text-link text-link-primary" href=entry_href>
           <StorePathAbbreviated sp=entry.store_path.clone() />
         </a>
-        <StorePathCopyButton sp=entry.store_path />
+        <StorePathCopyButton sp=entry.store_path store_dir=entry.store_dir />
       </th>
       <td>
         { caches }
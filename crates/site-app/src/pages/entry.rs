@@ -49,10 +49,16 @@ fn EntryInner(entry: Entry) -> impl IntoView {
   view! {
     <div class="flex flex-col md:grid md:grid-cols-[max-content_auto] gap-4">
       <div class="hidden md:block"/>
-      <TitleTile store_path={entry.store_path.clone()} />
+      <TitleTile
+        store_path={entry.store_path.clone()}
+        store_dir={entry.store_dir.clone()}
+      />
       <ActionTile entry_id=entry.id />
       <div class="flex flex-row gap-4 flex-wrap">
-        <StorePathTile store_path={entry.store_path.clone()} />
+        <StorePathTile
+          store_path={entry.store_path.clone()}
+          store_dir={entry.store_dir.clone()}
+        />
         <CachesTile entry={entry.clone()} />
       </div>
     </div>
@@ -8,6 +8,7 @@ use crate::components::{CacheItemLink, LockClosedHeroIcon};
 pub(crate) fn CachesTile(entry: Entry) -> impl IntoView {
   let caches = Signal::stored(entry.caches.clone());
   let store_path = Signal::stored(entry.store_path);
+  let drv_path = Signal::stored(entry.deriver_data.drv_path());
   view! {
     <div class="flex-1 p-6 elevation-flat flex flex-col gap-4">
       <div class="flex flex-col gap-1">
@@ -23,11 +24,12 @@ pub(crate) fn CachesTile(entry: Entry) -> impl IntoView {
         <thead>
           <th>"Name"</th>
           <th>"Download Url"</th>
+          <th>"Build Log"</th>
           <th>"Visibility"</th>
         </thead>
         <tbody class="animate-fade-in min-h-10">
           <For each=caches key=|r| *r children=move |r| view! {
-            <CachesTileRow store_path=store_path cache_id=r />
+            <CachesTileRow store_path=store_path drv_path=drv_path cache_id=r />
           } />
         </tbody>
       </table>
@@ -38,6 +40,7 @@ pub(crate) fn CachesTile(entry: Entry) -> impl IntoView {
 #[component]
 pub(crate) fn CachesTileRow(
   store_path: Signal<StorePath<String>>,
+  drv_path: Signal<Option<StorePath<String>>>,
   cache_id: RecordId<Cache>,
 ) -> impl IntoView {
   let query_client = expect_context::<QueryClient>();
@@ -48,10 +51,10 @@ pub(crate) fn CachesTileRow(
   let suspend = move || {
     Suspend::new(async move {
       match resource.await {
-        Ok(Some(c)) => {
-          view! { <CachesTileDataRow cache=c store_path=store_path /> }
-            .into_any()
+        Ok(Some(c)) => view! {
+          <CachesTileDataRow cache=c store_path=store_path drv_path=drv_path />
         }
+        .into_any(),
         Ok(None) => None::<()>.into_any(),
         Err(e) => format!("Error: {e}").into_any(),
       }
@@ -66,6 +69,7 @@ pub(crate) fn CachesTileRow(
 #[component]
 pub(crate) fn CachesTileDataRow(
   store_path: Signal<StorePath<String>>,
+  drv_path: Signal<Option<StorePath<String>>>,
   cache: PvCache,
 ) -> impl IntoView {
   let download_url = format!(
@@ -86,6 +90,9 @@ pub(crate) fn CachesTileDataRow(
       <td>
         <a href={download_url} class="text-link text-link-primary">"Download"</a>
       </td>
+      <td>
+        <BuildLogLink cache_id=cache.id cache_name=cache.name.to_string() drv_path=drv_path />
+      </td>
       <td class="flex flex-row items-center gap-1">
         { cache.visibility.to_string() }
         { vis_icon }
@@ -93,3 +100,41 @@ pub(crate) fn CachesTileDataRow(
     </tr>
   }
 }
+
+#[component]
+fn BuildLogLink(
+  cache_id: RecordId<Cache>,
+  cache_name: String,
+  drv_path: Signal<Option<StorePath<String>>>,
+) -> impl IntoView {
+  let Some(drv_path) = drv_path() else {
+    return "None".into_any();
+  };
+
+  let query_client = expect_context::<QueryClient>();
+  let query_scope = crate::resources::build_log::build_log_query_scope();
+  let resource = query_client.resource(query_scope, {
+    let drv_path = drv_path.clone();
+    move || (cache_id, drv_path.clone())
+  });
+
+  let log_url = format!("/api/v1/c/{cache_name}/log/{drv_path}");
+  let suspend = move || {
+    let log_url = log_url.clone();
+    Suspend::new(async move {
+      match resource.await {
+        Ok(Some(_)) => view! {
+          <a href={log_url} class="text-link text-link-primary">"View"</a>
+        }
+        .into_any(),
+        Ok(None) => "None".into_any(),
+        Err(e) => format!("Error: {e}").into_any(),
+      }
+    })
+  };
+
+  view! {
+    <Transition fallback=|| ()>{ suspend }</Transition>
+  }
+  .into_any()
+}
@@ -1,10 +1,14 @@
 use leptos::prelude::*;
-use models::StorePath;
+use models::{StoreDir, StorePath};
 
 use crate::components::CopyButton;
 
 #[component]
-pub(crate) fn StorePathTile(store_path: StorePath<String>) -> impl IntoView {
+pub(crate) fn StorePathTile(
+  store_path: StorePath<String>,
+  store_dir: StoreDir,
+) -> impl IntoView {
+  let prefix = format!("{store_dir}/");
   let string = store_path.to_string();
   let separator_index =
     string.find('-').expect("no separator found in store path");
@@ -34,7 +38,7 @@ pub(crate) fn StorePathTile(store_path: StorePath<String>) -> impl IntoView {
       <div class="flex-1 flex flex-col justify-around">
         <div class="grid gap-x-4 gap-y-1 grid-cols-[repeat(2,auto)]">
           <p class=KEY_CLASS>"Prefix"</p>
-          { value_element("/nix/store/") }
+          { value_element(&prefix) }
           <p class=KEY_CLASS>"Digest"</p>
           { value_element(digest) }
           <p class=KEY_CLASS>"Name"</p>
@@ -1,11 +1,14 @@
 use leptos::prelude::*;
-use models::StorePath;
+use models::{StoreDir, StorePath};
 
 use crate::components::CopyButton;
 
 #[component]
-pub(crate) fn TitleTile(store_path: StorePath<String>) -> impl IntoView {
-  let path = store_path.to_absolute_path();
+pub(crate) fn TitleTile(
+  store_path: StorePath<String>,
+  store_dir: StoreDir,
+) -> impl IntoView {
+  let path = store_dir.absolute_path(&store_path);
   view! {
     <div class="p-6 elevation-flat flex flex-row gap-2 items-center">
       <p class="text-base-12 text-xl">
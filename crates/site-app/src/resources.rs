@@ -1,3 +1,4 @@
+pub mod build_log;
 pub mod cache;
 pub mod entry;
 pub mod org;
@@ -0,0 +1,45 @@
+use leptos::prelude::*;
+use leptos_fetch::QueryScope;
+use models::{model::Model, BuildLog, Cache, RecordId, StorePath};
+
+#[cfg(feature = "ssr")]
+use crate::resources::authorize_for_org;
+
+pub fn build_log_query_scope() -> QueryScope<
+  (RecordId<Cache>, StorePath<String>),
+  Result<Option<BuildLog>, ServerFnError>,
+> {
+  QueryScope::new(fetch_build_log)
+    .with_invalidation_link(move |_| [BuildLog::TABLE_NAME])
+}
+
+#[server(prefix = "/api/sfn")]
+pub async fn fetch_build_log(
+  cache_and_drv_path: (RecordId<Cache>, StorePath<String>),
+) -> Result<Option<BuildLog>, ServerFnError> {
+  use domain::DomainService;
+
+  let (cache, drv_path) = cache_and_drv_path;
+
+  let domain_service: DomainService = expect_context();
+  let cache = domain_service
+    .meta()
+    .fetch_cache_by_id(cache)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to fetch cache: {e}");
+      ServerFnError::new("internal error")
+    })?
+    .ok_or(ServerFnError::new("cache does not exist"))?;
+
+  authorize_for_org(cache.org)?;
+
+  domain_service
+    .meta()
+    .fetch_build_log_by_cache_id_and_drv_path(cache.id, &drv_path)
+    .await
+    .map_err(|e| {
+      tracing::error!("failed to fetch build log: {e}");
+      ServerFnError::new("internal error")
+    })
+}
@@ -51,6 +51,7 @@
               -e PADDLE_API_KEY=$PADDLE_API_KEY \
               -e PADDLE_CLIENT_KEY=$PADDLE_CLIENT_KEY \
               -e PADDLE_SANDBOX=1 \
+              -e SECRET_ENCRYPTION_KEY=$SECRET_ENCRYPTION_KEY \
               -e QUICKWIT_URL=$QUICKWIT_URL \
               grid:latest
           '';